@@ -77,6 +77,10 @@ pub fn main() -> GameResult {
                 // reset the mouse delta for the next frame
                 // necessary because it's calculated cumulatively each cycle
                 ctx.mouse_context.reset_delta();
+                // and save the input state for the `just_pressed`/`just_released` queries
+                ctx.keyboard_context.save_keyboard_state();
+                ctx.mouse_context.save_mouse_state();
+                ctx.gamepad_context.save_gamepad_state();
 
                 ggez::timer::yield_now();
             }
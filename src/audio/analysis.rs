@@ -0,0 +1,405 @@
+//! Measuring the levels and spectrum of what is being played.
+
+use std::f32::consts::PI;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time;
+
+/// How many frames a tap collects before handing them to its analyzer.
+const FLUSH_FRAMES: usize = 128;
+
+/// The loudness of a stretch of audio, from `0.0` for silence
+/// to `1.0` for full scale.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Levels {
+    /// The root mean square of the samples, which follows how loud
+    /// the sound is perceived to be.
+    pub rms: f32,
+    /// The largest absolute value of any sample.
+    pub peak: f32,
+}
+
+/// One frame of audio, summed up over its channels.
+#[derive(Debug, Clone, Copy, Default)]
+struct Frame {
+    /// The average of the channels.
+    mono: f32,
+    /// The largest absolute value of any channel.
+    peak: f32,
+    /// The mean square of the channels.
+    power: f32,
+}
+
+/// The latest frames fed to an analyzer.
+#[derive(Debug)]
+struct History {
+    frames: Vec<Frame>,
+    /// Where the next frame goes, which is also the oldest one.
+    position: usize,
+    sample_rate: u32,
+}
+
+/// Measures the [`Levels`](struct.Levels.html) and frequency spectrum of
+/// the last few milliseconds of a sound, for visualizers and for reacting
+/// to music during the game.
+///
+/// An `Analyzer` is a handle; clones of it share the same measurements.
+/// Attach it to a source with
+/// [`SoundSource::set_analyzer()`](trait.SoundSource.html#tymethod.set_analyzer),
+/// or to everything being played with
+/// [`Mixer::set_analyzer()`](struct.Mixer.html#method.set_analyzer),
+/// then read it once a frame.  It should only be attached to one thing
+/// at a time.
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # fn t(ctx: &mut Context) {
+/// let analyzer = audio::Analyzer::new(1024);
+/// audio::mixer(ctx).set_analyzer(Some(&analyzer));
+///
+/// // Every frame:
+/// let levels = analyzer.levels();
+/// let spectrum = analyzer.spectrum();
+/// let bass: f32 = spectrum
+///     .iter()
+///     .enumerate()
+///     .filter(|(bin, _)| analyzer.frequency(*bin) < 150.0)
+///     .map(|(_, magnitude)| magnitude)
+///     .sum();
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Analyzer {
+    history: Arc<Mutex<History>>,
+    size: usize,
+}
+
+impl Analyzer {
+    /// Creates a new `Analyzer` looking at the last `size` frames of
+    /// audio.  The size is rounded up to a power of two, and to at
+    /// least 32; 1024 or 2048 are good choices at 44.1 kHz.
+    pub fn new(size: usize) -> Self {
+        let size = size.max(32).next_power_of_two();
+        Analyzer {
+            history: Arc::new(Mutex::new(History {
+                frames: vec![Frame::default(); size],
+                position: 0,
+                sample_rate: 44100,
+            })),
+            size,
+        }
+    }
+
+    /// Returns how many frames the analyzer looks at.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the sample rate of the audio last fed to the analyzer.
+    pub fn sample_rate(&self) -> u32 {
+        self.history.lock().unwrap().sample_rate
+    }
+
+    /// Returns how long a stretch of audio the analyzer looks at.
+    pub fn window(&self) -> time::Duration {
+        time::Duration::from_secs_f64(self.size as f64 / f64::from(self.sample_rate()))
+    }
+
+    /// Returns the levels of the latest frames.
+    pub fn levels(&self) -> Levels {
+        let history = self.history.lock().unwrap();
+        let (peak, power) = history
+            .frames
+            .iter()
+            .fold((0.0f32, 0.0), |(peak, power), frame| {
+                (peak.max(frame.peak), power + frame.power)
+            });
+        Levels {
+            rms: (power / self.size as f32).sqrt(),
+            peak,
+        }
+    }
+
+    /// Returns the magnitude spectrum of the latest frames, with the
+    /// channels mixed together.  It has `size() / 2` bins, going up in
+    /// steps of `sample_rate() / size()` Hz from 0 Hz; a full-scale sine
+    /// wave gives a magnitude of about `1.0` in its bin.
+    pub fn spectrum(&self) -> Vec<f32> {
+        let mut re = Vec::with_capacity(self.size);
+        {
+            let history = self.history.lock().unwrap();
+            let (newer, older) = history.frames.split_at(history.position);
+            re.extend(older.iter().chain(newer).map(|frame| frame.mono));
+        }
+        // A Hann window, so that sounds between two bins don't
+        // smear across the whole spectrum.
+        let n = self.size as f32;
+        for (i, sample) in re.iter_mut().enumerate() {
+            *sample *= 0.5 - 0.5 * (2.0 * PI * i as f32 / n).cos();
+        }
+        let mut im = vec![0.0; self.size];
+        fft(&mut re, &mut im);
+
+        // The window halves the amplitude, and half of it
+        // ends up in the negative frequencies.
+        let scale = 4.0 / n;
+        re.iter()
+            .zip(&im)
+            .take(self.size / 2)
+            .map(|(re, im)| (re * re + im * im).sqrt() * scale)
+            .collect()
+    }
+
+    /// Returns the frequency in Hz that a bin of the
+    /// [`spectrum()`](#method.spectrum) is centered on.
+    pub fn frequency(&self, bin: usize) -> f32 {
+        bin as f32 * self.sample_rate() as f32 / self.size as f32
+    }
+
+    /// Returns the bin of the [`spectrum()`](#method.spectrum)
+    /// that the given frequency in Hz falls in.
+    pub fn bin(&self, frequency: f32) -> usize {
+        let bin = (frequency * self.size as f32 / self.sample_rate() as f32).round();
+        (bin.max(0.0) as usize).min(self.size / 2 - 1)
+    }
+
+    /// Forgets everything the analyzer has heard, as if it had
+    /// only heard silence.
+    pub fn clear(&self) {
+        let mut history = self.history.lock().unwrap();
+        history
+            .frames
+            .iter_mut()
+            .for_each(|f| *f = Frame::default());
+    }
+
+    fn append(&self, frames: &[Frame], sample_rate: u32) {
+        let mut history = self.history.lock().unwrap();
+        history.sample_rate = sample_rate;
+        for frame in frames {
+            let position = history.position;
+            history.frames[position] = *frame;
+            history.position = (position + 1) % self.size;
+        }
+    }
+}
+
+impl fmt::Debug for Analyzer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Analyzer: {:p}>", self)
+    }
+}
+
+/// An in-place radix-2 FFT.  The length must be a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if j > i {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len *= 2;
+    }
+}
+
+/// Feeds samples going past to an analyzer, a chunk at a time
+/// so it doesn't have to be locked for every one.
+pub(crate) struct TapWriter {
+    analyzer: Analyzer,
+    pending: Vec<Frame>,
+    frame: Frame,
+    channel: u16,
+    sample_rate: u32,
+}
+
+impl TapWriter {
+    pub(crate) fn new(analyzer: Analyzer) -> Self {
+        TapWriter {
+            analyzer,
+            pending: Vec::with_capacity(FLUSH_FRAMES),
+            frame: Frame::default(),
+            channel: 0,
+            sample_rate: 44100,
+        }
+    }
+
+    /// Adds the next sample of interleaved audio in the given format.
+    #[inline]
+    pub(crate) fn push(&mut self, sample: f32, channels: u16, sample_rate: u32) {
+        self.frame.mono += sample;
+        self.frame.peak = self.frame.peak.max(sample.abs());
+        self.frame.power += sample * sample;
+        self.channel += 1;
+        self.sample_rate = sample_rate;
+        if self.channel >= channels {
+            let channels = f32::from(self.channel);
+            self.frame.mono /= channels;
+            self.frame.power /= channels;
+            self.pending.push(self.frame);
+            self.frame = Frame::default();
+            self.channel = 0;
+            if self.pending.len() >= FLUSH_FRAMES {
+                self.flush();
+            }
+        }
+    }
+
+    /// Hands the frames collected so far to the analyzer.
+    pub(crate) fn flush(&mut self) {
+        if !self.pending.is_empty() {
+            self.analyzer.append(&self.pending, self.sample_rate);
+            self.pending.clear();
+        }
+    }
+}
+
+impl Drop for TapWriter {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Passes a sound through unchanged, feeding it to an analyzer if it has one.
+pub(crate) struct AnalysisTap<I> {
+    input: I,
+    writer: Option<TapWriter>,
+}
+
+impl<I> AnalysisTap<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    pub(crate) fn new(input: I, analyzer: Option<Analyzer>) -> Self {
+        AnalysisTap {
+            input,
+            writer: analyzer.map(TapWriter::new),
+        }
+    }
+}
+
+impl<I> Iterator for AnalysisTap<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next();
+        if let Some(ref mut writer) = self.writer {
+            match sample {
+                Some(sample) => {
+                    writer.push(sample, self.input.channels(), self.input.sample_rate())
+                }
+                None => writer.flush(),
+            }
+        }
+        sample
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> rodio::Source for AnalysisTap<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<time::Duration> {
+        self.input.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn sine(frequency: f32, amplitude: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (i as f32 * frequency * 2.0 * PI / 44100.0).sin() * amplitude)
+            .collect()
+    }
+
+    #[test]
+    fn levels_of_a_sine() {
+        let analyzer = Analyzer::new(1000);
+        assert_eq!(1024, analyzer.size());
+        assert_eq!(Levels::default(), analyzer.levels());
+
+        let input = SamplesBuffer::new(1, 44100, sine(441.0, 0.5, 4410));
+        let passed: Vec<f32> = AnalysisTap::new(input, Some(analyzer.clone())).collect();
+        assert_eq!(sine(441.0, 0.5, 4410), passed);
+
+        let levels = analyzer.levels();
+        assert!((levels.peak - 0.5).abs() < 0.01);
+        assert!((levels.rms - 0.5 / 2.0f32.sqrt()).abs() < 0.01);
+        analyzer.clear();
+        assert_eq!(Levels::default(), analyzer.levels());
+    }
+
+    #[test]
+    fn spectrum_peaks_at_the_frequency() {
+        let analyzer = Analyzer::new(1024);
+        // Stereo, with the sine in one channel only.
+        let samples: Vec<f32> = sine(2000.0, 1.0, 4096)
+            .into_iter()
+            .flat_map(|sample| vec![sample, 0.0])
+            .collect();
+        let input = SamplesBuffer::new(2, 44100, samples);
+        let _ = AnalysisTap::new(input, Some(analyzer.clone())).count();
+
+        let spectrum = analyzer.spectrum();
+        assert_eq!(512, spectrum.len());
+        let loudest = (0..spectrum.len())
+            .max_by(|a, b| spectrum[*a].total_cmp(&spectrum[*b]))
+            .unwrap();
+        assert_eq!(analyzer.bin(2000.0), loudest);
+        assert!((analyzer.frequency(loudest) - 2000.0).abs() < 44100.0 / 1024.0);
+        // The channels are averaged, so it's at half scale.
+        assert!((spectrum[loudest] - 0.5).abs() < 0.1);
+        assert!(spectrum[analyzer.bin(8000.0)] < 0.01);
+    }
+
+    #[test]
+    fn fft_of_an_impulse_is_flat() {
+        let mut re = vec![0.0; 8];
+        let mut im = vec![0.0; 8];
+        re[0] = 1.0;
+        fft(&mut re, &mut im);
+        assert!(re.iter().all(|x| (x - 1.0).abs() < 1e-6));
+        assert!(im.iter().all(|x| x.abs() < 1e-6));
+    }
+}
@@ -0,0 +1,201 @@
+//! An `AudioContext` that mixes into memory instead of an output device.
+
+use std::fmt;
+use std::sync::Mutex;
+use std::time;
+
+use super::mixer::{Mixer, MixerOutput};
+use super::{AudioContext, SoundData};
+
+/// The audio rendered so far, along with the virtual clock.
+struct CaptureState {
+    output: MixerOutput,
+    samples: Vec<f32>,
+    elapsed: time::Duration,
+    frames: u64,
+    follows_timer: bool,
+}
+
+/// An `AudioContext` that doesn't touch any output device, but mixes
+/// everything played into an in-memory buffer instead, at a virtual clock
+/// that only moves when it is told to.
+///
+/// This makes it possible to test code that plays sounds without having a
+/// sound card, and to check what was actually played, or to render audio
+/// to a file faster than real time.  Enable it with
+/// [`ModuleConf::audio_capture()`](../conf/struct.ModuleConf.html#method.audio_capture)
+/// and get it back with [`audio::capture()`](fn.capture.html).
+///
+/// By default the clock follows the game's timer: every frame of
+/// [`event::run()`](../event/fn.run.html) or
+/// [`event::Runner::step()`](../event/struct.Runner.html#method.step)
+/// renders [`timer::delta()`](../timer/fn.delta.html) worth of audio.
+/// It can also be advanced by hand with [`advance()`](#method.advance).
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # use ggez::audio::SoundSource;
+/// # fn t() -> GameResult {
+/// let mut ctx = ContextBuilder::new("test", "ggez")
+///     .modules(conf::ModuleConf::default().audio_capture(true))
+///     .headless(800, 600)?;
+/// let mut sound = audio::Source::new(&mut ctx, "/pew.ogg")?;
+/// sound.play(&mut ctx)?;
+///
+/// let capture = audio::capture(&ctx).unwrap();
+/// capture.advance(std::time::Duration::from_secs(1));
+/// assert!(capture.samples().iter().any(|sample| sample.abs() > 0.1));
+/// # Ok(())
+/// # }
+/// ```
+pub struct CaptureAudioContext {
+    mixer: Mixer,
+    state: Mutex<CaptureState>,
+}
+
+impl CaptureAudioContext {
+    /// Creates a new `CaptureAudioContext` mixing audio
+    /// in the given format.
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        let (mixer, output) = Mixer::new(channels.max(1), sample_rate.max(1));
+        CaptureAudioContext {
+            mixer,
+            state: Mutex::new(CaptureState {
+                output,
+                samples: Vec::new(),
+                elapsed: time::Duration::ZERO,
+                frames: 0,
+                follows_timer: true,
+            }),
+        }
+    }
+
+    /// Returns the number of channels of the captured audio.
+    pub fn channels(&self) -> u16 {
+        self.mixer.channels()
+    }
+
+    /// Returns the sample rate of the captured audio.
+    pub fn sample_rate(&self) -> u32 {
+        self.mixer.sample_rate()
+    }
+
+    /// Moves the clock forward by `dt`, mixing that much audio
+    /// from every sound that is playing into the buffer.
+    pub fn advance(&self, dt: time::Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.elapsed += dt;
+
+        // Counting from the start keeps rounding errors
+        // from adding up over many small steps.
+        let target = (state.elapsed.as_secs_f64() * f64::from(self.sample_rate())).round() as u64;
+        let frames = target.saturating_sub(state.frames);
+        state.frames = target;
+
+        let count = frames as usize * usize::from(self.channels());
+        let CaptureState {
+            ref mut output,
+            ref mut samples,
+            ..
+        } = *state;
+        samples.extend(output.by_ref().take(count));
+    }
+
+    /// Returns how far the clock has been advanced in total.
+    pub fn elapsed(&self) -> time::Duration {
+        self.state.lock().unwrap().elapsed
+    }
+
+    /// Returns whether the clock is advanced along with the game's timer.
+    pub fn follows_timer(&self) -> bool {
+        self.state.lock().unwrap().follows_timer
+    }
+
+    /// Sets whether the clock is advanced along with the game's timer.
+    /// If not, it only moves when [`advance()`](#method.advance) is called.
+    pub fn set_follows_timer(&self, follows_timer: bool) {
+        self.state.lock().unwrap().follows_timer = follows_timer;
+    }
+
+    /// Returns a copy of the interleaved samples captured so far.
+    pub fn samples(&self) -> Vec<f32> {
+        self.state.lock().unwrap().samples.clone()
+    }
+
+    /// Returns the interleaved samples captured so far
+    /// and clears the buffer.
+    pub fn take_samples(&self) -> Vec<f32> {
+        std::mem::take(&mut self.state.lock().unwrap().samples)
+    }
+
+    /// Clears the buffer of captured samples.  The clock is left as it is.
+    pub fn clear(&self) {
+        self.state.lock().unwrap().samples.clear();
+    }
+
+    /// Returns the samples captured so far as a `SoundData`, which can be
+    /// played back or saved with
+    /// [`SoundData::encode_wav()`](struct.SoundData.html#method.encode_wav).
+    pub fn to_sound_data(&self) -> SoundData {
+        let state = self.state.lock().unwrap();
+        SoundData::from_samples(self.channels(), self.sample_rate(), &state.samples)
+            .expect("Captured audio is always made of whole frames")
+    }
+}
+
+impl AudioContext for CaptureAudioContext {
+    fn mixer(&self) -> &Mixer {
+        &self.mixer
+    }
+
+    fn capture(&self) -> Option<&CaptureAudioContext> {
+        Some(self)
+    }
+}
+
+impl fmt::Debug for CaptureAudioContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<CaptureAudioContext: {:p}>", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn advancing_mixes_into_the_buffer() {
+        let capture = CaptureAudioContext::new(2, 44100);
+        assert!(capture.samples().is_empty());
+
+        let sink = capture.mixer().new_sink(None);
+        sink.append(SamplesBuffer::new(2, 44100, vec![0.5f32; 4410]));
+
+        capture.advance(time::Duration::from_millis(500));
+        let samples = capture.take_samples();
+        assert_eq!(44100, samples.len());
+        // The sound starts after a short bit of silence
+        // while the sink gets going.
+        let start = samples
+            .iter()
+            .position(|sample| (sample - 0.5).abs() < 1e-4)
+            .unwrap();
+        assert!(samples[start..start + 4410]
+            .iter()
+            .all(|sample| (sample - 0.5).abs() < 1e-4));
+        assert!(samples[start + 4500..].iter().all(|sample| *sample == 0.0));
+        assert!(capture.samples().is_empty());
+        assert_eq!(time::Duration::from_millis(500), capture.elapsed());
+    }
+
+    #[test]
+    fn small_steps_dont_drift() {
+        let capture = CaptureAudioContext::new(1, 44100);
+        for _ in 0..60 {
+            capture.advance(time::Duration::from_secs_f64(1.0 / 60.0));
+        }
+        assert_eq!(44100, capture.samples().len());
+        assert_eq!(44100, capture.to_sound_data().samples().unwrap());
+    }
+}
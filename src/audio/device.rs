@@ -0,0 +1,261 @@
+//! Playing the mixer on an output device, and switching between devices.
+
+use std::cell::OnceCell;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time;
+
+use rodio::cpal;
+use rodio::cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rodio::source::UniformSourceIterator;
+
+use super::mixer::MixerOutput;
+use crate::error::{GameError, GameResult};
+
+/// How many frames the device takes from the mixer at a time.
+const FEED_FRAMES: usize = 256;
+
+fn audio_error<E: fmt::Display>(what: &str) -> impl FnOnce(E) -> GameError + '_ {
+    move |e| GameError::AudioError(format!("{}: {}", what, e))
+}
+
+/// Returns the names of all the audio output devices of the system.
+pub fn output_devices() -> GameResult<Vec<String>> {
+    let devices = cpal::default_host()
+        .output_devices()
+        .map_err(audio_error("Could not list audio output devices"))?;
+    Ok(devices.filter_map(|device| device.name().ok()).collect())
+}
+
+/// Returns the name of the system's default audio output device, if it has one.
+pub fn default_output_device() -> Option<String> {
+    cpal::default_host()
+        .default_output_device()
+        .and_then(|device| device.name().ok())
+}
+
+/// Finds the output device with the given name, or the default one.
+pub(crate) fn find_device(name: Option<&str>) -> GameResult<cpal::Device> {
+    let host = cpal::default_host();
+    match name {
+        Some(name) => host
+            .output_devices()
+            .map_err(audio_error("Could not list audio output devices"))?
+            .find(|device| device.name().is_ok_and(|n| n == name))
+            .ok_or_else(|| {
+                GameError::AudioError(format!("No audio output device named {:?}", name))
+            }),
+        None => host.default_output_device().ok_or_else(|| {
+            GameError::AudioError(String::from("There is no default audio output device"))
+        }),
+    }
+}
+
+/// Returns the channel count and sample rate a device plays at by default.
+pub(crate) fn device_format(device: &cpal::Device) -> GameResult<(u16, u32)> {
+    let config = device
+        .default_output_config()
+        .map_err(audio_error("Could not get the audio output format"))?;
+    Ok((config.channels(), config.sample_rate().0))
+}
+
+/// Takes samples from the mixer a chunk at a time, so the audio
+/// thread doesn't have to lock it for every sample.
+struct DeviceFeed {
+    output: Arc<Mutex<MixerOutput>>,
+    buffer: Vec<f32>,
+    position: usize,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl DeviceFeed {
+    fn new(output: Arc<Mutex<MixerOutput>>) -> Self {
+        let (channels, sample_rate) = {
+            use rodio::Source;
+            let output = output.lock().unwrap();
+            (output.channels(), output.sample_rate())
+        };
+        DeviceFeed {
+            output,
+            buffer: Vec::new(),
+            position: 0,
+            channels,
+            sample_rate,
+        }
+    }
+}
+
+impl Iterator for DeviceFeed {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position == self.buffer.len() {
+            self.buffer.clear();
+            self.position = 0;
+            let mut output = self.output.lock().unwrap();
+            self.buffer.extend(
+                output
+                    .by_ref()
+                    .take(FEED_FRAMES * usize::from(self.channels)),
+            );
+        }
+        let sample = self.buffer.get(self.position).copied();
+        self.position += 1;
+        sample
+    }
+}
+
+impl rodio::Source for DeviceFeed {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<time::Duration> {
+        None
+    }
+}
+
+/// An output device playing the mixer.  It stops when dropped.
+pub(crate) struct OutputDevice {
+    name: String,
+    _stream: cpal::Stream,
+    /// A `rodio` stream of its own on the device, for
+    /// `AudioContext::device()`, opened the first time it is asked for.
+    rodio_stream: OnceCell<(rodio::OutputStream, rodio::OutputStreamHandle)>,
+    /// The error the stream ran into, if any, until it is reported.
+    error: Arc<Mutex<Option<String>>>,
+}
+
+impl OutputDevice {
+    /// Starts playing the mixer output on the given device.
+    pub(crate) fn open(device: &cpal::Device, output: Arc<Mutex<MixerOutput>>) -> GameResult<Self> {
+        let name = device
+            .name()
+            .map_err(audio_error("Could not get the audio output device's name"))?;
+        let config = device
+            .default_output_config()
+            .map_err(audio_error("Could not get the audio output format"))?;
+        let error = Arc::new(Mutex::new(None));
+
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => {
+                build_stream::<f32>(device, &config.config(), output, &name, &error)
+            }
+            cpal::SampleFormat::I16 => {
+                build_stream::<i16>(device, &config.config(), output, &name, &error)
+            }
+            cpal::SampleFormat::U16 => {
+                build_stream::<u16>(device, &config.config(), output, &name, &error)
+            }
+        }?;
+        stream
+            .play()
+            .map_err(audio_error("Could not start audio output"))?;
+
+        Ok(OutputDevice {
+            name,
+            _stream: stream,
+            rodio_stream: OnceCell::new(),
+            error,
+        })
+    }
+
+    /// Returns the name of the device.
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns a handle to a `rodio` stream playing on the device,
+    /// next to the mixer rather than through it.
+    pub(crate) fn rodio_handle(&self) -> GameResult<&rodio::OutputStreamHandle> {
+        if self.rodio_stream.get().is_none() {
+            let device = find_device(Some(&self.name))?;
+            let stream = rodio::OutputStream::try_from_device(&device)
+                .map_err(audio_error("Could not open audio output"))?;
+            let _ = self.rodio_stream.set(stream);
+        }
+        Ok(&self.rodio_stream.get().unwrap().1)
+    }
+
+    /// Returns the error the device ran into, if it did.
+    /// Each error is only returned once.
+    pub(crate) fn check(&self) -> GameResult {
+        match self.error.lock().unwrap().take() {
+            Some(error) => Err(GameError::AudioError(error)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn build_stream<T: cpal::Sample>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    output: Arc<Mutex<MixerOutput>>,
+    name: &str,
+    error: &Arc<Mutex<Option<String>>>,
+) -> GameResult<cpal::Stream> {
+    let mut source = UniformSourceIterator::<_, f32>::new(
+        DeviceFeed::new(output),
+        config.channels,
+        config.sample_rate.0,
+    );
+    let data_callback = move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+        for sample in data.iter_mut() {
+            *sample = T::from(&source.next().unwrap_or(0.0));
+        }
+    };
+
+    let error = error.clone();
+    let name = name.to_string();
+    let error_callback = move |e: cpal::StreamError| {
+        let message = match e {
+            cpal::StreamError::DeviceNotAvailable => {
+                format!("Audio output device {:?} was lost", name)
+            }
+            e => format!("Audio output device {:?} failed: {}", name, e),
+        };
+        error!("{}", message);
+        *error.lock().unwrap() = Some(message);
+    };
+
+    device
+        .build_output_stream(config, data_callback, error_callback)
+        .map_err(audio_error("Could not open audio output"))
+}
+
+impl fmt::Debug for OutputDevice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<OutputDevice: {:?}>", self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::Mixer;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn feed_takes_chunks_of_frames() {
+        let (mixer, output) = Mixer::new(2, 44100);
+        let output = Arc::new(Mutex::new(output));
+        let mut feed = DeviceFeed::new(output.clone());
+        assert_eq!(Some(0.0), feed.next());
+        assert_eq!(FEED_FRAMES * 2 - 1, feed.buffer.len() - feed.position);
+
+        // A new sound is only heard once the buffered chunk runs out.
+        let sink = mixer.new_sink(None);
+        sink.append(SamplesBuffer::new(2, 44100, vec![1.0f32; 44100]));
+        assert!(feed.by_ref().take(FEED_FRAMES * 2 - 1).all(|s| s == 0.0));
+        assert!(feed.take(4000).any(|s| s == 1.0));
+    }
+}
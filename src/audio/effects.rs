@@ -0,0 +1,708 @@
+//! Effects that can be applied to sounds as they play: filters, echo and reverb.
+//!
+//! Every `SoundSource` has an [`EffectChain`](struct.EffectChain.html),
+//! and so does every bus of the [`Mixer`](struct.Mixer.html).  A bus
+//! applies its effects to every voice on it separately, not to their mix.
+//! Effects are applied in the order they were added, and changing them
+//! affects sounds that are already playing.
+//!
+//! ```rust,no_run
+//! # use ggez::*;
+//! # use ggez::audio::SoundSource;
+//! # fn t(ctx: &mut Context) -> GameResult {
+//! let mut sound = audio::Source::new(ctx, "/sound.ogg")?;
+//! let muffle = sound.effects().push(audio::Effect::low_pass(800.0));
+//! sound.play(ctx)?;
+//! // Later, when the player comes out of the water:
+//! sound.effects().set(muffle, audio::Effect::low_pass(20_000.0));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time;
+
+/// The longest an effect is allowed to keep ringing after its sound ends.
+const MAX_TAIL: time::Duration = time::Duration::from_secs(10);
+
+/// The level below which an effect's tail is considered silent.
+const SILENCE: f32 = 0.001;
+
+/// An effect that can be added to an [`EffectChain`](struct.EffectChain.html).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    /// A biquad filter that lets frequencies below `cutoff` through.
+    LowPass {
+        /// The cutoff frequency in Hz.
+        cutoff: f32,
+        /// The resonance; `0.707` gives a flat response.
+        q: f32,
+    },
+    /// A biquad filter that lets frequencies above `cutoff` through.
+    HighPass {
+        /// The cutoff frequency in Hz.
+        cutoff: f32,
+        /// The resonance; `0.707` gives a flat response.
+        q: f32,
+    },
+    /// A biquad filter that only lets frequencies around `center` through.
+    BandPass {
+        /// The center frequency in Hz.
+        center: f32,
+        /// How narrow the band is; higher values let fewer frequencies through.
+        q: f32,
+    },
+    /// Repeats the sound after a delay, each time quieter than the last.
+    Echo {
+        /// The time between echoes.
+        delay: time::Duration,
+        /// How much quieter each echo is than the one before, from `0.0` to `1.0`.
+        feedback: f32,
+        /// How loud the echoes are compared to the original sound.
+        mix: f32,
+    },
+    /// A simple reverb, making the sound seem to be in a room.
+    Reverb {
+        /// How big the room is, from `0.0` to `1.0`.
+        room_size: f32,
+        /// How much the room's walls soften high frequencies, from `0.0` to `1.0`.
+        damping: f32,
+        /// The balance between the original sound (`0.0`) and the reverb (`1.0`).
+        mix: f32,
+    },
+}
+
+impl Effect {
+    /// A low-pass filter with a flat response below `cutoff`.
+    pub fn low_pass(cutoff: f32) -> Self {
+        Effect::LowPass {
+            cutoff,
+            q: std::f32::consts::FRAC_1_SQRT_2,
+        }
+    }
+
+    /// A high-pass filter with a flat response above `cutoff`.
+    pub fn high_pass(cutoff: f32) -> Self {
+        Effect::HighPass {
+            cutoff,
+            q: std::f32::consts::FRAC_1_SQRT_2,
+        }
+    }
+
+    /// A band-pass filter around `center`, about an octave wide.
+    pub fn band_pass(center: f32) -> Self {
+        Effect::BandPass { center, q: 1.4 }
+    }
+
+    /// An echo repeating every `delay`, each echo half as loud as the one before.
+    pub fn echo(delay: time::Duration) -> Self {
+        Effect::Echo {
+            delay,
+            feedback: 0.5,
+            mix: 0.5,
+        }
+    }
+
+    /// A reverb for a medium-sized room.
+    pub fn reverb() -> Self {
+        Effect::Reverb {
+            room_size: 0.5,
+            damping: 0.5,
+            mix: 0.3,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ChainState {
+    effects: Mutex<Vec<Effect>>,
+    version: AtomicUsize,
+}
+
+/// A list of effects applied to a sound, or to all the sounds on a bus.
+///
+/// It is a handle that can be cloned cheaply; all clones refer to the same
+/// effects, and changes made through any of them are picked up by the
+/// sounds playing with it.
+///
+/// # Panics
+///
+/// Like a `Vec`, methods taking an index panic if it is out of bounds.
+#[derive(Clone, Debug, Default)]
+pub struct EffectChain {
+    state: Arc<ChainState>,
+}
+
+impl EffectChain {
+    /// Creates a new, empty `EffectChain`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an effect to the end of the chain and returns its index.
+    pub fn push(&self, effect: Effect) -> usize {
+        self.modify(|effects| {
+            effects.push(effect);
+            effects.len() - 1
+        })
+    }
+
+    /// Replaces the effect at the given index.  If it is the same kind of
+    /// effect as before, it keeps its state, so filters can be swept and
+    /// echoes retuned without clicks.
+    pub fn set(&self, index: usize, effect: Effect) {
+        self.modify(|effects| effects[index] = effect)
+    }
+
+    /// Removes the effect at the given index, moving later effects down.
+    pub fn remove(&self, index: usize) -> Effect {
+        self.modify(|effects| effects.remove(index))
+    }
+
+    /// Removes all effects.
+    pub fn clear(&self) {
+        self.modify(|effects| effects.clear())
+    }
+
+    /// Returns the effect at the given index, if there is one.
+    pub fn get(&self, index: usize) -> Option<Effect> {
+        self.state.effects.lock().unwrap().get(index).copied()
+    }
+
+    /// Returns all the effects in the chain, in the order they are applied.
+    pub fn effects(&self) -> Vec<Effect> {
+        self.state.effects.lock().unwrap().clone()
+    }
+
+    /// Returns the number of effects in the chain.
+    pub fn len(&self) -> usize {
+        self.state.effects.lock().unwrap().len()
+    }
+
+    /// Returns whether the chain has no effects.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn modify<T>(&self, f: impl FnOnce(&mut Vec<Effect>) -> T) -> T {
+        let mut effects = self.state.effects.lock().unwrap();
+        let result = f(&mut effects);
+        let _ = self.state.version.fetch_add(1, Ordering::Release);
+        result
+    }
+
+    fn version(&self) -> usize {
+        self.state.version.load(Ordering::Acquire)
+    }
+}
+
+/// Coefficients and per-channel history of a biquad filter,
+/// after Robert Bristow-Johnson's audio EQ cookbook.
+#[derive(Debug)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    /// `z1` and `z2` for each channel.
+    history: Vec<(f32, f32)>,
+}
+
+impl Biquad {
+    fn new(effect: &Effect, channels: u16, sample_rate: u32) -> Self {
+        let mut biquad = Biquad {
+            b0: 1.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            history: vec![(0.0, 0.0); channels as usize],
+        };
+        biquad.set(effect, sample_rate);
+        biquad
+    }
+
+    fn set(&mut self, effect: &Effect, sample_rate: u32) {
+        let (frequency, q) = match *effect {
+            Effect::LowPass { cutoff, q } | Effect::HighPass { cutoff, q } => (cutoff, q),
+            Effect::BandPass { center, q } => (center, q),
+            _ => unreachable!("not a filter"),
+        };
+        let nyquist = sample_rate as f32 / 2.0;
+        let w0 = 2.0 * PI * frequency.clamp(10.0, nyquist * 0.99) / sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q.max(0.01));
+        let (b0, b1, b2) = match *effect {
+            Effect::LowPass { .. } => ((1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0),
+            Effect::HighPass { .. } => ((1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0),
+            _ => (alpha, 0.0, -alpha),
+        };
+        let a0 = 1.0 + alpha;
+        self.b0 = b0 / a0;
+        self.b1 = b1 / a0;
+        self.b2 = b2 / a0;
+        self.a1 = -2.0 * cos / a0;
+        self.a2 = (1.0 - alpha) / a0;
+    }
+
+    fn process(&mut self, channel: usize, x: f32) -> f32 {
+        let (z1, z2) = self.history[channel];
+        let y = self.b0 * x + z1;
+        self.history[channel] = (self.b1 * x - self.a1 * y + z2, self.b2 * x - self.a2 * y);
+        y
+    }
+}
+
+/// An interleaved delay line used by the echo.
+#[derive(Debug)]
+struct Echo {
+    buffer: Vec<f32>,
+    position: usize,
+    feedback: f32,
+    mix: f32,
+}
+
+impl Echo {
+    fn new(effect: &Effect, channels: u16, sample_rate: u32) -> Self {
+        let mut echo = Echo {
+            buffer: Vec::new(),
+            position: 0,
+            feedback: 0.0,
+            mix: 0.0,
+        };
+        echo.set(effect, channels, sample_rate);
+        echo
+    }
+
+    fn set(&mut self, effect: &Effect, channels: u16, sample_rate: u32) {
+        if let Effect::Echo {
+            delay,
+            feedback,
+            mix,
+        } = *effect
+        {
+            let frames = ((delay.as_secs_f32() * sample_rate as f32) as usize).max(1);
+            let len = frames * channels as usize;
+            if len != self.buffer.len() {
+                self.buffer = vec![0.0; len];
+                self.position = 0;
+            }
+            self.feedback = feedback.clamp(0.0, 0.99);
+            self.mix = mix;
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let delayed = self.buffer[self.position];
+        self.buffer[self.position] = x + delayed * self.feedback;
+        self.position = (self.position + 1) % self.buffer.len();
+        x + delayed * self.mix
+    }
+
+    /// How many samples it takes for the echoes to die out,
+    /// counting every channel.
+    fn tail(&self) -> usize {
+        if self.feedback <= 0.0 {
+            return self.buffer.len();
+        }
+        let repeats = (SILENCE.ln() / self.feedback.ln()).ceil().max(1.0);
+        self.buffer.len() * repeats as usize
+    }
+}
+
+/// Comb filter delay lengths from Freeverb, in frames at 44.1 kHz.
+const COMB_TUNING: [usize; 4] = [1116, 1188, 1277, 1356];
+/// All-pass filter delay lengths from Freeverb, in frames at 44.1 kHz.
+const ALLPASS_TUNING: [usize; 2] = [556, 441];
+/// How many frames the right channel's delays are longer than the left's.
+const STEREO_SPREAD: usize = 23;
+
+#[derive(Debug)]
+struct Comb {
+    buffer: Vec<f32>,
+    position: usize,
+    filter_store: f32,
+}
+
+impl Comb {
+    fn process(&mut self, x: f32, feedback: f32, damping: f32) -> f32 {
+        let y = self.buffer[self.position];
+        self.filter_store = y * (1.0 - damping) + self.filter_store * damping;
+        self.buffer[self.position] = x + self.filter_store * feedback;
+        self.position = (self.position + 1) % self.buffer.len();
+        y
+    }
+}
+
+#[derive(Debug)]
+struct AllPass {
+    buffer: Vec<f32>,
+    position: usize,
+}
+
+impl AllPass {
+    fn process(&mut self, x: f32) -> f32 {
+        let delayed = self.buffer[self.position];
+        self.buffer[self.position] = x + delayed * 0.5;
+        self.position = (self.position + 1) % self.buffer.len();
+        delayed - x
+    }
+}
+
+/// A Schroeder-style reverb along the lines of Freeverb,
+/// with parallel comb filters feeding series all-pass filters.
+#[derive(Debug)]
+struct Reverb {
+    /// Comb and all-pass filters for each channel.
+    channels: Vec<(Vec<Comb>, Vec<AllPass>)>,
+    feedback: f32,
+    damping: f32,
+    mix: f32,
+}
+
+impl Reverb {
+    fn new(effect: &Effect, channels: u16, sample_rate: u32) -> Self {
+        let scale = |frames: usize| (frames * sample_rate as usize / 44100).max(1);
+        let channels = (0..channels as usize)
+            .map(|channel| {
+                let spread = if channel % 2 == 1 { STEREO_SPREAD } else { 0 };
+                let combs = COMB_TUNING
+                    .iter()
+                    .map(|&frames| Comb {
+                        buffer: vec![0.0; scale(frames + spread)],
+                        position: 0,
+                        filter_store: 0.0,
+                    })
+                    .collect();
+                let allpasses = ALLPASS_TUNING
+                    .iter()
+                    .map(|&frames| AllPass {
+                        buffer: vec![0.0; scale(frames + spread)],
+                        position: 0,
+                    })
+                    .collect();
+                (combs, allpasses)
+            })
+            .collect();
+        let mut reverb = Reverb {
+            channels,
+            feedback: 0.0,
+            damping: 0.0,
+            mix: 0.0,
+        };
+        reverb.set(effect);
+        reverb
+    }
+
+    fn set(&mut self, effect: &Effect) {
+        if let Effect::Reverb {
+            room_size,
+            damping,
+            mix,
+        } = *effect
+        {
+            self.feedback = 0.7 + 0.28 * room_size.clamp(0.0, 1.0);
+            self.damping = damping.clamp(0.0, 1.0) * 0.4;
+            self.mix = mix.clamp(0.0, 1.0);
+        }
+    }
+
+    fn process(&mut self, channel: usize, x: f32) -> f32 {
+        let (feedback, damping) = (self.feedback, self.damping);
+        let (ref mut combs, ref mut allpasses) = self.channels[channel];
+        let input = x * 0.03;
+        let mut wet: f32 = combs
+            .iter_mut()
+            .map(|comb| comb.process(input, feedback, damping))
+            .sum();
+        for allpass in allpasses.iter_mut() {
+            wet = allpass.process(wet);
+        }
+        x * (1.0 - self.mix) + wet * self.mix * 3.0
+    }
+
+    /// How many samples it takes for the reverb to die out,
+    /// counting every channel.
+    fn tail(&self) -> usize {
+        let longest = self.channels.iter().fold(0, |longest, (combs, _)| {
+            combs
+                .iter()
+                .map(|comb| comb.buffer.len())
+                .fold(longest, usize::max)
+        });
+        let repeats = (SILENCE.ln() / self.feedback.ln()).ceil().max(1.0);
+        longest * repeats as usize * self.channels.len()
+    }
+}
+
+/// The running state of one effect.
+#[derive(Debug)]
+enum Processor {
+    Filter(Biquad),
+    Echo(Echo),
+    Reverb(Reverb),
+}
+
+impl Processor {
+    fn new(effect: &Effect, channels: u16, sample_rate: u32) -> Self {
+        match effect {
+            Effect::LowPass { .. } | Effect::HighPass { .. } | Effect::BandPass { .. } => {
+                Processor::Filter(Biquad::new(effect, channels, sample_rate))
+            }
+            Effect::Echo { .. } => Processor::Echo(Echo::new(effect, channels, sample_rate)),
+            Effect::Reverb { .. } => Processor::Reverb(Reverb::new(effect, channels, sample_rate)),
+        }
+    }
+
+    /// Updates the parameters of the effect, keeping its state.
+    /// Returns `false` if the effect is of a different kind.
+    fn update(&mut self, effect: &Effect, channels: u16, sample_rate: u32) -> bool {
+        match (self, effect) {
+            (
+                Processor::Filter(biquad),
+                Effect::LowPass { .. } | Effect::HighPass { .. } | Effect::BandPass { .. },
+            ) => biquad.set(effect, sample_rate),
+            (Processor::Echo(echo), Effect::Echo { .. }) => echo.set(effect, channels, sample_rate),
+            (Processor::Reverb(reverb), Effect::Reverb { .. }) => reverb.set(effect),
+            _ => return false,
+        }
+        true
+    }
+
+    fn process(&mut self, channel: usize, x: f32) -> f32 {
+        match self {
+            Processor::Filter(biquad) => biquad.process(channel, x),
+            Processor::Echo(echo) => echo.process(x),
+            Processor::Reverb(reverb) => reverb.process(channel, x),
+        }
+    }
+
+    fn tail(&self) -> usize {
+        match self {
+            Processor::Filter(_) => 0,
+            Processor::Echo(echo) => echo.tail(),
+            Processor::Reverb(reverb) => reverb.tail(),
+        }
+    }
+}
+
+/// Runs a sound through an `EffectChain`, letting the effects
+/// ring out after the sound itself has ended.
+pub(crate) struct EffectsSource<I> {
+    input: I,
+    chain: EffectChain,
+    version: usize,
+    processors: Vec<Processor>,
+    channels: u16,
+    sample_rate: u32,
+    current_channel: usize,
+    /// The number of samples left to play after the input ended.
+    tail: Option<usize>,
+}
+
+impl<I> EffectsSource<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    pub(crate) fn new(input: I, chain: EffectChain) -> Self {
+        let mut source = EffectsSource {
+            channels: input.channels(),
+            sample_rate: input.sample_rate(),
+            input,
+            chain,
+            version: 0,
+            processors: Vec::new(),
+            current_channel: 0,
+            tail: None,
+        };
+        source.sync();
+        source
+    }
+
+    /// Brings the processors up to date with the chain.
+    fn sync(&mut self) {
+        // Don't hold up the audio thread if the chain is being changed
+        // right now; we'll try again on the next sample.
+        let effects = match self.chain.state.effects.try_lock() {
+            Ok(effects) => effects,
+            Err(_) => return,
+        };
+        self.version = self.chain.version();
+        self.processors.truncate(effects.len());
+        for (i, effect) in effects.iter().enumerate() {
+            let (channels, sample_rate) = (self.channels, self.sample_rate);
+            match self.processors.get_mut(i) {
+                Some(processor) => {
+                    if !processor.update(effect, channels, sample_rate) {
+                        *processor = Processor::new(effect, channels, sample_rate);
+                    }
+                }
+                None => self
+                    .processors
+                    .push(Processor::new(effect, channels, sample_rate)),
+            }
+        }
+    }
+}
+
+impl<I> Iterator for EffectsSource<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.chain.version() != self.version {
+            self.sync();
+        }
+
+        let sample = match self.tail {
+            None => match self.input.next() {
+                Some(sample) => sample,
+                None => {
+                    let channels = self.channels as usize;
+                    let tail = self
+                        .processors
+                        .iter()
+                        .map(Processor::tail)
+                        .sum::<usize>()
+                        .min(MAX_TAIL.as_secs() as usize * self.sample_rate as usize * channels);
+                    // Finish the frame we're in, so the tail starts on the first channel.
+                    self.tail = Some(tail + (channels - self.current_channel) % channels);
+                    return self.next();
+                }
+            },
+            Some(0) => return None,
+            Some(ref mut left) => {
+                *left -= 1;
+                0.0
+            }
+        };
+
+        let channel = self.current_channel;
+        self.current_channel = (self.current_channel + 1) % self.channels as usize;
+        Some(
+            self.processors
+                .iter_mut()
+                .fold(sample, |sample, processor| {
+                    processor.process(channel, sample)
+                }),
+        )
+    }
+}
+
+impl<I> rodio::Source for EffectsSource<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        match self.tail {
+            Some(left) => Some(left),
+            None => self.input.current_frame_len(),
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<time::Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn sine(frequency: f32, len: usize) -> SamplesBuffer<f32> {
+        let samples = (0..len)
+            .map(|i| (2.0 * PI * frequency * i as f32 / 44100.0).sin())
+            .collect::<Vec<_>>();
+        SamplesBuffer::new(1, 44100, samples)
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0, |peak, s| s.abs().max(peak))
+    }
+
+    #[test]
+    fn filters_pass_and_stop_bands() {
+        let chain = EffectChain::new();
+        let _ = chain.push(Effect::low_pass(500.0));
+        let low: Vec<f32> = EffectsSource::new(sine(100.0, 44100), chain.clone()).collect();
+        assert!(peak(&low[22050..]) > 0.9);
+        let high: Vec<f32> = EffectsSource::new(sine(8000.0, 44100), chain.clone()).collect();
+        assert!(peak(&high[22050..]) < 0.05);
+
+        chain.set(0, Effect::high_pass(500.0));
+        let low: Vec<f32> = EffectsSource::new(sine(100.0, 44100), chain.clone()).collect();
+        assert!(peak(&low[22050..]) < 0.1);
+
+        chain.set(0, Effect::band_pass(1000.0));
+        let center: Vec<f32> = EffectsSource::new(sine(1000.0, 44100), chain).collect();
+        assert!(peak(&center[22050..]) > 0.9);
+    }
+
+    #[test]
+    fn parameters_change_while_playing() {
+        let chain = EffectChain::new();
+        let _ = chain.push(Effect::low_pass(20_000.0));
+        let mut source = EffectsSource::new(sine(8000.0, 88200), chain.clone());
+        let open: Vec<f32> = source.by_ref().take(44100).collect();
+        assert!(peak(&open[22050..]) > 0.9);
+
+        chain.set(0, Effect::low_pass(200.0));
+        let muffled: Vec<f32> = source.by_ref().take(44100).collect();
+        assert!(peak(&muffled[22050..]) < 0.05);
+    }
+
+    #[test]
+    fn echo_repeats_and_rings_out() {
+        let chain = EffectChain::new();
+        let _ = chain.push(Effect::Echo {
+            delay: time::Duration::from_millis(10),
+            feedback: 0.5,
+            mix: 1.0,
+        });
+        let mut samples = vec![0.0; 1000];
+        samples[0] = 1.0;
+        let output: Vec<f32> =
+            EffectsSource::new(SamplesBuffer::new(1, 44100, samples), chain).collect();
+
+        assert!((output[0] - 1.0).abs() < f32::EPSILON);
+        assert!((output[441] - 1.0).abs() < f32::EPSILON);
+        assert!((output[882] - 0.5).abs() < f32::EPSILON);
+        // The echoes keep going after the sound itself ends.
+        assert!(output.len() > 1000);
+        assert!((output[1323] - 0.25).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn reverb_has_a_tail() {
+        let chain = EffectChain::new();
+        let _ = chain.push(Effect::reverb());
+        let output: Vec<f32> = EffectsSource::new(sine(440.0, 4410), chain).collect();
+        assert!(output.len() > 4410);
+        assert!(peak(&output[4410..]) > 0.01);
+        assert!(peak(&output[output.len() - 100..]) < 0.01);
+    }
+
+    #[test]
+    fn empty_chain_passes_through() {
+        let output: Vec<f32> = EffectsSource::new(
+            SamplesBuffer::new(2, 44100, vec![0.5; 10]),
+            EffectChain::new(),
+        )
+        .collect();
+        assert_eq!(vec![0.5; 10], output);
+    }
+}
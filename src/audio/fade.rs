@@ -0,0 +1,135 @@
+//! Fading sounds out on the audio thread.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time;
+
+/// Fade-out requests for the sounds played by one source.
+#[derive(Debug, Default)]
+pub(crate) struct FadeControl {
+    /// Bumped on every request, so each sound can tell whether
+    /// one came in since it started.
+    requests: AtomicUsize,
+    duration_micros: AtomicU64,
+}
+
+impl FadeControl {
+    /// Fades out every sound currently playing or queued
+    /// over the given time.
+    pub(crate) fn fade_out(&self, dur: time::Duration) {
+        self.duration_micros
+            .store(dur.as_micros() as u64, Ordering::SeqCst);
+        let _ = self.requests.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Plays a sound until it is faded out, then ends it.
+pub(crate) struct FadeOut<I> {
+    input: I,
+    control: Arc<FadeControl>,
+    seen_requests: usize,
+    /// The samples left until silence and the length of the
+    /// whole fade, once fading.
+    fade: Option<(u64, u64)>,
+}
+
+impl<I> FadeOut<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    /// Wraps a sound, which will only react to fade-out requests
+    /// made after this.
+    pub(crate) fn new(input: I, control: Arc<FadeControl>) -> Self {
+        let seen_requests = control.requests.load(Ordering::SeqCst);
+        FadeOut {
+            input,
+            control,
+            seen_requests,
+            fade: None,
+        }
+    }
+}
+
+impl<I> Iterator for FadeOut<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        if self.fade.is_none()
+            && self.control.requests.load(Ordering::Relaxed) != self.seen_requests
+        {
+            let micros = self.control.duration_micros.load(Ordering::SeqCst);
+            let samples = micros as f64 / 1_000_000.0
+                * f64::from(self.input.sample_rate())
+                * f64::from(self.input.channels());
+            let samples = samples.round() as u64;
+            self.fade = Some((samples, samples.max(1)));
+        }
+        let gain = match self.fade {
+            Some((0, _)) => return None,
+            Some((ref mut left, total)) => {
+                *left -= 1;
+                *left as f32 / total as f32
+            }
+            None => 1.0,
+        };
+        self.input.next().map(|sample| sample * gain)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> rodio::Source for FadeOut<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<time::Duration> {
+        self.input.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn fades_out_and_ends() {
+        let control = Arc::new(FadeControl::default());
+        let input = SamplesBuffer::new(1, 1000, vec![1.0f32; 1000]);
+        let mut sound = FadeOut::new(input, control.clone());
+        assert_eq!(Some(1.0), sound.next());
+
+        control.fade_out(time::Duration::from_millis(10));
+        let rest: Vec<f32> = sound.collect();
+        assert_eq!(10, rest.len());
+        assert!(rest.windows(2).all(|pair| pair[1] < pair[0]));
+        assert_eq!(0.0, rest[9]);
+    }
+
+    #[test]
+    fn ignores_earlier_requests() {
+        let control = Arc::new(FadeControl::default());
+        control.fade_out(time::Duration::ZERO);
+        let input = SamplesBuffer::new(1, 1000, vec![1.0f32; 100]);
+        assert_eq!(100, FadeOut::new(input, control).count());
+    }
+}
@@ -0,0 +1,575 @@
+//! The mixer every `Source` and `SpatialSource` is played through.
+//!
+//! Sources are routed either straight to the master output or through a
+//! named bus (such as `"music"`, `"sfx"` or `"voice"`), each of which has
+//! its own volume and mute switch.  Buses are created the first time
+//! they are mentioned.  Changes to bus and master settings apply
+//! immediately to every sound that is currently playing.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time;
+
+use rodio::source::UniformSourceIterator;
+
+use super::analysis::{Analyzer, TapWriter};
+use super::effects::{EffectChain, EffectsSource};
+use super::spatial::Listener;
+
+/// A boxed source as stored by the mixer.
+type MixerInput = Box<dyn rodio::Source<Item = f32> + Send>;
+
+/// Volume, mute state and effects shared between the game thread
+/// and the audio thread.
+#[derive(Debug)]
+struct GainControls {
+    volume: AtomicU32,
+    muted: AtomicBool,
+    effects: EffectChain,
+}
+
+impl GainControls {
+    fn new() -> Self {
+        GainControls {
+            volume: AtomicU32::new(1.0f32.to_bits()),
+            muted: AtomicBool::new(false),
+            effects: EffectChain::new(),
+        }
+    }
+
+    fn volume(&self) -> f32 {
+        f32::from_bits(self.volume.load(Ordering::Relaxed))
+    }
+
+    fn set_volume(&self, volume: f32) {
+        self.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+
+    fn muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// The factor samples are multiplied by.
+    fn gain(&self) -> f32 {
+        if self.muted() {
+            0.0
+        } else {
+            self.volume()
+        }
+    }
+}
+
+/// State shared between a `Mixer` and its `MixerOutput`.
+struct MixerShared {
+    channels: u16,
+    sample_rate: u32,
+    master: GainControls,
+    paused: AtomicBool,
+    buses: Mutex<HashMap<String, Arc<GainControls>>>,
+    pending: Mutex<Vec<MixerInput>>,
+    has_pending: AtomicBool,
+    listener: Arc<Mutex<Listener>>,
+    voices: Arc<AtomicUsize>,
+    analyzer: Mutex<Option<Analyzer>>,
+    /// Bumped whenever the analyzer is changed.
+    analyzer_version: AtomicUsize,
+}
+
+/// Controls the master output and the named buses of an `AudioContext`.
+///
+/// Get it with [`audio::mixer()`](fn.mixer.html).
+#[derive(Clone)]
+pub struct Mixer {
+    shared: Arc<MixerShared>,
+}
+
+impl Mixer {
+    /// Creates a new mixer producing audio in the given format, along with
+    /// the source that has to be played on an output device for the
+    /// mixer to be heard.
+    pub(crate) fn new(channels: u16, sample_rate: u32) -> (Mixer, MixerOutput) {
+        let shared = Arc::new(MixerShared {
+            channels,
+            sample_rate,
+            master: GainControls::new(),
+            paused: AtomicBool::new(false),
+            buses: Mutex::new(HashMap::new()),
+            pending: Mutex::new(Vec::new()),
+            has_pending: AtomicBool::new(false),
+            listener: Arc::new(Mutex::new(Listener::default())),
+            voices: Arc::new(AtomicUsize::new(0)),
+            analyzer: Mutex::new(None),
+            analyzer_version: AtomicUsize::new(0),
+        });
+        let output = MixerOutput {
+            shared: shared.clone(),
+            sources: Vec::new(),
+            current_channel: 0,
+            tap: None,
+            analyzer_version: 0,
+        };
+        (Mixer { shared }, output)
+    }
+
+    /// Creates a new `Sink` whose sounds are played through the given bus,
+    /// or directly to the master output if `bus` is `None`.
+    pub(crate) fn new_sink(&self, bus: Option<&str>) -> rodio::Sink {
+        let (sink, queue) = rodio::Sink::new_idle();
+        self.add_to_bus(queue, bus);
+        sink
+    }
+
+    /// Adds a source to the mix, played through the given bus or directly
+    /// to the master output.  It is removed again once it ends.
+    pub(crate) fn add_to_bus<S>(&self, source: S, bus: Option<&str>)
+    where
+        S: rodio::Source<Item = f32> + Send + 'static,
+    {
+        // The bus gain comes after the conversion, so that changes
+        // to it aren't held up by the samples buffered there.
+        let controls = bus.map(|name| self.bus_controls(name));
+        let effects = controls
+            .as_ref()
+            .map(|controls| controls.effects.clone())
+            .unwrap_or_default();
+        let uniform =
+            UniformSourceIterator::new(source, self.shared.channels, self.shared.sample_rate);
+        let source = BusGain {
+            input: EffectsSource::new(uniform, effects),
+            controls,
+        };
+        self.shared.pending.lock().unwrap().push(Box::new(source));
+        self.shared.has_pending.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns the listener, shared with every `SpatialSource` playing.
+    pub(crate) fn listener_handle(&self) -> Arc<Mutex<Listener>> {
+        self.shared.listener.clone()
+    }
+
+    /// Wraps a sound so it counts towards
+    /// [`active_voices()`](#method.active_voices) while it plays.
+    pub(crate) fn count_voice<S>(&self, sound: S) -> CountedVoice<S> {
+        CountedVoice {
+            input: sound,
+            voices: self.shared.voices.clone(),
+            started: false,
+        }
+    }
+
+    fn bus_controls(&self, name: &str) -> Arc<GainControls> {
+        let mut buses = self.shared.buses.lock().unwrap();
+        buses
+            .entry(name.to_string())
+            .or_insert_with(|| Arc::new(GainControls::new()))
+            .clone()
+    }
+
+    /// Returns the number of channels the mixer outputs.
+    pub fn channels(&self) -> u16 {
+        self.shared.channels
+    }
+
+    /// Returns the sample rate the mixer outputs at.
+    pub fn sample_rate(&self) -> u32 {
+        self.shared.sample_rate
+    }
+
+    /// Gets the master volume, which scales every sound played.
+    pub fn master_volume(&self) -> f32 {
+        self.shared.master.volume()
+    }
+
+    /// Sets the master volume, which scales every sound played.
+    /// `1.0` is the default.
+    pub fn set_master_volume(&self, volume: f32) {
+        self.shared.master.set_volume(volume)
+    }
+
+    /// Returns whether the master output is muted.
+    pub fn muted(&self) -> bool {
+        self.shared.master.muted()
+    }
+
+    /// Mutes or unmutes the master output.  Muted sounds keep playing,
+    /// they just can't be heard.
+    pub fn set_muted(&self, muted: bool) {
+        self.shared.master.set_muted(muted)
+    }
+
+    /// Pauses every sound; they stay where they are until
+    /// [`resume_all()`](#method.resume_all) is called.
+    pub fn pause_all(&self) {
+        self.shared.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Resumes every sound paused by [`pause_all()`](#method.pause_all).
+    /// Sounds paused individually stay paused.
+    pub fn resume_all(&self) {
+        self.shared.paused.store(false, Ordering::SeqCst);
+    }
+
+    /// Returns whether all sounds are paused by [`pause_all()`](#method.pause_all).
+    pub fn is_paused(&self) -> bool {
+        self.shared.paused.load(Ordering::SeqCst)
+    }
+
+    /// Gets the volume of the named bus.
+    pub fn bus_volume(&self, bus: &str) -> f32 {
+        self.bus_controls(bus).volume()
+    }
+
+    /// Sets the volume of the named bus, creating the bus if
+    /// it doesn't exist yet.  `1.0` is the default.
+    pub fn set_bus_volume(&self, bus: &str, volume: f32) {
+        self.bus_controls(bus).set_volume(volume)
+    }
+
+    /// Returns whether the named bus is muted.
+    pub fn bus_muted(&self, bus: &str) -> bool {
+        self.bus_controls(bus).muted()
+    }
+
+    /// Mutes or unmutes the named bus, creating the bus if
+    /// it doesn't exist yet.
+    pub fn set_bus_muted(&self, bus: &str, muted: bool) {
+        self.bus_controls(bus).set_muted(muted)
+    }
+
+    /// Returns the chain of effects applied to every sound on the named bus,
+    /// creating the bus if it doesn't exist yet.
+    ///
+    /// The effects run on each sound separately, before it's mixed with
+    /// the others on the bus, so every sound has an echo or reverb of its
+    /// own and they cost as much as adding them to each sound would.
+    pub fn bus_effects(&self, bus: &str) -> EffectChain {
+        self.bus_controls(bus).effects.clone()
+    }
+
+    /// Returns how many sounds are playing right now, paused ones included.
+    /// Sounds count from the moment they start until they end or are stopped.
+    pub fn active_voices(&self) -> usize {
+        self.shared.voices.load(Ordering::Relaxed)
+    }
+
+    /// Gets the [`Analyzer`](struct.Analyzer.html) fed with the
+    /// master output, if there is one.
+    pub fn analyzer(&self) -> Option<Analyzer> {
+        self.shared.analyzer.lock().unwrap().clone()
+    }
+
+    /// Feeds everything that is played, after the master volume, to the
+    /// given [`Analyzer`](struct.Analyzer.html), or stops feeding the
+    /// current one if `analyzer` is `None`.
+    pub fn set_analyzer(&self, analyzer: Option<&Analyzer>) {
+        *self.shared.analyzer.lock().unwrap() = analyzer.cloned();
+        let _ = self.shared.analyzer_version.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Gets the [`Listener`](struct.Listener.html) that
+    /// [`SpatialSource`](struct.SpatialSource.html)s are heard by.
+    pub fn listener(&self) -> Listener {
+        *self.shared.listener.lock().unwrap()
+    }
+
+    /// Sets the [`Listener`](struct.Listener.html) that
+    /// [`SpatialSource`](struct.SpatialSource.html)s are heard by.
+    /// Usually it follows the player or the camera around.
+    pub fn set_listener(&self, listener: Listener) {
+        *self.shared.listener.lock().unwrap() = listener;
+    }
+
+    /// Returns the names of all buses created so far, sorted alphabetically.
+    pub fn buses(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.shared.buses.lock().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+impl fmt::Debug for Mixer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Mixer: {:p}>", self)
+    }
+}
+
+/// Applies the volume and mute state of a bus to a source.
+struct BusGain<I> {
+    input: I,
+    controls: Option<Arc<GainControls>>,
+}
+
+impl<I> Iterator for BusGain<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next()?;
+        Some(match self.controls {
+            Some(ref controls) => sample * controls.gain(),
+            None => sample,
+        })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> rodio::Source for BusGain<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<time::Duration> {
+        self.input.total_duration()
+    }
+}
+
+/// A sound counted by [`Mixer::active_voices()`](struct.Mixer.html#method.active_voices).
+pub(crate) struct CountedVoice<I> {
+    input: I,
+    voices: Arc<AtomicUsize>,
+    started: bool,
+}
+
+impl<I> Iterator for CountedVoice<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        if !self.started {
+            self.started = true;
+            let _ = self.voices.fetch_add(1, Ordering::Relaxed);
+        }
+        self.input.next()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> rodio::Source for CountedVoice<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<time::Duration> {
+        self.input.total_duration()
+    }
+}
+
+impl<I> Drop for CountedVoice<I> {
+    fn drop(&mut self) {
+        if self.started {
+            let _ = self.voices.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The mixed output of a `Mixer`.  It never ends, playing
+/// silence when there is nothing to play.
+pub(crate) struct MixerOutput {
+    shared: Arc<MixerShared>,
+    sources: Vec<MixerInput>,
+    current_channel: u16,
+    tap: Option<TapWriter>,
+    analyzer_version: usize,
+}
+
+impl Iterator for MixerOutput {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        // New sources only join at the start of a frame,
+        // so they don't end up with their channels swapped.
+        if self.current_channel == 0 && self.shared.has_pending.load(Ordering::SeqCst) {
+            let mut pending = self.shared.pending.lock().unwrap();
+            self.sources.append(&mut pending);
+            self.shared.has_pending.store(false, Ordering::SeqCst);
+        }
+        if self.current_channel == 0 {
+            let version = self.shared.analyzer_version.load(Ordering::SeqCst);
+            if version != self.analyzer_version {
+                self.analyzer_version = version;
+                let analyzer = self.shared.analyzer.lock().unwrap().clone();
+                self.tap = analyzer.map(TapWriter::new);
+            }
+        }
+        self.current_channel = (self.current_channel + 1) % self.shared.channels;
+
+        let sample = self.mix();
+        if let Some(ref mut tap) = self.tap {
+            tap.push(sample, self.shared.channels, self.shared.sample_rate);
+        }
+        Some(sample)
+    }
+}
+
+impl MixerOutput {
+    /// Mixes the next sample of every source together.
+    fn mix(&mut self) -> f32 {
+        // While paused we don't pull from the sources at all,
+        // so they pick up exactly where they left off.
+        if self.shared.paused.load(Ordering::Relaxed) {
+            return 0.0;
+        }
+
+        let mut sum = 0.0;
+        let mut i = 0;
+        while i < self.sources.len() {
+            match self.sources[i].next() {
+                Some(sample) => {
+                    sum += sample;
+                    i += 1;
+                }
+                None => {
+                    let _ = self.sources.swap_remove(i);
+                }
+            }
+        }
+        sum * self.shared.master.gain()
+    }
+}
+
+impl rodio::Source for MixerOutput {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.shared.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.shared.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<time::Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn next_sample(output: &mut MixerOutput) -> f32 {
+        output.next().expect("mixer output ended")
+    }
+
+    #[test]
+    fn bus_and_master_gain_apply_live() {
+        let (mixer, mut output) = Mixer::new(2, 44100);
+        let sink = mixer.new_sink(Some("sfx"));
+        sink.append(SamplesBuffer::new(2, 44100, vec![1.0f32; 4410]));
+
+        // Skip the silence while the sink starts up.
+        assert!(output
+            .by_ref()
+            .take(2000)
+            .any(|sample| (sample - 1.0).abs() < 1e-4));
+        assert!((next_sample(&mut output) - 1.0).abs() < 1e-4);
+
+        mixer.set_bus_volume("sfx", 0.5);
+        mixer.set_master_volume(0.5);
+        assert!((next_sample(&mut output) - 0.25).abs() < 1e-4);
+
+        mixer.set_bus_muted("sfx", true);
+        assert!(next_sample(&mut output).abs() < f32::EPSILON);
+        mixer.set_bus_muted("sfx", false);
+
+        mixer.pause_all();
+        assert!(next_sample(&mut output).abs() < f32::EPSILON);
+        mixer.resume_all();
+        assert!((next_sample(&mut output) - 0.25).abs() < 1e-4);
+
+        mixer.set_muted(true);
+        assert!(next_sample(&mut output).abs() < f32::EPSILON);
+        assert_eq!(vec!["sfx".to_string()], mixer.buses());
+    }
+
+    #[test]
+    fn voices_count_while_playing() {
+        let (mixer, mut output) = Mixer::new(2, 44100);
+        let sink = mixer.new_sink(None);
+        sink.append(mixer.count_voice(SamplesBuffer::new(2, 44100, vec![1.0f32; 4000])));
+        sink.append(mixer.count_voice(SamplesBuffer::new(2, 44100, vec![1.0f32; 4000])));
+        assert_eq!(0, mixer.active_voices());
+        assert!(output
+            .by_ref()
+            .take(2000)
+            .any(|sample| (sample - 1.0).abs() < 1e-4));
+        assert_eq!(1, mixer.active_voices());
+        let _ = output.by_ref().take(20000).count();
+        assert_eq!(0, mixer.active_voices());
+    }
+
+    #[test]
+    fn master_output_feeds_the_analyzer() {
+        let (mixer, mut output) = Mixer::new(2, 44100);
+        let analyzer = Analyzer::new(256);
+        mixer.set_analyzer(Some(&analyzer));
+        mixer.set_master_volume(0.5);
+        let sink = mixer.new_sink(None);
+        sink.append(SamplesBuffer::new(2, 44100, vec![1.0f32; 44100]));
+        let _ = output.by_ref().take(8000).count();
+        assert!((analyzer.levels().peak - 0.5).abs() < 1e-4);
+        assert_eq!(Some(44100), mixer.analyzer().map(|a| a.sample_rate()));
+
+        // The output lets go of the analyzer at the next frame.
+        mixer.set_analyzer(None);
+        let _ = output.by_ref().take(2).count();
+        analyzer.clear();
+        let _ = output.by_ref().take(8000).count();
+        assert_eq!(0.0, analyzer.levels().peak);
+    }
+
+    #[test]
+    fn output_never_ends() {
+        let (mixer, mut output) = Mixer::new(2, 44100);
+        let sink = mixer.new_sink(None);
+        sink.append(SamplesBuffer::new(2, 44100, vec![1.0f32; 2]));
+        drop(sink);
+        assert_eq!(44100, output.by_ref().take(44100).count());
+        assert!(output.sources.is_empty());
+    }
+}
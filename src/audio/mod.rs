@@ -0,0 +1,841 @@
+//! Provides an interface to output sound to the user's speakers.
+//!
+//! It consists of two main types: [`SoundData`](struct.SoundData.html)
+//! is just an array of raw sound data bytes, and a [`Source`](struct.Source.html) is a
+//! `SoundData` connected to a particular sound channel ready to be played.
+//! Long pieces of music are better played with a
+//! [`StreamingSource`](struct.StreamingSource.html), which decodes
+//! straight from a file instead, and sounds generated by the game itself
+//! are played with a [`ProceduralSource`](struct.ProceduralSource.html).
+//! Tracker modules are played with a [`TrackerSource`](struct.TrackerSource.html).
+//! [`Music`](struct.Music.html) takes care of queueing and crossfading
+//! background music.
+//!
+//! Every sound is played through a [`Mixer`](struct.Mixer.html), which has a
+//! master volume and any number of named buses that sources can be assigned to.
+//! Instead of an output device, the mixer can also be played into memory by a
+//! [`CaptureAudioContext`](struct.CaptureAudioContext.html), for testing.
+
+use std::fmt;
+use std::io;
+use std::io::Read;
+use std::path;
+use std::time;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::context::Context;
+use crate::error::GameError;
+use crate::error::GameResult;
+use crate::filesystem;
+
+#[macro_use]
+mod sink;
+
+mod analysis;
+mod capture;
+mod device;
+mod effects;
+mod fade;
+mod mixer;
+mod music;
+mod pool;
+mod procedural;
+mod spatial;
+mod streaming;
+mod tracker;
+mod wav;
+
+pub use self::analysis::{Analyzer, Levels};
+pub use self::capture::CaptureAudioContext;
+pub use self::device::{default_output_device, output_devices};
+pub use self::effects::{Effect, EffectChain};
+pub use self::mixer::Mixer;
+pub use self::music::{Music, Track};
+pub use self::pool::{SoundPool, VoiceStealing};
+pub use self::procedural::ProceduralSource;
+pub use self::spatial::{Attenuation, Listener, SpatialSource};
+pub use self::streaming::StreamingSource;
+pub use self::tracker::{TrackerFormat, TrackerPosition, TrackerSource};
+
+/// A trait object defining an audio context, allowing us to someday
+/// use something other than `rodio` if we really want.
+///
+/// End-users usually don't need to mess with this, but it's there
+/// if you want to bypass `ggez`'s sound functionality and write your
+/// own.
+pub trait AudioContext {
+    /// Returns the mixer all sounds are played through.
+    fn mixer(&self) -> &Mixer;
+
+    /// Returns the context as a `CaptureAudioContext` if it is one.
+    fn capture(&self) -> Option<&CaptureAudioContext> {
+        None
+    }
+
+    /// Returns a `rodio` handle to the output device, for playing sounds
+    /// on it directly.  Those don't go through the [`mixer()`](#tymethod.mixer),
+    /// so they ignore the master volume and buses, and they keep playing
+    /// on the old device after [`set_device()`](#method.set_device).
+    ///
+    /// # Panics
+    ///
+    /// The default implementation panics, as do contexts that capture
+    /// audio or have it disabled.  The context playing on a device
+    /// panics if it can't open a `rodio` stream on it.
+    #[deprecated(
+        since = "0.8.0",
+        note = "sounds are played through `mixer()`; use `device_name()` and `set_device()` to choose the device"
+    )]
+    fn device(&self) -> &rodio::OutputStreamHandle {
+        panic!("This audio context has no output device")
+    }
+
+    /// Returns the name of the output device sounds are played on, if any.
+    fn device_name(&self) -> Option<&str> {
+        None
+    }
+
+    /// Switches to the output device with the given name, or to the
+    /// system's default one if `name` is `None`.  Sounds keep playing
+    /// on the new device.
+    fn set_device(&mut self, _name: Option<&str>) -> GameResult {
+        Err(GameError::AudioError(String::from(
+            "This audio context has no output device",
+        )))
+    }
+
+    /// Returns an error if the output device has stopped working since
+    /// the last time this was called, such as when it was unplugged.
+    /// The loss is reported from the audio thread, so it is only noticed
+    /// by the next call after it happens.
+    fn check_device(&self) -> GameResult {
+        Ok(())
+    }
+
+    /// Returns how many sounds are playing through the mixer.
+    fn active_voices(&self) -> usize {
+        self.mixer().active_voices()
+    }
+}
+
+/// A struct that contains all information for tracking sound info.
+///
+/// You generally don't have to create this yourself, it will be part
+/// of your `Context` object.
+pub(crate) struct RodioAudioContext {
+    device: device::OutputDevice,
+    output: Arc<Mutex<mixer::MixerOutput>>,
+    mixer: Mixer,
+}
+
+impl RodioAudioContext {
+    /// Create new `RodioAudioContext` playing on the output device with
+    /// the given name.  If there is no such device, the default one is
+    /// used instead.
+    pub fn new(device_name: Option<&str>) -> GameResult<Self> {
+        let device = match device::find_device(device_name) {
+            Ok(device) => device,
+            Err(e) if device_name.is_some() => {
+                warn!("{}, using the default audio output device instead", e);
+                device::find_device(None)?
+            }
+            Err(e) => return Err(e),
+        };
+        // The mixer runs in the format of the device it starts out on,
+        // so it doesn't have to be converted there.
+        let (channels, sample_rate) = device::device_format(&device).unwrap_or((2, 44100));
+        let (mixer, output) = Mixer::new(channels, sample_rate);
+        let output = Arc::new(Mutex::new(output));
+        Ok(Self {
+            device: device::OutputDevice::open(&device, output.clone())?,
+            output,
+            mixer,
+        })
+    }
+}
+
+impl AudioContext for RodioAudioContext {
+    fn mixer(&self) -> &Mixer {
+        &self.mixer
+    }
+
+    fn device(&self) -> &rodio::OutputStreamHandle {
+        match self.device.rodio_handle() {
+            Ok(handle) => handle,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    fn device_name(&self) -> Option<&str> {
+        Some(self.device.name())
+    }
+
+    fn set_device(&mut self, name: Option<&str>) -> GameResult {
+        let device = device::find_device(name)?;
+        // The old device stops when it is dropped here; until then both
+        // take turns pulling from the mixer, but only for a moment.
+        self.device = device::OutputDevice::open(&device, self.output.clone())?;
+        Ok(())
+    }
+
+    fn check_device(&self) -> GameResult {
+        self.device.check()
+    }
+}
+
+impl fmt::Debug for RodioAudioContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<RodioAudioContext: {:p}>", self)
+    }
+}
+
+/// A structure that implements `AudioContext` but does nothing; serves as a
+/// stub for when you don't need audio.  Will panic if you try to actually
+/// play sound from it.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct NullAudioContext;
+
+impl AudioContext for NullAudioContext {
+    fn mixer(&self) -> &Mixer {
+        panic!("Audio module disabled")
+    }
+
+    fn active_voices(&self) -> usize {
+        0
+    }
+}
+
+/// Returns the mixer of the `Context`, which controls the master
+/// volume and the named buses.
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # fn t(ctx: &mut Context) {
+/// audio::mixer(ctx).set_bus_volume("music", 0.5);
+/// audio::mixer(ctx).set_bus_muted("sfx", true);
+/// # }
+/// ```
+pub fn mixer(ctx: &Context) -> &Mixer {
+    ctx.audio_context.mixer()
+}
+
+/// Returns how many sounds are playing right now, across every source,
+/// [`SoundPool`](struct.SoundPool.html) and bus.  Paused sounds count
+/// too, but ones queued up behind others don't until they start.
+pub fn active_voices(ctx: &Context) -> usize {
+    ctx.audio_context.active_voices()
+}
+
+/// Returns the `CaptureAudioContext` of the `Context`, if audio is
+/// being captured instead of played on an output device.
+pub fn capture(ctx: &Context) -> Option<&CaptureAudioContext> {
+    ctx.audio_context.capture()
+}
+
+/// Returns the name of the output device sounds are played on, or
+/// `None` if audio is disabled or captured.
+pub fn output_device(ctx: &Context) -> Option<&str> {
+    ctx.audio_context.device_name()
+}
+
+/// Switches to the output device with the given name, as listed by
+/// [`output_devices()`](fn.output_devices.html), or to the system's
+/// default one if `name` is `None`.  Sounds that are playing carry on
+/// on the new device.
+///
+/// The choice is also stored in the `Context`'s
+/// [`Conf`](../conf/struct.Conf.html), so it is kept if you save that.
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # fn t(ctx: &mut Context) -> GameResult {
+/// if let Err(e) = audio::check_device(ctx) {
+///     println!("{}, switching to the default device", e);
+///     audio::set_output_device(ctx, None)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn set_output_device(ctx: &mut Context, name: Option<&str>) -> GameResult {
+    ctx.audio_context.set_device(name)?;
+    ctx.conf.audio.output_device = name.map(String::from);
+    Ok(())
+}
+
+/// Returns an error if the output device has stopped working since the
+/// last call, such as when it was unplugged.  Sounds don't play until
+/// you switch to another device with
+/// [`set_output_device()`](fn.set_output_device.html).
+///
+/// Nothing checks for this on its own: a lost device is only noticed
+/// the next time this is called, so call it in your `update()` to find
+/// out within a frame.
+pub fn check_device(ctx: &Context) -> GameResult {
+    ctx.audio_context.check_device()
+}
+
+/// Advances a capturing audio context along with the timer,
+/// once a frame after the timer has been ticked.
+pub(crate) fn follow_timer(ctx: &Context) {
+    if let Some(capture) = capture(ctx) {
+        if capture.follows_timer() {
+            capture.advance(crate::timer::delta(ctx));
+        }
+    }
+}
+
+/// Static sound data stored in memory.
+/// It is `Arc`'ed, so cheap to clone.
+#[derive(Clone, Debug)]
+pub struct SoundData {
+    data: Arc<[u8]>,
+    /// Filled in the first time it's needed, or `None` if the
+    /// data couldn't be decoded.
+    info: Arc<OnceLock<Option<SoundInfo>>>,
+}
+
+/// The properties of a `SoundData` that are only known once it is decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct SoundInfo {
+    channels: u16,
+    sample_rate: u32,
+    samples: u64,
+}
+
+impl SoundData {
+    /// Load the file at the given path and create a new `SoundData` from it.
+    pub fn new<P: AsRef<path::Path>>(context: &mut Context, path: P) -> GameResult<Self> {
+        let path = path.as_ref();
+        let file = &mut filesystem::open(context, path)?;
+        SoundData::from_read(file)
+    }
+
+    /// Copies the data in the given slice into a new `SoundData` object.
+    pub fn from_bytes(data: &[u8]) -> Self {
+        SoundData::from(Arc::from(data))
+    }
+
+    /// Creates a `SoundData` from any `Read` object; this involves
+    /// copying it into a buffer.
+    pub fn from_read<R>(reader: &mut R) -> GameResult<Self>
+    where
+        R: Read,
+    {
+        let mut buffer = Vec::new();
+        let _ = reader.read_to_end(&mut buffer)?;
+
+        Ok(SoundData::from(buffer))
+    }
+
+    /// Creates a `SoundData` from raw, interleaved samples ranging from
+    /// `-1.0` to `1.0`, such as ones made by a sound effect generator.
+    /// They are stored as 16-bit WAV data.
+    ///
+    /// Fails if there are no channels, the sample rate is zero, or
+    /// the samples don't make up a whole number of frames.
+    pub fn from_samples(channels: u16, sample_rate: u32, samples: &[f32]) -> GameResult<Self> {
+        if channels == 0 || sample_rate == 0 {
+            return Err(GameError::AudioError(format!(
+                "Invalid sound format: {} channels at {} Hz",
+                channels, sample_rate
+            )));
+        }
+        if samples.len() % usize::from(channels) != 0 {
+            return Err(GameError::AudioError(format!(
+                "{} samples can't be split evenly between {} channels",
+                samples.len(),
+                channels
+            )));
+        }
+        let samples: Vec<i16> = samples.iter().map(|&sample| wav::to_i16(sample)).collect();
+        let mut buffer = Vec::with_capacity(44 + samples.len() * 2);
+        wav::write(&mut buffer, channels, sample_rate, &samples)
+            .expect("Writing to a Vec should never fail");
+
+        let data = SoundData::from(buffer);
+        let _ = data.info.set(Some(SoundInfo {
+            channels,
+            sample_rate,
+            samples: samples.len() as u64 / u64::from(channels),
+        }));
+        Ok(data)
+    }
+
+    /// Decodes the sound and saves it as a 16-bit WAV file
+    /// at the given path, using [`filesystem::create()`](../filesystem/fn.create.html).
+    pub fn encode_wav<P: AsRef<path::Path>>(&self, ctx: &mut Context, path: P) -> GameResult {
+        use rodio::Source;
+        use std::io::Write;
+        let decoder = rodio::Decoder::new(io::Cursor::new(self.clone()))?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        let samples: Vec<i16> = decoder.collect();
+
+        let mut writer = io::BufWriter::new(filesystem::create(ctx, path)?);
+        wav::write(&mut writer, channels, sample_rate, &samples)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Indicates if the data can be played as a sound.
+    pub fn can_play(&self) -> bool {
+        let cursor = io::Cursor::new(self.clone());
+        rodio::Decoder::new(cursor).is_ok()
+    }
+
+    /// Returns the number of channels of the sound.
+    pub fn channels(&self) -> GameResult<u16> {
+        Ok(self.info()?.channels)
+    }
+
+    /// Returns the sample rate of the sound.
+    pub fn sample_rate(&self) -> GameResult<u32> {
+        Ok(self.info()?.sample_rate)
+    }
+
+    /// Returns the length of the sound in samples per channel.
+    pub fn samples(&self) -> GameResult<u64> {
+        Ok(self.info()?.samples)
+    }
+
+    /// Returns how long the sound takes to play at normal speed.
+    pub fn duration(&self) -> GameResult<time::Duration> {
+        let info = self.info()?;
+        let nanos = u128::from(info.samples) * 1_000_000_000 / u128::from(info.sample_rate);
+        Ok(time::Duration::from_nanos(nanos as u64))
+    }
+
+    /// Finds out the length of the sound the first time it is called,
+    /// and returns the same answer from then on.
+    fn info(&self) -> GameResult<SoundInfo> {
+        self.info.get_or_init(|| self.read_info()).ok_or_else(|| {
+            GameError::AudioError("Could not decode the given audio data".to_string())
+        })
+    }
+
+    /// Reads the length of WAV files from their header,
+    /// and decodes anything else all the way through.
+    fn read_info(&self) -> Option<SoundInfo> {
+        use rodio::Source;
+        if let Ok(reader) = hound::WavReader::new(io::Cursor::new(&self.data[..])) {
+            let spec = reader.spec();
+            return Some(SoundInfo {
+                channels: spec.channels,
+                sample_rate: spec.sample_rate,
+                samples: u64::from(reader.duration()),
+            });
+        }
+        let mut decoder = rodio::Decoder::new(io::Cursor::new(self.clone())).ok()?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        let samples = decoder.by_ref().count() as u64 / u64::from(channels.max(1));
+        Some(SoundInfo {
+            channels,
+            sample_rate,
+            samples,
+        })
+    }
+}
+
+impl From<Arc<[u8]>> for SoundData {
+    #[inline]
+    fn from(arc: Arc<[u8]>) -> Self {
+        SoundData {
+            data: arc,
+            info: Arc::new(OnceLock::new()),
+        }
+    }
+}
+
+impl From<Vec<u8>> for SoundData {
+    fn from(v: Vec<u8>) -> Self {
+        SoundData::from(Arc::<[u8]>::from(v))
+    }
+}
+
+impl From<Box<[u8]>> for SoundData {
+    fn from(b: Box<[u8]>) -> Self {
+        SoundData::from(Arc::<[u8]>::from(b))
+    }
+}
+
+impl AsRef<[u8]> for SoundData {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+}
+
+/// A trait defining the operations possible on a sound;
+/// it is implemented by `Source`, `SpatialSource`, `StreamingSource`,
+/// `ProceduralSource` and `TrackerSource`.
+pub trait SoundSource {
+    /// Plays the audio source; restarts the sound if currently playing
+    fn play(&mut self, ctx: &Context) -> GameResult {
+        self.stop(ctx)?;
+        self.play_later()
+    }
+
+    /// Plays the `SoundSource`; waits until done if the sound is currently playing
+    fn play_later(&self) -> GameResult;
+
+    /// Plays the audio source starting at the given position;
+    /// restarts the sound if currently playing
+    ///
+    /// The default implementation can only start from the beginning,
+    /// and returns an error for any other position.
+    fn play_from(&mut self, ctx: &Context, pos: time::Duration) -> GameResult {
+        if !pos.is_zero() {
+            return Err(GameError::AudioError(
+                "This sound source can't start playing partway through".to_string(),
+            ));
+        }
+        self.play(ctx)
+    }
+
+    /// Moves playback to the given position, keeping the source paused
+    /// if it was.  Has no effect if the source is stopped.
+    fn seek(&mut self, ctx: &Context, pos: time::Duration) -> GameResult {
+        if self.stopped() {
+            return Ok(());
+        }
+        let paused = self.paused();
+        self.play_from(ctx, pos)?;
+        if paused {
+            self.pause();
+        }
+        Ok(())
+    }
+
+    /// Returns how long the sound takes to play through once at normal speed,
+    /// or `None` if that can't be known without decoding all of it.
+    /// The default implementation always returns `None`.
+    fn duration(&self) -> Option<time::Duration> {
+        None
+    }
+
+    /// Play source "in the background"; cannot be stopped
+    fn play_detached(&mut self, ctx: &Context) -> GameResult;
+
+    /// Sets the source to repeat playback infinitely on next [`play()`](#method.play)
+    fn set_repeat(&mut self, repeat: bool);
+
+    /// Sets the fade-in time of the source
+    fn set_fade_in(&mut self, dur: time::Duration);
+
+    /// Sets the speed ratio (by adjusting the playback speed)
+    fn set_pitch(&mut self, ratio: f32);
+
+    /// Gets whether or not the source is set to repeat.
+    fn repeat(&self) -> bool;
+
+    /// Pauses playback
+    fn pause(&self);
+
+    /// Resumes playback
+    fn resume(&self);
+
+    /// Stops playback
+    fn stop(&mut self, ctx: &Context) -> GameResult;
+
+    /// Fades the sound out over the given time, after which it stops.
+    /// Anything queued with [`play_later()`](#method.play_later) is faded
+    /// out too, but sounds played after this aren't affected.
+    ///
+    /// The default implementation can't fade, and pauses the sound right away.
+    fn fade_out(&self, _dur: time::Duration) {
+        self.pause()
+    }
+
+    /// Returns whether or not the source is stopped
+    /// -- that is, has no more data to play.
+    fn stopped(&self) -> bool;
+
+    /// Gets the current volume.
+    fn volume(&self) -> f32;
+
+    /// Sets the current volume.
+    fn set_volume(&mut self, value: f32);
+
+    /// Get whether or not the source is paused.
+    fn paused(&self) -> bool;
+
+    /// Get whether or not the source is playing (ie, not paused
+    /// and not stopped).
+    fn playing(&self) -> bool;
+
+    /// Get the time the source has been playing since the last call to [`play()`](#method.play),
+    /// plus the position given to [`play_from()`](#method.play_from) or [`seek()`](#method.seek).
+    ///
+    /// Time measurement is based on audio samples consumed, so it may drift from the system
+    fn elapsed(&self) -> time::Duration;
+
+    /// Set the update interval of the internal sample counter.
+    ///
+    /// This parameter determines the precision of the time measured by [`elapsed()`](#method.elapsed).
+    fn set_query_interval(&mut self, t: time::Duration);
+
+    /// Assigns the source to the named bus of the [`Mixer`](struct.Mixer.html),
+    /// or to the master output if `bus` is `None`.
+    ///
+    /// Takes effect the next time the source is played or stopped.
+    /// The default implementation does nothing, for sources that always
+    /// play through the master output.
+    fn set_bus(&mut self, _bus: Option<&str>) {}
+
+    /// Gets the name of the bus the source is assigned to.
+    fn bus(&self) -> Option<&str> {
+        None
+    }
+
+    /// Returns the chain of [`Effect`](enum.Effect.html)s applied to the source.
+    /// Changes to it apply to the sound while it is playing.
+    ///
+    /// The default implementation returns a chain that isn't applied to
+    /// anything, for sources that don't support effects.
+    fn effects(&self) -> &EffectChain {
+        static NO_EFFECTS: OnceLock<EffectChain> = OnceLock::new();
+        NO_EFFECTS.get_or_init(EffectChain::new)
+    }
+
+    /// Feeds the sound of the source to the given
+    /// [`Analyzer`](struct.Analyzer.html), after its effects but before its
+    /// volume, or stops feeding one if `analyzer` is `None`.
+    ///
+    /// Takes effect the next time the source is played.
+    /// The default implementation does nothing, for sources that can't
+    /// be analyzed.
+    fn set_analyzer(&mut self, _analyzer: Option<&Analyzer>) {}
+
+    /// Gets the `Analyzer` the source feeds, if any.
+    fn analyzer(&self) -> Option<&Analyzer> {
+        None
+    }
+}
+
+/// Internal state used by audio sources.
+#[derive(Debug)]
+pub(crate) struct SourceState {
+    repeat: bool,
+    fade_in: time::Duration,
+    speed: f32,
+    query_interval: time::Duration,
+    play_time: Arc<AtomicUsize>,
+    fade: Arc<fade::FadeControl>,
+    bus: Option<String>,
+    effects: EffectChain,
+    analyzer: Option<Analyzer>,
+    mixer: Mixer,
+}
+
+impl SourceState {
+    /// Create a new `SourceState` with the default settings.
+    pub fn new(ctx: &Context) -> Self {
+        SourceState {
+            repeat: false,
+            fade_in: time::Duration::from_millis(0),
+            speed: 1.0,
+            query_interval: time::Duration::from_millis(100),
+            play_time: Arc::new(AtomicUsize::new(0)),
+            fade: Arc::new(fade::FadeControl::default()),
+            bus: None,
+            effects: EffectChain::new(),
+            analyzer: None,
+            mixer: ctx.audio_context.mixer().clone(),
+        }
+    }
+    /// Sets the source to repeat playback infinitely on next [`play()`](#method.play)
+    pub fn set_repeat(&mut self, repeat: bool) {
+        self.repeat = repeat;
+    }
+
+    /// Sets the fade-in time of the source.
+    pub fn set_fade_in(&mut self, dur: time::Duration) {
+        self.fade_in = dur;
+    }
+
+    /// Sets the pitch ratio (by adjusting the playback speed).
+    pub fn set_pitch(&mut self, ratio: f32) {
+        self.speed = ratio;
+    }
+
+    /// Gets whether or not the source is set to repeat.
+    pub fn repeat(&self) -> bool {
+        self.repeat
+    }
+
+    /// Get the time the source has been playing since the last call to [`play()`](#method.play).
+    ///
+    /// Time measurement is based on audio samples consumed, so it may drift from the system
+    /// clock over longer periods of time.
+    pub fn elapsed(&self) -> time::Duration {
+        let t = self.play_time.load(Ordering::SeqCst);
+        time::Duration::from_micros(t as u64)
+    }
+
+    /// Set the update interval of the internal sample counter.
+    ///
+    /// This parameter determines the precision of the time measured by [`elapsed()`](#method.elapsed).
+    pub fn set_query_interval(&mut self, t: time::Duration) {
+        self.query_interval = t;
+    }
+
+    /// Sets the time measured by [`elapsed()`](#method.elapsed).
+    pub fn set_elapsed(&self, t: time::Duration) {
+        self.play_time
+            .store(t.as_micros() as usize, Ordering::SeqCst);
+    }
+
+    /// Gets the chain of effects applied to the source.
+    pub fn effects(&self) -> &EffectChain {
+        &self.effects
+    }
+
+    /// Fades out the sounds playing, which then end.
+    pub fn fade_out(&self, dur: time::Duration) {
+        self.fade.fade_out(dur)
+    }
+
+    /// Applies the pitch, fade-in and effects to a sound, has it
+    /// count the time measured by [`elapsed()`](#method.elapsed)
+    /// and towards the mixer's active voices, lets it be faded out
+    /// and feeds it to the analyzer.
+    fn prepare<S>(&self, sound: S) -> impl rodio::Source<Item = f32> + Send + 'static
+    where
+        S: rodio::Source + Send + 'static,
+        S::Item: rodio::Sample,
+    {
+        use rodio::Source;
+        let counter = self.play_time.clone();
+        let period_mus = self.query_interval.as_secs() as usize * 1_000_000
+            + self.query_interval.subsec_micros() as usize;
+
+        let sound = sound
+            .speed(self.speed)
+            .fade_in(self.fade_in)
+            .periodic_access(self.query_interval, move |_| {
+                let _ = counter.fetch_add(period_mus, Ordering::SeqCst);
+            })
+            .convert_samples();
+        let sound = effects::EffectsSource::new(sound, self.effects.clone());
+        let sound = fade::FadeOut::new(sound, self.fade.clone());
+        let sound = analysis::AnalysisTap::new(sound, self.analyzer.clone());
+        self.mixer.count_voice(sound)
+    }
+
+    /// Sets the analyzer the sounds played are fed to.
+    pub fn set_analyzer(&mut self, analyzer: Option<&Analyzer>) {
+        self.analyzer = analyzer.cloned();
+    }
+
+    /// Gets the analyzer the sounds played are fed to.
+    pub fn analyzer(&self) -> Option<&Analyzer> {
+        self.analyzer.as_ref()
+    }
+
+    /// Sets the name of the mixer bus the source is played through.
+    pub fn set_bus(&mut self, bus: Option<&str>) {
+        self.bus = bus.map(String::from);
+    }
+
+    /// Gets the name of the mixer bus the source is played through.
+    pub fn bus(&self) -> Option<&str> {
+        self.bus.as_deref()
+    }
+
+    /// Creates a new sink connected to the source's bus.
+    fn new_sink(&self, ctx: &Context) -> rodio::Sink {
+        ctx.audio_context.mixer().new_sink(self.bus())
+    }
+}
+
+/// A source of audio data that is connected to an output
+/// channel and ready to play.  It will stop playing when
+/// dropped.
+// TODO LATER: Check and see if this matches Love2d's semantics!
+// Eventually it might read from a streaming decoder of some kind,
+// but for now it is just an in-memory SoundData structure.
+// The source of a rodio decoder must be Send, which something
+// that contains a reference to a ZipFile is not, so we are going
+// to just slurp all the data into memory for now.
+// There's really a lot of work that needs to be done here, since
+// rodio has gotten better (if still somewhat arcane) and our filesystem
+// code has done the data-slurping-from-zip's for us
+// but for now it works.
+pub struct Source {
+    sink: rodio::Sink,
+    data: io::Cursor<SoundData>,
+    state: SourceState,
+}
+
+impl Source {
+    /// Create a new `Source` from the given file.
+    pub fn new<P: AsRef<path::Path>>(context: &mut Context, path: P) -> GameResult<Self> {
+        let path = path.as_ref();
+        let data = SoundData::new(context, path)?;
+        Source::from_data(context, data)
+    }
+
+    /// Creates a new `Source` using the given `SoundData` object.
+    pub fn from_data(context: &mut Context, data: SoundData) -> GameResult<Self> {
+        if !data.can_play() {
+            return Err(GameError::AudioError(
+                "Could not decode the given audio data".to_string(),
+            ));
+        }
+        let state = SourceState::new(context);
+        Ok(Source {
+            sink: state.new_sink(context),
+            data: io::Cursor::new(data),
+            state,
+        })
+    }
+
+    /// Appends the sound to the sink, starting at the given position.
+    fn append_from(&self, pos: time::Duration) -> GameResult {
+        // Creating a new Decoder each time seems a little messy,
+        // since it may do checking and data-type detection that is
+        // redundant, but it's not super expensive.
+        // See https://github.com/ggez/ggez/issues/98 for discussion
+        use rodio::Source;
+        let cursor = self.data.clone();
+
+        if self.state.repeat {
+            let sound = rodio::Decoder::new(cursor)?
+                .repeat_infinite()
+                .skip_duration(pos);
+            self.sink.append(self.state.prepare(sound));
+        } else if pos.is_zero() || pos < self.data.get_ref().duration()? {
+            let sound = rodio::Decoder::new(cursor)?.skip_duration(pos);
+            self.sink.append(self.state.prepare(sound));
+        }
+
+        Ok(())
+    }
+}
+
+impl SoundSource for Source {
+    fn play_later(&self) -> GameResult {
+        self.append_from(time::Duration::ZERO)
+    }
+
+    fn play_from(&mut self, ctx: &Context, pos: time::Duration) -> GameResult {
+        self.stop(ctx)?;
+        self.state.set_elapsed(pos);
+        self.append_from(pos)
+    }
+
+    fn duration(&self) -> Option<time::Duration> {
+        self.data.get_ref().duration().ok()
+    }
+
+    impl_sink_source_methods!();
+}
+
+impl fmt::Debug for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Audio source: {:p}>", self)
+    }
+}
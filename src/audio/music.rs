@@ -0,0 +1,374 @@
+//! Background music: a queue of tracks with crossfades and ducking.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::path;
+use std::time;
+
+use super::{SoundSource, StreamingSource};
+use crate::context::Context;
+use crate::error::GameResult;
+use crate::timer;
+
+/// A piece of music for [`Music`](struct.Music.html) to play.
+///
+/// By default a track plays through once.  It can be set to loop, either
+/// as a whole or just a section of it, so that everything before the
+/// section plays once as an intro.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Track {
+    path: path::PathBuf,
+    looping: bool,
+    loop_start: time::Duration,
+    loop_end: Option<time::Duration>,
+}
+
+impl Track {
+    /// Creates a new `Track` streamed from the given file.
+    pub fn new<P: AsRef<path::Path>>(path: P) -> Self {
+        Track {
+            path: path.as_ref().to_path_buf(),
+            looping: false,
+            loop_start: time::Duration::ZERO,
+            loop_end: None,
+        }
+    }
+
+    /// Sets whether the whole track loops.
+    pub fn looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self.loop_start = time::Duration::ZERO;
+        self.loop_end = None;
+        self
+    }
+
+    /// Plays the track from the start, then loops the section from `start`
+    /// to `end` (or to the end of the track if `end` is `None`) forever.
+    pub fn loop_section(mut self, start: time::Duration, end: Option<time::Duration>) -> Self {
+        self.looping = true;
+        self.loop_start = start;
+        self.loop_end = end;
+        self
+    }
+
+    /// Returns the path of the file the track is streamed from.
+    pub fn path(&self) -> &path::Path {
+        &self.path
+    }
+
+    /// Returns whether the track loops.
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Returns the section of the track that loops, if it does.
+    pub fn loop_points(&self) -> Option<(time::Duration, Option<time::Duration>)> {
+        if self.looping {
+            Some((self.loop_start, self.loop_end))
+        } else {
+            None
+        }
+    }
+}
+
+/// Plays background music: a queue of [`Track`](struct.Track.html)s,
+/// streamed from files, with crossfades between them and ducking to make
+/// room for dialogue and such.
+///
+/// Call [`update()`](#method.update) once a frame to move on through the
+/// queue and to animate the ducking.
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # use std::time::Duration;
+/// # fn t(ctx: &mut Context) -> GameResult {
+/// let mut music = audio::Music::new();
+/// music.set_crossfade(Duration::from_secs(2));
+/// music.play(ctx, audio::Track::new("/title.ogg").looping(true))?;
+///
+/// // When the game starts:
+/// music.play(ctx, audio::Track::new("/level1.ogg").loop_section(
+///     Duration::from_secs(8),
+///     None,
+/// ))?;
+///
+/// // While someone is talking:
+/// music.duck(0.3, Duration::from_millis(250));
+///
+/// // Every frame:
+/// music.update(ctx)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Music {
+    current: Option<(Track, StreamingSource)>,
+    fading: Vec<StreamingSource>,
+    queue: VecDeque<Track>,
+    crossfade: time::Duration,
+    volume: f32,
+    duck_level: f32,
+    duck_target: f32,
+    /// How much the duck level changes per second.
+    duck_speed: f32,
+    bus: Option<String>,
+    paused: bool,
+}
+
+impl Default for Music {
+    fn default() -> Self {
+        Music {
+            current: None,
+            fading: Vec::new(),
+            queue: VecDeque::new(),
+            crossfade: time::Duration::ZERO,
+            volume: 1.0,
+            duck_level: 1.0,
+            duck_target: 1.0,
+            duck_speed: 0.0,
+            bus: None,
+            paused: false,
+        }
+    }
+}
+
+impl Music {
+    /// Creates a new `Music` player with nothing playing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts playing `track` right away, crossfading from
+    /// whatever was playing.  The queue is left as it is.
+    pub fn play(&mut self, ctx: &mut Context, track: Track) -> GameResult {
+        let fade_in = if self.current.is_some() {
+            self.crossfade
+        } else {
+            time::Duration::ZERO
+        };
+        self.start(ctx, track, fade_in)
+    }
+
+    /// Adds `track` to the end of the queue.  It is played after the
+    /// current track and everything queued before it, starting on the
+    /// next [`update()`](#method.update) if nothing is playing.
+    pub fn enqueue(&mut self, track: Track) {
+        self.queue.push_back(track);
+    }
+
+    /// Crossfades to the next track in the queue, or fades out the
+    /// current one if the queue is empty.
+    pub fn skip(&mut self, ctx: &mut Context) -> GameResult {
+        match self.queue.pop_front() {
+            Some(track) => self.play(ctx, track),
+            None => {
+                self.fade_out_current(self.crossfade);
+                Ok(())
+            }
+        }
+    }
+
+    /// Fades out the current track over `fade` and clears the queue.
+    pub fn stop(&mut self, fade: time::Duration) {
+        self.queue.clear();
+        self.fade_out_current(fade);
+    }
+
+    /// Removes every track from the queue, letting the current one play on.
+    pub fn clear_queue(&mut self) {
+        self.queue.clear();
+    }
+
+    /// Returns the track that is playing, if any.
+    pub fn current(&self) -> Option<&Track> {
+        self.current.as_ref().map(|(track, _)| track)
+    }
+
+    /// Returns the tracks in the queue, next one first.
+    pub fn queued(&self) -> impl Iterator<Item = &Track> {
+        self.queue.iter()
+    }
+
+    /// Returns whether a track is playing.  Tracks that are
+    /// fading out after being replaced don't count.
+    pub fn is_playing(&self) -> bool {
+        self.current
+            .as_ref()
+            .is_some_and(|(_, source)| !source.stopped())
+    }
+
+    /// Gets how long changes from one track to the next take.
+    pub fn crossfade(&self) -> time::Duration {
+        self.crossfade
+    }
+
+    /// Sets how long changes from one track to the next take.  When a
+    /// track that doesn't loop is about to end, the next one in the queue
+    /// starts this long before it does.  Tracks whose length can't be
+    /// told without decoding all of them, such as MP3s, are followed by
+    /// the next one as soon as they end instead.  Defaults to no
+    /// crossfade at all.
+    pub fn set_crossfade(&mut self, crossfade: time::Duration) {
+        self.crossfade = crossfade;
+    }
+
+    /// Gets the volume of the music.
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    /// Sets the volume of the music.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+        self.apply_volume();
+    }
+
+    /// Lowers the volume of the music to `level` times its usual volume
+    /// over `fade`, until [`unduck()`](#method.unduck) is called.
+    pub fn duck(&mut self, level: f32, fade: time::Duration) {
+        self.duck_to(level, fade);
+    }
+
+    /// Brings the volume of the music back up over `fade`
+    /// after [`duck()`](#method.duck).
+    pub fn unduck(&mut self, fade: time::Duration) {
+        self.duck_to(1.0, fade);
+    }
+
+    /// Returns how much the music is currently ducked,
+    /// from `0.0` to `1.0` for not at all.
+    pub fn duck_level(&self) -> f32 {
+        self.duck_level
+    }
+
+    fn duck_to(&mut self, level: f32, fade: time::Duration) {
+        self.duck_target = level;
+        if fade.is_zero() {
+            self.duck_level = level;
+            self.apply_volume();
+        } else {
+            self.duck_speed = (level - self.duck_level).abs() / fade.as_secs_f32();
+        }
+    }
+
+    /// Sets the mixer bus tracks are played through,
+    /// starting with the next one.
+    pub fn set_bus(&mut self, bus: Option<&str>) {
+        self.bus = bus.map(String::from);
+    }
+
+    /// Gets the mixer bus tracks are played through.
+    pub fn bus(&self) -> Option<&str> {
+        self.bus.as_deref()
+    }
+
+    /// Pauses the music, including any tracks fading out.
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.sources().for_each(|source| source.pause());
+    }
+
+    /// Resumes the music after [`pause()`](#method.pause).
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.sources().for_each(|source| source.resume());
+    }
+
+    /// Returns whether the music is paused.
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Moves on to the next track when the current one ends, drops tracks
+    /// that have faded out, and animates ducking.  Call this once a frame.
+    pub fn update(&mut self, ctx: &mut Context) -> GameResult {
+        if self.duck_level != self.duck_target {
+            let step = self.duck_speed * timer::delta(ctx).as_secs_f32();
+            self.duck_level = if self.duck_level < self.duck_target {
+                (self.duck_level + step).min(self.duck_target)
+            } else {
+                (self.duck_level - step).max(self.duck_target)
+            };
+        }
+        self.fading.retain(|source| !source.stopped());
+
+        if self.paused {
+            self.apply_volume();
+            return Ok(());
+        }
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|(_, source)| source.stopped())
+        {
+            self.current = None;
+        }
+        // Tracks that end on their own are crossfaded into the next one
+        // a little before they do, if their length is known.  Otherwise
+        // the next one starts once they have stopped, above.
+        let next_due = match self.current {
+            Some((ref track, ref source)) => {
+                !track.looping
+                    && !self.crossfade.is_zero()
+                    && source
+                        .duration()
+                        .is_some_and(|duration| source.elapsed() + self.crossfade >= duration)
+            }
+            None => true,
+        };
+        if next_due {
+            if let Some(track) = self.queue.pop_front() {
+                self.play(ctx, track)?;
+            }
+        }
+        self.apply_volume();
+        Ok(())
+    }
+
+    fn start(&mut self, ctx: &mut Context, track: Track, fade_in: time::Duration) -> GameResult {
+        let mut source = StreamingSource::new(ctx, &track.path)?;
+        source.set_bus(self.bus());
+        if track.looping {
+            let rate = f64::from(source.sample_rate());
+            let frames = |t: time::Duration| (t.as_secs_f64() * rate).round() as u64;
+            source.set_loop_points(frames(track.loop_start), track.loop_end.map(frames))?;
+            source.set_repeat(true);
+        }
+        source.set_fade_in(fade_in);
+        source.set_volume(self.volume * self.duck_level);
+        source.play(ctx)?;
+        if self.paused {
+            source.pause();
+        }
+
+        if let Some((_, old)) = self.current.replace((track, source)) {
+            old.fade_out(self.crossfade);
+            self.fading.push(old);
+        }
+        Ok(())
+    }
+
+    fn fade_out_current(&mut self, fade: time::Duration) {
+        if let Some((_, source)) = self.current.take() {
+            source.fade_out(fade);
+            self.fading.push(source);
+        }
+    }
+
+    fn sources(&mut self) -> impl Iterator<Item = &mut StreamingSource> {
+        self.current
+            .iter_mut()
+            .map(|(_, source)| source)
+            .chain(self.fading.iter_mut())
+    }
+
+    fn apply_volume(&mut self) {
+        let volume = self.volume * self.duck_level;
+        self.sources().for_each(|source| source.set_volume(volume));
+    }
+}
+
+impl fmt::Debug for Music {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Music: {:p}>", self)
+    }
+}
@@ -0,0 +1,344 @@
+//! Pools of voices playing the same sound, with a cap on how many play at once.
+
+use std::fmt;
+use std::io;
+use std::path;
+use std::time;
+
+use super::{EffectChain, SoundData, SourceState};
+use crate::context::Context;
+use crate::error::{GameError, GameResult};
+
+/// What a [`SoundPool`](struct.SoundPool.html) does when it is asked to
+/// play while all of its voices are busy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VoiceStealing {
+    /// Cuts off the voice that has been playing the longest.
+    #[default]
+    Oldest,
+    /// Cuts off the voice that was played at the lowest volume,
+    /// the oldest one of those if there is a tie.
+    Quietest,
+    /// Doesn't play the new sound at all.
+    Reject,
+}
+
+/// One sound played by the pool.
+struct Voice {
+    sink: rodio::Sink,
+    /// When the voice was started, counting plays of the pool.
+    started: u64,
+    /// The volume the voice was randomly given, before the pool's volume.
+    gain: f32,
+}
+
+/// A small xorshift generator, since picking pitches and volumes
+/// doesn't need anything better.
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Xorshift gets stuck at zero.
+        Rng(if seed == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            seed
+        })
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a number from `min` up to `max`.
+    fn range(&mut self, (min, max): (f32, f32)) -> f32 {
+        if min >= max {
+            return min;
+        }
+        let unit = (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32;
+        min + (max - min) * unit
+    }
+}
+
+/// Picks the voice to make room for a new one, as the index
+/// into `voices`, which are given as their start and gain.
+fn pick_stolen<I>(stealing: VoiceStealing, voices: I) -> Option<usize>
+where
+    I: Iterator<Item = (u64, f32)>,
+{
+    let voices = voices.enumerate();
+    match stealing {
+        VoiceStealing::Oldest => voices
+            .min_by_key(|(_, (started, _))| *started)
+            .map(|(i, _)| i),
+        VoiceStealing::Quietest => voices
+            .min_by(|(_, a), (_, b)| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)))
+            .map(|(i, _)| i),
+        VoiceStealing::Reject => None,
+    }
+}
+
+/// Plays many overlapping instances of one sound, such as gunshots or
+/// footsteps, without letting them pile up.
+///
+/// Every call to [`play()`](#method.play) starts a new voice, up to
+/// [`max_voices()`](#method.max_voices) at a time; past that, the
+/// [`VoiceStealing`](enum.VoiceStealing.html) policy decides whether a
+/// voice is cut off to make room or the new one is dropped.  Each voice
+/// can be given a random pitch and volume so repeated sounds don't all
+/// sound the same.  Every voice stops when the pool is dropped.
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # fn t(ctx: &mut Context) -> GameResult {
+/// let mut shots = audio::SoundPool::new(ctx, "/pew.ogg")?;
+/// shots.set_max_voices(16);
+/// shots.set_stealing(audio::VoiceStealing::Oldest);
+/// shots.set_pitch_range(0.9, 1.1);
+/// shots.set_volume_range(0.7, 1.0);
+///
+/// // Every time something fires:
+/// shots.play(ctx)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct SoundPool {
+    data: SoundData,
+    state: SourceState,
+    voices: Vec<Voice>,
+    max_voices: usize,
+    stealing: VoiceStealing,
+    pitch_range: (f32, f32),
+    volume_range: (f32, f32),
+    volume: f32,
+    paused: bool,
+    /// How many voices have been started, to tell which ones are oldest.
+    plays: u64,
+    rng: Rng,
+}
+
+impl SoundPool {
+    /// Creates a new `SoundPool` playing the given file.
+    pub fn new<P: AsRef<path::Path>>(context: &mut Context, path: P) -> GameResult<Self> {
+        let data = SoundData::new(context, path)?;
+        SoundPool::from_data(context, data)
+    }
+
+    /// Creates a new `SoundPool` playing the given `SoundData`.
+    /// It starts out with 8 voices, which steal from the oldest,
+    /// and no randomization.
+    pub fn from_data(context: &mut Context, data: SoundData) -> GameResult<Self> {
+        if !data.can_play() {
+            return Err(GameError::AudioError(
+                "Could not decode the given audio data".to_string(),
+            ));
+        }
+        let seed = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|t| t.as_nanos() as u64)
+            .unwrap_or(0);
+        Ok(SoundPool {
+            data,
+            state: SourceState::new(context),
+            voices: Vec::new(),
+            max_voices: 8,
+            stealing: VoiceStealing::default(),
+            pitch_range: (1.0, 1.0),
+            volume_range: (1.0, 1.0),
+            volume: 1.0,
+            paused: false,
+            plays: 0,
+            rng: Rng::new(seed),
+        })
+    }
+
+    /// Starts playing a new voice.  Returns `false` if there was no
+    /// voice free and the pool's policy is to
+    /// [`Reject`](enum.VoiceStealing.html#variant.Reject) new ones.
+    pub fn play(&mut self, ctx: &Context) -> GameResult<bool> {
+        self.voices.retain(|voice| !voice.sink.empty());
+        if self.max_voices == 0 {
+            return Ok(false);
+        }
+        while self.voices.len() >= self.max_voices {
+            let voices = self.voices.iter().map(|voice| (voice.started, voice.gain));
+            match pick_stolen(self.stealing, voices) {
+                // Dropping the sink cuts the voice off.
+                Some(i) => drop(self.voices.remove(i)),
+                None => return Ok(false),
+            }
+        }
+
+        let gain = self.rng.range(self.volume_range);
+        self.state.set_pitch(self.rng.range(self.pitch_range));
+        let sound = rodio::Decoder::new(io::Cursor::new(self.data.clone()))?;
+        let sink = self.state.new_sink(ctx);
+        sink.set_volume(self.volume * gain);
+        if self.paused {
+            sink.pause();
+        }
+        sink.append(self.state.prepare(sound));
+
+        self.voices.push(Voice {
+            sink,
+            started: self.plays,
+            gain,
+        });
+        self.plays += 1;
+        Ok(true)
+    }
+
+    /// Returns how many voices of the pool are playing.
+    pub fn active_voices(&self) -> usize {
+        self.voices
+            .iter()
+            .filter(|voice| !voice.sink.empty())
+            .count()
+    }
+
+    /// Gets the most voices that can play at once.
+    pub fn max_voices(&self) -> usize {
+        self.max_voices
+    }
+
+    /// Sets the most voices that can play at once.  If more are
+    /// playing already, they are left to finish.
+    pub fn set_max_voices(&mut self, max_voices: usize) {
+        self.max_voices = max_voices;
+    }
+
+    /// Gets what happens when a voice is played while all are busy.
+    pub fn stealing(&self) -> VoiceStealing {
+        self.stealing
+    }
+
+    /// Sets what happens when a voice is played while all are busy.
+    pub fn set_stealing(&mut self, stealing: VoiceStealing) {
+        self.stealing = stealing;
+    }
+
+    /// Gets the range the pitch of each voice is picked from.
+    pub fn pitch_range(&self) -> (f32, f32) {
+        self.pitch_range
+    }
+
+    /// Sets the range the pitch ratio of each new voice is picked from
+    /// at random.  Setting both ends to the same value plays every
+    /// voice at that pitch.
+    pub fn set_pitch_range(&mut self, min: f32, max: f32) {
+        self.pitch_range = (min, max);
+    }
+
+    /// Gets the range the volume of each voice is picked from.
+    pub fn volume_range(&self) -> (f32, f32) {
+        self.volume_range
+    }
+
+    /// Sets the range the volume of each new voice is picked from at
+    /// random, before it is multiplied by the pool's volume.
+    pub fn set_volume_range(&mut self, min: f32, max: f32) {
+        self.volume_range = (min, max);
+    }
+
+    /// Gets the volume of the pool.
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    /// Sets the volume of the pool, which applies to
+    /// the voices that are playing too.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+        for voice in &self.voices {
+            voice.sink.set_volume(volume * voice.gain);
+        }
+    }
+
+    /// Seeds the generator used to pick pitches and volumes, to make
+    /// them the same every run.  It is seeded from the clock otherwise.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Pauses every voice.  Voices played while paused start paused.
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.voices.iter().for_each(|voice| voice.sink.pause());
+    }
+
+    /// Resumes every voice after [`pause()`](#method.pause).
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.voices.iter().for_each(|voice| voice.sink.play());
+    }
+
+    /// Returns whether the pool is paused.
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stops every voice right away.
+    pub fn stop(&mut self) {
+        self.voices.clear();
+    }
+
+    /// Fades out every voice playing over the given time.
+    /// Voices played later aren't affected.
+    pub fn fade_out(&self, dur: time::Duration) {
+        self.state.fade_out(dur)
+    }
+
+    /// Sets the mixer bus new voices are played through.
+    pub fn set_bus(&mut self, bus: Option<&str>) {
+        self.state.set_bus(bus)
+    }
+
+    /// Gets the mixer bus new voices are played through.
+    pub fn bus(&self) -> Option<&str> {
+        self.state.bus()
+    }
+
+    /// Returns the chain of effects applied to every voice.
+    pub fn effects(&self) -> &EffectChain {
+        self.state.effects()
+    }
+}
+
+impl fmt::Debug for SoundPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<SoundPool: {:p}>", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_values_stay_in_range() {
+        let mut rng = Rng::new(0);
+        let values: Vec<f32> = (0..1000).map(|_| rng.range((0.5, 2.0))).collect();
+        assert!(values.iter().all(|v| (0.5..2.0).contains(v)));
+        assert!(values.iter().any(|v| *v < 0.7));
+        assert!(values.iter().any(|v| *v > 1.8));
+        assert_eq!(1.5, rng.range((1.5, 1.5)));
+
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        assert!((0..10).all(|_| a.next_u64() == b.next_u64()));
+    }
+
+    #[test]
+    fn stealing_picks_the_right_voice() {
+        let voices = [(3, 0.5), (1, 0.8), (2, 0.2), (4, 0.2)];
+        let pick = |stealing| pick_stolen(stealing, voices.iter().copied());
+        assert_eq!(Some(1), pick(VoiceStealing::Oldest));
+        assert_eq!(Some(2), pick(VoiceStealing::Quietest));
+        assert_eq!(None, pick(VoiceStealing::Reject));
+        assert_eq!(None, pick_stolen(VoiceStealing::Oldest, std::iter::empty()));
+    }
+}
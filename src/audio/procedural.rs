@@ -0,0 +1,234 @@
+//! Sources whose samples are generated by the game as they play.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time;
+
+use super::{SoundSource, SourceState};
+use crate::context::Context;
+use crate::error::GameError;
+use crate::error::GameResult;
+
+type SampleIter = Box<dyn Iterator<Item = f32> + Send>;
+type Generator = Arc<dyn Fn() -> SampleIter + Send + Sync>;
+
+/// The part of a `ProceduralSource` that lives on the audio thread.
+struct GeneratedSound {
+    generator: Generator,
+    samples: SampleIter,
+    channels: u16,
+    sample_rate: u32,
+    repeat: bool,
+    /// Whether the current run of the generator has produced anything,
+    /// so that an empty one doesn't get restarted forever.
+    produced: bool,
+}
+
+impl GeneratedSound {
+    fn new(generator: Generator, channels: u16, sample_rate: u32, repeat: bool) -> Self {
+        GeneratedSound {
+            samples: generator(),
+            generator,
+            channels,
+            sample_rate,
+            repeat,
+            produced: false,
+        }
+    }
+}
+
+impl Iterator for GeneratedSound {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(sample) = self.samples.next() {
+            self.produced = true;
+            return Some(sample);
+        }
+        if !self.repeat || !self.produced {
+            return None;
+        }
+        self.samples = (self.generator)();
+        self.produced = false;
+        self.next()
+    }
+}
+
+impl rodio::Source for GeneratedSound {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<time::Duration> {
+        None
+    }
+}
+
+/// A source of audio data generated on the fly by the game, for
+/// synthesized sound effects and such.  It will stop playing when dropped.
+///
+/// The samples are interleaved and range from `-1.0` to `1.0`.  Each time
+/// the source is played, it gets a fresh iterator of samples from the
+/// function it was created with; if it is set to repeat, it gets another
+/// one whenever the previous one runs out.
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # use ggez::audio::SoundSource;
+/// # fn t(ctx: &mut Context) -> GameResult {
+/// // Half a second of a 440 Hz sine wave.
+/// let mut beep = audio::ProceduralSource::from_fn(ctx, 1, 44100, |i| {
+///     let t = i as f32 / 44100.0;
+///     if t < 0.5 {
+///         Some((t * 440.0 * std::f32::consts::TAU).sin())
+///     } else {
+///         None
+///     }
+/// })?;
+/// beep.play(ctx)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ProceduralSource {
+    sink: rodio::Sink,
+    state: SourceState,
+    generator: Generator,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl ProceduralSource {
+    /// Creates a new `ProceduralSource` that plays the samples of the
+    /// iterators returned by `generator`.
+    pub fn new<F, I>(
+        context: &mut Context,
+        channels: u16,
+        sample_rate: u32,
+        generator: F,
+    ) -> GameResult<Self>
+    where
+        F: Fn() -> I + Send + Sync + 'static,
+        I: IntoIterator<Item = f32>,
+        I::IntoIter: Send + 'static,
+    {
+        if channels == 0 || sample_rate == 0 {
+            return Err(GameError::AudioError(format!(
+                "Invalid sound format: {} channels at {} Hz",
+                channels, sample_rate
+            )));
+        }
+        let state = SourceState::new(context);
+        Ok(ProceduralSource {
+            sink: state.new_sink(context),
+            state,
+            generator: Arc::new(move || Box::new(generator().into_iter()) as SampleIter),
+            channels,
+            sample_rate,
+        })
+    }
+
+    /// Creates a new `ProceduralSource` that calls `f` with the index of
+    /// each sample (counting every channel) to get its value.  The sound
+    /// ends when `f` returns `None`.
+    pub fn from_fn<F>(
+        context: &mut Context,
+        channels: u16,
+        sample_rate: u32,
+        f: F,
+    ) -> GameResult<Self>
+    where
+        F: Fn(u64) -> Option<f32> + Send + Sync + 'static,
+    {
+        let f = Arc::new(f);
+        ProceduralSource::new(context, channels, sample_rate, move || {
+            let f = f.clone();
+            (0..).map_while(move |i| f(i))
+        })
+    }
+
+    /// Returns the number of channels of the sound.
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Returns the sample rate of the sound.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Appends the sound to the sink, starting at the given position.
+    fn append_from(&self, pos: time::Duration) {
+        use rodio::Source;
+        let sound = GeneratedSound::new(
+            self.generator.clone(),
+            self.channels,
+            self.sample_rate,
+            self.state.repeat,
+        )
+        .skip_duration(pos);
+        self.sink.append(self.state.prepare(sound));
+    }
+}
+
+impl SoundSource for ProceduralSource {
+    fn play_later(&self) -> GameResult {
+        self.append_from(time::Duration::ZERO);
+        Ok(())
+    }
+
+    fn play_from(&mut self, ctx: &Context, pos: time::Duration) -> GameResult {
+        self.stop(ctx)?;
+        self.state.set_elapsed(pos);
+        self.append_from(pos);
+        Ok(())
+    }
+
+    /// Always returns `None`, since the length of a generated
+    /// sound isn't known until it ends.
+    fn duration(&self) -> Option<time::Duration> {
+        None
+    }
+
+    impl_sink_source_methods!();
+}
+
+impl fmt::Debug for ProceduralSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Procedural audio source: {:p}>", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp() -> Generator {
+        Arc::new(|| Box::new((0..3).map(|i| i as f32)) as SampleIter)
+    }
+
+    #[test]
+    fn generator_restarts_when_repeating() {
+        let once = GeneratedSound::new(ramp(), 1, 44100, false);
+        assert_eq!(vec![0.0, 1.0, 2.0], once.collect::<Vec<_>>());
+
+        let repeating = GeneratedSound::new(ramp(), 1, 44100, true);
+        assert_eq!(
+            vec![0.0, 1.0, 2.0, 0.0, 1.0, 2.0, 0.0],
+            repeating.take(7).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn empty_generator_ends_even_when_repeating() {
+        let empty: Generator = Arc::new(|| Box::new(std::iter::empty()) as SampleIter);
+        assert_eq!(None, GeneratedSound::new(empty, 1, 44100, true).next());
+    }
+}
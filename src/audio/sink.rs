@@ -0,0 +1,115 @@
+//! The parts of [`SoundSource`](../trait.SoundSource.html) that are the
+//! same for every source playing through a `rodio::Sink`.
+
+/// Implements the [`SoundSource`](trait.SoundSource.html) methods that
+/// only deal with the sink and the `SourceState`, for a type with a
+/// `sink: rodio::Sink` and a `state: SourceState` field.  The rest,
+/// `play_later()` and friends, are written out by hand in the impl
+/// this is expanded in.
+macro_rules! impl_sink_source_methods {
+    () => {
+        fn play_detached(&mut self, ctx: &$crate::context::Context) -> $crate::error::GameResult {
+            self.stop(ctx)?;
+            self.play_later()?;
+
+            let new_sink = self.state.new_sink(ctx);
+            let old_sink = std::mem::replace(&mut self.sink, new_sink);
+            old_sink.detach();
+
+            Ok(())
+        }
+
+        fn set_repeat(&mut self, repeat: bool) {
+            self.state.set_repeat(repeat)
+        }
+
+        fn set_fade_in(&mut self, dur: std::time::Duration) {
+            self.state.set_fade_in(dur)
+        }
+
+        fn set_pitch(&mut self, ratio: f32) {
+            self.state.set_pitch(ratio)
+        }
+
+        fn repeat(&self) -> bool {
+            self.state.repeat()
+        }
+
+        fn pause(&self) {
+            self.sink.pause()
+        }
+
+        fn resume(&self) {
+            self.sink.play()
+        }
+
+        fn stop(&mut self, ctx: &$crate::context::Context) -> $crate::error::GameResult {
+            // Sinks cannot be reused after calling `.stop()`. See
+            // https://github.com/tomaka/rodio/issues/171 for information.
+            // To stop the current sound we have to drop the old sink and
+            // create a new one in its place, connected to the mixer.
+
+            // We also need to carry over information from the previous sink.
+            let volume = self.volume();
+
+            self.sink = self.state.new_sink(ctx);
+            self.state.set_elapsed(std::time::Duration::ZERO);
+
+            // Restore information from the previous link.
+            self.set_volume(volume);
+            Ok(())
+        }
+
+        fn fade_out(&self, dur: std::time::Duration) {
+            self.state.fade_out(dur)
+        }
+
+        fn stopped(&self) -> bool {
+            self.sink.empty()
+        }
+
+        fn volume(&self) -> f32 {
+            self.sink.volume()
+        }
+
+        fn set_volume(&mut self, value: f32) {
+            self.sink.set_volume(value)
+        }
+
+        fn paused(&self) -> bool {
+            self.sink.is_paused()
+        }
+
+        fn playing(&self) -> bool {
+            !self.paused() && !self.stopped()
+        }
+
+        fn elapsed(&self) -> std::time::Duration {
+            self.state.elapsed()
+        }
+
+        fn set_query_interval(&mut self, t: std::time::Duration) {
+            self.state.set_query_interval(t)
+        }
+
+        fn set_bus(&mut self, bus: Option<&str>) {
+            self.state.set_bus(bus)
+        }
+
+        fn bus(&self) -> Option<&str> {
+            self.state.bus()
+        }
+
+        fn effects(&self) -> &$crate::audio::EffectChain {
+            self.state.effects()
+        }
+
+        fn set_analyzer(&mut self, analyzer: Option<&$crate::audio::Analyzer>) {
+            self.state.set_analyzer(analyzer)
+        }
+
+        fn analyzer(&self) -> Option<&$crate::audio::Analyzer> {
+            self.state.analyzer()
+        }
+    };
+}
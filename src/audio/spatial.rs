@@ -0,0 +1,746 @@
+//! Positional audio: sources placed in the world around a listener.
+//!
+//! A [`SpatialSource`](struct.SpatialSource.html) is panned according to
+//! where it is relative to the [`Listener`](struct.Listener.html), made
+//! quieter with distance according to its
+//! [`Attenuation`](enum.Attenuation.html), and shifted in pitch when it
+//! and the listener move towards or away from each other.  The listener
+//! is shared by all sources and is set through the
+//! [`Mixer`](struct.Mixer.html).
+//!
+//! ```rust,no_run
+//! # use ggez::*;
+//! # use ggez::audio::SoundSource;
+//! # fn t(ctx: &mut Context) -> GameResult {
+//! let mut engine = audio::SpatialSource::new(ctx, "/engine.ogg")?;
+//! engine.set_attenuation(audio::Attenuation::inverse(50.0, 2000.0));
+//! engine.set_position([400.0, 300.0, 0.0]);
+//! engine.set_velocity([120.0, 0.0, 0.0]);
+//! engine.play(ctx)?;
+//!
+//! let player = [380.0, 320.0, 0.0];
+//! audio::mixer(ctx).set_listener(audio::Listener::new().position(player));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::f32::consts::FRAC_PI_4;
+use std::fmt;
+use std::io;
+use std::path;
+use std::sync::{Arc, Mutex};
+use std::time;
+
+use glam::Vec3;
+
+use super::{SoundData, SoundSource, SourceState};
+use crate::context::Context;
+use crate::error::GameError;
+use crate::error::GameResult;
+
+/// How often the position of a playing sound is looked at.
+const UPDATE_INTERVAL: time::Duration = time::Duration::from_millis(10);
+
+/// Doppler shifts are kept within this factor either way,
+/// so that sources moving at the speed of sound don't blow up.
+const MAX_DOPPLER_SHIFT: f32 = 8.0;
+
+/// How the volume of a [`SpatialSource`](struct.SpatialSource.html)
+/// falls off with its distance from the listener.
+///
+/// All of them play at full volume up to `min_distance`, and stop getting
+/// any quieter past `max_distance`.  Distances are in the same units as
+/// the positions of the source and the listener.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Attenuation {
+    /// The volume doesn't depend on distance at all.
+    None,
+    /// The volume falls off in a straight line, reaching
+    /// silence at `max_distance`.
+    Linear {
+        /// The distance up to which the sound plays at full volume.
+        min_distance: f32,
+        /// The distance at which the sound can't be heard anymore.
+        max_distance: f32,
+    },
+    /// The volume is `min_distance / distance` with the default rolloff
+    /// of `1.0`, which is roughly how sound behaves in the real world.
+    Inverse {
+        /// The distance up to which the sound plays at full volume.
+        min_distance: f32,
+        /// The distance past which the sound gets no quieter.
+        max_distance: f32,
+        /// How fast the volume falls off; higher is faster.
+        rolloff: f32,
+    },
+    /// The volume is `(distance / min_distance) ^ -rolloff`.
+    Exponential {
+        /// The distance up to which the sound plays at full volume.
+        min_distance: f32,
+        /// The distance past which the sound gets no quieter.
+        max_distance: f32,
+        /// How fast the volume falls off; higher is faster.
+        rolloff: f32,
+    },
+}
+
+impl Attenuation {
+    /// A linear falloff from full volume at `min_distance`
+    /// to silence at `max_distance`.
+    pub fn linear(min_distance: f32, max_distance: f32) -> Self {
+        Attenuation::Linear {
+            min_distance,
+            max_distance,
+        }
+    }
+
+    /// An inverse-distance falloff with a rolloff of `1.0`.
+    pub fn inverse(min_distance: f32, max_distance: f32) -> Self {
+        Attenuation::Inverse {
+            min_distance,
+            max_distance,
+            rolloff: 1.0,
+        }
+    }
+
+    /// An exponential falloff with a rolloff of `1.0`.
+    pub fn exponential(min_distance: f32, max_distance: f32) -> Self {
+        Attenuation::Exponential {
+            min_distance,
+            max_distance,
+            rolloff: 1.0,
+        }
+    }
+
+    /// Returns the volume a sound at the given distance is played at,
+    /// from `0.0` to `1.0`.
+    pub fn gain(&self, distance: f32) -> f32 {
+        let clamp = |min: f32, max: f32| distance.max(min).min(max.max(min));
+        let gain = match *self {
+            Attenuation::None => 1.0,
+            Attenuation::Linear {
+                min_distance,
+                max_distance,
+            } => {
+                if max_distance <= min_distance {
+                    return if distance < max_distance { 1.0 } else { 0.0 };
+                }
+                let distance = clamp(min_distance, max_distance);
+                1.0 - (distance - min_distance) / (max_distance - min_distance)
+            }
+            Attenuation::Inverse {
+                min_distance,
+                max_distance,
+                rolloff,
+            } => {
+                let distance = clamp(min_distance, max_distance);
+                min_distance / (min_distance + rolloff * (distance - min_distance))
+            }
+            Attenuation::Exponential {
+                min_distance,
+                max_distance,
+                rolloff,
+            } => {
+                let distance = clamp(min_distance, max_distance);
+                (distance / min_distance).powf(-rolloff)
+            }
+        };
+        if gain.is_finite() {
+            gain.clamp(0.0, 1.0)
+        } else {
+            1.0
+        }
+    }
+}
+
+/// This falls off with the square of the distance past `1.0`, which is
+/// how `SpatialSource`s have always sounded.
+impl Default for Attenuation {
+    fn default() -> Self {
+        Attenuation::Exponential {
+            min_distance: 1.0,
+            max_distance: f32::INFINITY,
+            rolloff: 2.0,
+        }
+    }
+}
+
+/// Where sounds are heard from: the position, orientation and velocity
+/// of the player's ears.
+///
+/// By default the listener is at the origin, facing into the screen
+/// (`+z`) with the top of the screen (`-y`) as up.  That puts `+x` on its
+/// right, the same as ggez's screen coordinates, so 2D games only have
+/// to set the position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Listener {
+    /// Where the listener is.
+    pub position: mint::Point3<f32>,
+    /// The direction the listener is facing.
+    pub forward: mint::Vector3<f32>,
+    /// The direction of the top of the listener's head.
+    pub up: mint::Vector3<f32>,
+    /// How fast the listener is moving, in units per second.
+    /// Only used for the doppler effect.
+    pub velocity: mint::Vector3<f32>,
+    /// How fast sound travels, in units per second.
+    /// Defaults to `343.0`, which is right if your units are meters.
+    pub speed_of_sound: f32,
+}
+
+impl Default for Listener {
+    fn default() -> Self {
+        Listener {
+            position: [0.0, 0.0, 0.0].into(),
+            forward: [0.0, 0.0, 1.0].into(),
+            up: [0.0, -1.0, 0.0].into(),
+            velocity: [0.0, 0.0, 0.0].into(),
+            speed_of_sound: 343.0,
+        }
+    }
+}
+
+impl Listener {
+    /// Creates a new `Listener` with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a listener halfway between two ears,
+    /// facing so that `right` is on its right.
+    pub fn from_ears<P>(left: P, right: P) -> Self
+    where
+        P: Into<mint::Point3<f32>>,
+    {
+        let left = Vec3::from(left.into());
+        let right = Vec3::from(right.into());
+        let default = Listener::default();
+        let right_axis = (right - left).normalize_or_zero();
+        if right_axis == Vec3::ZERO {
+            return default.position((left + right) / 2.0);
+        }
+        // Keep the default up direction as far as possible.
+        let mut up = Vec3::from(default.up);
+        up -= right_axis * up.dot(right_axis);
+        if up.length_squared() < 1e-6 {
+            up = Vec3::Z.cross(right_axis);
+        }
+        let up = up.normalize();
+        default
+            .position((left + right) / 2.0)
+            .facing(up.cross(right_axis), up)
+    }
+
+    /// Sets the position of the listener.
+    pub fn position<P>(mut self, position: P) -> Self
+    where
+        P: Into<mint::Point3<f32>>,
+    {
+        self.position = position.into();
+        self
+    }
+
+    /// Sets the direction the listener is facing and the direction of
+    /// the top of its head.
+    pub fn facing<V>(mut self, forward: V, up: V) -> Self
+    where
+        V: Into<mint::Vector3<f32>>,
+    {
+        self.forward = forward.into();
+        self.up = up.into();
+        self
+    }
+
+    /// Sets the velocity of the listener.
+    pub fn velocity<V>(mut self, velocity: V) -> Self
+    where
+        V: Into<mint::Vector3<f32>>,
+    {
+        self.velocity = velocity.into();
+        self
+    }
+
+    /// Sets the speed of sound.
+    pub fn speed_of_sound(mut self, speed: f32) -> Self {
+        self.speed_of_sound = speed;
+        self
+    }
+
+    /// The direction to the listener's right.
+    fn right(&self) -> Vec3 {
+        Vec3::from(self.forward)
+            .cross(Vec3::from(self.up))
+            .normalize_or_zero()
+    }
+}
+
+/// Settings of a `SpatialSource`, shared with the sounds it is playing.
+#[derive(Debug, Clone, Copy)]
+struct SpatialParams {
+    position: Vec3,
+    velocity: Vec3,
+    attenuation: Attenuation,
+    doppler_factor: f32,
+    listener: Option<Listener>,
+}
+
+impl SpatialParams {
+    /// Works out the volume of each ear and the doppler shift.
+    fn mix(&self, listener: &Listener) -> ([f32; 2], f32) {
+        let listener = self.listener.as_ref().unwrap_or(listener);
+        let offset = self.position - Vec3::from(listener.position);
+        let distance = offset.length();
+        let gain = self.attenuation.gain(distance);
+
+        // Constant-power panning, straight ahead if the sound
+        // is right on top of the listener.
+        let pan = if distance > f32::EPSILON {
+            (offset / distance).dot(listener.right())
+        } else {
+            0.0
+        };
+        let angle = (pan + 1.0) * FRAC_PI_4;
+        let gains = [gain * angle.cos(), gain * angle.sin()];
+
+        let speed = listener.speed_of_sound;
+        let factor = self.doppler_factor;
+        let pitch = if distance > f32::EPSILON && factor > 0.0 && speed > 0.0 {
+            // The speeds of both along the line from the source to
+            // the listener, as in OpenAL.
+            let to_listener = -offset / distance;
+            let max = speed / factor;
+            let listener_speed = to_listener.dot(Vec3::from(listener.velocity)).min(max);
+            let source_speed = to_listener.dot(self.velocity).min(max);
+            let shift = (speed - factor * listener_speed) / (speed - factor * source_speed);
+            if shift.is_finite() {
+                shift.clamp(1.0 / MAX_DOPPLER_SHIFT, MAX_DOPPLER_SHIFT)
+            } else {
+                MAX_DOPPLER_SHIFT
+            }
+        } else {
+            1.0
+        };
+        (gains, pitch)
+    }
+}
+
+/// Turns a sound into a stereo one placed relative to the listener.
+/// The input is mixed down to mono first.
+struct SpatialSound<I> {
+    input: I,
+    params: Arc<Mutex<SpatialParams>>,
+    listener: Arc<Mutex<Listener>>,
+    sample_rate: u32,
+    update_frames: u32,
+    frames_until_update: u32,
+    gains: [f32; 2],
+    gain_steps: [f32; 2],
+    pitch: f32,
+    /// The input frames on either side of the current position,
+    /// which is `fraction` of the way from one to the other.
+    previous: f32,
+    next: f32,
+    fraction: f32,
+    /// The right channel of the current output frame, if it
+    /// hasn't been returned yet.
+    right: Option<f32>,
+    input_ended: bool,
+    started: bool,
+}
+
+impl<I> SpatialSound<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    fn new(input: I, params: Arc<Mutex<SpatialParams>>, listener: Arc<Mutex<Listener>>) -> Self {
+        let sample_rate = input.sample_rate();
+        let update_frames =
+            ((u64::from(sample_rate) * UPDATE_INTERVAL.as_millis() as u64 / 1000) as u32).max(1);
+        SpatialSound {
+            input,
+            params,
+            listener,
+            sample_rate,
+            update_frames,
+            frames_until_update: 0,
+            gains: [0.0; 2],
+            gain_steps: [0.0; 2],
+            pitch: 1.0,
+            previous: 0.0,
+            next: 0.0,
+            fraction: 0.0,
+            right: None,
+            input_ended: false,
+            started: false,
+        }
+    }
+
+    /// Reads the next input frame and mixes it down to mono.
+    fn next_input_frame(&mut self) -> Option<f32> {
+        let channels = self.input.channels().max(1);
+        let mut sum = self.input.next()?;
+        for _ in 1..channels {
+            sum += self.input.next().unwrap_or(0.0);
+        }
+        Some(sum / f32::from(channels))
+    }
+
+    /// Picks up changes to the positions.  The volumes are ramped towards
+    /// their new values over the next update interval, so they don't click.
+    fn update(&mut self) {
+        self.frames_until_update = self.update_frames;
+        let params = match self.params.try_lock() {
+            Ok(params) => *params,
+            Err(_) => return,
+        };
+        let listener = match self.listener.try_lock() {
+            Ok(listener) => *listener,
+            Err(_) => return,
+        };
+        let (gains, pitch) = params.mix(&listener);
+        self.pitch = pitch;
+        if !self.started {
+            self.gains = gains;
+            self.gain_steps = [0.0; 2];
+        } else {
+            let frames = self.update_frames as f32;
+            self.gain_steps = [
+                (gains[0] - self.gains[0]) / frames,
+                (gains[1] - self.gains[1]) / frames,
+            ];
+        }
+    }
+}
+
+impl<I> Iterator for SpatialSound<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(right) = self.right.take() {
+            return Some(right);
+        }
+
+        if !self.started {
+            self.update();
+            self.previous = self.next_input_frame()?;
+            self.next = self.next_input_frame().unwrap_or(0.0);
+            self.started = true;
+        } else {
+            // Step through the input at the doppler-shifted rate.
+            self.fraction += self.pitch;
+            while self.fraction >= 1.0 {
+                if self.input_ended {
+                    return None;
+                }
+                self.fraction -= 1.0;
+                self.previous = self.next;
+                self.next = match self.next_input_frame() {
+                    Some(frame) => frame,
+                    None => {
+                        self.input_ended = true;
+                        0.0
+                    }
+                };
+            }
+        }
+
+        if self.frames_until_update == 0 {
+            self.update();
+        }
+        self.frames_until_update -= 1;
+
+        let sample = self.previous + (self.next - self.previous) * self.fraction;
+        self.gains[0] += self.gain_steps[0];
+        self.gains[1] += self.gain_steps[1];
+        self.right = Some(sample * self.gains[1]);
+        Some(sample * self.gains[0])
+    }
+}
+
+impl<I> rodio::Source for SpatialSound<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<time::Duration> {
+        None
+    }
+}
+
+/// A source of audio data located in the world relative to the
+/// [`Listener`](struct.Listener.html).  Will stop playing when dropped.
+///
+/// The sound is mixed down to mono, then panned to stereo depending on
+/// which side of the listener it is, made quieter with distance and
+/// shifted in pitch by the doppler effect.  Changes to the position and
+/// velocity of the source and the listener apply while it is playing.
+pub struct SpatialSource {
+    sink: rodio::Sink,
+    data: io::Cursor<SoundData>,
+    state: SourceState,
+    params: Arc<Mutex<SpatialParams>>,
+    listener: Arc<Mutex<Listener>>,
+}
+
+impl SpatialSource {
+    /// Create a new `SpatialSource` from the given file.
+    pub fn new<P: AsRef<path::Path>>(context: &mut Context, path: P) -> GameResult<Self> {
+        let path = path.as_ref();
+        let data = SoundData::new(context, path)?;
+        SpatialSource::from_data(context, data)
+    }
+
+    /// Creates a new `SpatialSource` using the given `SoundData` object.
+    pub fn from_data(context: &mut Context, data: SoundData) -> GameResult<Self> {
+        if !data.can_play() {
+            return Err(GameError::AudioError(
+                "Could not decode the given audio data".to_string(),
+            ));
+        }
+        let state = SourceState::new(context);
+        let params = SpatialParams {
+            position: Vec3::ZERO,
+            velocity: Vec3::ZERO,
+            attenuation: Attenuation::default(),
+            doppler_factor: 1.0,
+            listener: None,
+        };
+
+        Ok(SpatialSource {
+            sink: state.new_sink(context),
+            data: io::Cursor::new(data),
+            state,
+            params: Arc::new(Mutex::new(params)),
+            listener: context.audio_context.mixer().listener_handle(),
+        })
+    }
+
+    /// Appends the sound to the sink, starting at the given position.
+    fn append_from(&self, pos: time::Duration) -> GameResult {
+        // Creating a new Decoder each time seems a little messy,
+        // since it may do checking and data-type detection that is
+        // redundant, but it's not super expensive.
+        // See https://github.com/ggez/ggez/issues/98 for discussion
+        use rodio::Source;
+        let cursor = self.data.clone();
+
+        if self.state.repeat {
+            let sound = rodio::Decoder::new(cursor)?
+                .repeat_infinite()
+                .skip_duration(pos);
+            self.append_spatial(self.state.prepare(sound));
+        } else if pos.is_zero() || pos < self.data.get_ref().duration()? {
+            let sound = rodio::Decoder::new(cursor)?.skip_duration(pos);
+            self.append_spatial(self.state.prepare(sound));
+        }
+
+        Ok(())
+    }
+
+    /// Appends a sound to the sink, placed according to the current
+    /// positions, which it keeps following.
+    fn append_spatial<S>(&self, sound: S)
+    where
+        S: rodio::Source<Item = f32> + Send + 'static,
+    {
+        self.sink.append(SpatialSound::new(
+            sound,
+            self.params.clone(),
+            self.listener.clone(),
+        ));
+    }
+
+    /// Set location of the sound.
+    pub fn set_position<P>(&mut self, pos: P)
+    where
+        P: Into<mint::Point3<f32>>,
+    {
+        self.params.lock().unwrap().position = Vec3::from(pos.into());
+    }
+
+    /// Gets the location of the sound.
+    pub fn position(&self) -> mint::Point3<f32> {
+        self.params.lock().unwrap().position.into()
+    }
+
+    /// Sets how fast the sound is moving, in units per second.
+    /// This is only used for the doppler effect.
+    pub fn set_velocity<V>(&mut self, velocity: V)
+    where
+        V: Into<mint::Vector3<f32>>,
+    {
+        self.params.lock().unwrap().velocity = Vec3::from(velocity.into());
+    }
+
+    /// Gets how fast the sound is moving.
+    pub fn velocity(&self) -> mint::Vector3<f32> {
+        self.params.lock().unwrap().velocity.into()
+    }
+
+    /// Sets how the volume of the sound falls off with distance.
+    pub fn set_attenuation(&mut self, attenuation: Attenuation) {
+        self.params.lock().unwrap().attenuation = attenuation;
+    }
+
+    /// Gets how the volume of the sound falls off with distance.
+    pub fn attenuation(&self) -> Attenuation {
+        self.params.lock().unwrap().attenuation
+    }
+
+    /// Sets how strong the doppler effect is; `1.0` is realistic,
+    /// and `0.0` turns it off.
+    pub fn set_doppler_factor(&mut self, factor: f32) {
+        self.params.lock().unwrap().doppler_factor = factor;
+    }
+
+    /// Gets how strong the doppler effect is.
+    pub fn doppler_factor(&self) -> f32 {
+        self.params.lock().unwrap().doppler_factor
+    }
+
+    /// Has the source be heard by the given listener instead of the
+    /// [mixer's](struct.Mixer.html#method.listener), or goes back to the
+    /// mixer's if `listener` is `None`.
+    pub fn set_listener(&mut self, listener: Option<Listener>) {
+        self.params.lock().unwrap().listener = listener;
+    }
+
+    /// Set locations of the listener's ears, for this source only.
+    /// See [`Listener::from_ears()`](struct.Listener.html#method.from_ears).
+    pub fn set_ears<P>(&mut self, left: P, right: P)
+    where
+        P: Into<mint::Point3<f32>>,
+    {
+        self.set_listener(Some(Listener::from_ears(left, right)));
+    }
+}
+
+impl SoundSource for SpatialSource {
+    /// Plays the `SpatialSource`; waits until done if the sound is currently playing.
+    fn play_later(&self) -> GameResult {
+        self.append_from(time::Duration::ZERO)
+    }
+
+    fn play_from(&mut self, ctx: &Context, pos: time::Duration) -> GameResult {
+        self.stop(ctx)?;
+        self.state.set_elapsed(pos);
+        self.append_from(pos)
+    }
+
+    fn duration(&self) -> Option<time::Duration> {
+        self.data.get_ref().duration().ok()
+    }
+
+    impl_sink_source_methods!();
+}
+
+impl fmt::Debug for SpatialSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Spatial audio source: {:p}>", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn params(position: [f32; 3], velocity: [f32; 3]) -> SpatialParams {
+        SpatialParams {
+            position: Vec3::from(position),
+            velocity: Vec3::from(velocity),
+            attenuation: Attenuation::None,
+            doppler_factor: 1.0,
+            listener: None,
+        }
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn attenuation_curves() {
+        let linear = Attenuation::linear(10.0, 110.0);
+        assert!(close(1.0, linear.gain(5.0)));
+        assert!(close(0.5, linear.gain(60.0)));
+        assert!(close(0.0, linear.gain(500.0)));
+
+        let inverse = Attenuation::inverse(10.0, 40.0);
+        assert!(close(1.0, inverse.gain(0.0)));
+        assert!(close(0.5, inverse.gain(20.0)));
+        assert!(close(0.25, inverse.gain(1000.0)));
+
+        let exponential = Attenuation::Exponential {
+            min_distance: 1.0,
+            max_distance: 10.0,
+            rolloff: 2.0,
+        };
+        assert!(close(0.25, exponential.gain(2.0)));
+        assert!(close(0.01, exponential.gain(20.0)));
+        assert!(close(1.0, Attenuation::None.gain(1e6)));
+    }
+
+    #[test]
+    fn panning_follows_listener_orientation() {
+        let listener = Listener::new();
+        let ([left, right], _) = params([10.0, 0.0, 0.0], [0.0; 3]).mix(&listener);
+        assert!(close(0.0, left) && close(1.0, right));
+
+        // Turned around, the sound is on the left.
+        let turned = listener.facing([0.0, 0.0, -1.0], [0.0, -1.0, 0.0]);
+        let ([left, right], _) = params([10.0, 0.0, 0.0], [0.0; 3]).mix(&turned);
+        assert!(close(1.0, left) && close(0.0, right));
+
+        let ([left, right], _) = params([0.0, 0.0, 5.0], [0.0; 3]).mix(&listener);
+        assert!(close(left, right));
+
+        let ears = Listener::from_ears([-1.0, 0.0, 0.0], [1.0, 0.0, 0.0]);
+        assert!(close(1.0, ears.right().x));
+    }
+
+    #[test]
+    fn doppler_shifts_pitch() {
+        let listener = Listener::new().speed_of_sound(100.0);
+        let (_, approaching) = params([10.0, 0.0, 0.0], [-50.0, 0.0, 0.0]).mix(&listener);
+        assert!(close(2.0, approaching));
+        let (_, leaving) = params([10.0, 0.0, 0.0], [50.0, 0.0, 0.0]).mix(&listener);
+        assert!(close(2.0 / 3.0, leaving));
+
+        let mut still = params([10.0, 0.0, 0.0], [-50.0, 0.0, 0.0]);
+        still.doppler_factor = 0.0;
+        assert!(close(1.0, still.mix(&listener).1));
+    }
+
+    #[test]
+    fn spatial_sound_resamples_and_pans() {
+        let ramp: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        let input = SamplesBuffer::new(1, 1000, ramp);
+        let listener = Listener::new().speed_of_sound(100.0);
+        let params = params([10.0, 0.0, 0.0], [-50.0, 0.0, 0.0]);
+        let sound = SpatialSound::new(
+            input,
+            Arc::new(Mutex::new(params)),
+            Arc::new(Mutex::new(listener)),
+        );
+        let output: Vec<f32> = sound.collect();
+        // Twice as fast, all on the right.
+        assert_eq!(100, output.len());
+        assert!(output.chunks(2).all(|frame| close(0.0, frame[0])));
+        assert!(close(0.0, output[1]) && close(2.0, output[3]) && close(98.0, output[99]));
+    }
+}
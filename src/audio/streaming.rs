@@ -0,0 +1,621 @@
+//! Sources that decode their sound bit by bit while it is playing.
+
+use std::fmt;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path;
+use std::thread;
+use std::time;
+use std::vec;
+
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+use hound::SampleFormat;
+use lewton::inside_ogg::OggStreamReader;
+
+use super::{SoundSource, SourceState};
+use crate::context::Context;
+use crate::error::GameError;
+use crate::error::GameResult;
+
+/// How many frames before the position it is asked for an OGG file is
+/// seeked to.  The first packet decoded after a seek only primes the
+/// decoder, so the frames it covers have to be decoded from earlier on.
+const VORBIS_SEEK_MARGIN: u64 = 8192;
+
+/// The longest an Ogg page can be, so the last page of
+/// a file starts at most this many bytes from its end.
+const OGG_MAX_PAGE_LEN: u64 = 27 + 255 + 255 * 255;
+
+/// Something a stream can be decoded from on the audio thread.
+trait StreamFile: Read + Seek + Send {}
+
+impl<T> StreamFile for T where T: Read + Seek + Send {}
+
+/// A file shared between several decoders, each of
+/// which has its own position in it.
+#[derive(Clone)]
+struct SharedFile {
+    file: Arc<Mutex<Box<dyn StreamFile>>>,
+    position: u64,
+}
+
+impl SharedFile {
+    fn new(file: Box<dyn StreamFile>) -> Self {
+        SharedFile {
+            file: Arc::new(Mutex::new(file)),
+            position: 0,
+        }
+    }
+
+    /// Returns a new handle to the file, positioned at its start.
+    fn rewound(&self) -> Self {
+        SharedFile {
+            file: self.file.clone(),
+            position: 0,
+        }
+    }
+}
+
+impl Read for SharedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut file = self.file.lock().unwrap();
+        let _ = file.seek(SeekFrom::Start(self.position))?;
+        let read = file.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for SharedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(offset) => {
+                let position = self.position as i64 + offset;
+                if position < 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "invalid seek to a negative position",
+                    ));
+                }
+                position as u64
+            }
+            SeekFrom::End(_) => self.file.lock().unwrap().seek(pos)?,
+        };
+        Ok(self.position)
+    }
+}
+
+type FileReader = io::BufReader<SharedFile>;
+
+/// Decodes a stream.  WAV and OGG files are decoded with `hound` and
+/// `lewton` directly, so that they can be seeked in; anything else goes
+/// through `rodio`'s decoder, which can only be read from the start.
+enum StreamDecoder {
+    Wav(hound::WavReader<FileReader>),
+    Vorbis(Box<OggStreamReader<FileReader>>, vec::IntoIter<i16>),
+    Other(Box<rodio::Decoder<FileReader>>),
+}
+
+impl StreamDecoder {
+    fn open(file: &SharedFile) -> GameResult<Self> {
+        if let Ok(reader) = hound::WavReader::new(io::BufReader::new(file.rewound())) {
+            let spec = reader.spec();
+            return match (spec.sample_format, spec.bits_per_sample) {
+                (SampleFormat::Float, 32)
+                | (SampleFormat::Int, 8)
+                | (SampleFormat::Int, 16)
+                | (SampleFormat::Int, 24) => Ok(StreamDecoder::Wav(reader)),
+                (format, bits) => Err(GameError::AudioError(format!(
+                    "Unsupported WAV sample format: {} bit {:?}",
+                    bits, format
+                ))),
+            };
+        }
+        if let Ok(reader) = OggStreamReader::new(io::BufReader::new(file.rewound())) {
+            return Ok(StreamDecoder::Vorbis(
+                Box::new(reader),
+                Vec::new().into_iter(),
+            ));
+        }
+        let decoder = rodio::Decoder::new(io::BufReader::new(file.rewound()))?;
+        Ok(StreamDecoder::Other(Box::new(decoder)))
+    }
+
+    fn channels(&self) -> u16 {
+        use rodio::Source;
+        match self {
+            StreamDecoder::Wav(reader) => reader.spec().channels,
+            StreamDecoder::Vorbis(reader, _) => u16::from(reader.ident_hdr.audio_channels),
+            StreamDecoder::Other(decoder) => decoder.channels(),
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        use rodio::Source;
+        match self {
+            StreamDecoder::Wav(reader) => reader.spec().sample_rate,
+            StreamDecoder::Vorbis(reader, _) => reader.ident_hdr.audio_sample_rate,
+            StreamDecoder::Other(decoder) => decoder.sample_rate(),
+        }
+    }
+
+    /// Returns the length of the stream, if it can be told
+    /// without decoding all of it.
+    fn duration(&self, file: &SharedFile) -> Option<time::Duration> {
+        use rodio::Source;
+        match self {
+            StreamDecoder::Wav(reader) => Some(time::Duration::from_secs_f64(
+                f64::from(reader.duration()) / f64::from(reader.spec().sample_rate),
+            )),
+            StreamDecoder::Vorbis(reader, _) => last_granule_position(file).map(|frames| {
+                time::Duration::from_secs_f64(
+                    frames as f64 / f64::from(reader.ident_hdr.audio_sample_rate),
+                )
+            }),
+            StreamDecoder::Other(decoder) => decoder.total_duration(),
+        }
+    }
+
+    /// Moves to the given frame, or to somewhere before it, and returns
+    /// the frame it ended up at.  Decoders that can't seek stay where
+    /// they are, at the start.  If this fails, the decoder has to be
+    /// opened again.
+    fn seek(&mut self, frame: u64) -> GameResult<u64> {
+        match self {
+            StreamDecoder::Wav(reader) => {
+                let frame = frame.min(u64::from(reader.duration())) as u32;
+                reader.seek(frame)?;
+                Ok(u64::from(frame))
+            }
+            StreamDecoder::Vorbis(reader, buffered) => {
+                let goal = frame.saturating_sub(VORBIS_SEEK_MARGIN);
+                // Seeking to 0 would land on the header pages.
+                if goal == 0 {
+                    return Ok(0);
+                }
+                let seek_error = |e: lewton::VorbisError| GameError::AudioError(e.to_string());
+                reader.seek_absgp_pg(goal).map_err(seek_error)?;
+                // Only the end of a page tells where in the stream the
+                // samples decoded up to it belong.
+                let mut samples = Vec::new();
+                while reader.get_last_absgp().is_none() {
+                    match reader.read_dec_packet_itl().map_err(seek_error)? {
+                        Some(packet) => samples.extend(packet),
+                        None => break,
+                    }
+                }
+                let channels = usize::from(reader.ident_hdr.audio_channels).max(1);
+                let end = reader.get_last_absgp().ok_or_else(|| {
+                    GameError::AudioError("Could not find a position to seek to".to_string())
+                })?;
+                let start = end.saturating_sub((samples.len() / channels) as u64);
+                *buffered = samples.into_iter();
+                Ok(start)
+            }
+            StreamDecoder::Other(_) => Ok(0),
+        }
+    }
+}
+
+/// Finds the granule position of the last Ogg page that has one, which
+/// for a Vorbis stream is the number of frames in it.
+fn last_granule_position(file: &SharedFile) -> Option<u64> {
+    let mut file = file.rewound();
+    let len = file.seek(SeekFrom::End(0)).ok()?;
+    let _ = file
+        .seek(SeekFrom::Start(len.saturating_sub(OGG_MAX_PAGE_LEN)))
+        .ok()?;
+    let mut tail = Vec::new();
+    let _ = file.read_to_end(&mut tail).ok()?;
+    // A page header starts with "OggS" and version 0, followed by a flag
+    // byte and then the granule position, which is -1 on pages where no
+    // packet ends.
+    tail.windows(14)
+        .rev()
+        .filter(|header| header.starts_with(b"OggS\0"))
+        .map(|header| {
+            let mut granule = [0; 8];
+            granule.copy_from_slice(&header[6..14]);
+            i64::from_le_bytes(granule)
+        })
+        .find(|&granule| granule >= 0)
+        .map(|granule| granule as u64)
+}
+
+impl Iterator for StreamDecoder {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        match self {
+            StreamDecoder::Wav(reader) => {
+                let spec = reader.spec();
+                match (spec.sample_format, spec.bits_per_sample) {
+                    (SampleFormat::Float, _) => reader
+                        .samples::<f32>()
+                        .next()
+                        .map(|s| (s.unwrap_or(0.0).clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16),
+                    (_, 8) => reader
+                        .samples::<i8>()
+                        .next()
+                        .map(|s| i16::from(s.unwrap_or(0)) * 256),
+                    (_, 24) => reader
+                        .samples::<i32>()
+                        .next()
+                        .map(|s| (s.unwrap_or(0) >> 8) as i16),
+                    _ => reader.samples::<i16>().next().map(|s| s.unwrap_or(0)),
+                }
+            }
+            // Some packets decode to no samples at all, so keep going
+            // until one that does or the end of the stream.
+            StreamDecoder::Vorbis(reader, buffered) => loop {
+                if let Some(sample) = buffered.next() {
+                    return Some(sample);
+                }
+                *buffered = reader.read_dec_packet_itl().ok()??.into_iter();
+            },
+            StreamDecoder::Other(decoder) => decoder.next(),
+        }
+    }
+}
+
+/// A decoder along with the number of samples it has produced so far.
+struct PositionedDecoder {
+    decoder: StreamDecoder,
+    position: u64,
+}
+
+impl PositionedDecoder {
+    /// Opens the file and moves to the given sample, seeking as far
+    /// as the format allows and decoding the rest of the way.
+    fn open_at(file: &SharedFile, target: u64) -> GameResult<Self> {
+        let mut decoder = StreamDecoder::open(file)?;
+        let channels = u64::from(decoder.channels()).max(1);
+        let position = match decoder.seek(target / channels) {
+            Ok(frame) => frame * channels,
+            Err(_) => {
+                decoder = StreamDecoder::open(file)?;
+                0
+            }
+        };
+        let mut opened = PositionedDecoder { decoder, position };
+        while opened.position < target && opened.next().is_some() {}
+        Ok(opened)
+    }
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.decoder.next()?;
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+/// Opens decoders positioned at the loop start on a thread of its own,
+/// so that all the audio thread has to do when the loop wraps around is
+/// take the one prepared in the meantime.
+struct LoopPreparer {
+    requests: mpsc::Sender<()>,
+    prepared: mpsc::Receiver<GameResult<PositionedDecoder>>,
+}
+
+impl LoopPreparer {
+    fn new(file: &SharedFile, start: u64) -> GameResult<Self> {
+        let (requests, requested) = mpsc::channel();
+        let (done, prepared) = mpsc::channel();
+        let file = file.clone();
+        // The thread ends once the `StreamSource` is dropped,
+        // which hangs up on it.
+        let _ = thread::Builder::new()
+            .name("ggez audio stream".to_string())
+            .spawn(move || {
+                while requested.recv().is_ok() {
+                    if done.send(PositionedDecoder::open_at(&file, start)).is_err() {
+                        break;
+                    }
+                }
+            })?;
+        requests
+            .send(())
+            .map_err(|_| GameError::AudioError("Stream loop thread stopped".to_string()))?;
+        Ok(LoopPreparer { requests, prepared })
+    }
+
+    /// Takes the prepared decoder, waiting for it if it isn't ready
+    /// yet, and starts preparing the next one.
+    fn take(&self) -> Option<PositionedDecoder> {
+        let next = self.prepared.recv().ok()?.ok()?;
+        let _ = self.requests.send(());
+        Some(next)
+    }
+}
+
+/// The part of a `StreamingSource` that lives on the audio thread.
+struct StreamSource {
+    current: PositionedDecoder,
+    channels: u16,
+    sample_rate: u32,
+    /// The loop start and end, in samples, if the stream repeats.
+    loop_points: Option<(u64, Option<u64>)>,
+    /// Prepares the decoder that takes over when the loop wraps around.
+    next_loop: Option<LoopPreparer>,
+}
+
+impl StreamSource {
+    fn new(
+        file: &SharedFile,
+        start: u64,
+        loop_points: Option<(u64, Option<u64>)>,
+    ) -> GameResult<Self> {
+        let current = PositionedDecoder::open_at(file, start)?;
+        let next_loop = match loop_points {
+            Some((loop_start, _)) => Some(LoopPreparer::new(file, loop_start)?),
+            None => None,
+        };
+        Ok(StreamSource {
+            channels: current.decoder.channels(),
+            sample_rate: current.decoder.sample_rate(),
+            current,
+            loop_points,
+            next_loop,
+        })
+    }
+
+    /// Jumps back to the loop start.  Returns `false` if that failed.
+    fn wrap_around(&mut self) -> bool {
+        match self.next_loop.as_ref().and_then(LoopPreparer::take) {
+            Some(next) => {
+                self.current = next;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Iterator for StreamSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let (start, end) = match self.loop_points {
+            Some(points) => points,
+            None => return self.current.next(),
+        };
+
+        if end.is_some_and(|end| self.current.position >= end) && !self.wrap_around() {
+            return None;
+        }
+        match self.current.next() {
+            Some(sample) => Some(sample),
+            // If we never got past the loop start, the loop is empty
+            // and wrapping around would just spin forever.
+            None if self.current.position > start && self.wrap_around() => self.current.next(),
+            None => None,
+        }
+    }
+}
+
+impl rodio::Source for StreamSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<time::Duration> {
+        None
+    }
+}
+
+/// A source of audio data that is decoded from a file while it plays,
+/// instead of being loaded into memory first like a [`Source`](struct.Source.html).
+/// Use it for long pieces of music.  It will stop playing when dropped.
+///
+/// When set to repeat it loops seamlessly, either over the whole file or
+/// between the loop points given to
+/// [`set_loop_points()`](#method.set_loop_points).
+///
+/// Files in the resources zip are only streamed bit by bit if they are
+/// stored in it uncompressed; compressed ones are unpacked into memory
+/// when the source is created.
+pub struct StreamingSource {
+    sink: rodio::Sink,
+    state: SourceState,
+    file: SharedFile,
+    channels: u16,
+    sample_rate: u32,
+    duration: Option<time::Duration>,
+    loop_start: u64,
+    loop_end: Option<u64>,
+}
+
+impl StreamingSource {
+    /// Create a new `StreamingSource` from the given file.
+    pub fn new<P: AsRef<path::Path>>(context: &mut Context, path: P) -> GameResult<Self> {
+        let file = context.filesystem.open_send(path)?;
+        StreamingSource::from_shared_file(context, SharedFile::new(Box::new(file)))
+    }
+
+    /// Creates a new `StreamingSource` that decodes the given reader,
+    /// such as a `std::fs::File` or a `std::io::Cursor`.
+    pub fn from_reader<R>(context: &mut Context, reader: R) -> GameResult<Self>
+    where
+        R: Read + Seek + Send + 'static,
+    {
+        StreamingSource::from_shared_file(context, SharedFile::new(Box::new(reader)))
+    }
+
+    fn from_shared_file(context: &mut Context, file: SharedFile) -> GameResult<Self> {
+        let probe = StreamDecoder::open(&file).map_err(|_| {
+            GameError::AudioError("Could not decode the given audio data".to_string())
+        })?;
+        let state = SourceState::new(context);
+        Ok(StreamingSource {
+            sink: state.new_sink(context),
+            state,
+            channels: probe.channels(),
+            sample_rate: probe.sample_rate(),
+            duration: probe.duration(&file),
+            file,
+            loop_start: 0,
+            loop_end: None,
+        })
+    }
+
+    /// Returns the number of channels of the sound.
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Returns the sample rate of the sound.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Sets the points the source loops between when it is set to repeat,
+    /// counted in samples per channel from the start of the file.
+    /// `None` as the end means the end of the file.
+    ///
+    /// Takes effect on next [`play()`](trait.SoundSource.html#method.play).
+    pub fn set_loop_points(&mut self, start: u64, end: Option<u64>) -> GameResult {
+        if end.is_some_and(|end| end <= start) {
+            return Err(GameError::AudioError(format!(
+                "Loop end {:?} must come after loop start {}",
+                end, start
+            )));
+        }
+        self.loop_start = start;
+        self.loop_end = end;
+        Ok(())
+    }
+
+    /// Gets the loop start and end set by
+    /// [`set_loop_points()`](#method.set_loop_points).
+    pub fn loop_points(&self) -> (u64, Option<u64>) {
+        (self.loop_start, self.loop_end)
+    }
+
+    /// Appends the sound to the sink, starting at the given sample.
+    fn append_from(&self, start: u64) -> GameResult {
+        let channels = u64::from(self.channels);
+        let loop_points = if self.state.repeat {
+            Some((
+                self.loop_start * channels,
+                self.loop_end.map(|end| end * channels),
+            ))
+        } else {
+            None
+        };
+
+        let sound = StreamSource::new(&self.file, start, loop_points)?;
+        self.sink.append(self.state.prepare(sound));
+        Ok(())
+    }
+}
+
+impl SoundSource for StreamingSource {
+    fn play_later(&self) -> GameResult {
+        self.append_from(0)
+    }
+
+    /// Starts playing at the given position.
+    ///
+    /// WAV and OGG files are seeked in directly.  Other formats are decoded
+    /// from their start up to `pos` before this returns, so starting far
+    /// into a long file can take a moment.
+    fn play_from(&mut self, ctx: &Context, pos: time::Duration) -> GameResult {
+        self.stop(ctx)?;
+        self.state.set_elapsed(pos);
+        let frame = (pos.as_secs_f64() * f64::from(self.sample_rate)) as u64;
+        self.append_from(frame * u64::from(self.channels))
+    }
+
+    /// Returns the length of the file if its format records it,
+    /// which WAV and FLAC files do but OGG files don't.
+    fn duration(&self) -> Option<time::Duration> {
+        self.duration
+    }
+
+    impl_sink_source_methods!();
+}
+
+impl fmt::Debug for StreamingSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Streaming audio source: {:p}>", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A mono 16-bit WAV file whose samples count up from 0.
+    fn counting_wav(len: i16) -> Box<dyn StreamFile> {
+        let samples: Vec<i16> = (0..len).collect();
+        let mut bytes = Vec::new();
+        super::super::wav::write(&mut bytes, 1, 8000, &samples).unwrap();
+        Box::new(io::Cursor::new(bytes))
+    }
+
+    #[test]
+    fn streams_without_looping() {
+        let file = SharedFile::new(counting_wav(10));
+        let stream = StreamSource::new(&file, 4, None).unwrap();
+        assert_eq!(vec![4, 5, 6, 7, 8, 9], stream.collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn loops_between_loop_points() {
+        let file = SharedFile::new(counting_wav(10));
+        let stream = StreamSource::new(&file, 0, Some((3, Some(6)))).unwrap();
+        assert_eq!(
+            vec![0, 1, 2, 3, 4, 5, 3, 4, 5, 3, 4],
+            stream.take(11).collect::<Vec<_>>()
+        );
+
+        let stream = StreamSource::new(&file, 7, Some((8, None))).unwrap();
+        assert_eq!(vec![7, 8, 9, 8, 9, 8], stream.take(6).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn empty_loop_ends() {
+        let file = SharedFile::new(counting_wav(4));
+        let stream = StreamSource::new(&file, 0, Some((6, None))).unwrap();
+        assert_eq!(vec![0, 1, 2, 3], stream.collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn seeks_in_ogg_files() {
+        let path = path::Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/sound.ogg");
+        let file = SharedFile::new(Box::new(std::fs::File::open(path).unwrap()));
+        let all: Vec<i16> = StreamSource::new(&file, 0, None).unwrap().collect();
+        let start = all.len() as u64 / 2;
+        assert!(start > VORBIS_SEEK_MARGIN * 2);
+
+        let stream = StreamSource::new(&file, start, None).unwrap();
+        assert_eq!(all[start as usize..], stream.collect::<Vec<_>>()[..]);
+    }
+
+    #[test]
+    fn reads_the_length_of_ogg_files() {
+        let path = path::Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/sound.ogg");
+        let file = SharedFile::new(Box::new(std::fs::File::open(path).unwrap()));
+        let decoder = StreamDecoder::open(&file).unwrap();
+        let channels = usize::from(decoder.channels());
+        let rate = f64::from(decoder.sample_rate());
+        let duration = decoder.duration(&file).unwrap();
+
+        let frames = StreamDecoder::open(&file).unwrap().count() / channels;
+        assert_eq!(
+            frames as u64,
+            (duration.as_secs_f64() * rate).round() as u64
+        );
+    }
+}
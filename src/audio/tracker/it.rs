@@ -0,0 +1,421 @@
+//! Loading Impulse Tracker IT files.
+
+use super::module::*;
+use super::s3m;
+use super::TrackerFormat;
+use crate::error::GameResult;
+
+pub(crate) fn load(data: &[u8]) -> GameResult<Module> {
+    let bytes = Bytes(data);
+    let title = bytes.string(4, 26)?;
+    let order_count = usize::from(bytes.u16_le(32)?);
+    let instrument_count = usize::from(bytes.u16_le(34)?);
+    let sample_count = usize::from(bytes.u16_le(36)?);
+    let pattern_count = usize::from(bytes.u16_le(38)?);
+    let compatible_version = bytes.u16_le(42)?;
+    let flags = bytes.u16_le(44)?;
+    let global_volume = bytes.u8(48)?;
+    let speed = bytes.u8(50)?;
+    let tempo = bytes.u8(51)?;
+    let channel_panning = bytes.slice(64, 64)?;
+    let order_table = bytes.slice(192, order_count)?;
+
+    let pointers = 192 + order_count;
+    let pointer = |i: usize| -> GameResult<usize> { Ok(bytes.u32_le(pointers + i * 4)? as usize) };
+
+    let use_instruments = flags & 4 != 0;
+    let mut instruments = Vec::new();
+    if use_instruments {
+        if compatible_version < 0x200 {
+            return Err(format_error(
+                "instruments from Impulse Tracker before 2.0 aren't supported",
+            ));
+        }
+        for i in 0..instrument_count {
+            instruments.push(read_instrument(bytes, pointer(i)?)?);
+        }
+    }
+    let mut samples = Vec::with_capacity(sample_count);
+    for i in 0..sample_count {
+        samples.push(read_sample(bytes, pointer(instrument_count + i)?)?);
+    }
+    let mut patterns = Vec::with_capacity(pattern_count);
+    let mut channels = 1;
+    for i in 0..pattern_count {
+        let offset = pointer(instrument_count + sample_count + i)?;
+        let (pattern, used) = read_pattern(bytes, offset)?;
+        patterns.push(pattern);
+        channels = channels.max(used);
+    }
+    // Patterns were read with every channel; drop the unused ones.
+    for pattern in patterns.iter_mut() {
+        pattern.cells = pattern
+            .cells
+            .chunks(64)
+            .flat_map(|row| row[..channels].iter().copied())
+            .collect();
+    }
+
+    let stereo = flags & 1 != 0;
+    let panning = channel_panning[..channels]
+        .iter()
+        .map(|&pan| match pan & 0x7F {
+            pan if stereo && pan <= 64 => (u16::from(pan) * 4).min(255) as u8,
+            _ => 0x80,
+        })
+        .collect();
+    Ok(Module {
+        title,
+        format: TrackerFormat::It,
+        channels,
+        orders: order_table
+            .iter()
+            .take_while(|&&o| o != 255)
+            .filter(|&&o| o != 254)
+            .map(|&o| usize::from(o))
+            .collect(),
+        restart: 0,
+        patterns,
+        samples,
+        instruments,
+        speed: if speed == 0 { 6 } else { speed },
+        tempo: if tempo < 32 { 125 } else { tempo },
+        global_volume: f32::from(global_volume.min(128)) / 128.0,
+        panning,
+        linear: flags & 8 != 0,
+    })
+}
+
+fn read_instrument(bytes: Bytes, offset: usize) -> GameResult<Instrument> {
+    if bytes.slice(offset, 4)? != b"IMPI" {
+        return Err(format_error("bad instrument header"));
+    }
+    let fadeout = bytes.u16_le(offset + 20)?;
+    let panning = bytes.u8(offset + 25)?;
+    let table = bytes.slice(offset + 64, NOTE_COUNT * 2)?;
+    let keymap = table
+        .chunks_exact(2)
+        .map(|entry| (entry[0].min(NOTE_COUNT as u8 - 1), u16::from(entry[1])))
+        .collect();
+
+    let envelope = offset + 304;
+    let envelope_flags = bytes.u8(envelope)?;
+    let point_count = usize::from(bytes.u8(envelope + 1)?).min(25);
+    let range = |start: usize| -> GameResult<(usize, usize)> {
+        Ok((
+            usize::from(bytes.u8(envelope + start)?),
+            usize::from(bytes.u8(envelope + start + 1)?),
+        ))
+    };
+    let volume_envelope = if envelope_flags & 1 != 0 {
+        let points = bytes
+            .slice(envelope + 6, point_count * 3)?
+            .chunks_exact(3)
+            .map(|p| (u16::from_le_bytes([p[1], p[2]]), p[0].min(64)))
+            .collect();
+        Envelope::new(
+            points,
+            Some(range(4)?).filter(|_| envelope_flags & 4 != 0),
+            Some(range(2)?).filter(|_| envelope_flags & 2 != 0),
+        )
+    } else {
+        None
+    };
+
+    Ok(Instrument {
+        keymap,
+        volume_envelope,
+        fadeout: f32::from(fadeout) / 1024.0,
+        panning: if panning & 0x80 == 0 {
+            Some((u16::from(panning) * 4).min(255) as u8)
+        } else {
+            None
+        },
+    })
+}
+
+fn read_sample(bytes: Bytes, offset: usize) -> GameResult<Sample> {
+    if bytes.slice(offset, 4)? != b"IMPS" {
+        return Err(format_error("bad sample header"));
+    }
+    let global_volume = bytes.u8(offset + 17)?;
+    let flags = bytes.u8(offset + 18)?;
+    let volume = bytes.u8(offset + 19)?;
+    let convert = bytes.u8(offset + 46)?;
+    let panning = bytes.u8(offset + 47)?;
+    let length = bytes.u32_le(offset + 48)? as usize;
+    let loop_start = bytes.u32_le(offset + 52)? as usize;
+    let loop_end = bytes.u32_le(offset + 56)? as usize;
+    let rate = bytes.u32_le(offset + 60)?;
+    let sustain_start = bytes.u32_le(offset + 64)? as usize;
+    let sustain_end = bytes.u32_le(offset + 68)? as usize;
+    let data_offset = bytes.u32_le(offset + 72)? as usize;
+
+    let sixteen_bit = flags & 2 != 0;
+    let signed = convert & 1 != 0;
+    let data = if flags & 1 == 0 {
+        Vec::new()
+    } else if flags & 8 != 0 {
+        let it215 = convert & 4 != 0;
+        let raw = bytes.slice_lenient(data_offset, usize::MAX);
+        if sixteen_bit {
+            decompress(raw, length, it215, 16)
+        } else {
+            decompress(raw, length, it215, 8)
+        }
+    } else if sixteen_bit {
+        samples_16(bytes.slice_lenient(data_offset, length * 2), signed)
+    } else if signed {
+        samples_i8(bytes.slice_lenient(data_offset, length))
+    } else {
+        samples_u8(bytes.slice_lenient(data_offset, length))
+    };
+
+    Ok(Sample {
+        looping: if flags & 0x10 != 0 {
+            Loop::new(loop_start, loop_end, flags & 0x40 != 0, data.len())
+        } else {
+            None
+        },
+        sustain_loop: if flags & 0x20 != 0 {
+            Loop::new(sustain_start, sustain_end, flags & 0x80 != 0, data.len())
+        } else {
+            None
+        },
+        data,
+        volume: volume.min(64),
+        global_volume: f32::from(global_volume.min(64)) / 64.0,
+        panning: if panning & 0x80 != 0 {
+            Some((u16::from(panning & 0x7F) * 4).min(255) as u8)
+        } else {
+            None
+        },
+        base_rate: rate as f32,
+    })
+}
+
+/// Reads a pattern with all 64 channels, returning it along
+/// with how many channels are actually used.
+fn read_pattern(bytes: Bytes, offset: usize) -> GameResult<(Pattern, usize)> {
+    if offset == 0 {
+        return Ok((Pattern::new(64, 64), 0));
+    }
+    let length = usize::from(bytes.u16_le(offset)?);
+    let rows = usize::from(bytes.u16_le(offset + 2)?);
+    let mut pattern = Pattern::new(rows, 64);
+    let mut data = bytes.slice_lenient(offset + 8, length).iter().copied();
+    let mut next = || data.next().unwrap_or(0);
+
+    let mut masks = [0u8; 64];
+    let mut last = [Cell::default(); 64];
+    let mut used = 0;
+    let mut row = 0;
+    while row < rows {
+        let channel_byte = next();
+        if channel_byte == 0 {
+            row += 1;
+            continue;
+        }
+        let channel = usize::from((channel_byte - 1) & 63);
+        if channel_byte & 0x80 != 0 {
+            masks[channel] = next();
+        }
+        let mask = masks[channel];
+        let last = &mut last[channel];
+        let mut cell = Cell::default();
+        if mask & 1 != 0 {
+            last.note = match next() {
+                note @ 0..=119 => Note::On(note),
+                255 => Note::Off,
+                254 => Note::Cut,
+                _ => Note::Fade,
+            };
+        }
+        if mask & 2 != 0 {
+            last.instrument = next();
+        }
+        if mask & 4 != 0 {
+            last.volume = read_volume(next());
+        }
+        if mask & 8 != 0 {
+            let command = next();
+            let param = next();
+            last.effect = read_effect(command, param);
+        }
+        if mask & 0x11 != 0 {
+            cell.note = last.note;
+        }
+        if mask & 0x22 != 0 {
+            cell.instrument = last.instrument;
+        }
+        if mask & 0x44 != 0 {
+            cell.volume = last.volume;
+        }
+        if mask & 0x88 != 0 {
+            cell.effect = last.effect;
+        }
+        pattern.cells[row * 64 + channel] = cell;
+        used = used.max(channel + 1);
+    }
+    Ok((pattern, used))
+}
+
+fn read_volume(volume: u8) -> VolumeCommand {
+    const PORTAMENTO: [u16; 10] = [0, 1, 4, 8, 16, 32, 64, 96, 128, 255];
+    match volume {
+        0..=64 => VolumeCommand::Set(volume),
+        65..=74 => VolumeCommand::Slide(VolumeSlide::FineUp(volume - 65)),
+        75..=84 => VolumeCommand::Slide(VolumeSlide::FineDown(volume - 75)),
+        85..=94 => VolumeCommand::Slide(VolumeSlide::Up(volume - 85)),
+        95..=104 => VolumeCommand::Slide(VolumeSlide::Down(volume - 95)),
+        128..=192 => VolumeCommand::Panning((u16::from(volume - 128) * 4).min(255) as u8),
+        193..=202 => VolumeCommand::TonePortamento(PORTAMENTO[usize::from(volume - 193)] * 4),
+        _ => VolumeCommand::None,
+    }
+}
+
+fn read_effect(command: u8, param: u8) -> Effect {
+    match command {
+        // Unlike in S3M, the row to break to is in hex.
+        3 => Effect::PatternBreak(param),
+        // V goes up to 128.
+        22 => Effect::SetGlobalVolume(param.min(128) / 2),
+        // X is the full range already.
+        24 => Effect::SetPanning(param),
+        _ => s3m::read_effect(command, param),
+    }
+}
+
+/// Reads bits from the start of a block of compressed sample data.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, bits: u32) -> u32 {
+        let mut value = 0;
+        for i in 0..bits {
+            let byte = self.data.get(self.position / 8).copied().unwrap_or(0);
+            let bit = (byte >> (self.position % 8)) & 1;
+            value |= u32::from(bit) << i;
+            self.position += 1;
+        }
+        value
+    }
+}
+
+/// Decompresses IT 2.14 or 2.15 sample data of the given bit depth.
+fn decompress(mut raw: &[u8], length: usize, it215: bool, depth: u32) -> Vec<f32> {
+    let block_length = if depth == 16 { 0x4000 } else { 0x8000 };
+    let (top_width, width_bits) = if depth == 16 { (17, 4) } else { (9, 3) };
+    let scale = (1u32 << (depth - 1)) as f32;
+    let mut samples = Vec::with_capacity(length);
+
+    while samples.len() < length && raw.len() >= 2 {
+        let packed = usize::from(u16::from_le_bytes([raw[0], raw[1]]));
+        let block = raw.get(2..2 + packed).unwrap_or(&raw[2..]);
+        raw = raw.get(2 + packed..).unwrap_or(&[]);
+
+        let count = (length - samples.len()).min(block_length);
+        let mut bits = BitReader {
+            data: block,
+            position: 0,
+        };
+        let mut width = top_width;
+        let (mut d1, mut d2) = (0i32, 0i32);
+        let mut decoded = 0;
+        while decoded < count {
+            if bits.position > block.len() * 8 {
+                break;
+            }
+            let value = bits.read(width);
+            if width < 7 {
+                // A value of just the top bit changes the width.
+                if value == 1 << (width - 1) {
+                    let new = bits.read(width_bits) + 1;
+                    width = if new < width { new } else { new + 1 };
+                    continue;
+                }
+            } else if width < top_width {
+                let border = (((1u32 << depth) - 1) >> (top_width - width)) - (depth / 2);
+                if value > border && value <= border + depth {
+                    let new = value - border;
+                    width = if new < width { new } else { new + 1 };
+                    continue;
+                }
+            } else if width == top_width {
+                if value & (1 << depth) != 0 {
+                    width = (value + 1) & 0xFF;
+                    continue;
+                }
+            } else {
+                break;
+            }
+            // Sign-extend the value to the full depth.
+            let shift = 32 - width.min(depth);
+            let delta = ((value << shift) as i32) >> shift;
+            d1 = d1.wrapping_add(delta);
+            d2 = d2.wrapping_add(d1);
+            let value = if it215 { d2 } else { d1 };
+            // Wrap around like the original fixed-size integers do.
+            let value = (value << (32 - depth)) >> (32 - depth);
+            samples.push(value as f32 / scale);
+            decoded += 1;
+        }
+        samples.resize(samples.len() - decoded + count, 0.0);
+    }
+    samples.resize(length, 0.0);
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_unpack_with_masks() {
+        let mut file = vec![0u8; 8];
+        // Channel 3 with a note, instrument and volume, then the same
+        // channel reusing its last note in the next row, then an end row.
+        let data = [0x83, 0x07, 60, 2, 30, 0, 0x83, 0x10, 0, 0];
+        file[..2].copy_from_slice(&(data.len() as u16).to_le_bytes());
+        file[2..4].copy_from_slice(&3u16.to_le_bytes());
+        file.extend(data);
+        let mut padded = vec![0u8; 4];
+        padded.extend(file);
+
+        let (pattern, used) = read_pattern(Bytes(&padded), 4).unwrap();
+        assert_eq!(3, used);
+        assert_eq!(3, pattern.rows);
+        let cell = pattern.cells[2];
+        assert_eq!(Note::On(60), cell.note);
+        assert_eq!(2, cell.instrument);
+        assert_eq!(VolumeCommand::Set(30), cell.volume);
+        let cell = pattern.cells[64 + 2];
+        assert_eq!(Note::On(60), cell.note);
+        assert_eq!(0, cell.instrument);
+    }
+
+    #[test]
+    fn compressed_samples_decode() {
+        // 8-bit deltas of 1, 1 and -2, nine bits each, least
+        // significant bit first.  The ninth bit is only set
+        // to change the width.
+        let values = [1u32, 1, 0xFE];
+        let mut block = vec![0u8; 4];
+        for (i, value) in values.iter().enumerate() {
+            for bit in 0..9 {
+                let position = i * 9 + bit;
+                block[position / 8] |= (((value >> bit) & 1) as u8) << (position % 8);
+            }
+        }
+        let mut raw = (block.len() as u16).to_le_bytes().to_vec();
+        raw.extend(block);
+        let decoded = decompress(&raw, 3, false, 8);
+        assert_eq!(vec![1.0 / 128.0, 2.0 / 128.0, 0.0], decoded);
+        // IT 2.15 integrates twice.
+        let decoded = decompress(&raw, 3, true, 8);
+        assert_eq!(vec![1.0 / 128.0, 3.0 / 128.0, 3.0 / 128.0], decoded);
+    }
+}
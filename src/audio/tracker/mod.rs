@@ -0,0 +1,233 @@
+//! Playing tracker modules: songs made of patterns of notes played
+//! with small recorded samples, as MOD, S3M, XM and IT files.
+
+use std::fmt;
+use std::path;
+use std::sync::Arc;
+use std::time;
+
+use super::{SoundData, SoundSource, SourceState};
+use crate::context::Context;
+use crate::error::GameResult;
+
+mod it;
+mod module;
+mod player;
+mod protracker;
+mod s3m;
+mod xm;
+
+use self::module::Module;
+use self::player::{Controls, Player};
+
+/// The file formats a [`TrackerSource`](struct.TrackerSource.html) can play.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TrackerFormat {
+    /// ProTracker MOD, and the Soundtracker and multichannel variants of it.
+    Mod,
+    /// Scream Tracker 3 S3M.
+    S3m,
+    /// FastTracker 2 XM.
+    Xm,
+    /// Impulse Tracker IT.
+    It,
+}
+
+/// Where a [`TrackerSource`](struct.TrackerSource.html) is in its song.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TrackerPosition {
+    /// The index into the song's order list.
+    pub order: usize,
+    /// The pattern played at that order.
+    pub pattern: usize,
+    /// The row of the pattern.
+    pub row: usize,
+}
+
+/// A tracker module, played by mixing its samples as it goes.
+/// It will stop playing when dropped.
+///
+/// MOD, S3M, XM and IT files are supported; the format is worked out
+/// from the data.  Modules are loaded like any other `SoundData`, so
+/// they can come from the resources zip.
+///
+/// Besides the usual `SoundSource` controls, you can follow which row
+/// of which pattern is playing to sync the game to the music, mute
+/// channels and speed the song up or down.  Playing the song again,
+/// or from a position, resets the effects the song applied itself,
+/// such as tempo changes, but keeps the muted channels and the tempo
+/// factor.
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # use ggez::audio::SoundSource;
+/// # fn t(ctx: &mut Context) -> GameResult {
+/// let mut song = audio::TrackerSource::new(ctx, "/song.xm")?;
+/// song.on_row(|pos| {
+///     if pos.row % 16 == 0 {
+///         println!("Bar {} of order {}", pos.row / 16, pos.order);
+///     }
+/// });
+/// song.set_channel_muted(3, true);
+/// song.set_tempo_factor(1.25);
+/// song.play(ctx)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TrackerSource {
+    sink: rodio::Sink,
+    state: SourceState,
+    module: Arc<Module>,
+    controls: Arc<Controls>,
+    sample_rate: u32,
+    /// How long the song lasts without repeating, at normal tempo.
+    duration: Option<time::Duration>,
+}
+
+impl TrackerSource {
+    /// Creates a new `TrackerSource` from the module at the given path.
+    pub fn new<P: AsRef<path::Path>>(context: &mut Context, path: P) -> GameResult<Self> {
+        let data = SoundData::new(context, path)?;
+        TrackerSource::from_data(context, data)
+    }
+
+    /// Creates a new `TrackerSource` from a module that was loaded
+    /// into a `SoundData`.
+    pub fn from_data(context: &mut Context, data: SoundData) -> GameResult<Self> {
+        let module = Arc::new(Module::parse(data.as_ref())?);
+        let state = SourceState::new(context);
+        Ok(TrackerSource {
+            sink: state.new_sink(context),
+            state,
+            controls: Arc::new(Controls::new(&module)),
+            sample_rate: context.audio_context.mixer().sample_rate(),
+            duration: Player::song_length(module.clone()),
+            module,
+        })
+    }
+
+    /// Returns the title of the song.
+    pub fn title(&self) -> &str {
+        &self.module.title
+    }
+
+    /// Returns the format the song was loaded from.
+    pub fn format(&self) -> TrackerFormat {
+        self.module.format
+    }
+
+    /// Returns the number of channels of the song.
+    pub fn channels(&self) -> usize {
+        self.module.channels
+    }
+
+    /// Returns the number of entries in the song's order list.
+    pub fn orders(&self) -> usize {
+        self.module.orders.len()
+    }
+
+    /// Returns the row that was last started.
+    pub fn position(&self) -> TrackerPosition {
+        self.controls.position()
+    }
+
+    /// Sets a function to call whenever a new row starts, replacing
+    /// any previous one.
+    ///
+    /// The function is called on the audio thread, slightly ahead
+    /// of the row being heard, so it should be quick; sending the
+    /// position over a channel is a good way to use it.
+    pub fn on_row<F>(&mut self, f: F)
+    where
+        F: FnMut(TrackerPosition) + Send + 'static,
+    {
+        self.controls.set_callback(Some(Box::new(f)));
+    }
+
+    /// Removes the function set with [`on_row()`](#method.on_row).
+    pub fn remove_row_callback(&mut self) {
+        self.controls.set_callback(None);
+    }
+
+    /// Mutes or unmutes a channel.  Muted channels keep playing
+    /// silently, so they come back in time.
+    pub fn set_channel_muted(&mut self, channel: usize, muted: bool) {
+        self.controls.set_muted(channel, muted)
+    }
+
+    /// Returns whether a channel is muted.
+    pub fn channel_muted(&self, channel: usize) -> bool {
+        self.controls.muted(channel)
+    }
+
+    /// Sets what the song's tempo is multiplied by, to speed it up or
+    /// slow it down without changing its pitch.  The default is `1.0`.
+    pub fn set_tempo_factor(&mut self, factor: f32) {
+        self.controls.set_tempo_factor(factor)
+    }
+
+    /// Returns what the song's tempo is multiplied by.
+    pub fn tempo_factor(&self) -> f32 {
+        self.controls.tempo_factor()
+    }
+
+    /// Returns the song's current tempo, in beats per minute,
+    /// before the tempo factor.
+    pub fn tempo(&self) -> usize {
+        self.controls.tempo()
+    }
+
+    /// Returns the song's current speed, in ticks per row.
+    pub fn speed(&self) -> usize {
+        self.controls.speed()
+    }
+
+    /// Starts playing the song from the start of the given order.
+    pub fn play_from_order(&mut self, ctx: &Context, order: usize) -> GameResult {
+        self.stop(ctx)?;
+        let player = self.player(order);
+        self.sink.append(self.state.prepare(player));
+        Ok(())
+    }
+
+    fn player(&self, order: usize) -> Player {
+        Player::new(
+            self.module.clone(),
+            self.controls.clone(),
+            self.sample_rate,
+            self.state.repeat,
+            order,
+        )
+    }
+}
+
+impl SoundSource for TrackerSource {
+    fn play_later(&self) -> GameResult {
+        self.sink.append(self.state.prepare(self.player(0)));
+        Ok(())
+    }
+
+    fn play_from(&mut self, ctx: &Context, pos: time::Duration) -> GameResult {
+        self.stop(ctx)?;
+        self.state.set_elapsed(pos);
+        let mut player = self.player(0);
+        player.fast_forward(pos);
+        self.sink.append(self.state.prepare(player));
+        Ok(())
+    }
+
+    /// Returns how long the song plays for without repeating, at the
+    /// current tempo factor, or `None` if it loops forever by itself.
+    fn duration(&self) -> Option<time::Duration> {
+        self.duration
+            .map(|d| d.div_f32(self.controls.tempo_factor()))
+    }
+
+    impl_sink_source_methods!();
+}
+
+impl fmt::Debug for TrackerSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Tracker audio source: {:p}>", self)
+    }
+}
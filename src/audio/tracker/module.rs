@@ -0,0 +1,476 @@
+//! The song data shared by every tracker format, and reading it from bytes.
+
+use crate::error::{GameError, GameResult};
+
+use super::TrackerFormat;
+
+/// The note every sample plays at its own sample rate.
+pub(crate) const BASE_NOTE: u8 = 60;
+
+/// Pitches are kept as Amiga periods times four, as in S3M and IT.
+/// This is the period of `BASE_NOTE` for a sample playing at 8363 Hz.
+const BASE_PERIOD: f32 = 1712.0;
+
+/// The highest note of every format.
+pub(crate) const NOTE_COUNT: usize = 120;
+
+/// A whole song.
+#[derive(Debug, Clone)]
+pub(crate) struct Module {
+    pub title: String,
+    pub format: TrackerFormat,
+    pub channels: usize,
+    /// The patterns played in order, with the markers skipped
+    /// over and the end marker and everything after removed.
+    pub orders: Vec<usize>,
+    /// Where playback goes when the end of the orders is reached.
+    pub restart: usize,
+    pub patterns: Vec<Pattern>,
+    pub samples: Vec<Sample>,
+    /// Empty if instrument numbers in the patterns pick samples directly.
+    pub instruments: Vec<Instrument>,
+    pub speed: u8,
+    pub tempo: u8,
+    /// From `0.0` to `1.0`.
+    pub global_volume: f32,
+    /// The starting panning of each channel, from `0` for left to `255`.
+    pub panning: Vec<u8>,
+    /// Whether pitch slides are in fractions of a semitone
+    /// rather than in periods.
+    pub linear: bool,
+}
+
+impl Module {
+    /// Parses a module in any of the supported formats.
+    pub(crate) fn parse(data: &[u8]) -> GameResult<Module> {
+        let module = if data.starts_with(b"Extended Module: ") {
+            super::xm::load(data)?
+        } else if data.starts_with(b"IMPM") {
+            super::it::load(data)?
+        } else if data.get(44..48) == Some(b"SCRM") {
+            super::s3m::load(data)?
+        } else {
+            super::protracker::load(data)?
+        };
+        if module.orders.is_empty() || module.channels == 0 {
+            return Err(format_error("the module has nothing to play"));
+        }
+        Ok(module)
+    }
+
+    /// Returns the pattern played at the given order, if there is one.
+    pub(crate) fn pattern_at(&self, order: usize) -> Option<&Pattern> {
+        self.orders
+            .get(order)
+            .and_then(|&pattern| self.patterns.get(pattern))
+    }
+}
+
+/// A grid of cells, one row after the other, with a cell for each channel.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Pattern {
+    pub rows: usize,
+    pub cells: Vec<Cell>,
+}
+
+impl Pattern {
+    /// Creates an empty pattern.
+    pub(crate) fn new(rows: usize, channels: usize) -> Self {
+        Pattern {
+            rows,
+            cells: vec![Cell::default(); rows * channels],
+        }
+    }
+
+    /// Returns the cell for a channel in a row, if there is one.
+    pub(crate) fn cell(&self, row: usize, channel: usize, channels: usize) -> Option<&Cell> {
+        self.cells.get(row * channels + channel)
+    }
+}
+
+/// What a channel is told to do on a row.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct Cell {
+    pub note: Note,
+    /// Starting from `1`, or `0` for none.
+    pub instrument: u8,
+    pub volume: VolumeCommand,
+    pub effect: Effect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum Note {
+    #[default]
+    None,
+    /// A note to play, from `0` for C-0.
+    On(u8),
+    /// Releases the note, letting its envelope finish.
+    Off,
+    /// Stops the note right away.
+    Cut,
+    /// Starts fading out the note.
+    Fade,
+}
+
+/// A volume slide.  Slides with a parameter of zero repeat the
+/// channel's last slide in most formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum VolumeSlide {
+    /// Slides up on every tick but the first.
+    Up(u8),
+    /// Slides down on every tick but the first.
+    Down(u8),
+    /// Slides up once, on the first tick.
+    FineUp(u8),
+    /// Slides down once, on the first tick.
+    FineDown(u8),
+    /// Repeats the last slide.
+    Memory,
+}
+
+impl VolumeSlide {
+    /// Decodes the `Axy` volume slide of MOD and XM,
+    /// where `x` slides up and `y` slides down.
+    pub(crate) fn from_xy(param: u8) -> Self {
+        match (param >> 4, param & 0x0F) {
+            (0, 0) => VolumeSlide::Memory,
+            (0, down) => VolumeSlide::Down(down),
+            (up, _) => VolumeSlide::Up(up),
+        }
+    }
+
+    /// Decodes the `Dxy` volume slide of S3M and IT,
+    /// which also has fine slides.
+    pub(crate) fn from_dxy(param: u8) -> Self {
+        match (param >> 4, param & 0x0F) {
+            (0, 0) => VolumeSlide::Memory,
+            (0xF, 0) => VolumeSlide::Up(0xF),
+            (up, 0xF) if up != 0 => VolumeSlide::FineUp(up),
+            (0xF, down) => VolumeSlide::FineDown(down),
+            (0, down) => VolumeSlide::Down(down),
+            (up, _) => VolumeSlide::Up(up),
+        }
+    }
+}
+
+/// The command in the volume column of S3M, XM and IT patterns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum VolumeCommand {
+    #[default]
+    None,
+    /// Sets the volume, from `0` to `64`.
+    Set(u8),
+    Slide(VolumeSlide),
+    /// Sets the panning, from `0` for left to `255`.
+    Panning(u8),
+    /// Slides towards the note at the given speed, in pitch units.
+    TonePortamento(u16),
+}
+
+/// An effect in the effect column, whatever format it came from.  Pitch
+/// amounts are in pitch units: Amiga periods times four, or 64ths of
+/// a semitone in modules with linear slides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum Effect {
+    #[default]
+    None,
+    Arpeggio(u8, u8),
+    /// Slides the pitch on every tick but the first.  Zero repeats the
+    /// last slide.
+    PortamentoUp(u16),
+    PortamentoDown(u16),
+    /// Slides the pitch once, on the first tick.
+    FinePortamentoUp(u16),
+    FinePortamentoDown(u16),
+    /// Slides towards the note at the given speed.  Zero keeps going
+    /// at the last speed.
+    TonePortamento(u16),
+    /// Zero keeps the last speed or depth.
+    Vibrato {
+        speed: u8,
+        depth: u8,
+    },
+    TonePortamentoVolumeSlide(VolumeSlide),
+    VibratoVolumeSlide(VolumeSlide),
+    Tremolo {
+        speed: u8,
+        depth: u8,
+    },
+    /// From `0` for left to `255`.
+    SetPanning(u8),
+    /// Starts the note this many sample frames in.  Zero repeats the
+    /// last offset.
+    SampleOffset(u32),
+    VolumeSlide(VolumeSlide),
+    PositionJump(u8),
+    SetVolume(u8),
+    PatternBreak(u8),
+    SetSpeed(u8),
+    SetTempo(u8),
+    /// From `0` to `64`.
+    SetGlobalVolume(u8),
+    GlobalVolumeSlide(VolumeSlide),
+    /// Zero marks the start of the loop, anything else loops
+    /// back to it that many times.
+    PatternLoop(u8),
+    NoteCut(u8),
+    NoteDelay(u8),
+    Retrigger(u8),
+    PatternDelay(u8),
+    KeyOff(u8),
+}
+
+/// A section of a sample that repeats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Loop {
+    pub start: usize,
+    pub end: usize,
+    pub ping_pong: bool,
+}
+
+impl Loop {
+    /// Returns a loop if the given section is long enough to be one.
+    pub(crate) fn new(start: usize, end: usize, ping_pong: bool, len: usize) -> Option<Self> {
+        let end = end.min(len);
+        if end > start + 1 {
+            Some(Loop {
+                start,
+                end,
+                ping_pong,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// A recorded sound the song plays notes with.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Sample {
+    /// Mono samples from `-1.0` to `1.0`.
+    pub data: Vec<f32>,
+    pub looping: Option<Loop>,
+    /// A loop that is used while the note is held, instead of `looping`.
+    pub sustain_loop: Option<Loop>,
+    /// From `0` to `64`.
+    pub volume: u8,
+    /// From `0.0` to `1.0`.
+    pub global_volume: f32,
+    pub panning: Option<u8>,
+    /// The rate the sample plays at for `BASE_NOTE`.
+    pub base_rate: f32,
+}
+
+/// A sample for every note, plus an envelope.
+#[derive(Debug, Clone)]
+pub(crate) struct Instrument {
+    /// For every note, the note actually played and the
+    /// sample to play it with, starting from `1`, or `0` for none.
+    pub keymap: Vec<(u8, u16)>,
+    pub volume_envelope: Option<Envelope>,
+    /// How much the volume goes down every tick after the note is
+    /// released, from `0.0` to `1.0`.
+    pub fadeout: f32,
+    pub panning: Option<u8>,
+}
+
+/// The volume of a note over time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Envelope {
+    /// Ticks and values from `0` to `64`, in order.
+    pub points: Vec<(u16, u8)>,
+    /// The points the envelope loops between while the note is held.
+    pub sustain: Option<(usize, usize)>,
+    pub looping: Option<(usize, usize)>,
+}
+
+impl Envelope {
+    /// Creates an envelope, dropping the loops that point past its end.
+    pub(crate) fn new(
+        points: Vec<(u16, u8)>,
+        sustain: Option<(usize, usize)>,
+        looping: Option<(usize, usize)>,
+    ) -> Option<Self> {
+        if points.is_empty() {
+            return None;
+        }
+        let valid = |range: Option<(usize, usize)>| {
+            range.filter(|&(start, end)| start <= end && end < points.len())
+        };
+        Some(Envelope {
+            sustain: valid(sustain),
+            looping: valid(looping),
+            points,
+        })
+    }
+
+    /// Returns the value at the given tick, from `0.0` to `1.0`.
+    pub(crate) fn value(&self, tick: u16) -> f32 {
+        let next = self.points.iter().position(|&(t, _)| t > tick);
+        let value = match next {
+            Some(0) => f32::from(self.points[0].1),
+            Some(i) => {
+                let (t0, v0) = self.points[i - 1];
+                let (t1, v1) = self.points[i];
+                let x = f32::from(tick - t0) / f32::from(t1 - t0);
+                f32::from(v0) + (f32::from(v1) - f32::from(v0)) * x
+            }
+            None => f32::from(self.points[self.points.len() - 1].1),
+        };
+        value / 64.0
+    }
+
+    /// Returns the tick after the given one, taking the loops into account.
+    pub(crate) fn advance(&self, tick: u16, held: bool) -> u16 {
+        let next = tick.saturating_add(1);
+        let range = if held {
+            self.sustain.or(self.looping)
+        } else {
+            self.looping
+        };
+        match range {
+            Some((start, end)) if tick >= self.points[end].0 => self.points[start].0,
+            _ => next,
+        }
+    }
+}
+
+/// Returns the period of a note played with a sample at the given rate.
+pub(crate) fn note_period(note: u8, base_rate: f32) -> f32 {
+    let semitones = f32::from(note) - f32::from(BASE_NOTE);
+    BASE_PERIOD * 8363.0 / base_rate.max(1.0) / 2f32.powf(semitones / 12.0)
+}
+
+/// Returns the playback rate of a period.
+pub(crate) fn period_rate(period: f32) -> f32 {
+    BASE_PERIOD * 8363.0 / period.max(1.0)
+}
+
+/// Moves a period by some pitch units, up in pitch for positive amounts.
+pub(crate) fn slide_period(period: f32, units: f32, linear: bool) -> f32 {
+    if linear {
+        period / 2f32.powf(units / 768.0)
+    } else {
+        period - units
+    }
+}
+
+/// Returns the sample rate that plays `BASE_NOTE` for a sample
+/// tuned up or down by some semitones from 8363 Hz.
+pub(crate) fn tuned_rate(semitones: f32) -> f32 {
+    8363.0 * 2f32.powf(semitones / 12.0)
+}
+
+pub(crate) fn format_error(message: &str) -> GameError {
+    GameError::AudioError(format!("Could not load tracker module: {}", message))
+}
+
+/// Reads values out of a file, with errors instead of panics past its end.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Bytes<'a>(pub &'a [u8]);
+
+impl<'a> Bytes<'a> {
+    pub(crate) fn slice(&self, offset: usize, len: usize) -> GameResult<&'a [u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.0.get(offset..end))
+            .ok_or_else(|| format_error("the file is cut short"))
+    }
+
+    /// Like `slice()`, but returns whatever there is if the file ends
+    /// early, since sample data is often cut short.
+    pub(crate) fn slice_lenient(&self, offset: usize, len: usize) -> &'a [u8] {
+        let start = offset.min(self.0.len());
+        let end = offset.saturating_add(len).min(self.0.len());
+        &self.0[start..end]
+    }
+
+    pub(crate) fn u8(&self, offset: usize) -> GameResult<u8> {
+        Ok(self.slice(offset, 1)?[0])
+    }
+
+    pub(crate) fn u16_le(&self, offset: usize) -> GameResult<u16> {
+        let b = self.slice(offset, 2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn u16_be(&self, offset: usize) -> GameResult<u16> {
+        let b = self.slice(offset, 2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn u32_le(&self, offset: usize) -> GameResult<u32> {
+        let b = self.slice(offset, 4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Reads a fixed-size text field, which may be padded with zeros.
+    pub(crate) fn string(&self, offset: usize, len: usize) -> GameResult<String> {
+        let bytes = self.slice(offset, len)?;
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(len);
+        Ok(String::from_utf8_lossy(&bytes[..end])
+            .trim_end()
+            .to_string())
+    }
+}
+
+/// Converts signed 8-bit samples.
+pub(crate) fn samples_i8(data: &[u8]) -> Vec<f32> {
+    data.iter().map(|&b| f32::from(b as i8) / 128.0).collect()
+}
+
+/// Converts unsigned 8-bit samples.
+pub(crate) fn samples_u8(data: &[u8]) -> Vec<f32> {
+    data.iter()
+        .map(|&b| (f32::from(b) - 128.0) / 128.0)
+        .collect()
+}
+
+/// Converts little-endian 16-bit samples, signed or not.
+pub(crate) fn samples_16(data: &[u8], signed: bool) -> Vec<f32> {
+    data.chunks_exact(2)
+        .map(|b| {
+            let value = u16::from_le_bytes([b[0], b[1]]);
+            let value = if signed {
+                value as i16
+            } else {
+                value.wrapping_sub(0x8000) as i16
+            };
+            f32::from(value) / 32768.0
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn periods_and_rates() {
+        assert!((note_period(BASE_NOTE, 8363.0) - 1712.0).abs() < 1e-3);
+        assert!((note_period(BASE_NOTE + 12, 8363.0) - 856.0).abs() < 1e-3);
+        assert!((period_rate(note_period(BASE_NOTE, 22050.0)) - 22050.0).abs() < 0.1);
+        assert!((slide_period(1712.0, 768.0, true) - 856.0).abs() < 1e-3);
+        assert_eq!(1708.0, slide_period(1712.0, 4.0, false));
+    }
+
+    #[test]
+    fn volume_slides_decode() {
+        assert_eq!(VolumeSlide::Up(2), VolumeSlide::from_xy(0x23));
+        assert_eq!(VolumeSlide::Down(3), VolumeSlide::from_xy(0x03));
+        assert_eq!(VolumeSlide::FineUp(2), VolumeSlide::from_dxy(0x2F));
+        assert_eq!(VolumeSlide::FineDown(3), VolumeSlide::from_dxy(0xF3));
+        assert_eq!(VolumeSlide::Up(0xF), VolumeSlide::from_dxy(0xF0));
+        assert_eq!(VolumeSlide::Memory, VolumeSlide::from_dxy(0));
+    }
+
+    #[test]
+    fn envelopes_interpolate_and_sustain() {
+        let envelope = Envelope::new(vec![(0, 0), (10, 64), (20, 32)], Some((1, 1)), None).unwrap();
+        assert_eq!(0.5, envelope.value(5));
+        assert_eq!(0.5, envelope.value(30));
+        // Held at the sustain point, then let go.
+        assert_eq!(10, envelope.advance(10, true));
+        assert_eq!(11, envelope.advance(10, false));
+    }
+}
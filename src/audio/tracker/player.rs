@@ -0,0 +1,1053 @@
+//! Playing a module: stepping through its patterns and mixing its channels.
+
+use std::collections::HashSet;
+use std::f32::consts::PI;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time;
+
+use super::module::*;
+use super::TrackerPosition;
+
+/// How many frames volume and panning changes are spread over, so
+/// they don't click.
+const RAMP_FRAMES: u32 = 64;
+
+/// Songs longer than this are taken to never end.
+const MAX_LENGTH: time::Duration = time::Duration::from_secs(4 * 60 * 60);
+
+type RowCallback = Box<dyn FnMut(TrackerPosition) + Send>;
+
+/// The settings of a `TrackerSource` that can be changed while it plays,
+/// and what it reports back.
+pub(crate) struct Controls {
+    muted: Vec<AtomicBool>,
+    tempo_factor: AtomicU32,
+    order: AtomicUsize,
+    pattern: AtomicUsize,
+    row: AtomicUsize,
+    speed: AtomicUsize,
+    tempo: AtomicUsize,
+    callback: Mutex<Option<RowCallback>>,
+}
+
+impl Controls {
+    pub(crate) fn new(module: &Module) -> Self {
+        Controls {
+            muted: (0..module.channels)
+                .map(|_| AtomicBool::new(false))
+                .collect(),
+            tempo_factor: AtomicU32::new(1.0f32.to_bits()),
+            order: AtomicUsize::new(0),
+            pattern: AtomicUsize::new(module.orders[0]),
+            row: AtomicUsize::new(0),
+            speed: AtomicUsize::new(usize::from(module.speed)),
+            tempo: AtomicUsize::new(usize::from(module.tempo)),
+            callback: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn muted(&self, channel: usize) -> bool {
+        self.muted
+            .get(channel)
+            .is_some_and(|muted| muted.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set_muted(&self, channel: usize, muted: bool) {
+        if let Some(m) = self.muted.get(channel) {
+            m.store(muted, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn tempo_factor(&self) -> f32 {
+        f32::from_bits(self.tempo_factor.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set_tempo_factor(&self, factor: f32) {
+        self.tempo_factor
+            .store(factor.max(0.01).to_bits(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn position(&self) -> TrackerPosition {
+        TrackerPosition {
+            order: self.order.load(Ordering::Relaxed),
+            pattern: self.pattern.load(Ordering::Relaxed),
+            row: self.row.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn speed(&self) -> usize {
+        self.speed.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn tempo(&self) -> usize {
+        self.tempo.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_callback(&self, callback: Option<RowCallback>) {
+        *self.callback.lock().unwrap() = callback;
+    }
+
+    fn report_row(&self, position: TrackerPosition) {
+        self.order.store(position.order, Ordering::Relaxed);
+        self.pattern.store(position.pattern, Ordering::Relaxed);
+        self.row.store(position.row, Ordering::Relaxed);
+        // The game thread only holds the lock to swap the callback.
+        if let Ok(mut callback) = self.callback.try_lock() {
+            if let Some(ref mut callback) = *callback {
+                callback(position);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Controls {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Controls: {:p}>", self)
+    }
+}
+
+/// The state of one channel of the song.
+#[derive(Debug, Clone)]
+struct Channel {
+    /// Indices into the module's samples and instruments.
+    sample: Option<usize>,
+    instrument: Option<usize>,
+    active: bool,
+    position: f64,
+    backwards: bool,
+
+    period: f32,
+    target_period: f32,
+    /// From `0` to `64`.
+    volume: i32,
+    /// From `0` for left to `255`.
+    panning: i32,
+
+    effect: Effect,
+    volume_command: VolumeCommand,
+    delayed: Option<Cell>,
+    /// Changes to the pitch and volume that only last for the current tick.
+    semitone_offset: f32,
+    period_offset: f32,
+    volume_offset: i32,
+
+    portamento: u16,
+    tone_portamento: u16,
+    vibrato_speed: u8,
+    vibrato_depth: u8,
+    vibrato_position: u8,
+    tremolo_speed: u8,
+    tremolo_depth: u8,
+    tremolo_position: u8,
+    volume_slide: VolumeSlide,
+    global_volume_slide: VolumeSlide,
+    offset: u32,
+    loop_row: usize,
+    loop_count: u8,
+
+    key_on: bool,
+    fading: bool,
+    fade: f32,
+    envelope_tick: u16,
+    envelope_volume: f32,
+
+    /// The gains applied to the left and right output right now, and
+    /// how much they change every frame while ramping.
+    gains: [f32; 2],
+    gain_steps: [f32; 2],
+    ramp: u32,
+    step: f64,
+}
+
+impl Channel {
+    fn new(panning: u8) -> Self {
+        Channel {
+            sample: None,
+            instrument: None,
+            active: false,
+            position: 0.0,
+            backwards: false,
+            period: 0.0,
+            target_period: 0.0,
+            volume: 0,
+            panning: i32::from(panning),
+            effect: Effect::None,
+            volume_command: VolumeCommand::None,
+            delayed: None,
+            semitone_offset: 0.0,
+            period_offset: 0.0,
+            volume_offset: 0,
+            portamento: 0,
+            tone_portamento: 0,
+            vibrato_speed: 0,
+            vibrato_depth: 0,
+            vibrato_position: 0,
+            tremolo_speed: 0,
+            tremolo_depth: 0,
+            tremolo_position: 0,
+            volume_slide: VolumeSlide::Up(0),
+            global_volume_slide: VolumeSlide::Up(0),
+            offset: 0,
+            loop_row: 0,
+            loop_count: 0,
+            key_on: false,
+            fading: false,
+            fade: 1.0,
+            envelope_tick: 0,
+            envelope_volume: 1.0,
+            gains: [0.0; 2],
+            gain_steps: [0.0; 2],
+            ramp: 0,
+            step: 0.0,
+        }
+    }
+
+    /// Moves the play position on by some frames, looping as needed.
+    fn advance(&mut self, sample: &Sample, frames: f64) {
+        let looping = match sample.sustain_loop {
+            Some(sustain) if self.key_on => Some(sustain),
+            _ => sample.looping,
+        };
+        if self.backwards {
+            self.position -= frames;
+        } else {
+            self.position += frames;
+        }
+        match looping {
+            Some(l) if l.ping_pong => {
+                let (start, end) = (l.start as f64, l.end as f64);
+                let length = end - start;
+                if self.position >= end || self.position < start {
+                    // Unfold the bounces into one long forward run.
+                    let run = if self.backwards {
+                        2.0 * length - (self.position - start)
+                    } else {
+                        self.position - start
+                    };
+                    let run = run.rem_euclid(2.0 * length);
+                    if run < length {
+                        self.position = start + run;
+                        self.backwards = false;
+                    } else {
+                        self.position = end - (run - length);
+                        self.backwards = true;
+                    }
+                }
+            }
+            Some(l) => {
+                let (start, end) = (l.start as f64, l.end as f64);
+                if self.position >= end {
+                    self.position = start + (self.position - start) % (end - start);
+                }
+            }
+            None => {
+                if self.position >= sample.data.len() as f64 {
+                    self.active = false;
+                }
+            }
+        }
+    }
+
+    /// Returns the sample at the play position, interpolated.
+    fn sample_value(&self, sample: &Sample) -> f32 {
+        let data = &sample.data;
+        if data.is_empty() {
+            return 0.0;
+        }
+        let index = (self.position as usize).min(data.len() - 1);
+        let fraction = (self.position - index as f64) as f32;
+        let next = match sample.looping {
+            Some(l) if !l.ping_pong && index + 1 >= l.end => data[l.start],
+            _ => data.get(index + 1).copied().unwrap_or(0.0),
+        };
+        data[index] + (next - data[index]) * fraction
+    }
+}
+
+/// Returns the value of the vibrato and tremolo waveform, from -1 to 1.
+fn waveform(position: u8) -> f32 {
+    (f32::from(position % 64) * 2.0 * PI / 64.0).sin()
+}
+
+/// Plays a module as a stereo rodio source.
+pub(crate) struct Player {
+    module: Arc<Module>,
+    controls: Arc<Controls>,
+    sample_rate: u32,
+    repeat: bool,
+    /// Whether only the song position is followed, without mixing
+    /// any sound, to find out how long it is.
+    dry: bool,
+    channels: Vec<Channel>,
+
+    order: usize,
+    row: usize,
+    tick: u32,
+    speed: u32,
+    tempo: u32,
+    global_volume: f32,
+    pattern_delay: u32,
+    row_repeats: u32,
+    jump_order: Option<usize>,
+    break_row: Option<usize>,
+    loop_to: Option<usize>,
+    visited: HashSet<(usize, usize)>,
+    ended: bool,
+
+    frames_left: u32,
+    frame_remainder: f64,
+    frame: [f32; 2],
+    output_channel: usize,
+    mix_scale: f32,
+}
+
+impl Player {
+    /// Creates a player starting at the given order.
+    pub(crate) fn new(
+        module: Arc<Module>,
+        controls: Arc<Controls>,
+        sample_rate: u32,
+        repeat: bool,
+        order: usize,
+    ) -> Self {
+        let channels = module.panning.iter().map(|&p| Channel::new(p)).collect();
+        Player {
+            controls,
+            sample_rate: sample_rate.max(1),
+            repeat,
+            dry: false,
+            channels,
+            order,
+            row: 0,
+            tick: 0,
+            speed: u32::from(module.speed),
+            tempo: u32::from(module.tempo),
+            global_volume: module.global_volume,
+            pattern_delay: 0,
+            row_repeats: 0,
+            jump_order: None,
+            break_row: None,
+            loop_to: None,
+            visited: HashSet::new(),
+            ended: order >= module.orders.len(),
+            frames_left: 0,
+            frame_remainder: 0.0,
+            frame: [0.0; 2],
+            output_channel: 0,
+            mix_scale: 1.0 / (module.channels as f32).sqrt(),
+            module,
+        }
+    }
+
+    /// Returns how long the song plays for when it isn't repeated,
+    /// or `None` if it seems to go on forever.
+    pub(crate) fn song_length(module: Arc<Module>) -> Option<time::Duration> {
+        let controls = Arc::new(Controls::new(&module));
+        let mut player = Player::new(module, controls, 44100, false, 0);
+        player.dry = true;
+        let max_frames = MAX_LENGTH.as_secs() * 44100;
+        let mut frames = 0u64;
+        while player.tick() {
+            frames += u64::from(player.frames_left);
+            if frames > max_frames {
+                return None;
+            }
+        }
+        Some(time::Duration::from_secs_f64(frames as f64 / 44100.0))
+    }
+
+    /// Skips the given amount of the song without mixing it.
+    pub(crate) fn fast_forward(&mut self, duration: time::Duration) {
+        let mut frames = (duration.as_secs_f64() * f64::from(self.sample_rate)).round() as u64;
+        while frames > 0 {
+            if self.frames_left == 0 && !self.tick() {
+                return;
+            }
+            let skipped = u64::from(self.frames_left).min(frames);
+            for i in 0..self.channels.len() {
+                let channel = &mut self.channels[i];
+                if let Some(sample) = channel.sample.filter(|_| channel.active) {
+                    let step = channel.step * skipped as f64;
+                    channel.advance(&self.module.samples[sample], step);
+                }
+                let ramped = channel.ramp.min(skipped as u32);
+                channel.gains[0] += channel.gain_steps[0] * ramped as f32;
+                channel.gains[1] += channel.gain_steps[1] * ramped as f32;
+                channel.ramp -= ramped;
+            }
+            self.frames_left -= skipped as u32;
+            frames -= skipped;
+        }
+    }
+
+    fn rows_at(&self, order: usize) -> usize {
+        self.module.pattern_at(order).map_or(64, |p| p.rows.max(1))
+    }
+
+    /// Plays one tick of the song.  Returns `false` once it has ended.
+    fn tick(&mut self) -> bool {
+        if self.ended {
+            return false;
+        }
+        if self.tick == 0 && self.row_repeats == 0 {
+            if !self.start_row() {
+                self.ended = true;
+                return false;
+            }
+        } else {
+            for c in 0..self.channels.len() {
+                self.update_effects(c);
+            }
+        }
+        if !self.dry {
+            for c in 0..self.channels.len() {
+                self.update_output(c);
+            }
+        }
+
+        let factor = if self.dry {
+            1.0
+        } else {
+            f64::from(self.controls.tempo_factor())
+        };
+        let frames = f64::from(self.sample_rate) * 2.5 / (f64::from(self.tempo) * factor)
+            + self.frame_remainder;
+        self.frames_left = frames.floor() as u32;
+        self.frame_remainder = frames - frames.floor();
+
+        self.tick += 1;
+        if self.tick >= self.speed {
+            self.tick = 0;
+            if self.row_repeats < self.pattern_delay {
+                self.row_repeats += 1;
+            } else {
+                self.row_repeats = 0;
+                self.pattern_delay = 0;
+                self.next_row();
+            }
+        }
+        true
+    }
+
+    fn next_row(&mut self) {
+        if let Some(row) = self.loop_to.take() {
+            self.row = row;
+            self.jump_order = None;
+            self.break_row = None;
+            return;
+        }
+        if self.jump_order.is_some() || self.break_row.is_some() {
+            self.order = self.jump_order.take().unwrap_or(self.order + 1);
+            let row = self.break_row.take().unwrap_or(0);
+            self.row = if self.order < self.module.orders.len() && row < self.rows_at(self.order) {
+                row
+            } else {
+                0
+            };
+        } else {
+            self.row += 1;
+            if self.row >= self.rows_at(self.order) {
+                self.row = 0;
+                self.order += 1;
+            }
+        }
+        if self.order >= self.module.orders.len() {
+            if self.repeat {
+                self.order = self.module.restart;
+                self.row = 0;
+            } else {
+                self.ended = true;
+            }
+        }
+    }
+
+    /// Reads the cells of the current row.  Returns `false` if the song
+    /// has come back to a row it played before, and so is over.
+    fn start_row(&mut self) -> bool {
+        if !self.repeat && !self.visited.insert((self.order, self.row)) {
+            return false;
+        }
+        let module = self.module.clone();
+        if !self.dry {
+            self.controls.report_row(TrackerPosition {
+                order: self.order,
+                pattern: module.orders[self.order],
+                row: self.row,
+            });
+        }
+        let channels = module.channels;
+        for c in 0..channels {
+            let cell = module
+                .pattern_at(self.order)
+                .and_then(|pattern| pattern.cell(self.row, c, channels))
+                .copied()
+                .unwrap_or_default();
+            self.start_cell(c, cell);
+        }
+        if !self.dry {
+            self.controls
+                .speed
+                .store(self.speed as usize, Ordering::Relaxed);
+            self.controls
+                .tempo
+                .store(self.tempo as usize, Ordering::Relaxed);
+        }
+        true
+    }
+
+    fn start_cell(&mut self, c: usize, cell: Cell) {
+        {
+            let channel = &mut self.channels[c];
+            channel.effect = cell.effect;
+            channel.volume_command = cell.volume;
+            channel.delayed = None;
+            channel.semitone_offset = 0.0;
+            channel.period_offset = 0.0;
+            channel.volume_offset = 0;
+        }
+        match cell.effect {
+            Effect::NoteDelay(delay) if delay > 0 => {
+                self.channels[c].delayed = Some(cell);
+            }
+            _ => self.trigger(c, cell),
+        }
+
+        let row = self.row;
+        let channel = &mut self.channels[c];
+        match cell.effect {
+            Effect::PortamentoUp(units) | Effect::PortamentoDown(units) if units > 0 => {
+                channel.portamento = units;
+            }
+            Effect::FinePortamentoUp(units) => {
+                channel.period = slide_period(channel.period, f32::from(units), self.module.linear);
+            }
+            Effect::FinePortamentoDown(units) => {
+                channel.period =
+                    slide_period(channel.period, -f32::from(units), self.module.linear);
+            }
+            Effect::TonePortamento(speed) if speed > 0 => channel.tone_portamento = speed,
+            Effect::Vibrato { speed, depth } => {
+                if speed > 0 {
+                    channel.vibrato_speed = speed;
+                }
+                if depth > 0 {
+                    channel.vibrato_depth = depth;
+                }
+            }
+            Effect::Tremolo { speed, depth } => {
+                if speed > 0 {
+                    channel.tremolo_speed = speed;
+                }
+                if depth > 0 {
+                    channel.tremolo_depth = depth;
+                }
+            }
+            Effect::VolumeSlide(slide)
+            | Effect::TonePortamentoVolumeSlide(slide)
+            | Effect::VibratoVolumeSlide(slide) => {
+                if slide != VolumeSlide::Memory {
+                    channel.volume_slide = slide;
+                }
+                channel.volume = fine_slide(channel.volume, channel.volume_slide);
+            }
+            Effect::GlobalVolumeSlide(slide) => {
+                if slide != VolumeSlide::Memory {
+                    channel.global_volume_slide = slide;
+                }
+                let volume = (self.global_volume * 64.0).round() as i32;
+                self.global_volume = fine_slide(volume, channel.global_volume_slide) as f32 / 64.0;
+            }
+            Effect::SetPanning(panning) => channel.panning = i32::from(panning),
+            Effect::SetVolume(volume) => channel.volume = i32::from(volume.min(64)),
+            Effect::PositionJump(order) => self.jump_order = Some(usize::from(order)),
+            Effect::PatternBreak(row) => self.break_row = Some(usize::from(row)),
+            Effect::SetSpeed(speed) => self.speed = u32::from(speed),
+            Effect::SetTempo(tempo) => self.tempo = u32::from(tempo),
+            Effect::SetGlobalVolume(volume) => {
+                self.global_volume = f32::from(volume.min(64)) / 64.0;
+            }
+            Effect::PatternLoop(0) => channel.loop_row = row,
+            Effect::PatternLoop(count) => {
+                if channel.loop_count == 0 {
+                    channel.loop_count = count;
+                    self.loop_to = Some(channel.loop_row);
+                } else {
+                    channel.loop_count -= 1;
+                    if channel.loop_count > 0 {
+                        self.loop_to = Some(channel.loop_row);
+                    }
+                }
+                if self.loop_to.is_some() {
+                    // Looped rows are played again on purpose.
+                    let order = self.order;
+                    let start = channel.loop_row;
+                    self.visited
+                        .retain(|&(o, r)| o != order || r < start || r > row);
+                }
+            }
+            Effect::NoteCut(0) => channel.volume = 0,
+            Effect::PatternDelay(rows) if self.pattern_delay == 0 => {
+                self.pattern_delay = u32::from(rows);
+            }
+            Effect::KeyOff(0) => self.release(c),
+            _ => (),
+        }
+
+        let channel = &mut self.channels[c];
+        match cell.volume {
+            VolumeCommand::Slide(slide) => {
+                channel.volume = fine_slide(channel.volume, slide);
+            }
+            VolumeCommand::TonePortamento(speed) if speed > 0 => {
+                channel.tone_portamento = speed;
+            }
+            _ => (),
+        }
+    }
+
+    /// Starts the note, instrument and volume of a cell.
+    fn trigger(&mut self, c: usize, cell: Cell) {
+        let module = self.module.clone();
+        let channel = &mut self.channels[c];
+        let portamento = matches!(
+            cell.effect,
+            Effect::TonePortamento(_) | Effect::TonePortamentoVolumeSlide(_)
+        ) || matches!(cell.volume, VolumeCommand::TonePortamento(_));
+
+        if cell.instrument != 0 {
+            let index = usize::from(cell.instrument) - 1;
+            if module.instruments.is_empty() {
+                channel.sample = Some(index).filter(|&i| i < module.samples.len());
+            } else {
+                channel.instrument = Some(index).filter(|&i| i < module.instruments.len());
+            }
+        }
+        let instrument = channel.instrument.map(|i| &module.instruments[i]);
+
+        if let Note::On(note) = cell.note {
+            let (note, sample) = match instrument {
+                Some(instrument) if !module.instruments.is_empty() => {
+                    let (note, sample) = instrument.keymap[usize::from(note)];
+                    (note, usize::from(sample).checked_sub(1))
+                }
+                None if !module.instruments.is_empty() => (note, None),
+                _ => (note, channel.sample),
+            };
+            let sample_index = sample.filter(|&s| s < module.samples.len());
+            if let Some(s) = sample_index {
+                let period = note_period(note, module.samples[s].base_rate);
+                if portamento && channel.active {
+                    channel.target_period = period;
+                } else if !module.samples[s].data.is_empty() {
+                    channel.sample = Some(s);
+                    channel.period = period;
+                    channel.target_period = period;
+                    channel.active = true;
+                    channel.backwards = false;
+                    channel.position = 0.0;
+                    channel.vibrato_position = 0;
+                    channel.tremolo_position = 0;
+                    if let Effect::SampleOffset(offset) = cell.effect {
+                        if offset > 0 {
+                            channel.offset = offset;
+                        }
+                        channel.position = f64::from(channel.offset);
+                        if channel.position >= module.samples[s].data.len() as f64 {
+                            channel.active = false;
+                        }
+                    }
+                }
+            } else if !portamento {
+                channel.active = false;
+            }
+        }
+
+        if cell.instrument != 0 {
+            if let Some(sample) = channel.sample.map(|s| &module.samples[s]) {
+                channel.volume = i32::from(sample.volume);
+                if let Some(panning) = sample.panning.or(instrument.and_then(|i| i.panning)) {
+                    channel.panning = i32::from(panning);
+                }
+            }
+            channel.key_on = true;
+            channel.fading = false;
+            channel.fade = 1.0;
+            channel.envelope_tick = 0;
+        } else if matches!(cell.note, Note::On(_)) && !portamento {
+            channel.key_on = true;
+            channel.fading = false;
+            channel.fade = 1.0;
+            channel.envelope_tick = 0;
+        }
+
+        match cell.note {
+            Note::Off => self.release(c),
+            Note::Cut => self.channels[c].active = false,
+            Note::Fade => self.channels[c].fading = true,
+            _ => (),
+        }
+        let channel = &mut self.channels[c];
+        match cell.volume {
+            VolumeCommand::Set(volume) => channel.volume = i32::from(volume),
+            VolumeCommand::Panning(panning) => channel.panning = i32::from(panning),
+            _ => (),
+        }
+    }
+
+    /// Lets go of the note, so its envelope can finish,
+    /// or cuts it if it has no envelope.
+    fn release(&mut self, c: usize) {
+        let module = &self.module;
+        let channel = &mut self.channels[c];
+        channel.key_on = false;
+        let has_envelope = channel
+            .instrument
+            .is_some_and(|i| module.instruments[i].volume_envelope.is_some());
+        if has_envelope {
+            channel.fading = true;
+        } else {
+            channel.active = false;
+        }
+    }
+
+    /// Applies the effects that happen on every tick but the first.
+    fn update_effects(&mut self, c: usize) {
+        let linear = self.module.linear;
+        let tick = self.tick;
+        let first_pass = self.row_repeats == 0;
+        let channel = &mut self.channels[c];
+        channel.semitone_offset = 0.0;
+        channel.period_offset = 0.0;
+        channel.volume_offset = 0;
+
+        match channel.effect {
+            Effect::Arpeggio(x, y) => {
+                channel.semitone_offset = f32::from([0, x, y][(tick % 3) as usize]);
+            }
+            Effect::PortamentoUp(_) => {
+                channel.period =
+                    slide_period(channel.period, f32::from(channel.portamento), linear);
+            }
+            Effect::PortamentoDown(_) => {
+                channel.period =
+                    slide_period(channel.period, -f32::from(channel.portamento), linear);
+            }
+            _ => (),
+        }
+        if matches!(
+            channel.effect,
+            Effect::TonePortamento(_) | Effect::TonePortamentoVolumeSlide(_)
+        ) || matches!(channel.volume_command, VolumeCommand::TonePortamento(_))
+        {
+            tone_portamento(channel, linear);
+        }
+        if let Effect::Vibrato { .. } | Effect::VibratoVolumeSlide(_) = channel.effect {
+            channel.vibrato_position = channel.vibrato_position.wrapping_add(channel.vibrato_speed);
+            channel.period_offset =
+                waveform(channel.vibrato_position) * f32::from(channel.vibrato_depth) * 8.0;
+        }
+        if let Effect::Tremolo { .. } = channel.effect {
+            channel.tremolo_position = channel.tremolo_position.wrapping_add(channel.tremolo_speed);
+            channel.volume_offset = (waveform(channel.tremolo_position)
+                * f32::from(channel.tremolo_depth)
+                * 4.0) as i32;
+        }
+        match channel.effect {
+            Effect::VolumeSlide(_)
+            | Effect::TonePortamentoVolumeSlide(_)
+            | Effect::VibratoVolumeSlide(_) => {
+                channel.volume = slide(channel.volume, channel.volume_slide);
+            }
+            Effect::GlobalVolumeSlide(_) => {
+                let volume = (self.global_volume * 64.0).round() as i32;
+                self.global_volume = slide(volume, channel.global_volume_slide) as f32 / 64.0;
+            }
+            _ => (),
+        }
+        if let VolumeCommand::Slide(volume_slide) = channel.volume_command {
+            channel.volume = slide(channel.volume, volume_slide);
+        }
+
+        if !first_pass {
+            return;
+        }
+        match channel.effect {
+            Effect::NoteCut(at) if u32::from(at) == tick => channel.volume = 0,
+            Effect::Retrigger(every) if every > 0 && tick % u32::from(every) == 0 => {
+                channel.position = 0.0;
+                channel.backwards = false;
+                channel.active = channel.sample.is_some();
+            }
+            Effect::NoteDelay(at) if u32::from(at) == tick => {
+                if let Some(cell) = channel.delayed.take() {
+                    self.trigger(c, cell);
+                }
+            }
+            Effect::KeyOff(at) if u32::from(at) == tick => self.release(c),
+            _ => (),
+        }
+    }
+
+    /// Works out the pitch and gains a channel plays at during the tick,
+    /// and moves its envelope on.
+    fn update_output(&mut self, c: usize) {
+        let module = &self.module;
+        let muted = self.controls.muted(c);
+        let channel = &mut self.channels[c];
+
+        let instrument = channel.instrument.map(|i| &module.instruments[i]);
+        channel.envelope_volume = 1.0;
+        if let Some(envelope) = instrument.and_then(|i| i.volume_envelope.as_ref()) {
+            channel.envelope_volume = envelope.value(channel.envelope_tick);
+            channel.envelope_tick = envelope.advance(channel.envelope_tick, channel.key_on);
+        }
+        if channel.fading {
+            channel.fade -= instrument.map_or(1.0, |i| i.fadeout);
+            if channel.fade <= 0.0 {
+                channel.fade = 0.0;
+                channel.active = false;
+            }
+        }
+
+        let mut target = [0.0; 2];
+        if let Some(sample) = channel.sample.filter(|_| channel.active) {
+            let sample = &module.samples[sample];
+            let period = slide_period(channel.period, -channel.period_offset, module.linear)
+                / 2f32.powf(channel.semitone_offset / 12.0);
+            channel.step = f64::from(period_rate(period)) / f64::from(self.sample_rate);
+
+            // Muted channels keep going, so unmuting picks up where the
+            // song is at.
+            if !muted {
+                let volume = (channel.volume + channel.volume_offset).clamp(0, 64) as f32 / 64.0
+                    * channel.envelope_volume
+                    * channel.fade
+                    * sample.global_volume
+                    * self.global_volume
+                    * self.mix_scale;
+                let pan = channel.panning.clamp(0, 255) as f32 / 255.0;
+                target = [
+                    volume * (2.0 * (1.0 - pan)).min(1.0),
+                    volume * (2.0 * pan).min(1.0),
+                ];
+            }
+        }
+        for (i, target) in target.iter().enumerate() {
+            channel.gain_steps[i] = (target - channel.gains[i]) / RAMP_FRAMES as f32;
+        }
+        channel.ramp = RAMP_FRAMES;
+    }
+
+    /// Mixes the next frame of every channel.
+    fn mix_frame(&mut self) -> [f32; 2] {
+        let mut frame = [0.0; 2];
+        for channel in self.channels.iter_mut() {
+            if channel.ramp > 0 {
+                channel.ramp -= 1;
+                channel.gains[0] += channel.gain_steps[0];
+                channel.gains[1] += channel.gain_steps[1];
+            }
+            let sample = match channel.sample {
+                Some(sample) if channel.active => &self.module.samples[sample],
+                _ => continue,
+            };
+            let value = channel.sample_value(sample);
+            frame[0] += value * channel.gains[0];
+            frame[1] += value * channel.gains[1];
+            channel.advance(sample, channel.step);
+        }
+        frame
+    }
+}
+
+/// Moves a channel's pitch towards its tone portamento's target.
+fn tone_portamento(channel: &mut Channel, linear: bool) {
+    let speed = f32::from(channel.tone_portamento);
+    if channel.target_period <= 0.0 {
+        return;
+    }
+    if channel.period > channel.target_period {
+        channel.period = slide_period(channel.period, speed, linear).max(channel.target_period);
+    } else if channel.period < channel.target_period {
+        channel.period = slide_period(channel.period, -speed, linear).min(channel.target_period);
+    }
+}
+
+/// Applies a volume slide on one of the later ticks of a row.
+fn slide(volume: i32, slide: VolumeSlide) -> i32 {
+    match slide {
+        VolumeSlide::Up(amount) => (volume + i32::from(amount)).min(64),
+        VolumeSlide::Down(amount) => (volume - i32::from(amount)).max(0),
+        _ => volume,
+    }
+}
+
+/// Applies a volume slide on the first tick of a row.
+fn fine_slide(volume: i32, slide: VolumeSlide) -> i32 {
+    match slide {
+        VolumeSlide::FineUp(amount) => (volume + i32::from(amount)).min(64),
+        VolumeSlide::FineDown(amount) => (volume - i32::from(amount)).max(0),
+        _ => volume,
+    }
+}
+
+impl Iterator for Player {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.output_channel == 0 {
+            while self.frames_left == 0 {
+                if !self.tick() {
+                    return None;
+                }
+            }
+            self.frames_left -= 1;
+            self.frame = self.mix_frame();
+        }
+        let sample = self.frame[self.output_channel];
+        self.output_channel = 1 - self.output_channel;
+        Some(sample)
+    }
+}
+
+impl rodio::Source for Player {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<time::Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A one-channel song with a single pattern, with a looping sample
+    /// that holds at one value.
+    fn song(rows: &[Cell]) -> Arc<Module> {
+        Arc::new(Module {
+            title: String::new(),
+            format: super::super::TrackerFormat::Mod,
+            channels: 1,
+            orders: vec![0],
+            restart: 0,
+            patterns: vec![Pattern {
+                rows: rows.len(),
+                cells: rows.to_vec(),
+            }],
+            samples: vec![Sample {
+                data: vec![0.5; 100],
+                looping: Loop::new(0, 100, false, 100),
+                volume: 64,
+                global_volume: 1.0,
+                base_rate: 8363.0,
+                ..Sample::default()
+            }],
+            instruments: Vec::new(),
+            speed: 2,
+            tempo: 125,
+            global_volume: 1.0,
+            panning: vec![0x80],
+            linear: false,
+        })
+    }
+
+    fn note() -> Cell {
+        Cell {
+            note: Note::On(BASE_NOTE),
+            instrument: 1,
+            ..Cell::default()
+        }
+    }
+
+    fn effect(effect: Effect) -> Cell {
+        Cell {
+            effect,
+            ..Cell::default()
+        }
+    }
+
+    fn start(module: Arc<Module>) -> (Player, Arc<Controls>) {
+        let controls = Arc::new(Controls::new(&module));
+        let player = Player::new(module, controls.clone(), 1000, false, 0);
+        (player, controls)
+    }
+
+    #[test]
+    fn rows_advance_and_are_reported() {
+        let (player, controls) = start(song(&[note(); 4]));
+        let rows = Arc::new(Mutex::new(Vec::new()));
+        let reported = rows.clone();
+        controls.set_callback(Some(Box::new(move |pos: TrackerPosition| {
+            reported.lock().unwrap().push(pos.row)
+        })));
+        // Two ticks of 20 frames a row, in stereo.
+        assert_eq!(4 * 2 * 20 * 2, player.count());
+        assert_eq!(vec![0, 1, 2, 3], *rows.lock().unwrap());
+        assert_eq!(3, controls.position().row);
+    }
+
+    #[test]
+    fn speed_and_tempo_change() {
+        let mut rows = [Cell::default(); 4];
+        rows[0] = effect(Effect::SetSpeed(1));
+        rows[1] = effect(Effect::SetTempo(250));
+        let (player, controls) = start(song(&rows));
+        assert_eq!((20 + 3 * 10) * 2, player.count());
+        assert_eq!(1, controls.speed());
+        assert_eq!(250, controls.tempo());
+
+        let (mut player, controls) = start(song(&rows));
+        controls.set_tempo_factor(2.0);
+        let mut samples = 0;
+        while player.next().is_some() {
+            samples += 1;
+        }
+        assert_eq!((10 + 3 * 5) * 2, samples);
+    }
+
+    #[test]
+    fn muted_channels_are_silent() {
+        let mut rows = [Cell::default(); 8];
+        rows[0] = note();
+        let (player, controls) = start(song(&rows));
+        controls.set_muted(0, true);
+        assert!(player.into_iter().all(|s| s == 0.0));
+
+        let (player, _) = start(song(&rows));
+        let loudest = player.fold(0.0f32, |max, s| max.max(s.abs()));
+        assert!((loudest - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn songs_end_when_they_jump_back() {
+        let mut rows = [Cell::default(); 4];
+        rows[0] = effect(Effect::PatternLoop(0));
+        rows[1] = effect(Effect::PatternLoop(1));
+        rows[3] = effect(Effect::PositionJump(0));
+        let (player, controls) = start(song(&rows));
+        let visited = Arc::new(Mutex::new(Vec::new()));
+        let reported = visited.clone();
+        controls.set_callback(Some(Box::new(move |pos: TrackerPosition| {
+            reported.lock().unwrap().push(pos.row)
+        })));
+        assert_eq!(6 * 2 * 20 * 2, player.count());
+        assert_eq!(vec![0, 1, 0, 1, 2, 3], *visited.lock().unwrap());
+
+        // Six rows of two ticks of 2.5 / 125 seconds.
+        let length = Player::song_length(song(&rows)).unwrap();
+        assert!((length.as_secs_f64() - 0.24).abs() < 1e-3);
+    }
+}
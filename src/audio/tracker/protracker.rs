@@ -0,0 +1,188 @@
+//! Loading ProTracker MOD files, and the 15-sample Soundtracker ones before them.
+
+use super::module::*;
+use super::TrackerFormat;
+use crate::error::GameResult;
+
+/// The PAL Amiga period of `BASE_NOTE` without finetune.
+const BASE_AMIGA_PERIOD: f32 = 428.0;
+
+/// Returns the number of channels that the tag at offset 1080 stands for.
+fn tag_channels(tag: &[u8]) -> Option<usize> {
+    match tag {
+        b"M.K." | b"M!K!" | b"M&K!" | b"FLT4" | b"4CHN" | b"N.T." => Some(4),
+        b"FLT8" | b"OKTA" | b"OCTA" | b"CD81" => Some(8),
+        [n, b'C', b'H', b'N'] if n.is_ascii_digit() => Some(usize::from(n - b'0')),
+        [a, b, b'C', b'H'] | [a, b, b'C', b'N'] if a.is_ascii_digit() && b.is_ascii_digit() => {
+            Some(usize::from(a - b'0') * 10 + usize::from(b - b'0'))
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn load(data: &[u8]) -> GameResult<Module> {
+    let bytes = Bytes(data);
+    let (sample_count, channels) = match data.get(1080..1084).and_then(tag_channels) {
+        Some(channels) => (31, channels),
+        None => (15, 4),
+    };
+    if channels == 0 || channels > 32 {
+        return Err(format_error("unsupported number of channels"));
+    }
+    let title = bytes.string(0, 20)?;
+
+    let mut samples = Vec::with_capacity(sample_count);
+    let mut lengths = Vec::with_capacity(sample_count);
+    for i in 0..sample_count {
+        let header = 20 + i * 30;
+        let length = usize::from(bytes.u16_be(header + 22)?) * 2;
+        let finetune = bytes.u8(header + 24)? & 0x0F;
+        let volume = bytes.u8(header + 25)?;
+        let loop_start = usize::from(bytes.u16_be(header + 26)?) * 2;
+        let loop_length = usize::from(bytes.u16_be(header + 28)?) * 2;
+        if volume > 64 {
+            return Err(format_error("not a tracker module"));
+        }
+        // Finetune goes in eighths of a semitone, from -8 to 7.
+        let finetune = f32::from(((finetune << 4) as i8) >> 4);
+        samples.push(Sample {
+            looping: if loop_length > 2 {
+                Loop::new(loop_start, loop_start + loop_length, false, length)
+            } else {
+                None
+            },
+            volume,
+            global_volume: 1.0,
+            base_rate: tuned_rate(finetune / 8.0),
+            ..Sample::default()
+        });
+        lengths.push(length);
+    }
+
+    let header_end = 20 + sample_count * 30;
+    let song_length = usize::from(bytes.u8(header_end)?);
+    let restart = usize::from(bytes.u8(header_end + 1)?);
+    let order_table = bytes.slice(header_end + 2, 128)?;
+    if song_length == 0 || song_length > 128 {
+        return Err(format_error("not a tracker module"));
+    }
+    let pattern_count = order_table.iter().copied().max().map_or(0, usize::from) + 1;
+    let orders: Vec<usize> = order_table[..song_length]
+        .iter()
+        .map(|&o| usize::from(o))
+        .collect();
+
+    let mut offset = header_end + 130 + if sample_count == 31 { 4 } else { 0 };
+    let mut patterns = Vec::with_capacity(pattern_count);
+    for _ in 0..pattern_count {
+        let raw = bytes.slice(offset, 64 * channels * 4)?;
+        offset += raw.len();
+        let cells = raw.chunks_exact(4).map(read_cell).collect();
+        patterns.push(Pattern { rows: 64, cells });
+    }
+
+    for (sample, length) in samples.iter_mut().zip(lengths) {
+        sample.data = samples_i8(bytes.slice_lenient(offset, length));
+        offset += length;
+        if let Some(looping) = sample.looping {
+            sample.looping = Loop::new(looping.start, looping.end, false, sample.data.len());
+        }
+    }
+
+    // Amiga channels are panned left, right, right, left.
+    let panning = (0..channels)
+        .map(|i| if (i + 1) % 4 < 2 { 0x40 } else { 0xC0 })
+        .collect();
+    Ok(Module {
+        title,
+        format: TrackerFormat::Mod,
+        channels,
+        restart: if restart < orders.len() { restart } else { 0 },
+        orders,
+        patterns,
+        samples,
+        instruments: Vec::new(),
+        speed: 6,
+        tempo: 125,
+        global_volume: 1.0,
+        panning,
+        linear: false,
+    })
+}
+
+fn read_cell(raw: &[u8]) -> Cell {
+    let period = (u16::from(raw[0] & 0x0F) << 8) | u16::from(raw[1]);
+    let note = if period == 0 {
+        Note::None
+    } else {
+        let semitones = 12.0 * (BASE_AMIGA_PERIOD / f32::from(period)).log2();
+        Note::On((f32::from(BASE_NOTE) + semitones).round().clamp(0.0, 119.0) as u8)
+    };
+    Cell {
+        note,
+        instrument: (raw[0] & 0xF0) | (raw[2] >> 4),
+        volume: VolumeCommand::None,
+        effect: read_effect(raw[2] & 0x0F, raw[3]),
+    }
+}
+
+/// Decodes a MOD effect, which XM shares.
+pub(crate) fn read_effect(command: u8, param: u8) -> Effect {
+    let (x, y) = (param >> 4, param & 0x0F);
+    let units = u16::from(param) * 4;
+    match command {
+        0x0 if param != 0 => Effect::Arpeggio(x, y),
+        0x1 => Effect::PortamentoUp(units),
+        0x2 => Effect::PortamentoDown(units),
+        0x3 => Effect::TonePortamento(units),
+        0x4 => Effect::Vibrato { speed: x, depth: y },
+        0x5 => Effect::TonePortamentoVolumeSlide(VolumeSlide::from_xy(param)),
+        0x6 => Effect::VibratoVolumeSlide(VolumeSlide::from_xy(param)),
+        0x7 => Effect::Tremolo { speed: x, depth: y },
+        0x8 => Effect::SetPanning(param),
+        0x9 => Effect::SampleOffset(u32::from(param) * 256),
+        0xA => Effect::VolumeSlide(VolumeSlide::from_xy(param)),
+        0xB => Effect::PositionJump(param),
+        0xC => Effect::SetVolume(param.min(64)),
+        0xD => Effect::PatternBreak(x * 10 + y),
+        0xE => match x {
+            0x1 => Effect::FinePortamentoUp(u16::from(y) * 4),
+            0x2 => Effect::FinePortamentoDown(u16::from(y) * 4),
+            0x6 => Effect::PatternLoop(y),
+            0x8 => Effect::SetPanning(y * 17),
+            0x9 => Effect::Retrigger(y),
+            0xA => Effect::VolumeSlide(VolumeSlide::FineUp(y)),
+            0xB => Effect::VolumeSlide(VolumeSlide::FineDown(y)),
+            0xC => Effect::NoteCut(y),
+            0xD => Effect::NoteDelay(y),
+            0xE => Effect::PatternDelay(y),
+            _ => Effect::None,
+        },
+        0xF if param == 0 => Effect::None,
+        0xF if param < 32 => Effect::SetSpeed(param),
+        0xF => Effect::SetTempo(param),
+        _ => Effect::None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_and_cells() {
+        assert_eq!(Some(4), tag_channels(b"M.K."));
+        assert_eq!(Some(6), tag_channels(b"6CHN"));
+        assert_eq!(Some(16), tag_channels(b"16CH"));
+        assert_eq!(None, tag_channels(b"\0\0\0\0"));
+
+        // C-2 with sample 17, setting the volume to 32.
+        let cell = read_cell(&[0x11, 0xAC, 0x1C, 0x20]);
+        assert_eq!(Note::On(BASE_NOTE), cell.note);
+        assert_eq!(17, cell.instrument);
+        assert_eq!(Effect::SetVolume(32), cell.effect);
+        // An octave up.
+        assert_eq!(Note::On(BASE_NOTE + 12), read_cell(&[0, 214, 0, 0]).note);
+        assert_eq!(Effect::PatternBreak(16), read_effect(0xD, 0x16));
+    }
+}
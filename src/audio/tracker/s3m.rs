@@ -0,0 +1,243 @@
+//! Loading Scream Tracker 3 S3M files.
+
+use super::module::*;
+use super::TrackerFormat;
+use crate::error::GameResult;
+
+pub(crate) fn load(data: &[u8]) -> GameResult<Module> {
+    let bytes = Bytes(data);
+    let title = bytes.string(0, 28)?;
+    let order_count = usize::from(bytes.u16_le(32)?);
+    let sample_count = usize::from(bytes.u16_le(34)?);
+    let pattern_count = usize::from(bytes.u16_le(36)?);
+    let unsigned_samples = bytes.u16_le(42)? == 2;
+    let global_volume = bytes.u8(48)?;
+    let speed = bytes.u8(49)?;
+    let tempo = bytes.u8(50)?;
+    let stereo = bytes.u8(51)? & 0x80 != 0;
+    let has_panning = bytes.u8(53)? == 252;
+    let channel_settings = bytes.slice(64, 32)?;
+
+    let order_table = bytes.slice(96, order_count)?;
+    let pointers = 96 + order_count;
+    let parapointer =
+        |i: usize| -> GameResult<usize> { Ok(usize::from(bytes.u16_le(pointers + i * 2)?) * 16) };
+
+    // Channels past the last one that is enabled are dropped.
+    let channels = channel_settings
+        .iter()
+        .rposition(|&c| c < 16)
+        .map_or(0, |last| last + 1);
+    let pan_table = if has_panning {
+        Some(bytes.slice(pointers + (sample_count + pattern_count) * 2, 32)?)
+    } else {
+        None
+    };
+    let panning = (0..channels)
+        .map(|i| {
+            let setting = pan_table.map_or(0, |table| table[i]);
+            if !stereo {
+                0x80
+            } else if setting & 0x20 != 0 {
+                (setting & 0x0F) * 17
+            } else if channel_settings[i] & 0x7F < 8 {
+                0x30
+            } else {
+                0xC0
+            }
+        })
+        .collect();
+
+    let mut samples = Vec::with_capacity(sample_count);
+    for i in 0..sample_count {
+        samples.push(read_sample(bytes, parapointer(i)?, unsigned_samples)?);
+    }
+    let mut patterns = Vec::with_capacity(pattern_count);
+    for i in 0..pattern_count {
+        patterns.push(read_pattern(
+            bytes,
+            parapointer(sample_count + i)?,
+            channels,
+        )?);
+    }
+
+    Ok(Module {
+        title,
+        format: TrackerFormat::S3m,
+        channels,
+        orders: order_table
+            .iter()
+            .take_while(|&&o| o != 255)
+            .filter(|&&o| o != 254)
+            .map(|&o| usize::from(o))
+            .collect(),
+        restart: 0,
+        patterns,
+        samples,
+        instruments: Vec::new(),
+        speed: if speed == 0 || speed == 255 { 6 } else { speed },
+        tempo: if tempo < 32 { 125 } else { tempo },
+        global_volume: f32::from(global_volume.min(64)) / 64.0,
+        panning,
+        linear: false,
+    })
+}
+
+fn read_sample(bytes: Bytes, offset: usize, unsigned: bool) -> GameResult<Sample> {
+    if offset == 0 || bytes.u8(offset)? != 1 {
+        return Ok(Sample::default());
+    }
+    let data_offset = ((usize::from(bytes.u8(offset + 13)?) << 16)
+        | usize::from(bytes.u16_le(offset + 14)?))
+        * 16;
+    let length = bytes.u32_le(offset + 16)? as usize;
+    let loop_start = bytes.u32_le(offset + 20)? as usize;
+    let loop_end = bytes.u32_le(offset + 24)? as usize;
+    let volume = bytes.u8(offset + 28)?.min(64);
+    let flags = bytes.u8(offset + 31)?;
+    let rate = bytes.u32_le(offset + 32)?;
+
+    let sixteen_bit = flags & 4 != 0;
+    let width = if sixteen_bit { 2 } else { 1 };
+    // Stereo samples have the whole left channel first; we only play that.
+    let raw = bytes.slice_lenient(data_offset, length * width);
+    let data = match (sixteen_bit, unsigned) {
+        (true, unsigned) => samples_16(raw, !unsigned),
+        (false, true) => samples_u8(raw),
+        (false, false) => samples_i8(raw),
+    };
+    Ok(Sample {
+        looping: if flags & 1 != 0 {
+            Loop::new(loop_start, loop_end, false, data.len())
+        } else {
+            None
+        },
+        data,
+        volume,
+        global_volume: 1.0,
+        base_rate: rate as f32,
+        ..Sample::default()
+    })
+}
+
+fn read_pattern(bytes: Bytes, offset: usize, channels: usize) -> GameResult<Pattern> {
+    let mut pattern = Pattern::new(64, channels);
+    if offset == 0 {
+        return Ok(pattern);
+    }
+    let length = usize::from(bytes.u16_le(offset)?);
+    let data = bytes.slice_lenient(offset + 2, length);
+    let mut data = data.iter().copied();
+    let mut next = || data.next().unwrap_or(0);
+    let mut row = 0;
+    while row < 64 {
+        let what = next();
+        if what == 0 {
+            row += 1;
+            continue;
+        }
+        let mut cell = Cell::default();
+        if what & 0x20 != 0 {
+            cell.note = match next() {
+                255 => Note::None,
+                254 => Note::Cut,
+                note => Note::On((note >> 4) * 12 + (note & 0x0F)),
+            };
+            cell.instrument = next();
+        }
+        if what & 0x40 != 0 {
+            cell.volume = VolumeCommand::Set(next().min(64));
+        }
+        if what & 0x80 != 0 {
+            let command = next();
+            let param = next();
+            cell.effect = read_effect(command, param);
+        }
+        let channel = usize::from(what & 0x1F);
+        if channel < channels {
+            pattern.cells[row * channels + channel] = cell;
+        }
+    }
+    Ok(pattern)
+}
+
+/// Decodes an S3M effect, which IT mostly shares.  Commands
+/// start from `1` for `A`.
+pub(crate) fn read_effect(command: u8, param: u8) -> Effect {
+    let (x, y) = (param >> 4, param & 0x0F);
+    let slide = VolumeSlide::from_dxy(param);
+    let portamento = |coarse: fn(u16) -> Effect, fine: fn(u16) -> Effect| match x {
+        0xF => fine(u16::from(y) * 4),
+        0xE => fine(u16::from(y)),
+        _ => coarse(u16::from(param) * 4),
+    };
+    match command {
+        1 if param != 0 => Effect::SetSpeed(param),
+        2 => Effect::PositionJump(param),
+        3 => Effect::PatternBreak(x * 10 + y),
+        4 => Effect::VolumeSlide(slide),
+        5 => portamento(Effect::PortamentoDown, Effect::FinePortamentoDown),
+        6 => portamento(Effect::PortamentoUp, Effect::FinePortamentoUp),
+        7 => Effect::TonePortamento(u16::from(param) * 4),
+        8 => Effect::Vibrato { speed: x, depth: y },
+        10 => Effect::Arpeggio(x, y),
+        11 => Effect::VibratoVolumeSlide(slide),
+        12 => Effect::TonePortamentoVolumeSlide(slide),
+        15 => Effect::SampleOffset(u32::from(param) * 256),
+        17 => Effect::Retrigger(y),
+        18 => Effect::Tremolo { speed: x, depth: y },
+        19 => match x {
+            0x8 => Effect::SetPanning(y * 17),
+            0xB => Effect::PatternLoop(y),
+            0xC => Effect::NoteCut(y),
+            0xD => Effect::NoteDelay(y),
+            0xE => Effect::PatternDelay(y),
+            _ => Effect::None,
+        },
+        20 if param >= 32 => Effect::SetTempo(param),
+        22 => Effect::SetGlobalVolume(param.min(64)),
+        24 => Effect::SetPanning((u16::from(param) * 2).min(255) as u8),
+        _ => Effect::None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effects_decode() {
+        assert_eq!(Effect::SetSpeed(3), read_effect(1, 3));
+        assert_eq!(Effect::PortamentoDown(8), read_effect(5, 2));
+        assert_eq!(Effect::FinePortamentoUp(12), read_effect(6, 0xF3));
+        assert_eq!(Effect::FinePortamentoUp(3), read_effect(6, 0xE3));
+        assert_eq!(
+            Effect::VolumeSlide(VolumeSlide::FineDown(2)),
+            read_effect(4, 0xF2)
+        );
+        assert_eq!(Effect::NoteDelay(3), read_effect(19, 0xD3));
+        assert_eq!(Effect::None, read_effect(20, 0x10));
+    }
+
+    #[test]
+    fn patterns_unpack() {
+        // One row with C-5, sample 1 and volume 40 on channel 2, then
+        // a row with a speed change on channel 0, then nothing.
+        let mut data = vec![0u8; 2];
+        data.extend([0x62, 0x50, 1, 40, 0, 0x80, 1, 3, 0]);
+        data.resize(data.len() + 62, 0);
+        let length = (data.len() - 2) as u16;
+        data[..2].copy_from_slice(&length.to_le_bytes());
+        // The parapointer can't be zero, so put it 16 bytes in.
+        let mut file = vec![0u8; 16];
+        file.extend(data);
+
+        let pattern = read_pattern(Bytes(&file), 16, 4).unwrap();
+        let cell = pattern.cell(0, 2, 4).unwrap();
+        assert_eq!(Note::On(60), cell.note);
+        assert_eq!(1, cell.instrument);
+        assert_eq!(VolumeCommand::Set(40), cell.volume);
+        assert_eq!(Effect::SetSpeed(3), pattern.cell(1, 0, 4).unwrap().effect);
+        assert_eq!(Cell::default(), *pattern.cell(2, 2, 4).unwrap());
+    }
+}
@@ -0,0 +1,284 @@
+//! Loading FastTracker 2 XM files.
+
+use super::module::*;
+use super::protracker;
+use super::TrackerFormat;
+use crate::error::GameResult;
+
+/// XM notes start from C-0 at one, but play an octave lower than ours.
+const NOTE_OFFSET: u8 = 11;
+
+pub(crate) fn load(data: &[u8]) -> GameResult<Module> {
+    let bytes = Bytes(data);
+    let title = bytes.string(17, 20)?;
+    let header_size = bytes.u32_le(60)? as usize;
+    let song_length = usize::from(bytes.u16_le(64)?).min(256);
+    let restart = usize::from(bytes.u16_le(66)?);
+    let channels = usize::from(bytes.u16_le(68)?);
+    let pattern_count = usize::from(bytes.u16_le(70)?);
+    let instrument_count = usize::from(bytes.u16_le(72)?);
+    let linear = bytes.u16_le(74)? & 1 != 0;
+    let speed = bytes.u16_le(76)?;
+    let tempo = bytes.u16_le(78)?;
+    let order_table = bytes.slice(80, song_length)?;
+    if channels == 0 || channels > 64 {
+        return Err(format_error("unsupported number of channels"));
+    }
+
+    let mut offset = 60 + header_size;
+    let mut patterns = Vec::with_capacity(pattern_count);
+    for _ in 0..pattern_count {
+        let header_length = bytes.u32_le(offset)? as usize;
+        let rows = usize::from(bytes.u16_le(offset + 5)?);
+        let packed_size = usize::from(bytes.u16_le(offset + 7)?);
+        let packed = bytes.slice(offset + header_length, packed_size)?;
+        patterns.push(read_pattern(packed, rows, channels));
+        offset += header_length + packed_size;
+    }
+
+    let mut samples = Vec::new();
+    let mut instruments = Vec::with_capacity(instrument_count);
+    for _ in 0..instrument_count {
+        let (instrument, end) = read_instrument(bytes, offset, &mut samples)?;
+        instruments.push(instrument);
+        offset = end;
+    }
+
+    Ok(Module {
+        title,
+        format: TrackerFormat::Xm,
+        channels,
+        orders: order_table.iter().map(|&o| usize::from(o)).collect(),
+        restart: if restart < song_length { restart } else { 0 },
+        patterns,
+        samples,
+        instruments,
+        speed: if speed == 0 { 6 } else { speed.min(31) as u8 },
+        tempo: tempo.clamp(32, 255) as u8,
+        global_volume: 1.0,
+        panning: vec![0x80; channels],
+        linear,
+    })
+}
+
+fn read_pattern(packed: &[u8], rows: usize, channels: usize) -> Pattern {
+    let mut pattern = Pattern::new(rows, channels);
+    let mut data = packed.iter().copied();
+    let mut next = || data.next().unwrap_or(0);
+    for cell in pattern.cells.iter_mut() {
+        let first = next();
+        let flags = if first & 0x80 != 0 { first } else { 0x1F };
+        let note = if first & 0x80 != 0 {
+            if flags & 1 != 0 {
+                next()
+            } else {
+                0
+            }
+        } else {
+            first
+        };
+        let instrument = if flags & 2 != 0 { next() } else { 0 };
+        let volume = if flags & 4 != 0 { next() } else { 0 };
+        let command = if flags & 8 != 0 { next() } else { 0 };
+        let param = if flags & 16 != 0 { next() } else { 0 };
+        *cell = Cell {
+            note: match note {
+                0 => Note::None,
+                97 => Note::Off,
+                note if note < 97 => Note::On(note + NOTE_OFFSET),
+                _ => Note::None,
+            },
+            instrument,
+            volume: read_volume(volume),
+            effect: read_effect(command, param),
+        };
+    }
+    pattern
+}
+
+fn read_volume(volume: u8) -> VolumeCommand {
+    let y = volume & 0x0F;
+    match volume >> 4 {
+        0x1..=0x4 => VolumeCommand::Set(volume - 0x10),
+        0x5 if volume == 0x50 => VolumeCommand::Set(64),
+        0x6 => VolumeCommand::Slide(VolumeSlide::Down(y)),
+        0x7 => VolumeCommand::Slide(VolumeSlide::Up(y)),
+        0x8 => VolumeCommand::Slide(VolumeSlide::FineDown(y)),
+        0x9 => VolumeCommand::Slide(VolumeSlide::FineUp(y)),
+        0xC => VolumeCommand::Panning(y * 17),
+        0xF => VolumeCommand::TonePortamento(u16::from(y) * 64),
+        _ => VolumeCommand::None,
+    }
+}
+
+fn read_effect(command: u8, param: u8) -> Effect {
+    let (x, y) = (param >> 4, param & 0x0F);
+    match command {
+        0x0..=0xF => protracker::read_effect(command, param),
+        // G
+        16 => Effect::SetGlobalVolume(param.min(64)),
+        // H
+        17 => Effect::GlobalVolumeSlide(VolumeSlide::from_xy(param)),
+        // K
+        20 => Effect::KeyOff(param),
+        // R
+        27 => Effect::Retrigger(y),
+        // X
+        33 => match x {
+            1 => Effect::FinePortamentoUp(u16::from(y)),
+            2 => Effect::FinePortamentoDown(u16::from(y)),
+            _ => Effect::None,
+        },
+        _ => Effect::None,
+    }
+}
+
+/// Reads an instrument and its samples, adding the samples to `samples`.
+/// Returns the instrument and the offset right after it.
+fn read_instrument(
+    bytes: Bytes,
+    offset: usize,
+    samples: &mut Vec<Sample>,
+) -> GameResult<(Instrument, usize)> {
+    let header_size = bytes.u32_le(offset)? as usize;
+    let sample_count = usize::from(bytes.u16_le(offset + 27)?);
+    let mut instrument = Instrument {
+        keymap: vec![(0, 0); NOTE_COUNT],
+        volume_envelope: None,
+        fadeout: 0.0,
+        panning: None,
+    };
+    let mut end = offset + header_size;
+    if sample_count == 0 {
+        return Ok((instrument, end));
+    }
+
+    let sample_header_size = bytes.u32_le(offset + 29)? as usize;
+    let keymap = bytes.slice(offset + 33, 96)?;
+    let first_sample = samples.len();
+    for (note, &sample) in keymap.iter().enumerate() {
+        if usize::from(sample) < sample_count {
+            let note = note as u8 + NOTE_OFFSET + 1;
+            let index = (first_sample + usize::from(sample) + 1) as u16;
+            instrument.keymap[usize::from(note)] = (note, index);
+        }
+    }
+
+    let points = bytes.slice(offset + 129, 48)?;
+    let point_count = usize::from(bytes.u8(offset + 225)?).min(12);
+    let sustain = usize::from(bytes.u8(offset + 227)?);
+    let loop_start = usize::from(bytes.u8(offset + 228)?);
+    let loop_end = usize::from(bytes.u8(offset + 229)?);
+    let envelope_flags = bytes.u8(offset + 233)?;
+    if envelope_flags & 1 != 0 {
+        let points = points
+            .chunks_exact(4)
+            .take(point_count)
+            .map(|p| {
+                let tick = u16::from_le_bytes([p[0], p[1]]);
+                (tick, p[2].min(64))
+            })
+            .collect();
+        instrument.volume_envelope = Envelope::new(
+            points,
+            Some((sustain, sustain)).filter(|_| envelope_flags & 2 != 0),
+            Some((loop_start, loop_end)).filter(|_| envelope_flags & 4 != 0),
+        );
+    }
+    instrument.fadeout = f32::from(bytes.u16_le(offset + 239)?) / 32768.0;
+
+    // The sample headers all come first, then the data of every sample.
+    let mut headers = Vec::with_capacity(sample_count);
+    for i in 0..sample_count {
+        let header = end + i * sample_header_size;
+        headers.push((
+            bytes.u32_le(header)? as usize,
+            bytes.u32_le(header + 4)? as usize,
+            bytes.u32_le(header + 8)? as usize,
+            bytes.u8(header + 12)?.min(64),
+            bytes.u8(header + 13)? as i8,
+            bytes.u8(header + 14)?,
+            bytes.u8(header + 15)?,
+            bytes.u8(header + 16)? as i8,
+        ));
+    }
+    end += sample_count * sample_header_size;
+    for (length, loop_start, loop_length, volume, finetune, flags, panning, relative_note) in
+        headers
+    {
+        let sixteen_bit = flags & 0x10 != 0;
+        let raw = bytes.slice_lenient(end, length);
+        end += length;
+        let data = if sixteen_bit {
+            delta_decode_16(raw)
+        } else {
+            delta_decode_8(raw)
+        };
+        let width = if sixteen_bit { 2 } else { 1 };
+        let (loop_start, loop_end) = (loop_start / width, (loop_start + loop_length) / width);
+        let semitones = f32::from(relative_note) + f32::from(finetune) / 128.0;
+        samples.push(Sample {
+            looping: match flags & 3 {
+                1 => Loop::new(loop_start, loop_end, false, data.len()),
+                2 => Loop::new(loop_start, loop_end, true, data.len()),
+                _ => None,
+            },
+            data,
+            volume,
+            global_volume: 1.0,
+            panning: Some(panning),
+            base_rate: tuned_rate(semitones),
+            ..Sample::default()
+        });
+    }
+    Ok((instrument, end))
+}
+
+fn delta_decode_8(raw: &[u8]) -> Vec<f32> {
+    let mut value = 0i8;
+    raw.iter()
+        .map(|&delta| {
+            value = value.wrapping_add(delta as i8);
+            f32::from(value) / 128.0
+        })
+        .collect()
+}
+
+fn delta_decode_16(raw: &[u8]) -> Vec<f32> {
+    let mut value = 0i16;
+    raw.chunks_exact(2)
+        .map(|b| {
+            value = value.wrapping_add(i16::from_le_bytes([b[0], b[1]]));
+            f32::from(value) / 32768.0
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_unpack() {
+        // A full cell, then a packed one with just a key off,
+        // then one with a volume and an effect.
+        let packed = [49, 1, 0x30, 0x0C, 0x20, 0x81, 97, 0x9C, 0x60 | 2, 0x0F, 3];
+        let pattern = read_pattern(&packed, 1, 3);
+        assert_eq!(Note::On(60), pattern.cells[0].note);
+        assert_eq!(1, pattern.cells[0].instrument);
+        assert_eq!(VolumeCommand::Set(0x20), pattern.cells[0].volume);
+        assert_eq!(Effect::SetVolume(0x20), pattern.cells[0].effect);
+        assert_eq!(Note::Off, pattern.cells[1].note);
+        assert_eq!(
+            VolumeCommand::Slide(VolumeSlide::Down(2)),
+            pattern.cells[2].volume
+        );
+        assert_eq!(Effect::SetSpeed(3), pattern.cells[2].effect);
+    }
+
+    #[test]
+    fn samples_are_delta_decoded() {
+        assert_eq!(vec![0.5, 0.25, 0.25], delta_decode_8(&[64, 224, 0]));
+        assert_eq!(vec![0.5], delta_decode_16(&0x4000i16.to_le_bytes()));
+    }
+}
@@ -0,0 +1,66 @@
+//! Just enough of the WAV format to write 16-bit PCM files.
+
+use std::io::{self, Write};
+
+/// Converts a sample in the range `-1.0..=1.0` to a 16-bit one,
+/// clipping anything outside that range.
+pub(crate) fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16
+}
+
+/// Writes interleaved samples as a 16-bit PCM WAV file.
+pub(crate) fn write<W: Write>(
+    writer: &mut W,
+    channels: u16,
+    sample_rate: u32,
+    samples: &[i16],
+) -> io::Result<()> {
+    let block_align = channels * 2;
+    let data_len = samples.len() as u32 * 2;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_len).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    // Uncompressed PCM
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&channels.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * u32::from(block_align)).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&16u16.to_le_bytes())?;
+
+    writer.write_all(b"data")?;
+    writer.write_all(&data_len.to_le_bytes())?;
+    for sample in samples {
+        writer.write_all(&sample.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn written_files_decode() {
+        use rodio::Source;
+        let samples = [0, 1000, -1000, i16::MAX, i16::MIN, 7];
+        let mut bytes = Vec::new();
+        write(&mut bytes, 2, 22050, &samples).unwrap();
+
+        let decoder = rodio::Decoder::new(io::Cursor::new(bytes)).unwrap();
+        assert_eq!(2, decoder.channels());
+        assert_eq!(22050, decoder.sample_rate());
+        assert_eq!(samples.to_vec(), decoder.collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn samples_clip() {
+        assert_eq!(i16::MAX, to_i16(2.0));
+        assert_eq!(-i16::MAX, to_i16(-1.5));
+        assert_eq!(0, to_i16(0.0));
+    }
+}
@@ -362,6 +362,7 @@ impl From<NumSamples> for u8 {
 /// ModuleConf {
 ///     gamepad: true,
 ///     audio: true,
+///     audio_capture: false,
 /// }
 /// # , ModuleConf::default()); }
 /// ```
@@ -374,6 +375,14 @@ pub struct ModuleConf {
     /// The audio module.
     #[default = true]
     pub audio: bool,
+
+    /// Whether to mix audio into memory with a
+    /// [`CaptureAudioContext`](../audio/struct.CaptureAudioContext.html)
+    /// instead of playing it on an output device.  This takes precedence
+    /// over `audio`.
+    #[serde(default)]
+    #[default = false]
+    pub audio_capture: bool,
 }
 
 impl ModuleConf {
@@ -388,6 +397,101 @@ impl ModuleConf {
         self.audio = audio;
         self
     }
+
+    /// Sets whether to capture audio into memory instead of
+    /// playing it on an output device.
+    pub fn audio_capture(mut self, audio_capture: bool) -> Self {
+        self.audio_capture = audio_capture;
+        self
+    }
+}
+
+/// Settings for how [`event::run()`](../event/fn.run.html) paces
+/// the game loop.
+///
+/// Defaults:
+///
+/// ```rust
+/// # use ggez::conf::*;
+/// # fn main() { assert_eq!(
+/// TimingSetup {
+///     fixed_update_fps: None,
+///     max_fixed_updates: 5,
+///     target_fps: None,
+/// }
+/// # , TimingSetup::default()); }
+/// ```
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, SmartDefault)]
+pub struct TimingSetup {
+    /// If set, [`EventHandler::fixed_update()`](../event/trait.EventHandler.html#method.fixed_update)
+    /// is called this many times per second of game time, as many times
+    /// each frame as it takes to catch up.  `Some(0)` is treated like `None`.
+    #[default(None)]
+    pub fixed_update_fps: Option<u32>,
+    /// The most fixed updates to run in one frame.  If the game falls
+    /// further behind than that, the rest of the backlog is dropped instead
+    /// of making every following frame even slower.  With 0, no fixed
+    /// updates are run at all.
+    #[default = 5]
+    pub max_fixed_updates: u32,
+    /// If set, frames are started no more often than this many times per
+    /// second, sleeping in between instead of spinning at full speed.
+    /// Useful when vsync is off or unavailable.  `Some(0)` is treated like `None`.
+    #[default(None)]
+    pub target_fps: Option<u32>,
+}
+
+impl TimingSetup {
+    /// Sets the number of fixed updates per second, or `None` to disable
+    /// fixed updates.
+    pub fn fixed_update_fps(mut self, fps: Option<u32>) -> Self {
+        self.fixed_update_fps = fps;
+        self
+    }
+
+    /// Sets the most fixed updates to run in one frame.
+    pub fn max_fixed_updates(mut self, max_fixed_updates: u32) -> Self {
+        self.max_fixed_updates = max_fixed_updates;
+        self
+    }
+
+    /// Sets the frame rate to cap the game loop at, or `None` to run as
+    /// fast as possible (or as vsync allows).
+    pub fn target_fps(mut self, fps: Option<u32>) -> Self {
+        self.target_fps = fps;
+        self
+    }
+}
+
+/// Settings for the audio output.
+///
+/// Defaults:
+///
+/// ```rust
+/// # use ggez::conf::*;
+/// # fn main() { assert_eq!(
+/// AudioSetup {
+///     output_device: None,
+/// }
+/// # , AudioSetup::default()); }
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, SmartDefault)]
+pub struct AudioSetup {
+    /// The name of the output device to play sound on, as listed by
+    /// [`audio::output_devices()`](../audio/fn.output_devices.html),
+    /// or `None` for the system's default.  If the device isn't there
+    /// the default one is used instead.
+    #[default(None)]
+    pub output_device: Option<String>,
+}
+
+impl AudioSetup {
+    /// Sets the name of the output device to play sound on,
+    /// or `None` for the system's default.
+    pub fn output_device(mut self, name: Option<&str>) -> Self {
+        self.output_device = name.map(String::from);
+        self
+    }
 }
 
 /// A structure containing configuration data
@@ -403,6 +507,8 @@ impl ModuleConf {
 ///     window_setup: WindowSetup::default(),
 ///     backend: Backend::default(),
 ///     modules: ModuleConf::default(),
+///     timing: TimingSetup::default(),
+///     audio: AudioSetup::default(),
 /// }
 /// # , Conf::default()); }
 /// ```
@@ -416,6 +522,12 @@ pub struct Conf {
     pub backend: Backend,
     /// Which modules to enable.
     pub modules: ModuleConf,
+    /// Game loop timing settings
+    #[serde(default)]
+    pub timing: TimingSetup,
+    /// Audio output settings
+    #[serde(default)]
+    pub audio: AudioSetup,
 }
 
 impl Conf {
@@ -458,6 +570,18 @@ impl Conf {
         self.modules = modules;
         self
     }
+
+    /// Sets the game loop timing settings
+    pub fn timing(mut self, timing: TimingSetup) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Sets the audio output settings
+    pub fn audio(mut self, audio: AudioSetup) -> Self {
+        self.audio = audio;
+        self
+    }
 }
 
 #[cfg(test)]
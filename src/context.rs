@@ -57,6 +57,10 @@ pub struct Context {
     /// Controls whether or not the event loop should be running.
     /// Set this with `ggez::event::quit()`.
     pub continuing: bool,
+    /// Whether real keyboard, mouse and gamepad events are kept from
+    /// changing the input state, because an
+    /// [`InputPlayer`](input/recording/struct.InputPlayer.html) is playing.
+    pub(crate) block_live_input: bool,
 
     /// Context-specific unique ID.
     /// Compiles to nothing in release mode, and so
@@ -79,13 +83,7 @@ impl Context {
         mut fs: Filesystem,
     ) -> GameResult<(Context, winit::event_loop::EventLoop<()>)> {
         let debug_id = DebugId::new();
-        let audio_context: Box<dyn audio::AudioContext> = if conf.modules.audio {
-            Box::new(audio::RodioAudioContext::new()?)
-        } else {
-            Box::new(audio::NullAudioContext::default())
-        };
         let events_loop = winit::event_loop::EventLoop::new();
-        let timer_context = timer::TimeContext::new();
         let backend_spec = graphics::GlBackendSpec::from(conf.backend);
         let graphics_context = graphics::context::GraphicsContext::new(
             &mut fs,
@@ -95,6 +93,46 @@ impl Context {
             backend_spec,
             debug_id,
         )?;
+        let ctx = Context::from_parts(conf, fs, graphics_context, debug_id, false)?;
+
+        Ok((ctx, events_loop))
+    }
+
+    /// Tries to create a new headless Context, without a window or event loop,
+    /// using settings from the given [`Conf`](../conf/struct.Conf.html) object.
+    /// Usually called by [`ContextBuilder::headless()`](struct.ContextBuilder.html#method.headless).
+    fn from_conf_headless(conf: conf::Conf, fs: Filesystem) -> GameResult<Context> {
+        let debug_id = DebugId::new();
+        let backend_spec = graphics::GlBackendSpec::from(conf.backend);
+        let graphics_context = graphics::context::GraphicsContext::new_headless(
+            &conf.window_setup,
+            conf.window_mode,
+            backend_spec,
+            debug_id,
+        )?;
+        Context::from_parts(conf, fs, graphics_context, debug_id, true)
+    }
+
+    /// Creates all the non-graphics state and puts the `Context` together.
+    /// A headless `Context` captures audio instead of looking for a device.
+    fn from_parts(
+        conf: conf::Conf,
+        fs: Filesystem,
+        graphics_context: graphics::context::GraphicsContext,
+        debug_id: DebugId,
+        headless: bool,
+    ) -> GameResult<Context> {
+        let capture = conf.modules.audio_capture || (headless && conf.modules.audio);
+        let audio_context: Box<dyn audio::AudioContext> = if capture {
+            Box::new(audio::CaptureAudioContext::new(2, 44100))
+        } else if conf.modules.audio {
+            Box::new(audio::RodioAudioContext::new(
+                conf.audio.output_device.as_deref(),
+            )?)
+        } else {
+            Box::new(audio::NullAudioContext::default())
+        };
+        let timer_context = timer::TimeContext::new();
         let mouse_context = mouse::MouseContext::new();
         let keyboard_context = keyboard::KeyboardContext::new();
         let gamepad_context: Box<dyn gamepad::GamepadContext> = if conf.modules.gamepad {
@@ -103,11 +141,12 @@ impl Context {
             Box::new(gamepad::NullGamepadContext::default())
         };
 
-        let ctx = Context {
+        Ok(Context {
             conf,
             filesystem: fs,
             gfx_context: graphics_context,
             continuing: true,
+            block_live_input: false,
             timer_context,
             audio_context,
             keyboard_context,
@@ -115,9 +154,7 @@ impl Context {
             mouse_context,
 
             debug_id,
-        };
-
-        Ok((ctx, events_loop))
+        })
     }
 }
 
@@ -176,6 +213,18 @@ impl ContextBuilder {
         self
     }
 
+    /// Sets the game loop timing settings.
+    pub fn timing(mut self, timing: conf::TimingSetup) -> Self {
+        self.conf.timing = timing;
+        self
+    }
+
+    /// Sets the audio output settings.
+    pub fn audio(mut self, audio: conf::AudioSetup) -> Self {
+        self.conf.audio = audio;
+        self
+    }
+
     /// Sets all the config options, overriding any previous
     /// ones from [`window_setup()`](#method.window_setup),
     /// [`window_mode()`](#method.window_mode), and
@@ -241,6 +290,45 @@ impl ContextBuilder {
 
     /// Build the `Context`.
     pub fn build(self) -> GameResult<(Context, winit::event_loop::EventLoop<()>)> {
+        let (config, fs) = self.load()?;
+        Context::from_conf(config, fs)
+    }
+
+    /// Build a headless `Context`, which has no window and no event loop.
+    /// Instead of a window it renders into an offscreen framebuffer of the
+    /// given size, using a software renderer (OSMesa) where there may not
+    /// be a display at all, such as on CI machines.
+    ///
+    /// [`graphics::present()`](graphics/fn.present.html),
+    /// [`graphics::screenshot()`](graphics/fn.screenshot.html) and
+    /// canvases work as usual, so this is handy for testing what your game
+    /// draws.  Anything that needs an actual window, such as
+    /// [`graphics::window()`](graphics/fn.window.html) or the mouse cursor
+    /// functions, will panic.
+    ///
+    /// Since there is no event loop you can't use
+    /// [`event::run()`](event/fn.run.html) with it; drive your game
+    /// yourself instead.
+    ///
+    /// There may not be an audio device either, so when the audio module
+    /// is on, sounds are mixed into memory by a
+    /// [`CaptureAudioContext`](audio/struct.CaptureAudioContext.html)
+    /// instead of played.  A `conf.toml` is loaded the same way as by
+    /// [`build()`](#method.build), except that the size given here
+    /// always wins.
+    ///
+    /// This works on Windows and on Unix-like systems other than macOS,
+    /// where it renders with OSMesa; elsewhere it returns an error.
+    pub fn headless(self, width: u16, height: u16) -> GameResult<Context> {
+        let (mut config, fs) = self.load()?;
+        config.window_mode = config
+            .window_mode
+            .dimensions(f32::from(width), f32::from(height));
+        Context::from_conf_headless(config, fs)
+    }
+
+    /// Sets up the filesystem and figures out the final `Conf` to use.
+    fn load(self) -> GameResult<(conf::Conf, Filesystem)> {
         let mut fs = Filesystem::new(
             self.game_id.as_ref(),
             self.author.as_ref(),
@@ -262,7 +350,7 @@ impl ContextBuilder {
             self.conf
         };
 
-        Context::from_conf(config, fs)
+        Ok((config, fs))
     }
 }
 
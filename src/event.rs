@@ -35,7 +35,11 @@ use self::winit_event::*;
 /// `winit` event loop.
 pub use winit::event_loop::{ControlFlow, EventLoop};
 
+use crate::audio;
 use crate::context::Context;
+use crate::timer;
+
+use std::time;
 
 /// Used in [`EventHandler::on_error()`](trait.EventHandler.html#method.on_error)
 /// to specify where an error originated
@@ -43,6 +47,8 @@ use crate::context::Context;
 pub enum ErrorOrigin {
     /// error originated in `update()`
     Update,
+    /// error originated in `fixed_update()`
+    FixedUpdate,
     /// error originated in `draw()`
     Draw,
 }
@@ -68,6 +74,18 @@ where
     /// This should be where the game's logic takes place.
     fn update(&mut self, _ctx: &mut Context) -> Result<(), E>;
 
+    /// Called at a fixed rate, set by
+    /// [`conf::TimingSetup::fixed_update_fps`](../conf/struct.TimingSetup.html#structfield.fixed_update_fps),
+    /// before each frame's `update()`; zero or more times per frame,
+    /// however many it takes to keep up with the clock.
+    ///
+    /// Game logic that should run at a steady rate, such as physics,
+    /// belongs here.  Use [`timer::interpolation_alpha()`](../timer/fn.interpolation_alpha.html)
+    /// in `draw()` to smooth out the difference between fixed updates and frames.
+    fn fixed_update(&mut self, _ctx: &mut Context) -> Result<(), E> {
+        Ok(())
+    }
+
     /// Called to do the drawing of your game.
     /// You probably want to start this with
     /// [`graphics::clear()`](../graphics/fn.clear.html) and end it
@@ -178,8 +196,9 @@ pub fn quit(ctx: &mut Context) {
 /// Runs the game's main loop, calling event callbacks on the given state
 /// object as events occur.
 ///
-/// It does not try to do any type of framerate limiting.  See the
-/// documentation for the [`timer`](../timer/index.html) module for more info.
+/// It does not try to do any type of framerate limiting unless
+/// [`conf.timing.target_fps`](../conf/struct.TimingSetup.html#structfield.target_fps)
+/// is set.  See the documentation for the [`timer`](../timer/index.html) module for more info.
 pub fn run<S: 'static, E>(mut ctx: Context, event_loop: EventLoop<()>, mut state: S) -> !
 where
     S: EventHandler<E>,
@@ -193,7 +212,10 @@ where
             return;
         }
 
-        *control_flow = ControlFlow::Poll;
+        *control_flow = match ctx.conf.timing.target_fps.filter(|&fps| fps > 0) {
+            Some(fps) => ControlFlow::WaitUntil(ctx.timer_context.next_frame_wakeup(fps)),
+            None => ControlFlow::Poll,
+        };
 
         let ctx = &mut ctx;
         let state = &mut state;
@@ -216,7 +238,7 @@ where
                 WindowEvent::ReceivedCharacter(ch) => {
                     state.text_input_event(ctx, ch);
                 }
-                WindowEvent::ModifiersChanged(mods) => {
+                WindowEvent::ModifiersChanged(mods) if !ctx.block_live_input => {
                     ctx.keyboard_context.set_modifiers(KeyMods::from(mods))
                 }
                 WindowEvent::KeyboardInput {
@@ -246,7 +268,7 @@ where
                     let (x, y) = match delta {
                         MouseScrollDelta::LineDelta(x, y) => (x, y),
                         MouseScrollDelta::PixelDelta(pos) => {
-                            let scale_factor = ctx.gfx_context.window.window().scale_factor();
+                            let scale_factor = ctx.gfx_context.window.scale_factor();
                             let dpi::LogicalPosition { x, y } = pos.to_logical::<f32>(scale_factor);
                             (x, y)
                         }
@@ -283,11 +305,19 @@ where
             Event::NewEvents(_) => (),
             Event::UserEvent(_) => (),
             Event::MainEventsCleared => {
+                // Events woke us up early, keep waiting for the next frame.
+                if let Some(fps) = ctx.conf.timing.target_fps.filter(|&fps| fps > 0) {
+                    if !ctx.timer_context.spin_until_next_frame(fps) {
+                        return;
+                    }
+                }
+
                 // If you are writing your own event loop, make sure
                 // you include `timer_context.tick()` and
                 // `ctx.process_event()` calls.  These update ggez's
                 // internal state however necessary.
                 ctx.timer_context.tick();
+                audio::follow_timer(ctx);
 
                 // Handle gamepad events if necessary.
                 if ctx.conf.modules.gamepad {
@@ -296,10 +326,18 @@ where
                     {
                         match event {
                             gilrs::EventType::ButtonPressed(button, _) => {
-                                state.gamepad_button_down_event(ctx, button, GamepadId(id));
+                                let id = GamepadId(id);
+                                if !ctx.block_live_input {
+                                    ctx.gamepad_context.set_button(id, button, true);
+                                }
+                                state.gamepad_button_down_event(ctx, button, id);
                             }
                             gilrs::EventType::ButtonReleased(button, _) => {
-                                state.gamepad_button_up_event(ctx, button, GamepadId(id));
+                                let id = GamepadId(id);
+                                if !ctx.block_live_input {
+                                    ctx.gamepad_context.set_button(id, button, false);
+                                }
+                                state.gamepad_button_up_event(ctx, button, id);
                             }
                             gilrs::EventType::AxisChanged(axis, value, _) => {
                                 state.gamepad_axis_event(ctx, axis, value, GamepadId(id));
@@ -309,6 +347,15 @@ where
                     }
                 }
 
+                if let Err(e) = fixed_updates(ctx, state) {
+                    error!("Error on EventHandler::fixed_update(): {:?}", e);
+                    eprintln!("Error on EventHandler::fixed_update(): {:?}", e);
+                    if state.on_error(ctx, ErrorOrigin::FixedUpdate, e) {
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                }
+
                 if let Err(e) = state.update(ctx) {
                     error!("Error on EventHandler::update(): {:?}", e);
                     eprintln!("Error on EventHandler::update(): {:?}", e);
@@ -330,6 +377,11 @@ where
                 // reset the mouse delta for the next frame
                 // necessary because it's calculated cumulatively each cycle
                 ctx.mouse_context.reset_delta();
+                // and remember this frame's input state for the
+                // `just_pressed`/`just_released` queries
+                ctx.keyboard_context.save_keyboard_state();
+                ctx.mouse_context.save_mouse_state();
+                ctx.gamepad_context.save_gamepad_state();
             }
             Event::RedrawRequested(_) => (),
             Event::RedrawEventsCleared => (),
@@ -342,8 +394,12 @@ where
 /// state it needs to, such as detecting window resizes.  If you are
 /// rolling your own event loop, you should call this on the events
 /// you receive before processing them yourself.
+///
+/// While an [`InputPlayer`](../input/recording/struct.InputPlayer.html)
+/// is playing, keyboard and mouse events are left out of the input state.
 pub fn process_event(ctx: &mut Context, event: &mut winit::event::Event<()>) {
     if let winit_event::Event::WindowEvent { event, .. } = event {
+        let live_input = !ctx.block_live_input;
         match event {
             winit_event::WindowEvent::Resized(physical_size) => {
                 ctx.gfx_context.window.resize(*physical_size);
@@ -352,35 +408,17 @@ pub fn process_event(ctx: &mut Context, event: &mut winit::event::Event<()>) {
             winit_event::WindowEvent::CursorMoved {
                 position: logical_position,
                 ..
-            } => {
-                let current_delta = crate::input::mouse::delta(ctx);
-                let current_pos = crate::input::mouse::position(ctx);
-                let diff = crate::graphics::Point2::new(
-                    logical_position.x as f32 - current_pos.x,
-                    logical_position.y as f32 - current_pos.y,
-                );
-                // Sum up the cumulative mouse change for this frame in `delta`:
-                ctx.mouse_context.set_delta(crate::graphics::Point2::new(
-                    current_delta.x + diff.x,
-                    current_delta.y + diff.y,
-                ));
-                // `last_delta` is not cumulative.
-                // It represents only the change between the last mouse event and the current one.
-                ctx.mouse_context.set_last_delta(diff);
-                ctx.mouse_context
-                    .set_last_position(crate::graphics::Point2::new(
-                        logical_position.x as f32,
-                        logical_position.y as f32,
-                    ));
+            } if live_input => {
+                set_mouse_position(ctx, logical_position.x as f32, logical_position.y as f32);
             }
-            winit_event::WindowEvent::MouseInput { button, state, .. } => {
+            winit_event::WindowEvent::MouseInput { button, state, .. } if live_input => {
                 let pressed = match state {
                     winit_event::ElementState::Pressed => true,
                     winit_event::ElementState::Released => false,
                 };
                 ctx.mouse_context.set_button(*button, pressed);
             }
-            winit_event::WindowEvent::ModifiersChanged(mods) => ctx
+            winit_event::WindowEvent::ModifiersChanged(mods) if live_input => ctx
                 .keyboard_context
                 .set_modifiers(crate::input::keyboard::KeyMods::from(*mods)),
             winit_event::WindowEvent::KeyboardInput {
@@ -391,7 +429,7 @@ pub fn process_event(ctx: &mut Context, event: &mut winit::event::Event<()>) {
                         ..
                     },
                 ..
-            } => {
+            } if live_input => {
                 let pressed = match state {
                     winit_event::ElementState::Pressed => true,
                     winit_event::ElementState::Released => false,
@@ -412,3 +450,254 @@ pub fn process_event(ctx: &mut Context, event: &mut winit::event::Event<()>) {
         }
     };
 }
+
+/// Moves the mouse to the given position, keeping track of how far it moved.
+fn set_mouse_position(ctx: &mut Context, x: f32, y: f32) {
+    let current_delta = crate::input::mouse::delta(ctx);
+    let current_pos = crate::input::mouse::position(ctx);
+    let diff = crate::graphics::Point2::new(x - current_pos.x, y - current_pos.y);
+    // Sum up the cumulative mouse change for this frame in `delta`:
+    ctx.mouse_context.set_delta(crate::graphics::Point2::new(
+        current_delta.x + diff.x,
+        current_delta.y + diff.y,
+    ));
+    // `last_delta` is not cumulative.
+    // It represents only the change between the last mouse event and the current one.
+    ctx.mouse_context.set_last_delta(diff);
+    ctx.mouse_context
+        .set_last_position(crate::graphics::Point2::new(x, y));
+}
+
+/// A synthetic input event, for feeding input to an
+/// [`EventHandler`](trait.EventHandler.html) without going through
+/// a window; see [`Runner`](struct.Runner.html).
+///
+/// Each variant updates ggez's input state the same way the matching
+/// real event would, and then calls the matching `EventHandler` callback.
+///
+/// Input events can be serialized; see the
+/// [`recording`](../input/recording/index.html) module.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "args")]
+pub enum InputEvent {
+    /// A keyboard key was pressed.
+    KeyDown(KeyCode),
+    /// A keyboard key was released.
+    KeyUp(KeyCode),
+    /// A unicode character was received.
+    TextInput(char),
+    /// The mouse was moved to the given position, in window coordinates.
+    MouseMotion {
+        /// Horizontal position
+        x: f32,
+        /// Vertical position
+        y: f32,
+    },
+    /// A mouse button was pressed at the current mouse position.
+    MouseButtonDown(#[serde(with = "crate::input::mouse::button_serde")] MouseButton),
+    /// A mouse button was released at the current mouse position.
+    MouseButtonUp(#[serde(with = "crate::input::mouse::button_serde")] MouseButton),
+    /// The mousewheel was scrolled.
+    MouseWheel {
+        /// Horizontal scroll amount
+        x: f32,
+        /// Vertical scroll amount
+        y: f32,
+    },
+    /// The mouse entered (`true`) or left (`false`) the window area.
+    MouseEnterOrLeave(bool),
+    /// A gamepad button was pressed.
+    GamepadButtonDown {
+        /// The button
+        button: Button,
+        /// The gamepad it belongs to
+        id: GamepadId,
+    },
+    /// A gamepad button was released.
+    GamepadButtonUp {
+        /// The button
+        button: Button,
+        /// The gamepad it belongs to
+        id: GamepadId,
+    },
+    /// A gamepad axis moved to the given value.
+    GamepadAxis {
+        /// The axis
+        axis: Axis,
+        /// The new value of the axis
+        value: f32,
+        /// The gamepad it belongs to
+        id: GamepadId,
+    },
+    /// The window gained (`true`) or lost (`false`) focus.
+    Focus(bool),
+    /// The window was asked to close.
+    Quit,
+}
+
+/// Calls `fixed_update()` as many times as the time passed since
+/// the last frame calls for, if fixed updates are enabled.
+fn fixed_updates<S, E>(ctx: &mut Context, state: &mut S) -> Result<(), E>
+where
+    S: EventHandler<E>,
+    E: std::error::Error,
+{
+    let timing = ctx.conf.timing;
+    if let Some(fps) = timing.fixed_update_fps.filter(|&fps| fps > 0) {
+        let mut updates = 0;
+        while updates < timing.max_fixed_updates && timer::check_update_time(ctx, fps) {
+            state.fixed_update(ctx)?;
+            updates += 1;
+        }
+        if updates >= timing.max_fixed_updates {
+            // Don't try to catch up any further; a frame that
+            // takes too long would only make the next one longer.
+            ctx.timer_context.drop_update_backlog(fps);
+        }
+    }
+    Ok(())
+}
+
+/// Updates the `Context`'s input state according to the given
+/// `InputEvent` and calls the matching callback on `state`.
+pub(crate) fn dispatch_input_event<S, E>(ctx: &mut Context, state: &mut S, event: InputEvent)
+where
+    S: EventHandler<E>,
+    E: std::error::Error,
+{
+    use crate::input::{keyboard, mouse};
+
+    match event {
+        InputEvent::KeyDown(keycode) => {
+            ctx.keyboard_context.set_key(keycode, true);
+            let repeat = keyboard::is_key_repeated(ctx);
+            state.key_down_event(ctx, keycode, ctx.keyboard_context.active_mods(), repeat);
+        }
+        InputEvent::KeyUp(keycode) => {
+            ctx.keyboard_context.set_key(keycode, false);
+            state.key_up_event(ctx, keycode, ctx.keyboard_context.active_mods());
+        }
+        InputEvent::TextInput(ch) => state.text_input_event(ctx, ch),
+        InputEvent::MouseMotion { x, y } => {
+            set_mouse_position(ctx, x, y);
+            let delta = mouse::last_delta(ctx);
+            state.mouse_motion_event(ctx, x, y, delta.x, delta.y);
+        }
+        InputEvent::MouseButtonDown(button) => {
+            ctx.mouse_context.set_button(button, true);
+            let position = mouse::position(ctx);
+            state.mouse_button_down_event(ctx, button, position.x, position.y);
+        }
+        InputEvent::MouseButtonUp(button) => {
+            ctx.mouse_context.set_button(button, false);
+            let position = mouse::position(ctx);
+            state.mouse_button_up_event(ctx, button, position.x, position.y);
+        }
+        InputEvent::MouseWheel { x, y } => state.mouse_wheel_event(ctx, x, y),
+        InputEvent::MouseEnterOrLeave(entered) => state.mouse_enter_or_leave(ctx, entered),
+        InputEvent::GamepadButtonDown { button, id } => {
+            ctx.gamepad_context.set_button(id, button, true);
+            state.gamepad_button_down_event(ctx, button, id)
+        }
+        InputEvent::GamepadButtonUp { button, id } => {
+            ctx.gamepad_context.set_button(id, button, false);
+            state.gamepad_button_up_event(ctx, button, id)
+        }
+        InputEvent::GamepadAxis { axis, value, id } => {
+            state.gamepad_axis_event(ctx, axis, value, id)
+        }
+        InputEvent::Focus(gained) => state.focus_event(ctx, gained),
+        InputEvent::Quit => {
+            if !state.quit_event(ctx) {
+                quit(ctx);
+            }
+        }
+    }
+}
+
+/// Steps an [`EventHandler`](trait.EventHandler.html) one frame at a
+/// time, as an alternative to [`run()`](fn.run.html) which never returns.
+///
+/// Each call to [`step()`](#method.step) feeds the given synthetic input
+/// to the game, advances the timer by a fixed delta and calls
+/// `update()` and `draw()` once, so the same input always gives the same
+/// result.  This makes it handy for testing game logic or replaying input,
+/// especially together with a
+/// [headless `Context`](../struct.ContextBuilder.html#method.headless).
+///
+/// ```rust,no_run
+/// # use ggez::{Context, ContextBuilder, GameResult, event::{self, EventHandler, InputEvent, KeyCode}};
+/// # struct MainState;
+/// # impl EventHandler for MainState {
+/// #     fn update(&mut self, _ctx: &mut Context) -> GameResult { Ok(()) }
+/// #     fn draw(&mut self, _ctx: &mut Context) -> GameResult { Ok(()) }
+/// # }
+/// # fn main() -> GameResult {
+/// let mut ctx = ContextBuilder::new("test", "ggez").headless(800, 600)?;
+/// let mut state = MainState;
+/// let mut runner = event::Runner::new(std::time::Duration::from_millis(16));
+/// runner.step(&mut ctx, &mut state, vec![InputEvent::KeyDown(KeyCode::Space)])?;
+/// for _ in 0..60 {
+///     runner.step(&mut ctx, &mut state, None)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Runner {
+    delta: time::Duration,
+}
+
+impl Runner {
+    /// Creates a new `Runner` that advances the timer by `delta` each step.
+    pub fn new(delta: time::Duration) -> Self {
+        Self { delta }
+    }
+
+    /// Returns how much the timer is advanced each step.
+    pub fn delta(&self) -> time::Duration {
+        self.delta
+    }
+
+    /// Sets how much the timer is advanced each step.
+    pub fn set_delta(&mut self, delta: time::Duration) {
+        self.delta = delta;
+    }
+
+    /// Runs a single frame: feeds `events` to `state` in order, ticks the
+    /// timer by the fixed delta and then calls `update()` and `draw()`.
+    ///
+    /// Unlike [`run()`](fn.run.html) this does not call
+    /// [`EventHandler::on_error()`](trait.EventHandler.html#method.on_error),
+    /// errors are returned directly instead.  It doesn't stop you from
+    /// stepping after [`quit()`](fn.quit.html) either, check
+    /// [`Context.continuing`](../struct.Context.html#structfield.continuing)
+    /// if you care about that.
+    pub fn step<S, E, I>(&mut self, ctx: &mut Context, state: &mut S, events: I) -> Result<(), E>
+    where
+        S: EventHandler<E>,
+        E: std::error::Error,
+        I: IntoIterator<Item = InputEvent>,
+    {
+        for event in events {
+            dispatch_input_event(ctx, state, event);
+        }
+
+        ctx.timer_context.tick_by(self.delta);
+        audio::follow_timer(ctx);
+
+        fixed_updates(ctx, state)?;
+        state.update(ctx)?;
+        state.draw(ctx)?;
+
+        // reset the mouse delta for the next frame
+        // necessary because it's calculated cumulatively each cycle
+        ctx.mouse_context.reset_delta();
+        // and remember this frame's input state for the
+        // `just_pressed`/`just_released` queries
+        ctx.keyboard_context.save_keyboard_state();
+        ctx.mouse_context.save_mouse_state();
+        ctx.gamepad_context.save_gamepad_state();
+        Ok(())
+    }
+}
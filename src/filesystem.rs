@@ -187,6 +187,15 @@ impl Filesystem {
         self.vfs.open(path.as_ref()).map(|f| File::VfsFile(f))
     }
 
+    /// Opens the given `path` in read-only mode, as a file that can
+    /// be read from another thread.
+    pub(crate) fn open_send<P: AsRef<path::Path>>(
+        &self,
+        path: P,
+    ) -> GameResult<Box<dyn vfs::SendFile>> {
+        self.vfs.open_send(path.as_ref())
+    }
+
     /// Opens a file in the user directory with the given
     /// [`filesystem::OpenOptions`](struct.OpenOptions.html).
     /// Note that even if you open a file read-write, it can only
@@ -0,0 +1,761 @@
+//! Frame-by-frame animation of sprites drawn from a sprite sheet.
+//!
+//! A [`Clip`](struct.Clip.html) is a list of [`Frame`](struct.Frame.html)s,
+//! each being a `src` rect of the sheet shown for some time, and an
+//! [`AnimationPlayer`](struct.AnimationPlayer.html) steps through one as
+//! the game runs.  Frames can carry named events, such as a footstep,
+//! which the player reports when it reaches them.
+//!
+//! Clips can be made by hand, cut from a grid with
+//! [`Clip::from_grid()`](struct.Clip.html#method.from_grid), or loaded
+//! along with the frames of a [`SpriteSheet`](struct.SpriteSheet.html)
+//! exported from Aseprite or TexturePacker.
+//!
+//! ```rust,no_run
+//! # use ggez::*;
+//! # use ggez::graphics::animation::{AnimationPlayer, SpriteSheet};
+//! # fn t(ctx: &mut Context) -> GameResult {
+//! let sheet = SpriteSheet::new(ctx, "/player.json")?;
+//! let image = sheet.load_image(ctx)?;
+//! let mut player = AnimationPlayer::new(sheet.clip("walk").unwrap().clone());
+//!
+//! // Every frame:
+//! player.update(ctx);
+//! if player.events().iter().any(|event| event == "step") {
+//!     // Play a footstep sound.
+//! }
+//! graphics::draw(ctx, &image, player.param().dest([100.0, 100.0]))?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::path;
+use std::time;
+
+use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+
+use crate::context::Context;
+use crate::error::{GameError, GameResult};
+use crate::graphics::{DrawParam, Image, Rect};
+use crate::timer;
+
+/// How long frames from sheets without durations, such as the ones
+/// TexturePacker exports, are shown for.
+const DEFAULT_FRAME_DURATION: time::Duration = time::Duration::from_millis(100);
+
+/// One image of an animation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// The part of the sheet to draw, as used by
+    /// [`DrawParam::src`](../struct.DrawParam.html#structfield.src).
+    pub src: Rect,
+    /// How long the frame is shown for.
+    pub duration: time::Duration,
+    /// The names of the events that happen when the frame is reached.
+    pub events: Vec<String>,
+}
+
+impl Frame {
+    /// Creates a frame with no events.
+    pub fn new(src: Rect, duration: time::Duration) -> Self {
+        Frame {
+            src,
+            duration,
+            events: Vec::new(),
+        }
+    }
+
+    /// Adds an event to the frame.
+    pub fn event(mut self, name: &str) -> Self {
+        self.events.push(name.to_string());
+        self
+    }
+}
+
+/// What an animation does when it gets to its last frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum PlayMode {
+    /// Starts over from the first frame.
+    #[default]
+    Loop,
+    /// Plays backwards to the first frame, then forwards again,
+    /// and so on.
+    PingPong,
+    /// Stops on the last frame.
+    Once,
+}
+
+/// A sequence of frames and how to play them.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Clip {
+    frames: Vec<Frame>,
+    mode: PlayMode,
+}
+
+impl Clip {
+    /// Creates a clip from the given frames.
+    pub fn new(frames: Vec<Frame>, mode: PlayMode) -> Self {
+        Clip { frames, mode }
+    }
+
+    /// Creates a clip from a sheet of equally sized frames laid out in
+    /// a grid, going left to right and then top to bottom.  `count`
+    /// frames are taken, starting at `first`.
+    pub fn from_grid(
+        columns: u32,
+        rows: u32,
+        first: u32,
+        count: u32,
+        duration: time::Duration,
+        mode: PlayMode,
+    ) -> Self {
+        let (w, h) = (1.0 / columns.max(1) as f32, 1.0 / rows.max(1) as f32);
+        let frames = (first..first + count)
+            .take_while(|&i| i < columns * rows)
+            .map(|i| {
+                let (column, row) = (i % columns, i / columns);
+                Frame::new(Rect::new(column as f32 * w, row as f32 * h, w, h), duration)
+            })
+            .collect();
+        Clip::new(frames, mode)
+    }
+
+    /// Returns the frames of the clip.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Returns the frames of the clip, for changing them.
+    pub fn frames_mut(&mut self) -> &mut Vec<Frame> {
+        &mut self.frames
+    }
+
+    /// Returns what the clip does when it gets to its last frame.
+    pub fn mode(&self) -> PlayMode {
+        self.mode
+    }
+
+    /// Sets what the clip does when it gets to its last frame.
+    pub fn set_mode(&mut self, mode: PlayMode) {
+        self.mode = mode;
+    }
+
+    /// Sets the duration of every frame.
+    pub fn set_frame_duration(&mut self, duration: time::Duration) {
+        for frame in self.frames.iter_mut() {
+            frame.duration = duration;
+        }
+    }
+
+    /// Adds an event to the frame with the given index, if there is one.
+    pub fn add_event(&mut self, frame: usize, name: &str) {
+        if let Some(frame) = self.frames.get_mut(frame) {
+            frame.events.push(name.to_string());
+        }
+    }
+
+    /// Returns how long it takes to play every frame once.
+    pub fn duration(&self) -> time::Duration {
+        self.frames.iter().map(|frame| frame.duration).sum()
+    }
+}
+
+/// Plays a [`Clip`](struct.Clip.html), keeping track of which frame
+/// to draw.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationPlayer {
+    clip: Clip,
+    frame: usize,
+    /// How long the current frame has been shown.
+    elapsed: time::Duration,
+    backwards: bool,
+    /// Whether the events of the first frame haven't been reported yet.
+    starting: bool,
+    finished: bool,
+    paused: bool,
+    speed: f32,
+    events: Vec<String>,
+}
+
+impl AnimationPlayer {
+    /// Creates a player starting at the first frame of the clip.
+    pub fn new(clip: Clip) -> Self {
+        AnimationPlayer {
+            clip,
+            frame: 0,
+            elapsed: time::Duration::ZERO,
+            backwards: false,
+            starting: true,
+            finished: false,
+            paused: false,
+            speed: 1.0,
+            events: Vec::new(),
+        }
+    }
+
+    /// Returns the clip being played.
+    pub fn clip(&self) -> &Clip {
+        &self.clip
+    }
+
+    /// Switches to another clip and plays it from the start.
+    pub fn set_clip(&mut self, clip: Clip) {
+        self.clip = clip;
+        self.restart();
+    }
+
+    /// Plays the clip from the start again.
+    pub fn restart(&mut self) {
+        self.frame = 0;
+        self.elapsed = time::Duration::ZERO;
+        self.backwards = false;
+        self.starting = true;
+        self.finished = false;
+        self.events.clear();
+    }
+
+    /// Moves the animation on by the time the last frame took,
+    /// as given by [`timer::delta()`](../../timer/fn.delta.html).
+    pub fn update(&mut self, ctx: &Context) {
+        self.advance(timer::delta(ctx));
+    }
+
+    /// Moves the animation on by the given time.
+    pub fn advance(&mut self, dt: time::Duration) {
+        self.events.clear();
+        if self.paused || self.clip.frames.is_empty() {
+            return;
+        }
+        if self.starting {
+            self.starting = false;
+            self.events
+                .extend(self.clip.frames[0].events.iter().cloned());
+        }
+        if self.finished || self.clip.duration().is_zero() {
+            return;
+        }
+        // An infinite speed, or a huge one, just gets as far as it can.
+        let dt = time::Duration::try_from_secs_f64(dt.as_secs_f64() * f64::from(self.speed))
+            .unwrap_or(time::Duration::MAX);
+        self.elapsed = self.elapsed.saturating_add(dt);
+        // Skip all whole cycles but one, so a huge step doesn't take ages
+        // or report the same events over and over.
+        if let Some(cycle) = self.cycle() {
+            if self.elapsed >= cycle.saturating_mul(2) {
+                let rest = self.elapsed.as_nanos() % cycle.as_nanos();
+                self.elapsed = cycle + time::Duration::from_nanos(rest as u64);
+            }
+        }
+        loop {
+            let duration = self.clip.frames[self.frame].duration;
+            if self.elapsed < duration {
+                break;
+            }
+            if !self.step() {
+                self.elapsed = duration;
+                break;
+            }
+            self.elapsed -= duration;
+            self.events
+                .extend(self.clip.frames[self.frame].events.iter().cloned());
+        }
+    }
+
+    /// Returns how long it takes a repeating clip to get back to the
+    /// same frame, going the same way.
+    fn cycle(&self) -> Option<time::Duration> {
+        let frames = &self.clip.frames;
+        match self.clip.mode {
+            PlayMode::Once => None,
+            PlayMode::Loop => Some(self.clip.duration()),
+            PlayMode::PingPong if frames.len() == 1 => Some(frames[0].duration),
+            PlayMode::PingPong => self.clip.duration().checked_mul(2).map(|both_ways| {
+                both_ways - frames[0].duration - frames[frames.len() - 1].duration
+            }),
+        }
+        .filter(|cycle| !cycle.is_zero())
+    }
+
+    /// Goes to the next frame.  Returns `false` if the clip has finished.
+    fn step(&mut self) -> bool {
+        let count = self.clip.frames.len();
+        match self.clip.mode {
+            PlayMode::Loop => self.frame = (self.frame + 1) % count,
+            PlayMode::Once if self.frame + 1 < count => self.frame += 1,
+            PlayMode::Once => {
+                self.finished = true;
+                return false;
+            }
+            PlayMode::PingPong if count == 1 => (),
+            PlayMode::PingPong => {
+                if self.backwards && self.frame == 0 {
+                    self.backwards = false;
+                } else if !self.backwards && self.frame + 1 == count {
+                    self.backwards = true;
+                }
+                if self.backwards {
+                    self.frame -= 1;
+                } else {
+                    self.frame += 1;
+                }
+            }
+        }
+        true
+    }
+
+    /// Returns the events of the frames reached during the last update.
+    pub fn events(&self) -> &[String] {
+        &self.events
+    }
+
+    /// Returns the index of the frame being shown.
+    pub fn frame_index(&self) -> usize {
+        self.frame
+    }
+
+    /// Returns the frame being shown, if the clip has any.
+    pub fn frame(&self) -> Option<&Frame> {
+        self.clip.frames.get(self.frame)
+    }
+
+    /// Returns the `src` rect of the frame being shown.
+    pub fn src(&self) -> Rect {
+        self.frame().map_or_else(Rect::one, |frame| frame.src)
+    }
+
+    /// Returns a `DrawParam` that draws the frame being shown.
+    pub fn param(&self) -> DrawParam {
+        DrawParam::new().src(self.src())
+    }
+
+    /// Returns whether a clip that plays once has got to its end.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Stops the animation from moving on.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Lets the animation move on again.
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Returns whether the animation is paused.
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Sets how fast the animation plays, with `1.0` being normal speed.
+    /// Negative and NaN speeds are treated as `0.0`.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(0.0);
+    }
+
+    /// Returns how fast the animation plays.
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+}
+
+/// The frames of a sprite sheet and the clips made from them, as
+/// exported to JSON by Aseprite or TexturePacker.
+///
+/// Both the hash and the array layouts are read.  Aseprite's frame
+/// durations and tags are used, each tag becoming a clip; TexturePacker's
+/// `animations` also become clips, looping, with every frame shown for
+/// 100 milliseconds.  Rotated frames aren't supported, and trimmed
+/// frames are drawn without the space that was trimmed off.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteSheet {
+    image: Option<path::PathBuf>,
+    frames: Vec<Frame>,
+    names: HashMap<String, usize>,
+    clips: HashMap<String, Clip>,
+}
+
+impl SpriteSheet {
+    /// Loads a sheet from the JSON file at the given path.  The
+    /// documentation for the [`filesystem`](../../filesystem/index.html)
+    /// module explains how the path must be specified.
+    pub fn new<P: AsRef<path::Path>>(ctx: &mut Context, path: P) -> GameResult<Self> {
+        let path = path.as_ref();
+        let mut buf = Vec::new();
+        let mut reader = ctx.filesystem.open(path)?;
+        let _ = reader.read_to_end(&mut buf)?;
+        let mut sheet = SpriteSheet::from_json(&buf)?;
+        // The image is found next to the JSON file.
+        if let (Some(image), Some(dir)) = (sheet.image.take(), path.parent()) {
+            sheet.image = Some(dir.join(image));
+        }
+        Ok(sheet)
+    }
+
+    /// Reads a sheet from JSON data.
+    pub fn from_json(json: &[u8]) -> GameResult<Self> {
+        let sheet: JsonSheet = serde_json::from_slice(json)?;
+        let (width, height) = (sheet.meta.size.w, sheet.meta.size.h);
+        if width <= 0.0 || height <= 0.0 {
+            return Err(GameError::ResourceLoadError(String::from(
+                "Sprite sheet has no size",
+            )));
+        }
+
+        let mut frames = Vec::with_capacity(sheet.frames.0.len());
+        let mut names = HashMap::new();
+        for (i, frame) in sheet.frames.0.into_iter().enumerate() {
+            if frame.rotated {
+                return Err(GameError::ResourceLoadError(String::from(
+                    "Rotated sprite sheet frames are not supported",
+                )));
+            }
+            let r = frame.frame;
+            let src = Rect::new(r.x / width, r.y / height, r.w / width, r.h / height);
+            let duration = frame
+                .duration
+                .map_or(DEFAULT_FRAME_DURATION, time::Duration::from_millis);
+            frames.push(Frame::new(src, duration));
+            if let Some(name) = frame.filename {
+                let _ = names.insert(name, i);
+            }
+        }
+
+        let mut clips = HashMap::new();
+        for tag in sheet.meta.frame_tags {
+            let range = frames.get(tag.from..=tag.to).ok_or_else(|| {
+                GameError::ResourceLoadError(format!(
+                    "Sprite sheet tag '{}' goes past the last frame",
+                    tag.name
+                ))
+            })?;
+            let mut clip_frames = range.to_vec();
+            let mode = match tag.direction.as_str() {
+                "pingpong" | "pingpong_reverse" => PlayMode::PingPong,
+                _ => PlayMode::Loop,
+            };
+            if tag.direction.ends_with("reverse") {
+                clip_frames.reverse();
+            }
+            let _ = clips.insert(tag.name, Clip::new(clip_frames, mode));
+        }
+        for (name, frame_names) in sheet.animations {
+            let clip_frames = frame_names
+                .iter()
+                .map(|frame| {
+                    names.get(frame).map(|&i| frames[i].clone()).ok_or_else(|| {
+                        GameError::ResourceLoadError(format!(
+                            "Sprite sheet animation '{}' has unknown frame '{}'",
+                            name, frame
+                        ))
+                    })
+                })
+                .collect::<GameResult<_>>()?;
+            let _ = clips.insert(name, Clip::new(clip_frames, PlayMode::Loop));
+        }
+
+        Ok(SpriteSheet {
+            image: sheet.meta.image.map(path::PathBuf::from),
+            frames,
+            names,
+            clips,
+        })
+    }
+
+    /// Returns the path of the sheet's image, if the JSON names one.
+    /// For sheets loaded with [`new()`](#method.new) it is relative to
+    /// the JSON file.
+    pub fn image_path(&self) -> Option<&path::Path> {
+        self.image.as_deref()
+    }
+
+    /// Loads the sheet's image.
+    pub fn load_image(&self, ctx: &mut Context) -> GameResult<Image> {
+        let path = self.image.as_ref().ok_or_else(|| {
+            GameError::ResourceLoadError(String::from("Sprite sheet names no image"))
+        })?;
+        Image::new(ctx, path)
+    }
+
+    /// Returns every frame of the sheet, in order.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Returns the frame with the given name.
+    pub fn frame(&self, name: &str) -> Option<&Frame> {
+        self.names.get(name).map(|&i| &self.frames[i])
+    }
+
+    /// Returns the clip with the given name.
+    pub fn clip(&self, name: &str) -> Option<&Clip> {
+        self.clips.get(name)
+    }
+
+    /// Returns an iterator over the names of the clips.
+    pub fn clip_names(&self) -> impl Iterator<Item = &str> {
+        self.clips.keys().map(String::as_str)
+    }
+
+    /// Returns a clip playing every frame of the sheet in order.
+    pub fn all_frames(&self, mode: PlayMode) -> Clip {
+        Clip::new(self.frames.clone(), mode)
+    }
+}
+
+#[derive(Deserialize)]
+struct JsonSheet {
+    frames: JsonFrames,
+    meta: JsonMeta,
+    #[serde(default)]
+    animations: HashMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct JsonFrame {
+    #[serde(default)]
+    filename: Option<String>,
+    frame: JsonRect,
+    #[serde(default)]
+    rotated: bool,
+    /// In milliseconds.
+    #[serde(default)]
+    duration: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct JsonRect {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+}
+
+#[derive(Deserialize)]
+struct JsonSize {
+    w: f32,
+    h: f32,
+}
+
+#[derive(Deserialize)]
+struct JsonMeta {
+    #[serde(default)]
+    image: Option<String>,
+    size: JsonSize,
+    #[serde(default, rename = "frameTags")]
+    frame_tags: Vec<JsonTag>,
+}
+
+#[derive(Deserialize)]
+struct JsonTag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: String,
+}
+
+/// The frames of a sheet, in the order they are written in.  They
+/// are either a list, or an object keyed by frame name, whose order
+/// matters but would be lost by reading it into a map.
+struct JsonFrames(Vec<JsonFrame>);
+
+impl<'de> Deserialize<'de> for JsonFrames {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FramesVisitor;
+
+        impl<'de> Visitor<'de> for FramesVisitor {
+            type Value = JsonFrames;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a list or map of frames")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<JsonFrames, A::Error> {
+                let mut frames = Vec::new();
+                while let Some(frame) = seq.next_element()? {
+                    frames.push(frame);
+                }
+                Ok(JsonFrames(frames))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<JsonFrames, A::Error> {
+                let mut frames = Vec::new();
+                while let Some((name, frame)) = map.next_entry::<String, JsonFrame>()? {
+                    frames.push(JsonFrame {
+                        filename: Some(name),
+                        ..frame
+                    });
+                }
+                Ok(JsonFrames(frames))
+            }
+        }
+
+        deserializer.deserialize_any(FramesVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> time::Duration {
+        time::Duration::from_millis(ms)
+    }
+
+    fn clip(count: u32, mode: PlayMode) -> Clip {
+        Clip::from_grid(count, 1, 0, count, ms(100), mode)
+    }
+
+    /// Returns the frames shown after each of a number of 100ms steps.
+    fn frames_after_steps(player: &mut AnimationPlayer, steps: usize) -> Vec<usize> {
+        (0..steps)
+            .map(|_| {
+                player.advance(ms(100));
+                player.frame_index()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn clips_loop_ping_pong_and_play_once() {
+        let mut looping = AnimationPlayer::new(clip(3, PlayMode::Loop));
+        assert_eq!(vec![1, 2, 0, 1], frames_after_steps(&mut looping, 4));
+
+        let mut ping_pong = AnimationPlayer::new(clip(3, PlayMode::PingPong));
+        assert_eq!(
+            vec![1, 2, 1, 0, 1, 2],
+            frames_after_steps(&mut ping_pong, 6)
+        );
+
+        let mut once = AnimationPlayer::new(clip(3, PlayMode::Once));
+        assert_eq!(vec![1, 2, 2, 2], frames_after_steps(&mut once, 4));
+        assert!(once.finished());
+        once.restart();
+        assert_eq!(0, once.frame_index());
+        assert!(!once.finished());
+    }
+
+    #[test]
+    fn durations_and_speed() {
+        let mut frames = clip(3, PlayMode::Loop).frames().to_vec();
+        frames[0].duration = ms(50);
+        let mut player = AnimationPlayer::new(Clip::new(frames, PlayMode::Loop));
+        player.advance(ms(40));
+        assert_eq!(0, player.frame_index());
+        player.advance(ms(20));
+        assert_eq!(1, player.frame_index());
+        // Two frames in one go.
+        player.advance(ms(190));
+        assert_eq!(0, player.frame_index());
+
+        player.set_speed(2.0);
+        player.advance(ms(25));
+        assert_eq!(1, player.frame_index());
+        player.pause();
+        player.advance(ms(1000));
+        assert_eq!(1, player.frame_index());
+        assert_eq!(Rect::new(1.0 / 3.0, 0.0, 1.0 / 3.0, 1.0), player.src());
+    }
+
+    #[test]
+    fn huge_and_infinite_speeds() {
+        let mut bouncing = clip(3, PlayMode::PingPong);
+        bouncing.add_event(1, "middle");
+        let mut player = AnimationPlayer::new(bouncing);
+        player.set_speed(f32::INFINITY);
+        // Goes through one whole cycle, and some of the next.
+        player.advance(ms(16));
+        assert!((2..=4).contains(&player.events().len()));
+        player.set_speed(f32::MAX);
+        player.advance(ms(16));
+        assert!((2..=4).contains(&player.events().len()));
+        assert!(player.frame_index() < 3);
+        player.set_speed(f32::NAN);
+        assert_eq!(0.0, player.speed());
+
+        let mut once = AnimationPlayer::new(clip(3, PlayMode::Once));
+        once.set_speed(f32::INFINITY);
+        once.advance(ms(16));
+        assert_eq!(2, once.frame_index());
+        assert!(once.finished());
+    }
+
+    #[test]
+    fn frame_events_are_reported() {
+        let mut clip = clip(4, PlayMode::Loop);
+        clip.add_event(0, "start");
+        clip.add_event(2, "step");
+        let mut player = AnimationPlayer::new(clip);
+        player.advance(ms(10));
+        assert_eq!(["start"], player.events());
+        player.advance(ms(10));
+        assert!(player.events().is_empty());
+        player.advance(ms(200));
+        assert_eq!(["step"], player.events());
+        player.advance(ms(200));
+        assert_eq!(["start"], player.events());
+    }
+
+    #[test]
+    fn aseprite_sheets_load() {
+        let json = br#"{
+            "frames": {
+                "walk 0.aseprite": { "frame": { "x": 0, "y": 0, "w": 16, "h": 16 }, "duration": 100 },
+                "walk 1.aseprite": { "frame": { "x": 16, "y": 0, "w": 16, "h": 16 }, "duration": 150 },
+                "walk 10.aseprite": { "frame": { "x": 32, "y": 0, "w": 16, "h": 16 }, "duration": 200 }
+            },
+            "meta": {
+                "image": "walk.png",
+                "size": { "w": 64, "h": 16 },
+                "frameTags": [
+                    { "name": "walk", "from": 0, "to": 2, "direction": "pingpong" },
+                    { "name": "back", "from": 1, "to": 2, "direction": "reverse" }
+                ]
+            }
+        }"#;
+        let sheet = SpriteSheet::from_json(json).unwrap();
+        assert_eq!(Some(path::Path::new("walk.png")), sheet.image_path());
+        // In the order they were written, not sorted.
+        assert_eq!(ms(200), sheet.frames()[2].duration);
+        assert_eq!(
+            Rect::new(0.25, 0.0, 0.25, 1.0),
+            sheet.frame("walk 1.aseprite").unwrap().src
+        );
+        let walk = sheet.clip("walk").unwrap();
+        assert_eq!(PlayMode::PingPong, walk.mode());
+        assert_eq!(3, walk.frames().len());
+        let back = sheet.clip("back").unwrap();
+        assert_eq!(PlayMode::Loop, back.mode());
+        assert_eq!(ms(200), back.frames()[0].duration);
+    }
+
+    #[test]
+    fn texture_packer_sheets_load() {
+        let json = br#"{
+            "frames": [
+                { "filename": "run_1.png", "frame": { "x": 0, "y": 0, "w": 8, "h": 8 }, "rotated": false, "trimmed": false },
+                { "filename": "run_2.png", "frame": { "x": 8, "y": 0, "w": 8, "h": 8 }, "rotated": false, "trimmed": false }
+            ],
+            "animations": { "run": ["run_2.png", "run_1.png"] },
+            "meta": { "image": "run.png", "size": { "w": 16, "h": 8 }, "scale": "1" }
+        }"#;
+        let sheet = SpriteSheet::from_json(json).unwrap();
+        let run = sheet.clip("run").unwrap();
+        assert_eq!(Rect::new(0.5, 0.0, 0.5, 1.0), run.frames()[0].src);
+        assert_eq!(DEFAULT_FRAME_DURATION, run.frames()[1].duration);
+        assert_eq!(vec!["run"], sheet.clip_names().collect::<Vec<_>>());
+
+        let rotated = br#"{
+            "frames": [{ "frame": { "x": 0, "y": 0, "w": 8, "h": 8 }, "rotated": true }],
+            "meta": { "size": { "w": 8, "h": 8 } }
+        }"#;
+        assert!(SpriteSheet::from_json(rotated).is_err());
+        assert!(SpriteSheet::from_json(b"{}").is_err());
+    }
+}
@@ -0,0 +1,484 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::context::Context;
+use crate::error::{GameError, GameResult};
+use crate::graphics::{FilterMode, Image, Rect};
+
+/// Where an image ended up in a [`TextureAtlas`](struct.TextureAtlas.html).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AtlasRegion {
+    /// The index of the page the image is on.
+    pub page: usize,
+    /// The image's part of the page in texture coordinates, from `0.0`
+    /// to `1.0`, ready to be used as [`DrawParam::src`](struct.DrawParam.html#structfield.src).
+    pub src: Rect,
+    /// The image's part of the page in pixels.
+    pub rect: Rect,
+}
+
+/// Many images packed into a few big ones, called pages, so that
+/// they can be drawn with a single [`SpriteBatch`](spritebatch/struct.SpriteBatch.html)
+/// per page.  Each image is looked up by the name it was added with.
+///
+/// Atlases are put together at runtime with a
+/// [`TextureAtlasBuilder`](struct.TextureAtlasBuilder.html):
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # use ggez::graphics::spritebatch::SpriteBatch;
+/// # fn t(ctx: &mut Context) -> GameResult {
+/// let mut builder = graphics::TextureAtlas::builder().padding(2);
+/// builder.add_file(ctx, "player", "/player.png")?;
+/// builder.add_file(ctx, "enemy", "/enemy.png")?;
+/// let atlas = builder.build(ctx)?;
+///
+/// let player = atlas.region("player").unwrap();
+/// let mut batch = SpriteBatch::new(atlas.page(player.page).unwrap().clone());
+/// let _ = batch.add(graphics::DrawParam::new().src(player.src).dest([10.0, 10.0]));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureAtlas {
+    pages: Vec<Image>,
+    regions: HashMap<String, AtlasRegion>,
+}
+
+impl TextureAtlas {
+    /// Creates a builder for a new atlas.
+    pub fn builder() -> TextureAtlasBuilder {
+        TextureAtlasBuilder::default()
+    }
+
+    /// Returns the region of the image with the given name.
+    pub fn region(&self, name: &str) -> Option<AtlasRegion> {
+        self.regions.get(name).copied()
+    }
+
+    /// Returns the `src` rect of the image with the given name,
+    /// for drawing it from its page.
+    pub fn src(&self, name: &str) -> Option<Rect> {
+        self.regions.get(name).map(|region| region.src)
+    }
+
+    /// Returns an iterator over the names and regions of every image.
+    pub fn regions(&self) -> impl Iterator<Item = (&str, AtlasRegion)> {
+        self.regions
+            .iter()
+            .map(|(name, region)| (name.as_str(), *region))
+    }
+
+    /// Returns the page with the given index.
+    pub fn page(&self, index: usize) -> Option<&Image> {
+        self.pages.get(index)
+    }
+
+    /// Returns all the pages of the atlas.
+    pub fn pages(&self) -> &[Image] {
+        &self.pages
+    }
+
+    /// Sets the filter mode of every page.
+    pub fn set_filter(&mut self, mode: FilterMode) {
+        for page in self.pages.iter_mut() {
+            page.set_filter(mode);
+        }
+    }
+}
+
+/// An image waiting to be packed.
+struct Entry {
+    name: String,
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+/// Collects images and packs them into a
+/// [`TextureAtlas`](struct.TextureAtlas.html).
+///
+/// Images are put `padding` pixels apart, and their edge pixels are
+/// repeated `extrude` pixels outwards, so that filtering and rounding
+/// don't pick up any of their neighbours when they are drawn.  Pages
+/// are at most `page_size` pixels big, and shrunk to fit the images
+/// on them.
+pub struct TextureAtlasBuilder {
+    page_width: u16,
+    page_height: u16,
+    padding: u16,
+    extrude: u16,
+    entries: Vec<Entry>,
+}
+
+impl Default for TextureAtlasBuilder {
+    fn default() -> Self {
+        TextureAtlasBuilder {
+            page_width: 2048,
+            page_height: 2048,
+            padding: 1,
+            extrude: 1,
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl TextureAtlasBuilder {
+    /// Sets the biggest size of a page.  The default is 2048x2048.
+    pub fn page_size(mut self, width: u16, height: u16) -> Self {
+        self.page_width = width;
+        self.page_height = height;
+        self
+    }
+
+    /// Sets how many pixels are left between images.  The default is 1.
+    pub fn padding(mut self, padding: u16) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Sets how many times the edge pixels of images are repeated
+    /// around them.  The default is 1.
+    pub fn extrude(mut self, extrude: u16) -> Self {
+        self.extrude = extrude;
+        self
+    }
+
+    /// Adds an image from a buffer of `u8` RGBA values, laid out as in
+    /// [`Image::from_rgba8()`](struct.Image.html#method.from_rgba8).
+    pub fn add_rgba8(&mut self, name: &str, width: u16, height: u16, rgba: &[u8]) -> GameResult {
+        if width == 0 || height == 0 {
+            return Err(GameError::ResourceLoadError(format!(
+                "Tried to add image '{}' of size {}x{} to an atlas, each dimension must be >0",
+                name, width, height
+            )));
+        }
+        let expected_bytes = usize::from(width) * usize::from(height) * 4;
+        if rgba.len() != expected_bytes {
+            return Err(GameError::ResourceLoadError(format!(
+                "Tried to add image '{}' of size {}x{} to an atlas, but gave {} bytes of data (expected {})",
+                name,
+                width,
+                height,
+                rgba.len(),
+                expected_bytes
+            )));
+        }
+        if self.entries.iter().any(|entry| entry.name == name) {
+            return Err(GameError::ResourceLoadError(format!(
+                "An image named '{}' was already added to the atlas",
+                name
+            )));
+        }
+        self.entries.push(Entry {
+            name: name.to_string(),
+            width: u32::from(width),
+            height: u32::from(height),
+            rgba: rgba.to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Adds an `Image`, reading its pixels back from the GPU.
+    pub fn add_image(&mut self, ctx: &mut Context, name: &str, image: &Image) -> GameResult {
+        let rgba = image.to_rgba8(ctx)?;
+        self.add_rgba8(name, image.width(), image.height(), &rgba)
+    }
+
+    /// Adds the image file at the given path.  The documentation for the
+    /// [`filesystem`](../filesystem/index.html) module explains how the
+    /// path must be specified.
+    pub fn add_file<P: AsRef<std::path::Path>>(
+        &mut self,
+        ctx: &mut Context,
+        name: &str,
+        path: P,
+    ) -> GameResult {
+        use std::io::Read;
+        let mut buf = Vec::new();
+        let mut reader = ctx.filesystem.open(path)?;
+        let _ = reader.read_to_end(&mut buf)?;
+        let img = image::load_from_memory(&buf)?.to_rgba8();
+        let (width, height) = img.dimensions();
+        let too_big = || GameError::ResourceLoadError(String::from("Image size > u16::MAX"));
+        let width = u16::try_from(width).map_err(|_| too_big())?;
+        let height = u16::try_from(height).map_err(|_| too_big())?;
+        self.add_rgba8(name, width, height, &img)
+    }
+
+    /// Packs the images added so far and uploads the pages.
+    pub fn build(self, ctx: &mut Context) -> GameResult<TextureAtlas> {
+        let (pages, regions) = self.pack()?;
+        let pages = pages
+            .into_iter()
+            .map(|page| Image::from_rgba8(ctx, page.width, page.height, &page.rgba))
+            .collect::<GameResult<_>>()?;
+        Ok(TextureAtlas { pages, regions })
+    }
+
+    /// Works out where every image goes and draws the pages.
+    fn pack(self) -> GameResult<(Vec<Page>, HashMap<String, AtlasRegion>)> {
+        let extrude = u32::from(self.extrude);
+        let padding = u32::from(self.padding);
+        let (page_width, page_height) = (u32::from(self.page_width), u32::from(self.page_height));
+
+        // Tall images first, which keeps the skylines flat.
+        let mut order: Vec<usize> = (0..self.entries.len()).collect();
+        order.sort_by_key(|&i| {
+            let entry = &self.entries[i];
+            (
+                std::cmp::Reverse(entry.height),
+                std::cmp::Reverse(entry.width),
+            )
+        });
+
+        let mut skylines: Vec<Skyline> = Vec::new();
+        let mut placements = vec![(0, 0, 0); self.entries.len()];
+        for i in order {
+            let entry = &self.entries[i];
+            let size = (
+                entry.width + extrude * 2 + padding,
+                entry.height + extrude * 2 + padding,
+            );
+            let placed = skylines
+                .iter_mut()
+                .enumerate()
+                .find_map(|(page, skyline)| skyline.insert(size).map(|at| (page, at)));
+            let (page, (x, y)) = match placed {
+                Some(placed) => placed,
+                None => {
+                    let mut skyline = Skyline::new(page_width, page_height, padding);
+                    let at = skyline.insert(size).ok_or_else(|| {
+                        GameError::ResourceLoadError(format!(
+                            "Image '{}' of size {}x{} does not fit on a {}x{} atlas page",
+                            entry.name, entry.width, entry.height, page_width, page_height
+                        ))
+                    })?;
+                    skylines.push(skyline);
+                    (skylines.len() - 1, at)
+                }
+            };
+            placements[i] = (page, x + extrude, y + extrude);
+        }
+
+        let mut pages: Vec<Page> = skylines
+            .iter()
+            .map(|skyline| {
+                let (width, height) = skyline.used;
+                Page {
+                    width: width.min(page_width) as u16,
+                    height: height.min(page_height) as u16,
+                    rgba: vec![
+                        0;
+                        width.min(page_width) as usize * height.min(page_height) as usize * 4
+                    ],
+                }
+            })
+            .collect();
+        let mut regions = HashMap::with_capacity(self.entries.len());
+        for (entry, (page, x, y)) in self.entries.into_iter().zip(placements) {
+            let target = &mut pages[page];
+            target.blit(&entry, x, y, extrude);
+            let (w, h) = (f32::from(target.width), f32::from(target.height));
+            let rect = Rect::new(x as f32, y as f32, entry.width as f32, entry.height as f32);
+            let src = Rect::new(rect.x / w, rect.y / h, rect.w / w, rect.h / h);
+            let _ = regions.insert(entry.name, AtlasRegion { page, src, rect });
+        }
+        Ok((pages, regions))
+    }
+}
+
+impl fmt::Debug for TextureAtlasBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<TextureAtlasBuilder: {:p}>", self)
+    }
+}
+
+/// The pixels of a page being put together.
+struct Page {
+    width: u16,
+    height: u16,
+    rgba: Vec<u8>,
+}
+
+impl Page {
+    /// Copies an image to the given position, repeating its edge
+    /// pixels `extrude` times around it.
+    fn blit(&mut self, entry: &Entry, x: u32, y: u32, extrude: u32) {
+        let (page_width, page_height) = (i64::from(self.width), i64::from(self.height));
+        let (extrude, width, height) = (
+            i64::from(extrude),
+            i64::from(entry.width),
+            i64::from(entry.height),
+        );
+        for dy in -extrude..height + extrude {
+            let ty = i64::from(y) + dy;
+            if ty < 0 || ty >= page_height {
+                continue;
+            }
+            let sy = dy.clamp(0, height - 1);
+            for dx in -extrude..width + extrude {
+                let tx = i64::from(x) + dx;
+                if tx < 0 || tx >= page_width {
+                    continue;
+                }
+                let sx = dx.clamp(0, width - 1);
+                let from = ((sy * width + sx) * 4) as usize;
+                let to = ((ty * page_width + tx) * 4) as usize;
+                self.rgba[to..to + 4].copy_from_slice(&entry.rgba[from..from + 4]);
+            }
+        }
+    }
+}
+
+/// Packs rectangles by keeping track of the top edge of what has been
+/// placed so far, and putting each rectangle as low down as it fits.
+struct Skyline {
+    width: u32,
+    height: u32,
+    /// The segments of the top edge, left to right, as `(x, y, width)`.
+    segments: Vec<(u32, u32, u32)>,
+    /// The right and bottom edges of everything placed.
+    used: (u32, u32),
+}
+
+impl Skyline {
+    fn new(width: u32, height: u32, margin: u32) -> Self {
+        Skyline {
+            width,
+            height,
+            segments: vec![(margin, margin, width.saturating_sub(margin))],
+            used: (0, 0),
+        }
+    }
+
+    /// Returns the top of the skyline under a rectangle starting at the
+    /// given segment, if it fits there.
+    fn fit(&self, start: usize, (width, height): (u32, u32)) -> Option<u32> {
+        let x = self.segments[start].0;
+        if x + width > self.width {
+            return None;
+        }
+        let mut top = 0;
+        let mut covered = 0;
+        for &(_, y, segment_width) in &self.segments[start..] {
+            top = top.max(y);
+            covered += segment_width;
+            if covered >= width {
+                break;
+            }
+        }
+        if covered < width || top + height > self.height {
+            None
+        } else {
+            Some(top)
+        }
+    }
+
+    /// Places a rectangle, returning its top left corner.
+    fn insert(&mut self, size: (u32, u32)) -> Option<(u32, u32)> {
+        let (start, y) = (0..self.segments.len())
+            .filter_map(|i| self.fit(i, size).map(|y| (i, y)))
+            .min_by_key(|&(i, y)| (y + size.1, self.segments[i].0))?;
+        let x = self.segments[start].0;
+        let (width, height) = size;
+
+        // Cut away the segments under the new one.
+        let mut end = start;
+        while end < self.segments.len() && self.segments[end].0 < x + width {
+            end += 1;
+        }
+        let last = self.segments[end - 1];
+        let remainder = (last.0 + last.2).saturating_sub(x + width);
+        let mut replacement = vec![(x, y + height, width)];
+        if remainder > 0 {
+            replacement.push((x + width, last.1, remainder));
+        }
+        let _ = self.segments.splice(start..end, replacement);
+
+        // Join neighbours of the same height.
+        self.segments.dedup_by(|next, previous| {
+            if next.1 == previous.1 {
+                previous.2 += next.2;
+                true
+            } else {
+                false
+            }
+        });
+
+        self.used = (self.used.0.max(x + width), self.used.1.max(y + height));
+        Some((x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builder(sizes: &[(u16, u16)]) -> TextureAtlasBuilder {
+        let mut builder = TextureAtlas::builder()
+            .page_size(64, 64)
+            .padding(1)
+            .extrude(1);
+        for (i, &(w, h)) in sizes.iter().enumerate() {
+            let rgba = vec![i as u8 + 1; usize::from(w) * usize::from(h) * 4];
+            builder.add_rgba8(&i.to_string(), w, h, &rgba).unwrap();
+        }
+        builder
+    }
+
+    #[test]
+    fn images_do_not_overlap() {
+        let sizes = [(20, 10), (10, 20), (16, 16), (30, 5), (8, 8), (12, 3)];
+        let (pages, regions) = builder(&sizes).pack().unwrap();
+        assert_eq!(1, pages.len());
+        let rects: Vec<Rect> = (0..sizes.len())
+            .map(|i| regions[&i.to_string()].rect)
+            .collect();
+        for (i, a) in rects.iter().enumerate() {
+            assert_eq!((f32::from(sizes[i].0), f32::from(sizes[i].1)), (a.w, a.h));
+            assert!(a.right() <= f32::from(pages[0].width));
+            assert!(a.bottom() <= f32::from(pages[0].height));
+            for b in &rects[i + 1..] {
+                // Extruded and padded, images are at least 3 pixels apart.
+                let apart = a.right() + 3.0 <= b.left()
+                    || b.right() + 3.0 <= a.left()
+                    || a.bottom() + 3.0 <= b.top()
+                    || b.bottom() + 3.0 <= a.top();
+                assert!(apart, "{:?} and {:?} overlap", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn images_spill_onto_new_pages() {
+        let (pages, regions) = builder(&[(40, 40), (40, 40), (10, 10)]).pack().unwrap();
+        assert_eq!(2, pages.len());
+        assert_ne!(regions["0"].page, regions["1"].page);
+        // The small one fits next to the first big one.
+        assert_eq!(0, regions["2"].page);
+        assert!(builder(&[(70, 10)]).pack().is_err());
+    }
+
+    #[test]
+    fn regions_are_extruded() {
+        let mut builder = TextureAtlas::builder().padding(0).extrude(1);
+        // A 2x1 image, red then green.
+        builder
+            .add_rgba8("a", 2, 1, &[255, 0, 0, 255, 0, 255, 0, 255])
+            .unwrap();
+        assert!(builder.add_rgba8("a", 1, 1, &[0; 4]).is_err());
+        assert!(builder.add_rgba8("b", 1, 1, &[0; 3]).is_err());
+        let (pages, regions) = builder.pack().unwrap();
+        let page = &pages[0];
+        assert_eq!((4, 3), (page.width, page.height));
+        assert_eq!(Rect::new(0.25, 1.0 / 3.0, 0.5, 1.0 / 3.0), regions["a"].src);
+        let pixel = |x: usize, y: usize| &page.rgba[(y * 4 + x) * 4..][..4];
+        for y in 0..3 {
+            assert_eq!(&[255, 0, 0, 255], pixel(0, y));
+            assert_eq!(&[255, 0, 0, 255], pixel(1, y));
+            assert_eq!(&[0, 255, 0, 255], pixel(2, y));
+            assert_eq!(&[0, 255, 0, 255], pixel(3, y));
+        }
+    }
+}
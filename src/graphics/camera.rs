@@ -0,0 +1,471 @@
+use std::time;
+
+use crate::context::Context;
+use crate::error::{GameError, GameResult};
+use crate::graphics::{self, Matrix4, Rect};
+use crate::timer;
+
+use glam::{Vec2, Vec3, Vec4};
+
+/// A camera shake in progress.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Shake {
+    intensity: f32,
+    duration: time::Duration,
+    elapsed: time::Duration,
+}
+
+impl Shake {
+    /// Returns how far the view is shaken, in screen coordinates.
+    fn offset(&self) -> Vec2 {
+        if self.elapsed >= self.duration {
+            return Vec2::ZERO;
+        }
+        let t = self.elapsed.as_secs_f32();
+        let fade = 1.0 - t / self.duration.as_secs_f32();
+        // A few sine waves that don't line up wobble about in a way
+        // that looks random enough, and move smoothly between frames.
+        let x = ((t * 53.0).sin() + (t * 97.0 + 1.3).sin()) * 0.5;
+        let y = ((t * 61.0 + 0.7).sin() + (t * 89.0 + 2.1).sin()) * 0.5;
+        Vec2::new(x, y) * self.intensity * fade
+    }
+}
+
+/// A 2D camera, which moves, zooms and turns the view of the world.
+///
+/// The camera shows the world centered on its position, scaled up by its
+/// zoom, in the area of the current
+/// [`screen_coordinates()`](fn.screen_coordinates.html).  Drawing between
+/// [`push_camera()`](fn.push_camera.html) and
+/// [`pop_camera()`](fn.pop_camera.html) goes through the camera, so a
+/// HUD can be drawn after popping it.
+///
+/// It can also follow a target smoothly, be kept inside the bounds of
+/// the world, and shake, all of which happen as the camera is updated
+/// each frame.
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # use ggez::graphics::{Camera2D, DrawParam, Rect};
+/// # fn t(ctx: &mut Context, map: &graphics::Image, hud: &graphics::Text) -> GameResult {
+/// let mut camera = Camera2D::new([0.0, 0.0]);
+/// camera.set_zoom(2.0);
+/// camera.set_bounds(Some(Rect::new(0.0, 0.0, 3200.0, 1600.0)));
+///
+/// // Every frame:
+/// camera.follow([120.0, 300.0]);
+/// camera.update(ctx);
+/// let clicked = camera.screen_to_world(ctx, input::mouse::position(ctx));
+///
+/// graphics::push_camera(ctx, &camera)?;
+/// graphics::draw(ctx, map, DrawParam::new())?;
+/// graphics::pop_camera(ctx)?;
+/// graphics::draw(ctx, hud, DrawParam::new())?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Camera2D {
+    position: Vec2,
+    zoom: f32,
+    rotation: f32,
+    bounds: Option<Rect>,
+    target: Option<Vec2>,
+    follow_speed: f32,
+    shake: Option<Shake>,
+}
+
+impl Camera2D {
+    /// Creates a camera looking at the given point of the world, with
+    /// no zoom or rotation.
+    pub fn new<P>(position: P) -> Self
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        Camera2D {
+            position: Vec2::from(position.into()),
+            zoom: 1.0,
+            rotation: 0.0,
+            bounds: None,
+            target: None,
+            follow_speed: 8.0,
+            shake: None,
+        }
+    }
+
+    /// Returns the point of the world at the center of the view.
+    pub fn position(&self) -> mint::Point2<f32> {
+        self.position.into()
+    }
+
+    /// Moves the camera to look at the given point of the world.
+    pub fn set_position<P>(&mut self, position: P)
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        self.position = Vec2::from(position.into());
+    }
+
+    /// Returns how much the view is scaled up.
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// Sets how much the view is scaled up; `2.0` shows everything
+    /// twice as big.  The default is `1.0`.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom.max(f32::EPSILON);
+    }
+
+    /// Returns how far the camera is turned, in radians.
+    pub fn rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    /// Turns the camera clockwise by the given angle in radians, which
+    /// turns the world the other way on screen.
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.rotation = rotation;
+    }
+
+    /// Returns the area of the world the view is kept inside.
+    pub fn bounds(&self) -> Option<Rect> {
+        self.bounds
+    }
+
+    /// Sets an area of the world for the view to be kept inside, or
+    /// `None` to let it go anywhere.  When the view is bigger than the
+    /// bounds, it is centered on them.
+    pub fn set_bounds(&mut self, bounds: Option<Rect>) {
+        self.bounds = bounds;
+    }
+
+    /// Sets a point for the camera to move towards as it is updated.
+    /// Call this each frame with the position of whatever the camera
+    /// should follow.
+    pub fn follow<P>(&mut self, target: P)
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        self.target = Some(Vec2::from(target.into()));
+    }
+
+    /// Stops moving towards the point given to [`follow()`](#method.follow).
+    pub fn stop_following(&mut self) {
+        self.target = None;
+    }
+
+    /// Returns how quickly the camera catches up with what it follows.
+    pub fn follow_speed(&self) -> f32 {
+        self.follow_speed
+    }
+
+    /// Sets how quickly the camera catches up with what it follows.
+    /// Each second, the camera covers all but `e^-speed` of the
+    /// distance left, so higher is quicker, and `f32::INFINITY` keeps
+    /// the target exactly in the middle.  The default is `8.0`.
+    pub fn set_follow_speed(&mut self, speed: f32) {
+        self.follow_speed = speed.max(0.0);
+    }
+
+    /// Shakes the view by up to `intensity` in screen coordinates,
+    /// dying down over `duration`.  This replaces any shake that is
+    /// still going.
+    pub fn shake(&mut self, intensity: f32, duration: time::Duration) {
+        self.shake = Some(Shake {
+            intensity,
+            duration,
+            elapsed: time::Duration::default(),
+        });
+    }
+
+    /// Returns whether the view is shaking.
+    pub fn shaking(&self) -> bool {
+        self.shake.is_some()
+    }
+
+    /// Moves the camera on by the time since the last frame, and keeps
+    /// it inside its bounds.
+    pub fn update(&mut self, ctx: &Context) {
+        self.advance(timer::delta(ctx));
+        self.position = self.clamped(graphics::screen_coordinates(ctx));
+    }
+
+    /// Moves the camera towards what it follows and moves the shake on,
+    /// by the given time.
+    pub fn advance(&mut self, dt: time::Duration) {
+        if let Some(target) = self.target {
+            // `INFINITY * 0.0` is NaN, so both ends are handled here.
+            if self.follow_speed == f32::INFINITY {
+                self.position = target;
+            } else if !dt.is_zero() {
+                let t = 1.0 - (-self.follow_speed * dt.as_secs_f32()).exp();
+                self.position += (target - self.position) * t;
+            }
+        }
+        if let Some(shake) = &mut self.shake {
+            shake.elapsed += dt;
+            if shake.elapsed >= shake.duration {
+                self.shake = None;
+            }
+        }
+    }
+
+    /// Returns the position kept inside the bounds, for a view of the
+    /// given size in screen coordinates.
+    fn clamped(&self, screen: Rect) -> Vec2 {
+        let bounds = match self.bounds {
+            Some(bounds) => bounds,
+            None => return self.position,
+        };
+        // How far the view reaches from its center, once turned.
+        let (sin, cos) = self.rotation.sin_cos();
+        let (w, h) = (screen.w.abs() / 2.0, screen.h.abs() / 2.0);
+        let reach =
+            Vec2::new(cos.abs() * w + sin.abs() * h, sin.abs() * w + cos.abs() * h) / self.zoom;
+        let clamp = |p: f32, min: f32, size: f32, reach: f32| {
+            if size <= reach * 2.0 {
+                min + size / 2.0
+            } else {
+                p.max(min + reach).min(min + size - reach)
+            }
+        };
+        Vec2::new(
+            clamp(self.position.x, bounds.x, bounds.w, reach.x),
+            clamp(self.position.y, bounds.y, bounds.h, reach.y),
+        )
+    }
+
+    /// Returns the transform from the world to screen coordinates,
+    /// for the given screen coordinates.
+    fn view(&self, screen: Rect) -> Matrix4 {
+        let center = Vec2::new(screen.x + screen.w / 2.0, screen.y + screen.h / 2.0);
+        let shake = self.shake.map_or(Vec2::ZERO, |shake| shake.offset());
+        Matrix4::from_translation((center + shake).extend(0.0))
+            * Matrix4::from_rotation_z(-self.rotation)
+            * Matrix4::from_scale(Vec3::new(self.zoom, self.zoom, 1.0))
+            * Matrix4::from_translation((-self.clamped(screen)).extend(0.0))
+    }
+
+    /// Returns the transform from the world to screen coordinates, as
+    /// the camera is now.  This is what [`push_camera()`](fn.push_camera.html)
+    /// applies.
+    pub fn transform(&self, ctx: &Context) -> mint::ColumnMatrix4<f32> {
+        self.view(graphics::screen_coordinates(ctx)).into()
+    }
+
+    /// Returns the area of the world the camera shows.  When the camera
+    /// is turned, this is the smallest rect around what is shown.
+    pub fn view_rect(&self, ctx: &Context) -> Rect {
+        let screen = graphics::screen_coordinates(ctx);
+        let inverse = self.view(screen).inverse();
+        let corners = [
+            (screen.left(), screen.top()),
+            (screen.right(), screen.top()),
+            (screen.left(), screen.bottom()),
+            (screen.right(), screen.bottom()),
+        ];
+        let (mut min, mut max) = (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN));
+        for &(x, y) in &corners {
+            let p = inverse.transform_point3(Vec3::new(x, y, 0.0)).truncate();
+            min = min.min(p);
+            max = max.max(p);
+        }
+        Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+    }
+
+    /// Returns the projection the camera is drawn with: the current one,
+    /// or the one from before the last `push_camera()` if a camera is
+    /// pushed.
+    fn world_to_clip(&self, ctx: &Context) -> Matrix4 {
+        let gfx = &ctx.gfx_context;
+        let projection = *gfx.projection_stack.last().unwrap_or(&gfx.projection);
+        projection * self.view(gfx.screen_rect)
+    }
+
+    /// Converts a point in the world to where it is in the window, in
+    /// pixels, the same as [`mouse::position()`](../input/mouse/fn.position.html).
+    ///
+    /// This goes through the projection the camera is pushed on top of,
+    /// so changes to the screen coordinates and the window's size are
+    /// accounted for.
+    pub fn world_to_screen<P>(&self, ctx: &Context, point: P) -> mint::Point2<f32>
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        let point = Vec2::from(point.into());
+        let clip = self.world_to_clip(ctx) * Vec4::new(point.x, point.y, 0.0, 1.0);
+        clip_to_window(clip.truncate().truncate() / clip.w, window_size(ctx)).into()
+    }
+
+    /// Converts a point in the window, in pixels, to where it is in the
+    /// world.  Use this to find what the mouse is over.
+    pub fn screen_to_world<P>(&self, ctx: &Context, point: P) -> mint::Point2<f32>
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        let clip = window_to_clip(Vec2::from(point.into()), window_size(ctx));
+        self.world_to_clip(ctx)
+            .inverse()
+            .project_point3(clip.extend(0.0))
+            .truncate()
+            .into()
+    }
+}
+
+impl Default for Camera2D {
+    fn default() -> Self {
+        Camera2D::new([0.0, 0.0])
+    }
+}
+
+fn window_size(ctx: &Context) -> Vec2 {
+    let (w, h) = graphics::drawable_size(ctx);
+    Vec2::new(w, h)
+}
+
+/// Converts a point from clip space, which goes from -1 to 1 with y
+/// up, to window pixels.
+fn clip_to_window(clip: Vec2, size: Vec2) -> Vec2 {
+    Vec2::new((clip.x + 1.0) / 2.0 * size.x, (1.0 - clip.y) / 2.0 * size.y)
+}
+
+/// The inverse of `clip_to_window()`.
+fn window_to_clip(window: Vec2, size: Vec2) -> Vec2 {
+    Vec2::new(window.x / size.x * 2.0 - 1.0, 1.0 - window.y / size.y * 2.0)
+}
+
+/// Starts drawing through the given camera, until
+/// [`pop_camera()`](fn.pop_camera.html) is called.
+///
+/// The camera's transform is applied on top of the current projection,
+/// so cameras can be nested.  Changing the projection or the screen
+/// coordinates while a camera is pushed is undone by popping it.
+pub fn push_camera(ctx: &mut Context, camera: &Camera2D) -> GameResult {
+    let gfx = &mut ctx.gfx_context;
+    let projection = gfx.projection();
+    let view = camera.view(gfx.screen_rect);
+    gfx.projection_stack.push(projection);
+    gfx.set_projection(projection * view);
+    gfx.set_global_mvp(Matrix4::IDENTITY)
+}
+
+/// Stops drawing through the camera pushed last, going back to the
+/// projection from before it was pushed.
+pub fn pop_camera(ctx: &mut Context) -> GameResult {
+    let gfx = &mut ctx.gfx_context;
+    let projection = gfx.projection_stack.pop().ok_or_else(|| {
+        GameError::RenderError(String::from(
+            "pop_camera() called without a matching push_camera()",
+        ))
+    })?;
+    gfx.set_projection(projection);
+    gfx.set_global_mvp(Matrix4::IDENTITY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen() -> Rect {
+        Rect::new(0.0, 0.0, 800.0, 600.0)
+    }
+
+    fn apply(m: Matrix4, x: f32, y: f32) -> Vec2 {
+        m.transform_point3(Vec3::new(x, y, 0.0)).truncate()
+    }
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        (a - b).length() < 0.01
+    }
+
+    #[test]
+    fn view_centers_zooms_and_turns() {
+        let mut camera = Camera2D::new([100.0, 50.0]);
+        assert!(close(
+            Vec2::new(400.0, 300.0),
+            apply(camera.view(screen()), 100.0, 50.0)
+        ));
+
+        camera.set_zoom(2.0);
+        assert!(close(
+            Vec2::new(420.0, 300.0),
+            apply(camera.view(screen()), 110.0, 50.0)
+        ));
+
+        // Turning the camera clockwise turns the world anticlockwise, so
+        // something to the right of the camera ends up above the middle.
+        camera.set_zoom(1.0);
+        camera.set_rotation(std::f32::consts::FRAC_PI_2);
+        assert!(close(
+            Vec2::new(400.0, 290.0),
+            apply(camera.view(screen()), 110.0, 50.0)
+        ));
+    }
+
+    #[test]
+    fn window_and_clip_space_round_trip() {
+        let size = Vec2::new(1600.0, 1200.0);
+        assert!(close(
+            Vec2::new(-1.0, 1.0),
+            window_to_clip(Vec2::ZERO, size)
+        ));
+        assert!(close(Vec2::new(0.0, 0.0), window_to_clip(size / 2.0, size)));
+        let p = Vec2::new(123.0, 456.0);
+        assert!(close(p, clip_to_window(window_to_clip(p, size), size)));
+    }
+
+    #[test]
+    fn bounds_keep_the_view_inside() {
+        let mut camera = Camera2D::new([0.0, 0.0]);
+        camera.set_bounds(Some(Rect::new(0.0, 0.0, 2000.0, 500.0)));
+        // Pushed right and down, since the view is 800x600 and the
+        // bounds aren't tall enough for it.
+        assert!(close(Vec2::new(400.0, 250.0), camera.clamped(screen())));
+        camera.set_position([5000.0, 100.0]);
+        assert!(close(Vec2::new(1600.0, 250.0), camera.clamped(screen())));
+        // Zoomed in, the view is 400x300, which fits.
+        camera.set_zoom(2.0);
+        camera.set_position([100.0, 100.0]);
+        assert!(close(Vec2::new(200.0, 150.0), camera.clamped(screen())));
+    }
+
+    #[test]
+    fn follow_handles_zero_time_and_infinite_speed() {
+        let mut camera = Camera2D::new([0.0, 0.0]);
+        camera.follow([10.0, 20.0]);
+        camera.advance(time::Duration::ZERO);
+        assert!(close(Vec2::new(0.0, 0.0), camera.position));
+
+        camera.set_follow_speed(f32::INFINITY);
+        camera.advance(time::Duration::ZERO);
+        assert!(close(Vec2::new(10.0, 20.0), camera.position));
+        camera.follow([30.0, 40.0]);
+        camera.advance(time::Duration::from_millis(16));
+        assert!(close(Vec2::new(30.0, 40.0), camera.position));
+    }
+
+    #[test]
+    fn follow_and_shake_wear_off() {
+        let mut camera = Camera2D::new([0.0, 0.0]);
+        camera.set_follow_speed(f32::INFINITY);
+        camera.follow([10.0, 20.0]);
+        camera.advance(time::Duration::from_millis(16));
+        assert!(close(Vec2::new(10.0, 20.0), camera.position));
+
+        camera.set_follow_speed(2.0);
+        camera.follow([110.0, 20.0]);
+        camera.advance(time::Duration::from_millis(500));
+        // All but e^-1 of the way there after half a second.
+        let expected = 110.0 - 100.0 * (-1.0f32).exp();
+        assert!((camera.position.x - expected).abs() < 0.01);
+
+        camera.shake(10.0, time::Duration::from_millis(100));
+        camera.advance(time::Duration::from_millis(30));
+        let offset = camera.shake.unwrap().offset();
+        assert!(offset.x.abs() <= 7.0 && offset.y.abs() <= 7.0);
+        assert!(camera.shaking());
+        camera.advance(time::Duration::from_millis(70));
+        assert!(!camera.shaking());
+    }
+}
@@ -1,9 +1,11 @@
 //! I guess these docs will never appear since we re-export the canvas
 //! module from graphics...
+use std::cell::OnceCell;
 use std::path;
+use std::rc::Rc;
 
 use gfx::format::{Format, Swizzle};
-use gfx::handle::RawRenderTargetView;
+use gfx::handle::{RawDepthStencilView, RawRenderTargetView};
 use gfx::memory::{Bind, Usage};
 use gfx::texture::{AaMode, Kind};
 use gfx::Factory;
@@ -26,6 +28,9 @@ where
     Spec: BackendSpec,
 {
     target: RawRenderTargetView<Spec::Resources>,
+    /// The depth/stencil view masks are drawn into, created the first
+    /// time one is drawn on the canvas.
+    depth: Rc<OnceCell<RawDepthStencilView<Spec::Resources>>>,
     image: ImageGeneric<Spec>,
     ms_canvas: Option<MultiSampledCanvasGeneric<Spec>>,
     debug_id: DebugId,
@@ -205,6 +210,7 @@ impl Canvas {
 
         Ok(Canvas {
             target,
+            depth: Rc::new(OnceCell::new()),
             image: Image {
                 texture: resource,
                 texture_handle: tex,
@@ -291,7 +297,13 @@ impl Canvas {
     pub fn resolve(&self, ctx: &mut Context) -> GameResult {
         if let Some(ms_canvas) = &self.ms_canvas {
             // save the old target to restore it after the resolve has finished
-            let old_target = std::mem::replace(&mut ctx.gfx_context.data.out, self.target.clone());
+            let old_target = ctx.gfx_context.data.out.clone();
+            let old_stencil = ctx.gfx_context.data.stencil.clone();
+            ctx.gfx_context.set_render_target(self.target.clone(), None);
+            // the whole image is resolved, whatever is being clipped or masked
+            let scissors = std::mem::take(&mut ctx.gfx_context.scissor_stack);
+            let drawing_mask = std::mem::replace(&mut ctx.gfx_context.drawing_mask, false);
+            ctx.gfx_context.data.stencil.1 = (0, 0);
             // set resolve shader
             let r_shader_id = ctx.gfx_context.resolve_shader.shader_id();
             let old_shader = std::mem::replace(
@@ -315,6 +327,9 @@ impl Canvas {
             crate::graphics::image::draw_image_raw(&ms_canvas.image, ctx, param)?;
             // restore the old target
             ctx.gfx_context.data.out = old_target;
+            ctx.gfx_context.data.stencil = old_stencil;
+            ctx.gfx_context.scissor_stack = scissors;
+            ctx.gfx_context.drawing_mask = drawing_mask;
             // and the old shader
             *ctx.gfx_context.current_shader.borrow_mut() = old_shader;
         }
@@ -376,17 +391,54 @@ fn flip_draw_param_vertical(param: DrawParam) -> DrawParam {
 
 /// Set the `Canvas` to render to. Specifying `Option::None` will cause all
 /// rendering to be done directly to the screen.
+///
+/// Ends any mask set with [`draw_mask()`](fn.draw_mask.html), since
+/// every target has a mask of its own.
 pub fn set_canvas(ctx: &mut Context, target: Option<&Canvas>) {
-    match target {
+    let (color, depth) = match target {
         Some(surface) => {
             surface.debug_id.assert(ctx);
-            ctx.gfx_context.data.out = match &surface.ms_canvas {
-                Some(ms_canvas) => ms_canvas.target.clone(),
-                _ => surface.target.clone(),
-            };
+            ctx.gfx_context.canvas_stencil = Some(surface.depth.clone());
+            (surface.target().clone(), surface.depth.get().cloned())
         }
         None => {
-            ctx.gfx_context.data.out = ctx.gfx_context.screen_render_target.clone();
+            ctx.gfx_context.canvas_stencil = None;
+            (
+                ctx.gfx_context.screen_render_target.clone(),
+                Some(ctx.gfx_context.depth_view.clone()),
+            )
         }
     };
+    ctx.gfx_context.set_render_target(color, depth);
+    clear_mask(ctx);
+}
+
+/// Creates the depth/stencil view a canvas of the given kind draws with,
+/// which holds its masks.
+pub(crate) fn create_depth_stencil<F, R>(
+    factory: &mut F,
+    kind: Kind,
+    depth_format: Format,
+) -> GameResult<RawDepthStencilView<R>>
+where
+    F: Factory<R>,
+    R: gfx::Resources,
+{
+    let texture_create_info = gfx::texture::Info {
+        kind,
+        levels: 1,
+        format: depth_format.0,
+        bind: Bind::DEPTH_STENCIL,
+        usage: Usage::Data,
+    };
+    let tex = factory.create_texture_raw(texture_create_info, Some(depth_format.1), None)?;
+    let view = factory.view_texture_as_depth_stencil_raw(
+        &tex,
+        gfx::texture::DepthStencilDesc {
+            level: 0,
+            layer: None,
+            flags: gfx::texture::DepthStencilFlags::empty(),
+        },
+    )?;
+    Ok(view)
 }
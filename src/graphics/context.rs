@@ -1,4 +1,4 @@
-use std::cell::RefCell;
+use std::cell::{OnceCell, RefCell};
 use std::rc::Rc;
 
 use gfx::traits::FactoryExt;
@@ -22,6 +22,87 @@ gfx_defines! {
     }
 }
 
+/// The GL context we render with; either attached to a window, or
+/// headless and rendering into an offscreen framebuffer.
+pub(crate) enum WindowContext {
+    Windowed(glutin::WindowedContext<glutin::PossiblyCurrent>),
+    Headless {
+        // Only kept around so the GL context stays alive.
+        _context: glutin::Context<glutin::PossiblyCurrent>,
+        // Dropped after the context, which on some platforms needs it.
+        _events_loop: Option<Box<glutin::event_loop::EventLoop<()>>>,
+        width: u16,
+        height: u16,
+    },
+}
+
+impl WindowContext {
+    /// Returns the window, or `None` if we're running headless.
+    pub(crate) fn try_window(&self) -> Option<&winit::window::Window> {
+        match self {
+            WindowContext::Windowed(window) => Some(window.window()),
+            WindowContext::Headless { .. } => None,
+        }
+    }
+
+    /// Returns the window.  Panics if we're running headless, the same way
+    /// disabled modules do.
+    pub(crate) fn window(&self) -> &winit::window::Window {
+        self.try_window()
+            .expect("Context is headless and has no window")
+    }
+
+    /// Returns the windowed GL context.  Panics if we're running headless.
+    pub(crate) fn windowed_mut(&mut self) -> &mut glutin::WindowedContext<glutin::PossiblyCurrent> {
+        match self {
+            WindowContext::Windowed(window) => window,
+            WindowContext::Headless { .. } => panic!("Context is headless and has no window"),
+        }
+    }
+
+    /// Returns the scale factor of the window; always 1.0 when headless.
+    pub(crate) fn scale_factor(&self) -> f64 {
+        self.try_window()
+            .map_or(1.0, |window| window.scale_factor())
+    }
+
+    /// Returns the size of the drawable area in physical pixels.
+    pub(crate) fn inner_size(&self) -> dpi::PhysicalSize<u32> {
+        match self {
+            WindowContext::Windowed(window) => window.window().inner_size(),
+            WindowContext::Headless { width, height, .. } => {
+                dpi::PhysicalSize::new(u32::from(*width), u32::from(*height))
+            }
+        }
+    }
+
+    /// Returns the outer size of the window in physical pixels;
+    /// the same as `inner_size()` when headless.
+    pub(crate) fn outer_size(&self) -> dpi::PhysicalSize<u32> {
+        match self {
+            WindowContext::Windowed(window) => window.window().outer_size(),
+            WindowContext::Headless { .. } => self.inner_size(),
+        }
+    }
+
+    /// Resizes the GL surface.  Does nothing when headless, since
+    /// the offscreen framebuffer has a fixed size.
+    pub(crate) fn resize(&self, size: dpi::PhysicalSize<u32>) {
+        if let WindowContext::Windowed(window) = self {
+            window.resize(size);
+        }
+    }
+
+    /// Swaps the buffers.  Does nothing when headless, since there
+    /// is nothing to present to.
+    pub(crate) fn swap_buffers(&self) -> Result<(), glutin::ContextError> {
+        match self {
+            WindowContext::Windowed(window) => window.swap_buffers(),
+            WindowContext::Headless { .. } => Ok(()),
+        }
+    }
+}
+
 /// A structure that contains graphics state.
 /// For instance,
 /// window info, DPI, rendering pipeline state, etc.
@@ -33,6 +114,19 @@ where
 {
     shader_globals: Globals,
     pub(crate) projection: Matrix4,
+    /// The projections saved by `push_camera()`, to go back to.
+    pub(crate) projection_stack: Vec<Matrix4>,
+    /// The rects drawing is clipped to, in screen coordinates, each
+    /// already intersected with the ones before it.
+    pub(crate) scissor_stack: Vec<Rect>,
+    /// Which side of the mask in the stencil buffer drawing is limited to,
+    /// if any.
+    pub(crate) mask: Option<MaskMode>,
+    /// Whether draws currently go into the mask instead of the target.
+    pub(crate) drawing_mask: bool,
+    /// The depth/stencil view of the canvas being drawn on, if it is one.
+    /// It is only created once a mask is drawn on the canvas.
+    pub(crate) canvas_stencil: Option<Rc<OnceCell<gfx::handle::RawDepthStencilView<B::Resources>>>>,
     pub(crate) white_image: ImageGeneric<B>,
     pub(crate) screen_rect: Rect,
     pub(crate) to_rgba8_buffer: gfx::handle::Buffer<B::Resources, u8>,
@@ -41,13 +135,12 @@ where
     srgb: bool,
 
     pub(crate) backend_spec: B,
-    pub(crate) window: glutin::WindowedContext<glutin::PossiblyCurrent>,
+    pub(crate) window: WindowContext,
     pub(crate) multisample_samples: u8,
     pub(crate) device: Box<B::Device>,
     pub(crate) factory: Box<B::Factory>,
     pub(crate) encoder: gfx::Encoder<B::Resources, B::CommandBuffer>,
     pub(crate) screen_render_target: gfx::handle::RawRenderTargetView<B::Resources>,
-    #[allow(dead_code)]
     pub(crate) depth_view: gfx::handle::RawDepthStencilView<B::Resources>,
 
     pub(crate) data: pipe::Data<B::Resources>,
@@ -90,21 +183,7 @@ impl GraphicsContextGeneric<GlBackendSpec> {
         debug_id: DebugId,
     ) -> GameResult<Self> {
         let srgb = window_setup.srgb;
-        let color_format = if srgb {
-            gfx::format::Format(
-                gfx::format::SurfaceType::R8_G8_B8_A8,
-                gfx::format::ChannelType::Srgb,
-            )
-        } else {
-            gfx::format::Format(
-                gfx::format::SurfaceType::R8_G8_B8_A8,
-                gfx::format::ChannelType::Unorm,
-            )
-        };
-        let depth_format = gfx::format::Format(
-            gfx::format::SurfaceType::D24_S8,
-            gfx::format::ChannelType::Unorm,
-        );
+        let (color_format, depth_format) = surface_formats(srgb);
 
         // WINDOW SETUP
         let gl_builder = glutin::ContextBuilder::new()
@@ -145,7 +224,7 @@ impl GraphicsContextGeneric<GlBackendSpec> {
             window_builder
         };
 
-        let (window, device, mut factory, screen_render_target, depth_view) = backend.init(
+        let (window, device, factory, screen_render_target, depth_view) = backend.init(
             window_builder,
             gl_builder,
             events_loop,
@@ -187,6 +266,87 @@ impl GraphicsContextGeneric<GlBackendSpec> {
             debug!("  Actually got: {}", device_info);
         }
 
+        let mut gfx = Self::from_device(
+            WindowContext::Windowed(window),
+            device,
+            factory,
+            screen_render_target,
+            depth_view,
+            window_setup,
+            window_mode,
+            backend,
+            debug_id,
+        )?;
+        gfx.set_window_mode(window_mode)?;
+        Ok(gfx)
+    }
+
+    /// Create a new GraphicsContext that has no window and instead renders
+    /// into an offscreen framebuffer the size of the given `WindowMode`.
+    pub(crate) fn new_headless(
+        window_setup: &WindowSetup,
+        window_mode: WindowMode,
+        backend: GlBackendSpec,
+        debug_id: DebugId,
+    ) -> GameResult<Self> {
+        let (color_format, depth_format) = surface_formats(window_setup.srgb);
+        let width = window_mode.width as u16;
+        let height = window_mode.height as u16;
+
+        // No vsync or multisampling here, there's no screen to sync to and
+        // we render into our own single-sampled framebuffer anyway.
+        let gl_builder = glutin::ContextBuilder::new()
+            .with_gl(glutin::GlRequest::Specific(
+                backend.api(),
+                backend.version_tuple(),
+            ))
+            .with_gl_profile(glutin::GlProfile::Core);
+
+        let (context, events_loop, device, factory, screen_render_target, depth_view) =
+            backend.init_headless(gl_builder, width, height, color_format, depth_format)?;
+        debug!(
+            "Headless context created, size {}x{}, actually got: {}",
+            width,
+            height,
+            backend.info(&device)
+        );
+
+        // The offscreen framebuffer is single-sampled no matter what was asked for.
+        let window_setup = window_setup.clone().samples(crate::conf::NumSamples::One);
+        Self::from_device(
+            WindowContext::Headless {
+                _context: context,
+                _events_loop: events_loop.map(Box::new),
+                width,
+                height,
+            },
+            device,
+            factory,
+            screen_render_target,
+            depth_view,
+            &window_setup,
+            window_mode,
+            backend,
+            debug_id,
+        )
+    }
+
+    /// Sets up all the gfx state that doesn't care whether or not we have a window.
+    #[allow(clippy::too_many_arguments)]
+    fn from_device(
+        window: WindowContext,
+        device: gfx_device_gl::Device,
+        mut factory: gfx_device_gl::Factory,
+        screen_render_target: gfx::handle::RawRenderTargetView<gfx_device_gl::Resources>,
+        depth_view: gfx::handle::RawDepthStencilView<gfx_device_gl::Resources>,
+        window_setup: &WindowSetup,
+        window_mode: WindowMode,
+        backend: GlBackendSpec,
+        debug_id: DebugId,
+    ) -> GameResult<Self> {
+        let srgb = window_setup.srgb;
+        let (color_format, depth_format) = surface_formats(srgb);
+
         // GFX SETUP
         let mut encoder = GlBackendSpec::encoder(&mut factory);
 
@@ -262,12 +422,20 @@ impl GraphicsContextGeneric<GlBackendSpec> {
         let texture = white_image.texture.clone();
         let typed_thingy = backend.raw_to_typed_shader_resource(texture);
 
+        let (screen_width, screen_height, _, _) = screen_render_target.get_dimensions();
         let data = pipe::Data {
             vbuf: quad_vertex_buffer.clone(),
             tex: (typed_thingy, sampler),
             rect_instance_properties: rect_inst_props,
             globals: globals_buffer,
             out: screen_render_target.clone(),
+            stencil: (Some(depth_view.clone()), (0, 0)),
+            scissor: gfx::Rect {
+                x: 0,
+                y: 0,
+                w: screen_width,
+                h: screen_height,
+            },
         };
 
         // Glyph cache stuff.
@@ -306,6 +474,11 @@ impl GraphicsContextGeneric<GlBackendSpec> {
         let mut gfx = Self {
             shader_globals: globals,
             projection: initial_projection,
+            projection_stack: Vec::new(),
+            scissor_stack: Vec::new(),
+            mask: None,
+            drawing_mask: false,
+            canvas_stencil: None,
             white_image,
             screen_rect: Rect::new(left, top, right - left, bottom - top),
             to_rgba8_buffer,
@@ -338,7 +511,6 @@ impl GraphicsContextGeneric<GlBackendSpec> {
             glyph_cache,
             glyph_state,
         };
-        gfx.set_window_mode(window_mode)?;
 
         // Calculate and apply the actual initial projection matrix
         let w = window_mode.width;
@@ -355,6 +527,26 @@ impl GraphicsContextGeneric<GlBackendSpec> {
     }
 }
 
+/// Returns the color and depth formats of the screen.
+fn surface_formats(srgb: bool) -> (gfx::format::Format, gfx::format::Format) {
+    let color_format = if srgb {
+        gfx::format::Format(
+            gfx::format::SurfaceType::R8_G8_B8_A8,
+            gfx::format::ChannelType::Srgb,
+        )
+    } else {
+        gfx::format::Format(
+            gfx::format::SurfaceType::R8_G8_B8_A8,
+            gfx::format::ChannelType::Unorm,
+        )
+    };
+    let depth_format = gfx::format::Format(
+        gfx::format::SurfaceType::D24_S8,
+        gfx::format::ChannelType::Unorm,
+    );
+    (color_format, depth_format)
+}
+
 // This is kinda awful 'cause it copies a couple times,
 // but still better than
 // having `winit` try to do the image loading for us.
@@ -414,12 +606,70 @@ where
     pub(crate) fn draw(&mut self, slice: Option<&gfx::Slice<B::Resources>>) -> GameResult {
         let slice = slice.unwrap_or(&self.quad_slice);
         let id = (*self.current_shader.borrow()).unwrap_or(self.default_shader);
-        let shader_handle = &self.shaders[id];
+        let shader_handle = &mut self.shaders[id];
 
-        shader_handle.draw(&mut self.encoder, slice, &self.data)?;
+        let (width, height, _, _) = self.data.out.get_dimensions();
+        self.data.scissor = scissor_pixels(
+            self.scissor_stack.last().copied(),
+            self.screen_rect,
+            (width, height),
+        );
+        if self.drawing_mask {
+            shader_handle.draw_mask(&mut *self.factory, &mut self.encoder, slice, &self.data)?;
+        } else {
+            shader_handle.draw(&mut self.encoder, slice, &self.data)?;
+        }
         Ok(())
     }
 
+    /// Sets the scissor rect back to the whole target, so that a clear
+    /// isn't clipped by whatever rect the last draw left behind.  The
+    /// rect only reaches the graphics card with a draw, so this draws
+    /// nothing with it.
+    pub(crate) fn reset_scissor(&mut self) {
+        let (width, height, _, _) = self.data.out.get_dimensions();
+        self.data.scissor = scissor_pixels(None, self.screen_rect, (width, height));
+        let nothing = gfx::Slice {
+            start: 0,
+            end: 0,
+            ..self.quad_slice.clone()
+        };
+        let shader_handle = &self.shaders[self.default_shader];
+        // The default shader always has a pipeline for its blend mode.
+        let _ = shader_handle.draw(&mut self.encoder, &nothing, &self.data);
+    }
+
+    /// Sets the color and depth/stencil views everything gets drawn into.
+    /// They must be the same size.
+    pub(crate) fn set_render_target(
+        &mut self,
+        color: gfx::handle::RawRenderTargetView<B::Resources>,
+        depth: Option<gfx::handle::RawDepthStencilView<B::Resources>>,
+    ) {
+        self.data.out = color;
+        self.data.stencil.0 = depth;
+    }
+
+    /// Returns the depth/stencil view of the current target, first
+    /// creating it if the target is a canvas that doesn't have one yet.
+    pub(crate) fn mask_stencil(
+        &mut self,
+    ) -> GameResult<gfx::handle::RawDepthStencilView<B::Resources>> {
+        if let Some(stencil) = &self.canvas_stencil {
+            if stencil.get().is_none() {
+                let (width, height, _, aa) = self.data.out.get_dimensions();
+                let kind = gfx::texture::Kind::D2(width, height, aa);
+                let view =
+                    canvas::create_depth_stencil(&mut *self.factory, kind, self.depth_format)?;
+                let _ = stencil.set(view);
+            }
+            self.data.stencil.0 = stencil.get().cloned();
+        }
+        self.data.stencil.0.clone().ok_or_else(|| {
+            GameError::RenderError(String::from("The current target has no stencil buffer"))
+        })
+    }
+
     /// Sets the blend mode of the active shader
     pub(crate) fn set_blend_mode(&mut self, mode: BlendMode) -> GameResult {
         let id = (*self.current_shader.borrow()).unwrap_or(self.default_shader);
@@ -506,7 +756,11 @@ where
 
     /// Sets window mode from a WindowMode object.
     pub(crate) fn set_window_mode(&mut self, mode: WindowMode) -> GameResult {
-        let window = self.window.window();
+        // A headless context has nothing to apply the mode to.
+        let window = match self.window.try_window() {
+            Some(window) => window,
+            None => return Ok(()),
+        };
 
         // TODO LATER: find out if single-dimension constraints are possible?
         let min_dimensions = if mode.min_width > 0.0 && mode.min_height > 0.0 {
@@ -582,12 +836,17 @@ where
     /// so it may cause squirrelliness to
     /// happen with canvases or other things that touch it.
     pub(crate) fn resize_viewport(&mut self) {
+        let window = match &self.window {
+            WindowContext::Windowed(window) => window,
+            // The offscreen framebuffer of a headless context never changes size.
+            WindowContext::Headless { .. } => return,
+        };
         if let Some((cv, dv)) = self.backend_spec.resize_viewport(
             &self.screen_render_target,
             &self.depth_view,
             self.color_format(),
             self.depth_format(),
-            &self.window,
+            window,
         ) {
             self.screen_render_target = cv;
             self.depth_view = dv;
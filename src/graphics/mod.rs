@@ -33,6 +33,8 @@ use crate::context::Context;
 use crate::GameError;
 use crate::GameResult;
 
+pub(crate) mod atlas;
+pub(crate) mod camera;
 pub(crate) mod canvas;
 pub(crate) mod context;
 pub(crate) mod drawparam;
@@ -44,8 +46,12 @@ pub(crate) mod types;
 
 pub use mint;
 
+pub mod animation;
 pub mod spritebatch;
+pub mod tilemap;
 
+pub use crate::graphics::atlas::*;
+pub use crate::graphics::camera::*;
 pub use crate::graphics::canvas::*;
 pub use crate::graphics::drawparam::*;
 pub use crate::graphics::image::*;
@@ -76,6 +82,15 @@ type BackendSpecInitResult<Device, Factory, Resources> = Result<
     glutin::CreationError,
 >;
 
+type BackendSpecHeadlessInitResult<Device, Factory, Resources> = GameResult<(
+    glutin::Context<glutin::PossiblyCurrent>,
+    Option<glutin::event_loop::EventLoop<()>>,
+    Device,
+    Factory,
+    gfx::handle::RawRenderTargetView<Resources>,
+    gfx::handle::RawDepthStencilView<Resources>,
+)>;
+
 type MainTargetView<Resources> = Option<(
     gfx::handle::RawRenderTargetView<Resources>,
     gfx::handle::RawDepthStencilView<Resources>,
@@ -160,6 +175,27 @@ pub trait BackendSpec: fmt::Debug {
         color_format: gfx::format::Format,
         depth_format: gfx::format::Format,
     ) -> BackendSpecInitResult<Self::Device, Self::Factory, Self::Resources>;
+
+    /// Creates a context without a window, rendering into an offscreen
+    /// framebuffer of the given size instead.  Also returns the event loop
+    /// the context was created with, if it needed one, which has to be
+    /// kept alive for as long as the context.
+    ///
+    /// By default this fails, for backends that can't do it.
+    fn init_headless<'a>(
+        &self,
+        _gl_builder: glutin::ContextBuilder<'a, glutin::NotCurrent>,
+        _width: u16,
+        _height: u16,
+        _color_format: gfx::format::Format,
+        _depth_format: gfx::format::Format,
+    ) -> BackendSpecHeadlessInitResult<Self::Device, Self::Factory, Self::Resources> {
+        Err(glutin::CreationError::NotSupported(
+            "headless contexts are not supported by this backend".to_string(),
+        )
+        .into())
+    }
+
     /// Create an Encoder for the backend.
     fn encoder(factory: &mut Self::Factory) -> gfx::Encoder<Self::Resources, Self::CommandBuffer>;
 
@@ -256,6 +292,68 @@ impl BackendSpec for GlBackendSpec {
             .map(|i| i.init_gfx_raw(color_format, depth_format))
     }
 
+    fn init_headless<'a>(
+        &self,
+        gl_builder: glutin::ContextBuilder<'a, glutin::NotCurrent>,
+        width: u16,
+        height: u16,
+        color_format: gfx::format::Format,
+        depth_format: gfx::format::Format,
+    ) -> BackendSpecHeadlessInitResult<Self::Device, Self::Factory, Self::Resources> {
+        let size = glutin::dpi::PhysicalSize::new(u32::from(width), u32::from(height));
+        let (context, events_loop) = build_headless_context(gl_builder, size)?;
+        // This is the same thing `init_gfx_raw()` does for windowed contexts.
+        let context = unsafe { context.make_current() }.map_err(|(_, e)| e)?;
+        let (device, mut factory) =
+            gfx_device_gl::create(|s| context.get_proc_address(s) as *const std::os::raw::c_void);
+
+        // There is no default framebuffer to draw into, so we make our own,
+        // pretty much the same way a `Canvas` does.
+        let kind = texture::Kind::D2(width, height, texture::AaMode::Single);
+        let color_info = texture::Info {
+            kind,
+            levels: 1,
+            format: color_format.0,
+            bind: gfx::memory::Bind::SHADER_RESOURCE
+                | gfx::memory::Bind::RENDER_TARGET
+                | gfx::memory::Bind::TRANSFER_SRC,
+            usage: gfx::memory::Usage::Data,
+        };
+        let color_tex = factory.create_texture_raw(color_info, Some(color_format.1), None)?;
+        let color_view = factory.view_texture_as_render_target_raw(
+            &color_tex,
+            texture::RenderDesc {
+                channel: color_format.1,
+                level: 0,
+                layer: None,
+            },
+        )?;
+        let depth_info = texture::Info {
+            kind,
+            levels: 1,
+            format: depth_format.0,
+            bind: gfx::memory::Bind::DEPTH_STENCIL,
+            usage: gfx::memory::Usage::Data,
+        };
+        let depth_tex = factory.create_texture_raw(depth_info, Some(depth_format.1), None)?;
+        let depth_view = factory.view_texture_as_depth_stencil_raw(
+            &depth_tex,
+            texture::DepthStencilDesc {
+                level: 0,
+                layer: None,
+                flags: texture::DepthStencilFlags::empty(),
+            },
+        )?;
+        Ok((
+            context,
+            events_loop,
+            device,
+            factory,
+            color_view,
+            depth_view,
+        ))
+    }
+
     fn info(&self, device: &Self::Device) -> String {
         let info = device.get_info();
         format!(
@@ -287,6 +385,64 @@ impl BackendSpec for GlBackendSpec {
     }
 }
 
+/// OSMesa is a pure software renderer, so it works even when there is no display
+/// or GPU around at all, which is exactly what we want for headless contexts.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+fn build_headless_context(
+    gl_builder: glutin::ContextBuilder<glutin::NotCurrent>,
+    size: glutin::dpi::PhysicalSize<u32>,
+) -> Result<HeadlessContext, glutin::CreationError> {
+    use glutin::platform::unix::HeadlessContextExt;
+    gl_builder.build_osmesa(size).map(|context| (context, None))
+}
+
+/// On Windows creating an event loop doesn't need a display server, so we
+/// can just ask glutin for a hidden context.  The event loop may be made on
+/// any thread, since tests don't run on the main one, but the context's
+/// hidden window belongs to it so it has to outlive the context.
+#[cfg(target_os = "windows")]
+fn build_headless_context(
+    gl_builder: glutin::ContextBuilder<glutin::NotCurrent>,
+    size: glutin::dpi::PhysicalSize<u32>,
+) -> Result<HeadlessContext, glutin::CreationError> {
+    use glutin::platform::windows::EventLoopExtWindows;
+    let events_loop = glutin::event_loop::EventLoop::new_any_thread();
+    let context = gl_builder.build_headless(&events_loop, size)?;
+    Ok((context, Some(events_loop)))
+}
+
+/// Elsewhere an event loop can only be made on the main thread, which
+/// isn't where tests run, so there's no headless context to be had.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "windows"
+)))]
+fn build_headless_context(
+    _gl_builder: glutin::ContextBuilder<glutin::NotCurrent>,
+    _size: glutin::dpi::PhysicalSize<u32>,
+) -> Result<HeadlessContext, glutin::CreationError> {
+    Err(glutin::CreationError::NotSupported(
+        "headless contexts are only supported on Windows and Unix-like systems other than macOS"
+            .to_string(),
+    ))
+}
+
+/// A headless GL context, with the event loop it needs to stay alive, if any.
+type HeadlessContext = (
+    glutin::Context<glutin::NotCurrent>,
+    Option<glutin::event_loop::EventLoop<()>>,
+);
+
 const QUAD_VERTS: [Vertex; 4] = [
     Vertex {
         pos: [0.0, 0.0],
@@ -350,6 +506,57 @@ pub(crate) mod pipe {
         globals: gfx::ConstantBuffer<Globals>,
         rect_instance_properties: gfx::InstanceBuffer<InstanceProperties>,
         out: gfx::RawRenderTarget,
+        stencil: MaskTarget,
+        scissor: gfx::Scissor,
+    }
+
+    /// The stencil buffer masks are drawn into and tested against.  It
+    /// works like `gfx::StencilTarget`, except that it can be left unset
+    /// for canvases that nobody has drawn a mask on yet, which then pass
+    /// the stencil test everywhere.
+    #[derive(Clone, Copy, Debug, Hash, PartialEq)]
+    pub struct MaskTarget;
+
+    impl<'a> gfx::pso::DataLink<'a> for MaskTarget {
+        type Init = gfx::state::Stencil;
+
+        fn new() -> Self {
+            MaskTarget
+        }
+
+        fn is_active(&self) -> bool {
+            true
+        }
+
+        fn link_depth_stencil(
+            &mut self,
+            init: &Self::Init,
+        ) -> Option<gfx_core::pso::DepthStencilDesc> {
+            use gfx::format::Formatted;
+            Some((gfx::format::DepthStencil::get_format(), (*init).into()))
+        }
+    }
+
+    impl<R: gfx::Resources> gfx::pso::DataBind<R> for MaskTarget {
+        type Data = (Option<gfx::handle::RawDepthStencilView<R>>, (u8, u8));
+
+        fn bind_to(
+            &self,
+            out: &mut gfx::pso::RawDataSet<R>,
+            data: &Self::Data,
+            man: &mut gfx::handle::Manager<R>,
+            _: &mut gfx::pso::AccessInfo<R>,
+        ) {
+            if let Some(view) = &data.0 {
+                out.pixel_targets.add_depth_stencil(
+                    man.ref_dsv(view),
+                    false,
+                    true,
+                    view.get_dimensions(),
+                );
+            }
+            out.ref_values.stencil = data.1;
+        }
     }
 
     pub fn new() -> Init<'static> {
@@ -367,8 +574,42 @@ pub(crate) mod pipe {
                 gfx::state::ColorMask::all(),
                 Some(gfx::preset::blend::ALPHA),
             ),
+            stencil: mask_test_stencil(),
+            scissor: (),
         }
     }
+
+    /// The stencil state of every normal draw.  It passes where the stencil
+    /// value is at least the reference value, so a reference of 0 draws
+    /// everywhere and a reference of 1 only draws where the mask is.
+    pub fn mask_test_stencil() -> gfx::state::Stencil {
+        let mut stencil = gfx::state::Stencil::new(
+            gfx::state::Comparison::LessEqual,
+            0xFF,
+            (
+                gfx::state::StencilOp::Keep,
+                gfx::state::StencilOp::Keep,
+                gfx::state::StencilOp::Keep,
+            ),
+        );
+        stencil.front.mask_write = 0;
+        stencil.back.mask_write = 0;
+        stencil
+    }
+
+    /// The stencil state used while drawing a mask, which writes the
+    /// reference value wherever something is drawn.
+    pub fn mask_write_stencil() -> gfx::state::Stencil {
+        gfx::state::Stencil::new(
+            gfx::state::Comparison::Always,
+            0xFF,
+            (
+                gfx::state::StencilOp::Keep,
+                gfx::state::StencilOp::Keep,
+                gfx::state::StencilOp::Replace,
+            ),
+        )
+    }
 }
 
 impl fmt::Display for InstanceProperties {
@@ -456,6 +697,7 @@ pub fn clear(ctx: &mut Context, color: Color) {
     let gfx = &mut ctx.gfx_context;
     let linear_color: types::LinearColor = color.into();
     let c: [f32; 4] = linear_color.into();
+    gfx.reset_scissor();
     gfx.encoder.clear_raw(&gfx.data.out, c.into());
 }
 
@@ -474,10 +716,17 @@ where
 /// Call this at the end of your [`EventHandler`](../event/trait.EventHandler.html)'s
 /// [`draw()`](../event/trait.EventHandler.html#tymethod.draw) method.
 ///
-/// Unsets any active canvas.
+/// Unsets any active canvas and mask.
+///
+/// For a headless `Context` this just finishes rendering into the
+/// offscreen framebuffer, which you can then read back with
+/// [`screenshot()`](fn.screenshot.html).
 pub fn present(ctx: &mut Context) -> GameResult<()> {
+    clear_mask(ctx);
     let gfx = &mut ctx.gfx_context;
-    gfx.data.out = gfx.screen_render_target.clone();
+    let (color, depth) = (gfx.screen_render_target.clone(), gfx.depth_view.clone());
+    gfx.set_render_target(color, Some(depth));
+    gfx.canvas_stencil = None;
     // We might want to give the user more control over when the
     // encoder gets flushed eventually, if we want them to be able
     // to do their own gfx drawing.  HOWEVER, the whole pipeline type
@@ -684,6 +933,150 @@ pub fn projection(context: &Context) -> mint::ColumnMatrix4<f32> {
     gfx.projection().into()
 }
 
+/// Clips drawing to the given rect, in screen coordinates, until
+/// [`pop_scissor()`](fn.pop_scissor.html) is called.  Nested rects
+/// intersect, so drawing stays inside all of them.
+///
+/// Everything drawn is clipped, including sprite batches, mesh batches
+/// and text; queued text is clipped by the rect in place when
+/// [`draw_queued_text()`](fn.draw_queued_text.html) is called.  The rect
+/// is in the screen coordinates current when drawing, whether those are
+/// the window's pixels or something set with
+/// [`set_screen_coordinates()`](fn.set_screen_coordinates.html), and is
+/// not moved by cameras or changes to the projection, so it suits UI
+/// panels.  It applies to canvases the same way as to the screen.
+pub fn push_scissor(ctx: &mut Context, rect: Rect) {
+    let stack = &mut ctx.gfx_context.scissor_stack;
+    let rect = match stack.last() {
+        Some(outer) => intersect(*outer, rect),
+        None => intersect(rect, rect),
+    };
+    stack.push(rect);
+}
+
+/// Stops clipping to the rect pushed last, going back to the one
+/// before it, if any.
+pub fn pop_scissor(ctx: &mut Context) -> GameResult {
+    match ctx.gfx_context.scissor_stack.pop() {
+        Some(_) => Ok(()),
+        None => Err(GameError::RenderError(String::from(
+            "pop_scissor() called without a matching push_scissor()",
+        ))),
+    }
+}
+
+/// Draws `drawable` as a mask, and from then on limits drawing to the
+/// inside or the outside of it, depending on `mode`, until
+/// [`clear_mask()`](fn.clear_mask.html) is called.  Any mask there was
+/// before is replaced.
+///
+/// The mask is made of every pixel the drawable covers, whatever its
+/// color, so transparent parts of images count too; meshes make the
+/// cleanest masks.  A circle `Mesh` with `MaskMode::Inside` gives a
+/// spotlight, for instance, and a rounded rectangle an avatar frame.
+/// The current shader is used to draw it, so one that discards
+/// fragments can cut those out of the mask.
+///
+/// Masks live in the stencil buffer of the current target, which can
+/// be the screen or a canvas; switching canvases with
+/// [`set_canvas()`](fn.set_canvas.html) or calling
+/// [`present()`](fn.present.html) ends the mask.  A canvas only gets
+/// a stencil buffer the first time a mask is drawn on it.
+pub fn draw_mask<D, T>(ctx: &mut Context, drawable: &D, params: T, mode: MaskMode) -> GameResult
+where
+    D: Drawable,
+    T: Into<DrawParam>,
+{
+    let gfx = &mut ctx.gfx_context;
+    let stencil = gfx.mask_stencil()?;
+    // Clears are clipped by the scissor rect the last draw left behind.
+    gfx.reset_scissor();
+    gfx.encoder
+        .clear_stencil_raw(&stencil, mode.stencil_values().0);
+    gfx.mask = Some(mode);
+    extend_mask(ctx, drawable, params)
+}
+
+/// Adds `drawable` to the mask set with
+/// [`draw_mask()`](fn.draw_mask.html), so drawing is limited to the
+/// inside or outside of all the shapes drawn into it.
+pub fn extend_mask<D, T>(ctx: &mut Context, drawable: &D, params: T) -> GameResult
+where
+    D: Drawable,
+    T: Into<DrawParam>,
+{
+    let mode = ctx.gfx_context.mask.ok_or_else(|| {
+        GameError::RenderError(String::from(
+            "extend_mask() called without a mask from draw_mask()",
+        ))
+    })?;
+    let write = mode.stencil_values().1;
+    ctx.gfx_context.data.stencil.1 = (write, write);
+    ctx.gfx_context.drawing_mask = true;
+    let result = drawable.draw(ctx, params.into());
+    ctx.gfx_context.drawing_mask = false;
+    ctx.gfx_context.data.stencil.1 = (1, 1);
+    result
+}
+
+/// Stops limiting drawing to the mask set with
+/// [`draw_mask()`](fn.draw_mask.html), if any.
+pub fn clear_mask(ctx: &mut Context) {
+    ctx.gfx_context.mask = None;
+    ctx.gfx_context.data.stencil.1 = (0, 0);
+}
+
+/// Returns which side of the mask drawing is limited to, or `None` if
+/// no mask is set.
+pub fn mask_mode(ctx: &Context) -> Option<MaskMode> {
+    ctx.gfx_context.mask
+}
+
+/// Returns the area two rects have in common, with a positive size, or
+/// an empty rect if they don't overlap.
+fn intersect(a: Rect, b: Rect) -> Rect {
+    let normal = |r: Rect| {
+        let (x, y) = (r.x.min(r.x + r.w), r.y.min(r.y + r.h));
+        (x, y, x + r.w.abs(), y + r.h.abs())
+    };
+    let (a, b) = (normal(a), normal(b));
+    let (left, top) = (a.0.max(b.0), a.1.max(b.1));
+    let (right, bottom) = (a.2.min(b.2).max(left), a.3.min(b.3).max(top));
+    Rect::new(left, top, right - left, bottom - top)
+}
+
+/// Works out the scissor rect for the graphics card: in pixels of the
+/// render target, counting down from the top, and covering the whole
+/// target when nothing is clipped.  gfx turns it the right way up for GL.
+pub(crate) fn scissor_pixels(rect: Option<Rect>, screen: Rect, target: (u16, u16)) -> gfx::Rect {
+    let (width, height) = target;
+    let rect = match rect {
+        Some(rect) => rect,
+        None => {
+            return gfx::Rect {
+                x: 0,
+                y: 0,
+                w: width,
+                h: height,
+            }
+        }
+    };
+    let (w, h) = (f32::from(width), f32::from(height));
+    let x = |x: f32| ((x - screen.x) / screen.w * w).round().max(0.0).min(w);
+    let y = |y: f32| ((y - screen.y) / screen.h * h).round().max(0.0).min(h);
+    // Screen coordinates can be flipped, so either side can end up first.
+    let (x0, x1) = (x(rect.left()), x(rect.right()));
+    let (y0, y1) = (y(rect.top()), y(rect.bottom()));
+    let (left, right) = (x0.min(x1), x0.max(x1));
+    let (top, bottom) = (y0.min(y1), y0.max(y1));
+    gfx::Rect {
+        x: left as u16,
+        y: top as u16,
+        w: (right - left) as u16,
+        h: (bottom - top) as u16,
+    }
+}
+
 /// Sets the blend mode of the currently active shader program
 pub fn set_blend_mode(ctx: &mut Context, mode: BlendMode) -> GameResult {
     ctx.gfx_context.set_blend_mode(mode)
@@ -775,6 +1168,8 @@ pub fn get_window_position(context: &Context) -> GameResult<winit::dpi::Physical
 /// Ideally you should not need to use this because ggez
 /// would provide all the functions you need without having
 /// to dip into Glutin itself.  But life isn't always ideal.
+///
+/// Panics if the `Context` is [headless](../struct.ContextBuilder.html#method.headless).
 pub fn window(context: &Context) -> &glutin::window::Window {
     let gfx = &context.gfx_context;
     gfx.window.window()
@@ -793,25 +1188,29 @@ pub fn supported_resolutions(
 /// Returns the size of the window in pixels as (width, height),
 /// including borders, titlebar, etc.
 /// Returns zeros if the window doesn't exist.
+///
+/// For a headless `Context` this is the size of its offscreen framebuffer.
 pub fn size(context: &Context) -> (f32, f32) {
     let gfx = &context.gfx_context;
-    let window = gfx.window.window();
-    let physical_size = window.outer_size();
+    let physical_size = gfx.window.outer_size();
     (physical_size.width as f32, physical_size.height as f32)
 }
 
 /// Returns the size of the window's underlying drawable in physical pixels as (width, height).
 /// Returns zeros if window doesn't exist.
+///
+/// For a headless `Context` this is the size of its offscreen framebuffer.
 pub fn drawable_size(context: &Context) -> (f32, f32) {
     let gfx = &context.gfx_context;
-    let window = gfx.window.window();
-    let physical_size = window.inner_size();
+    let physical_size = gfx.window.inner_size();
     (physical_size.width as f32, physical_size.height as f32)
 }
 
 /// Return raw window context
+///
+/// Panics if the `Context` is [headless](../struct.ContextBuilder.html#method.headless).
 pub fn window_raw(context: &mut Context) -> &mut glutin::WindowedContext<glutin::PossiblyCurrent> {
-    &mut context.gfx_context.window
+    context.gfx_context.window.windowed_mut()
 }
 
 /// Deletes all cached font data.
@@ -927,7 +1326,7 @@ pub fn transform_rect(rect: Rect, param: DrawParam) -> Rect {
 
 #[cfg(test)]
 mod tests {
-    use crate::graphics::{transform_rect, DrawParam, Rect};
+    use crate::graphics::{intersect, scissor_pixels, transform_rect, DrawParam, Rect};
     use approx::assert_relative_eq;
     use std::f32::consts::PI;
 
@@ -1081,4 +1480,44 @@ mod tests {
             assert_relative_eq!(real, expected);
         }
     }
+
+    #[test]
+    fn headless_test_scissor_rects() {
+        let screen = Rect::new(0.0, 0.0, 800.0, 600.0);
+        // Nothing clipped covers the whole target.
+        let full = scissor_pixels(None, screen, (800, 600));
+        assert_eq!((0, 0, 800, 600), (full.x, full.y, full.w, full.h));
+
+        let r = scissor_pixels(Some(Rect::new(10.0, 20.0, 100.0, 50.0)), screen, (800, 600));
+        assert_eq!((10, 20, 100, 50), (r.x, r.y, r.w, r.h));
+
+        // Screen coordinates that are scaled and flipped, on a canvas
+        // twice the size.
+        let flipped = Rect::new(0.0, 300.0, 400.0, -300.0);
+        let r = scissor_pixels(
+            Some(Rect::new(10.0, 240.0, 100.0, 50.0)),
+            flipped,
+            (800, 600),
+        );
+        assert_eq!((20, 20, 200, 100), (r.x, r.y, r.w, r.h));
+
+        // Off the edge of the target is cut off.
+        let r = scissor_pixels(
+            Some(Rect::new(-50.0, 580.0, 100.0, 50.0)),
+            screen,
+            (800, 600),
+        );
+        assert_eq!((0, 580, 50, 20), (r.x, r.y, r.w, r.h));
+
+        let a = Rect::new(0.0, 0.0, 100.0, 100.0);
+        assert_eq!(
+            Rect::new(50.0, 20.0, 50.0, 30.0),
+            intersect(a, Rect::new(50.0, 20.0, 100.0, 30.0))
+        );
+        assert_eq!(0.0, intersect(a, Rect::new(200.0, 0.0, 10.0, 10.0)).w);
+        assert_eq!(
+            Rect::new(10.0, 10.0, 20.0, 20.0),
+            intersect(a, Rect::new(30.0, 30.0, -20.0, -20.0))
+        );
+    }
 }
@@ -177,6 +177,7 @@ where
     C: Structure<ConstFormat>,
 {
     psos: HashMap<BlendMode, PipelineState<Spec::Resources, ConstMeta<C>>>,
+    mask: Option<PipelineState<Spec::Resources, ConstMeta<C>>>,
 }
 
 impl<Spec, C> PsoSet<Spec, C>
@@ -187,6 +188,7 @@ where
     pub fn new(cap: usize) -> Self {
         Self {
             psos: HashMap::with_capacity(cap),
+            mask: None,
         }
     }
 
@@ -209,6 +211,47 @@ where
             )),
         }
     }
+
+    pub fn mask(
+        &mut self,
+        factory: &mut Spec::Factory,
+        source: &MaskSource,
+    ) -> GameResult<&PipelineState<Spec::Resources, ConstMeta<C>>> {
+        match &mut self.mask {
+            Some(pso) => Ok(pso),
+            mask @ None => {
+                // Masks only touch the stencil buffer, so they get one
+                // pipeline of their own that doesn't write any color at all.
+                let init = ConstInit::<C>(
+                    graphics::pipe::Init {
+                        out: ("Target0", source.color_format, ColorMask::empty(), None),
+                        stencil: graphics::pipe::mask_write_stencil(),
+                        ..graphics::pipe::new()
+                    },
+                    source.name.clone(),
+                    PhantomData,
+                );
+                let set = factory.create_shader_set(&source.vertex_source, &source.pixel_source)?;
+                let pso = factory.create_pipeline_state(
+                    &set,
+                    Primitive::TriangleList,
+                    source.rasterizer,
+                    init,
+                )?;
+                Ok(mask.insert(pso))
+            }
+        }
+    }
+}
+
+/// What a shader's mask pipeline is made from.  Few shaders are ever
+/// used to draw masks, so the pipeline is only made the first time.
+pub(crate) struct MaskSource {
+    vertex_source: Vec<u8>,
+    pixel_source: Vec<u8>,
+    name: String,
+    rasterizer: Rasterizer,
+    color_format: format::Format,
 }
 
 /// An ID used by the ggez graphics context to uniquely identify a shader
@@ -259,6 +302,18 @@ where
 
     let mut psos = PsoSet::new(blend_modes.len());
     let name: String = name.into();
+    let sample = if multisample_samples > 1 {
+        Some(MultiSample)
+    } else {
+        None
+    };
+    let rasterizer = Rasterizer {
+        front_face: FrontFace::CounterClockwise,
+        cull_face: CullFace::Nothing,
+        method: RasterMethod::Fill,
+        offset: None,
+        samples: sample,
+    };
     for mode in blend_modes {
         let init = ConstInit::<C>(
             graphics::pipe::Init {
@@ -274,19 +329,6 @@ where
             PhantomData,
         );
         let set = factory.create_shader_set(vertex_source, pixel_source)?;
-        let sample = if multisample_samples > 1 {
-            Some(MultiSample)
-        } else {
-            None
-        };
-        let rasterizer = Rasterizer {
-            front_face: FrontFace::CounterClockwise,
-            cull_face: CullFace::Nothing,
-            method: RasterMethod::Fill,
-            offset: None,
-            samples: sample,
-        };
-
         let pso = factory.create_pipeline_state(&set, Primitive::TriangleList, rasterizer, init)?;
         psos.insert_mode(*mode, pso);
     }
@@ -295,6 +337,13 @@ where
         buffer: buffer.clone(),
         psos,
         active_blend_mode: blend_modes[0],
+        mask_source: MaskSource {
+            vertex_source: vertex_source.to_vec(),
+            pixel_source: pixel_source.to_vec(),
+            name,
+            rasterizer,
+            color_format,
+        },
     };
     let draw: ShaderHandlePtr<Spec> = Box::new(program);
 
@@ -418,6 +467,7 @@ struct ShaderProgram<Spec: graphics::BackendSpec, C: Structure<ConstFormat>> {
     buffer: Buffer<Spec::Resources, C>,
     psos: PsoSet<Spec, C>,
     active_blend_mode: BlendMode,
+    mask_source: MaskSource,
 }
 
 impl<Spec, C> fmt::Debug for ShaderProgram<Spec, C>
@@ -441,6 +491,20 @@ pub trait ShaderHandle<Spec: graphics::BackendSpec>: fmt::Debug {
         data: &graphics::pipe::Data<Spec::Resources>,
     ) -> GameResult;
 
+    /// Draw with the current Shader into the stencil buffer only,
+    /// for making a mask.  The default implementation returns an error.
+    fn draw_mask(
+        &mut self,
+        _factory: &mut Spec::Factory,
+        _encoder: &mut Encoder<Spec::Resources, Spec::CommandBuffer>,
+        _slice: &Slice<Spec::Resources>,
+        _data: &graphics::pipe::Data<Spec::Resources>,
+    ) -> GameResult {
+        Err(GameError::RenderError(
+            "This shader can't draw masks".into(),
+        ))
+    }
+
     /// Sets the shader program's blend mode
     fn set_blend_mode(&mut self, mode: BlendMode) -> GameResult;
 
@@ -464,6 +528,18 @@ where
         Ok(())
     }
 
+    fn draw_mask(
+        &mut self,
+        factory: &mut Spec::Factory,
+        encoder: &mut Encoder<Spec::Resources, Spec::CommandBuffer>,
+        slice: &Slice<Spec::Resources>,
+        data: &graphics::pipe::Data<Spec::Resources>,
+    ) -> GameResult {
+        let pso = self.psos.mask(factory, &self.mask_source)?;
+        encoder.draw(slice, pso, &ConstData(data, &self.buffer));
+        Ok(())
+    }
+
     fn set_blend_mode(&mut self, mode: BlendMode) -> GameResult {
         let _ = self.psos.mode(mode)?;
         self.active_blend_mode = mode;
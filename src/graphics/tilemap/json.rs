@@ -0,0 +1,475 @@
+//! Loading maps and tilesets from Tiled's JSON formats.
+
+use std::collections::HashMap;
+use std::path;
+use std::time;
+
+use super::load_tileset;
+use super::map::*;
+use crate::error::GameResult;
+
+fn yes() -> bool {
+    true
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn first() -> u32 {
+    1
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonMap {
+    #[serde(default)]
+    orientation: String,
+    #[serde(default)]
+    infinite: bool,
+    width: u32,
+    height: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    #[serde(default)]
+    backgroundcolor: String,
+    #[serde(default)]
+    tilesets: Vec<JsonTileset>,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonProperty {
+    name: String,
+    #[serde(default, rename = "type")]
+    kind: String,
+    value: serde_json::Value,
+}
+
+fn properties(properties: &[JsonProperty]) -> Properties {
+    properties
+        .iter()
+        .filter_map(|property| {
+            let value = match &property.value {
+                serde_json::Value::String(s) => s.clone(),
+                serde_json::Value::Bool(b) => b.to_string(),
+                serde_json::Value::Number(n) => n.to_string(),
+                _ => return None,
+            };
+            PropertyValue::parse(&property.kind, &value).map(|value| (property.name.clone(), value))
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonTileset {
+    #[serde(default = "first")]
+    firstgid: u32,
+    source: Option<String>,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    tilewidth: u32,
+    #[serde(default)]
+    tileheight: u32,
+    #[serde(default)]
+    tilecount: u32,
+    #[serde(default)]
+    columns: u32,
+    #[serde(default)]
+    spacing: u32,
+    #[serde(default)]
+    margin: u32,
+    #[serde(default)]
+    image: String,
+    #[serde(default)]
+    imagewidth: u32,
+    #[serde(default)]
+    imageheight: u32,
+    #[serde(default)]
+    tiles: Vec<JsonTile>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+impl JsonTileset {
+    fn into_tileset(self, dir: &path::Path, first_gid: u32) -> GameResult<Tileset> {
+        let tiles = self
+            .tiles
+            .into_iter()
+            .map(|tile| {
+                let data = TileData {
+                    class: tile.class.or(tile.kind).unwrap_or_default(),
+                    animation: tile
+                        .animation
+                        .iter()
+                        .map(|frame| TileFrame {
+                            tile_id: frame.tileid,
+                            duration: time::Duration::from_millis(frame.duration),
+                        })
+                        .collect(),
+                    properties: properties(&tile.properties),
+                };
+                (tile.id, data)
+            })
+            .collect::<HashMap<_, _>>();
+        Tileset {
+            name: self.name,
+            first_gid,
+            tile_width: self.tilewidth,
+            tile_height: self.tileheight,
+            tile_count: self.tilecount,
+            columns: self.columns,
+            spacing: self.spacing,
+            margin: self.margin,
+            image: if self.image.is_empty() {
+                path::PathBuf::new()
+            } else {
+                resolve(dir, &self.image)
+            },
+            image_width: self.imagewidth,
+            image_height: self.imageheight,
+            tiles,
+            properties: properties(&self.properties),
+        }
+        .validate()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonTile {
+    id: u32,
+    class: Option<String>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    #[serde(default)]
+    animation: Vec<JsonFrame>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonFrame {
+    tileid: u32,
+    duration: u64,
+}
+
+/// Tile layer data is either an array of IDs or an encoded string.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum JsonData {
+    Ids(Vec<u32>),
+    Encoded(String),
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    width: u32,
+    #[serde(default)]
+    height: u32,
+    data: Option<JsonData>,
+    #[serde(default)]
+    encoding: String,
+    #[serde(default)]
+    compression: String,
+    #[serde(default = "yes")]
+    visible: bool,
+    #[serde(default = "one")]
+    opacity: f32,
+    #[serde(default)]
+    offsetx: f32,
+    #[serde(default)]
+    offsety: f32,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    objects: Vec<JsonObject>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonPoint {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonText {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonObject {
+    #[serde(default)]
+    id: u32,
+    #[serde(default)]
+    name: String,
+    class: Option<String>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    #[serde(default)]
+    x: f32,
+    #[serde(default)]
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    rotation: f32,
+    #[serde(default = "yes")]
+    visible: bool,
+    #[serde(default)]
+    gid: u32,
+    #[serde(default)]
+    ellipse: bool,
+    #[serde(default)]
+    point: bool,
+    polygon: Option<Vec<JsonPoint>>,
+    polyline: Option<Vec<JsonPoint>>,
+    text: Option<JsonText>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+impl JsonObject {
+    fn into_object(self) -> MapObject {
+        let points = |points: Vec<JsonPoint>| {
+            points
+                .into_iter()
+                .map(|p| mint::Point2 { x: p.x, y: p.y })
+                .collect()
+        };
+        let shape = if self.ellipse {
+            ObjectShape::Ellipse
+        } else if self.point {
+            ObjectShape::Point
+        } else if let Some(polygon) = self.polygon {
+            ObjectShape::Polygon(points(polygon))
+        } else if let Some(polyline) = self.polyline {
+            ObjectShape::Polyline(points(polyline))
+        } else if let Some(text) = self.text {
+            ObjectShape::Text(text.text)
+        } else {
+            ObjectShape::Rectangle
+        };
+        MapObject {
+            id: self.id,
+            name: self.name,
+            class: self.class.or(self.kind).unwrap_or_default(),
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+            rotation: self.rotation,
+            visible: self.visible,
+            tile: Tile::from_raw(self.gid),
+            shape,
+            properties: properties(&self.properties),
+        }
+    }
+}
+
+/// Reads a `.json` or `.tmj` map.
+pub(crate) fn parse_map(data: &[u8], dir: &path::Path, read: ReadFile) -> GameResult<MapData> {
+    let map: JsonMap = serde_json::from_slice(data)?;
+    check_map(&map.orientation, map.infinite)?;
+
+    let mut tilesets = Vec::new();
+    for tileset in map.tilesets {
+        let first_gid = tileset.firstgid;
+        let tileset = match &tileset.source {
+            Some(source) => load_tileset(&resolve(dir, source), first_gid, read)?,
+            None => tileset.into_tileset(dir, first_gid)?,
+        };
+        tilesets.push(tileset);
+    }
+
+    let mut layers = Vec::new();
+    add_layers(map.layers, Inherited::default(), &mut layers)?;
+    Ok(MapData {
+        width: map.width,
+        height: map.height,
+        tile_width: map.tilewidth,
+        tile_height: map.tileheight,
+        background: parse_color(&map.backgroundcolor),
+        tilesets,
+        layers,
+        properties: properties(&map.properties),
+    })
+}
+
+/// Reads a `.json` or `.tsj` tileset.
+pub(crate) fn parse_tileset_file(
+    data: &[u8],
+    dir: &path::Path,
+    first_gid: u32,
+) -> GameResult<Tileset> {
+    let tileset: JsonTileset = serde_json::from_slice(data)?;
+    tileset.into_tileset(dir, first_gid)
+}
+
+/// Adds the layers in a map or group to `layers`, flattening groups.
+fn add_layers(from: Vec<JsonLayer>, inherited: Inherited, layers: &mut Vec<Layer>) -> GameResult {
+    for layer in from {
+        let inherited =
+            inherited.apply(layer.visible, layer.opacity, (layer.offsetx, layer.offsety));
+        let properties = properties(&layer.properties);
+        match layer.kind.as_str() {
+            "tilelayer" => {
+                let count = (layer.width * layer.height) as usize;
+                let ids = match layer.data {
+                    Some(JsonData::Ids(ids)) if ids.len() == count => ids,
+                    Some(JsonData::Ids(_)) => {
+                        return Err(map_error("tile layer has the wrong number of tiles"))
+                    }
+                    Some(JsonData::Encoded(text)) => {
+                        decode_tiles(&text, &layer.encoding, &layer.compression, count)?
+                    }
+                    None => return Err(map_error("infinite maps are not supported")),
+                };
+                layers.push(Layer::Tiles(TileLayer {
+                    name: layer.name,
+                    width: layer.width,
+                    height: layer.height,
+                    tiles: ids.into_iter().map(Tile::from_raw).collect(),
+                    visible: inherited.visible,
+                    opacity: inherited.opacity,
+                    offset: inherited.offset,
+                    properties,
+                }));
+            }
+            "objectgroup" => layers.push(Layer::Objects(ObjectLayer {
+                name: layer.name,
+                objects: layer
+                    .objects
+                    .into_iter()
+                    .map(JsonObject::into_object)
+                    .collect(),
+                visible: inherited.visible,
+                opacity: inherited.opacity,
+                offset: inherited.offset,
+                properties,
+            })),
+            "group" => add_layers(layer.layers, inherited, layers)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TSJ: &str = r#"{
+        "type": "tileset", "name": "water", "tilewidth": 16, "tileheight": 16,
+        "tilecount": 4, "columns": 2,
+        "image": "water.png", "imagewidth": 32, "imageheight": 32,
+        "tiles": [{
+            "id": 1, "type": "water",
+            "animation": [{"tileid": 1, "duration": 200}, {"tileid": 2, "duration": 200}]
+        }]
+    }"#;
+
+    const MAP: &str = r##"{
+        "type": "map", "orientation": "orthogonal", "infinite": false,
+        "width": 2, "height": 2, "tilewidth": 16, "tileheight": 16,
+        "properties": [
+            {"name": "gravity", "type": "float", "value": 9.5},
+            {"name": "tint", "type": "color", "value": "#80ff0000"},
+            {"name": "spawn", "type": "object", "value": 2}
+        ],
+        "tilesets": [
+            {"firstgid": 1, "name": "ground", "tilewidth": 16, "tileheight": 16,
+             "image": "ground.png", "imagewidth": 32, "imageheight": 16},
+            {"firstgid": 3, "source": "tilesets/water.tsj"}
+        ],
+        "layers": [
+            {"type": "tilelayer", "name": "ground", "width": 2, "height": 2,
+             "data": [1, 2, 0, 536870916], "visible": true, "opacity": 1},
+            {"type": "group", "name": "group", "offsety": 4, "visible": false, "layers": [
+                {"type": "tilelayer", "name": "packed", "width": 2, "height": 1,
+                 "encoding": "base64", "compression": "zlib", "data": "eJxjZGBgYAJiAAAYAAQ="},
+                {"type": "objectgroup", "name": "objects", "objects": [
+                    {"id": 2, "name": "start", "class": "spawn", "x": 8, "y": 8, "point": true},
+                    {"id": 3, "x": 0, "y": 0, "polyline": [{"x": 0, "y": 0}, {"x": 4, "y": 2}]},
+                    {"id": 4, "x": 0, "y": 32, "gid": 3, "width": 16, "height": 16}
+                ]}
+            ]},
+            {"type": "imagelayer", "name": "sky", "image": "sky.png"}
+        ]
+    }"##;
+
+    #[test]
+    fn json_maps_load() {
+        let mut read = |path: &path::Path| {
+            assert_eq!(path::Path::new("/maps/tilesets/water.tsj"), path);
+            Ok(TSJ.as_bytes().to_vec())
+        };
+        let map = parse_map(MAP.as_bytes(), path::Path::new("/maps"), &mut read).unwrap();
+
+        assert_eq!(
+            Some(&PropertyValue::Float(9.5)),
+            map.properties.get("gravity")
+        );
+        assert_eq!(
+            Some(&PropertyValue::Color(crate::graphics::Color::from_rgba(
+                255, 0, 0, 128
+            ))),
+            map.properties.get("tint")
+        );
+        assert_eq!(Some(&PropertyValue::Object(2)), map.properties.get("spawn"));
+
+        assert_eq!(
+            (2, 2),
+            (map.tilesets[0].columns, map.tilesets[0].tile_count)
+        );
+        let water = &map.tilesets[1];
+        assert_eq!(path::PathBuf::from("/maps/tilesets/water.png"), water.image);
+        assert_eq!(2, water.tiles[&1].animation.len());
+
+        assert_eq!(3, map.layers.len());
+        match &map.layers[0] {
+            Layer::Tiles(ground) => {
+                let rotated = ground.tile(1, 1).unwrap();
+                assert!(rotated.gid == 4 && rotated.flip_d && !rotated.flip_h);
+                assert_eq!(None, ground.tile(0, 1));
+            }
+            _ => panic!("expected a tile layer"),
+        }
+        match &map.layers[1] {
+            Layer::Tiles(packed) => {
+                assert!(!packed.visible);
+                assert_eq!(4.0, packed.offset.y);
+                assert_eq!(Some(2), packed.tile(1, 0).map(|t| t.gid));
+            }
+            _ => panic!("expected a tile layer"),
+        }
+        match &map.layers[2] {
+            Layer::Objects(objects) => {
+                let objects = &objects.objects;
+                assert_eq!(("start", "spawn"), (&*objects[0].name, &*objects[0].class));
+                assert_eq!(ObjectShape::Point, objects[0].shape);
+                assert_eq!(
+                    ObjectShape::Polyline(vec![
+                        mint::Point2 { x: 0.0, y: 0.0 },
+                        mint::Point2 { x: 4.0, y: 2.0 },
+                    ]),
+                    objects[1].shape
+                );
+                assert_eq!(Some(3), objects[2].tile.map(|t| t.gid));
+            }
+            _ => panic!("expected an object layer"),
+        }
+    }
+}
@@ -0,0 +1,609 @@
+//! The contents of a map, as loaded from either kind of Tiled file.
+
+use std::collections::HashMap;
+use std::path;
+use std::time;
+
+use crate::error::{GameError, GameResult};
+use crate::graphics::{Color, Rect};
+
+/// Tiled stores tile flips in the top bits of global tile IDs.
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+/// Only used by hexagonal maps, which aren't supported, but it has to
+/// be masked off all the same.
+const ROTATED_HEXAGONAL: u32 = 0x1000_0000;
+
+/// Reads the bytes of files that a map refers to, such as external
+/// tilesets.
+pub(crate) type ReadFile<'a> = &'a mut dyn FnMut(&path::Path) -> GameResult<Vec<u8>>;
+
+/// The value of a custom property set in Tiled.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    /// A `bool` property.
+    Bool(bool),
+    /// An `int` property.
+    Int(i64),
+    /// A `float` property.
+    Float(f64),
+    /// A `string` property.
+    String(String),
+    /// A `color` property.
+    Color(Color),
+    /// A `file` property, holding the path as written in the map.
+    File(String),
+    /// An `object` property, holding the ID of the object it refers to.
+    Object(u32),
+}
+
+impl PropertyValue {
+    /// Reads a property from its type and value as written in the map.
+    pub(crate) fn parse(kind: &str, value: &str) -> Option<Self> {
+        Some(match kind {
+            "bool" => PropertyValue::Bool(value == "true"),
+            "int" => PropertyValue::Int(value.parse().ok()?),
+            "float" => PropertyValue::Float(value.parse().ok()?),
+            "color" => PropertyValue::Color(parse_color(value).unwrap_or(Color::BLACK)),
+            "file" => PropertyValue::File(value.to_string()),
+            "object" => PropertyValue::Object(value.parse().ok()?),
+            "" | "string" => PropertyValue::String(value.to_string()),
+            // Class properties and any newer types are left out.
+            _ => return None,
+        })
+    }
+}
+
+/// The custom properties of a map, layer, tile or object.
+pub type Properties = HashMap<String, PropertyValue>;
+
+/// Reads a `#AARRGGBB` or `#RRGGBB` color.
+pub(crate) fn parse_color(value: &str) -> Option<Color> {
+    let hex = value.trim_start_matches('#');
+    let c = u32::from_str_radix(hex, 16).ok()?;
+    match hex.len() {
+        6 => Some(Color::from_rgb_u32(c)),
+        8 => Some(Color::from_rgba_u32(c.rotate_left(8))),
+        _ => None,
+    }
+}
+
+/// A tile placed in a layer or by an object.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Tile {
+    /// The global ID of the tile, which picks the tileset with the
+    /// highest `first_gid` that isn't above it.
+    pub gid: u32,
+    /// Whether the tile is mirrored left to right.
+    pub flip_h: bool,
+    /// Whether the tile is mirrored top to bottom.
+    pub flip_v: bool,
+    /// Whether the tile is mirrored across its top-left to bottom-right
+    /// diagonal, which together with the other flips rotates it.
+    pub flip_d: bool,
+}
+
+impl Tile {
+    /// Splits a global ID with flip flags, as stored by Tiled, into a
+    /// tile, or `None` for an empty cell.
+    pub fn from_raw(raw: u32) -> Option<Tile> {
+        let gid = raw
+            & !(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY | ROTATED_HEXAGONAL);
+        if gid == 0 {
+            return None;
+        }
+        Some(Tile {
+            gid,
+            flip_h: raw & FLIPPED_HORIZONTALLY != 0,
+            flip_v: raw & FLIPPED_VERTICALLY != 0,
+            flip_d: raw & FLIPPED_DIAGONALLY != 0,
+        })
+    }
+
+    /// Returns the rotation and scale that draw the tile with its flips,
+    /// around its center.
+    pub(crate) fn orientation(&self) -> (f32, [f32; 2]) {
+        let sign = |flip: bool| if flip { -1.0 } else { 1.0 };
+        let (h, v) = (sign(self.flip_h), sign(self.flip_v));
+        if self.flip_d {
+            // Swapping the axes and then flipping is the same as flipping
+            // horizontally and then turning a quarter anticlockwise.
+            (-std::f32::consts::FRAC_PI_2, [-v, h])
+        } else {
+            (0.0, [h, v])
+        }
+    }
+}
+
+/// One frame of an animated tile.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TileFrame {
+    /// The ID of the tile shown, local to its tileset.
+    pub tile_id: u32,
+    /// How long it is shown for.
+    pub duration: time::Duration,
+}
+
+/// Extra information about one tile of a tileset.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TileData {
+    /// The class, or type, set for the tile.
+    pub class: String,
+    /// The frames the tile cycles through, if it is animated.
+    pub animation: Vec<TileFrame>,
+    /// The custom properties of the tile.
+    pub properties: Properties,
+}
+
+/// A set of tiles cut from one image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tileset {
+    /// The name of the tileset.
+    pub name: String,
+    /// The global ID of the tileset's first tile.
+    pub first_gid: u32,
+    /// The width of each tile, in pixels.
+    pub tile_width: u32,
+    /// The height of each tile, in pixels.
+    pub tile_height: u32,
+    /// The number of tiles.
+    pub tile_count: u32,
+    /// The number of tiles in each row of the image.
+    pub columns: u32,
+    /// The pixels between tiles in the image.
+    pub spacing: u32,
+    /// The pixels around the tiles at the edges of the image.
+    pub margin: u32,
+    /// The path of the image, resolved from where the tileset is.
+    pub image: path::PathBuf,
+    /// The size of the image, in pixels.
+    pub image_width: u32,
+    /// The size of the image, in pixels.
+    pub image_height: u32,
+    /// Extra information about some of the tiles, by local ID.
+    pub tiles: HashMap<u32, TileData>,
+    /// The custom properties of the tileset.
+    pub properties: Properties,
+}
+
+impl Tileset {
+    /// Returns the part of the image a tile is cut from, as a `src`
+    /// rect for `DrawParam`.
+    pub fn src(&self, tile_id: u32) -> Rect {
+        let columns = self.columns.max(1);
+        let (column, row) = (tile_id % columns, tile_id / columns);
+        let x = self.margin + column * (self.tile_width + self.spacing);
+        let y = self.margin + row * (self.tile_height + self.spacing);
+        let (w, h) = (self.image_width as f32, self.image_height as f32);
+        Rect::new(
+            x as f32 / w,
+            y as f32 / h,
+            self.tile_width as f32 / w,
+            self.tile_height as f32 / h,
+        )
+    }
+
+    /// Returns whether a global tile ID belongs to this tileset.
+    pub fn contains(&self, gid: u32) -> bool {
+        gid >= self.first_gid && gid - self.first_gid < self.tile_count
+    }
+
+    /// Checks that the tileset can be drawn, and works out the
+    /// values Tiled can leave out.
+    pub(crate) fn validate(mut self) -> GameResult<Self> {
+        if self.image.as_os_str().is_empty() {
+            return Err(map_error(&format!(
+                "tileset '{}' is a collection of images, which is not supported",
+                self.name
+            )));
+        }
+        if self.tile_width == 0 || self.tile_height == 0 {
+            return Err(map_error(&format!(
+                "tileset '{}' has no tile size",
+                self.name
+            )));
+        }
+        let fit = |size: u32, tile: u32| {
+            (size.saturating_sub(self.margin * 2) + self.spacing) / (tile + self.spacing)
+        };
+        if self.columns == 0 {
+            self.columns = fit(self.image_width, self.tile_width).max(1);
+        }
+        if self.tile_count == 0 {
+            self.tile_count = self.columns * fit(self.image_height, self.tile_height);
+        }
+        Ok(self)
+    }
+}
+
+/// A layer of the map.  Group layers are flattened away, with their
+/// offsets, opacities and visibility applied to the layers in them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Layer {
+    /// A grid of tiles.
+    Tiles(TileLayer),
+    /// Shapes and tiles placed freely, usually holding game data such as
+    /// spawn points and triggers.
+    Objects(ObjectLayer),
+}
+
+impl Layer {
+    /// Returns the name of the layer.
+    pub fn name(&self) -> &str {
+        match self {
+            Layer::Tiles(layer) => &layer.name,
+            Layer::Objects(layer) => &layer.name,
+        }
+    }
+}
+
+/// A grid of tiles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileLayer {
+    /// The name of the layer.
+    pub name: String,
+    /// The width of the layer, in tiles.
+    pub width: u32,
+    /// The height of the layer, in tiles.
+    pub height: u32,
+    /// The tiles, row by row, with `None` for empty cells.
+    pub tiles: Vec<Option<Tile>>,
+    /// Whether the layer is drawn.
+    pub visible: bool,
+    /// From `0.0` to `1.0`.
+    pub opacity: f32,
+    /// How far the layer is drawn from the map's origin, in pixels.
+    pub offset: mint::Vector2<f32>,
+    /// The custom properties of the layer.
+    pub properties: Properties,
+}
+
+impl TileLayer {
+    /// Returns the tile at the given cell, if there is one.
+    pub fn tile(&self, x: u32, y: u32) -> Option<Tile> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.tiles
+            .get((y * self.width + x) as usize)
+            .copied()
+            .flatten()
+    }
+}
+
+/// A layer of objects.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectLayer {
+    /// The name of the layer.
+    pub name: String,
+    /// The objects, in the order they were placed.
+    pub objects: Vec<MapObject>,
+    /// Whether the layer is shown in Tiled.
+    pub visible: bool,
+    /// From `0.0` to `1.0`.
+    pub opacity: f32,
+    /// How far the layer is from the map's origin, in pixels.
+    pub offset: mint::Vector2<f32>,
+    /// The custom properties of the layer.
+    pub properties: Properties,
+}
+
+/// The shape of an object.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectShape {
+    /// A rectangle, or a tile if the object has one.
+    Rectangle,
+    /// An ellipse filling the object's bounds.
+    Ellipse,
+    /// A single point.
+    Point,
+    /// A closed shape, with points relative to the object's position.
+    Polygon(Vec<mint::Point2<f32>>),
+    /// An open line, with points relative to the object's position.
+    Polyline(Vec<mint::Point2<f32>>),
+    /// Some text.
+    Text(String),
+}
+
+/// An object placed on an object layer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapObject {
+    /// The unique ID of the object.
+    pub id: u32,
+    /// The name of the object.
+    pub name: String,
+    /// The class, or type, of the object.
+    pub class: String,
+    /// The position of the object, in pixels.  For tile objects
+    /// this is the bottom-left corner, otherwise the top-left one.
+    pub x: f32,
+    /// The position of the object, in pixels.
+    pub y: f32,
+    /// The size of the object, in pixels.
+    pub width: f32,
+    /// The size of the object, in pixels.
+    pub height: f32,
+    /// The rotation of the object around its position, in degrees
+    /// clockwise.
+    pub rotation: f32,
+    /// Whether the object is shown in Tiled.
+    pub visible: bool,
+    /// The tile the object shows, if any.
+    pub tile: Option<Tile>,
+    /// The shape of the object.
+    pub shape: ObjectShape,
+    /// The custom properties of the object.
+    pub properties: Properties,
+}
+
+/// Everything read from a map file, before any images are loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MapData {
+    pub width: u32,
+    pub height: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub background: Option<Color>,
+    pub tilesets: Vec<Tileset>,
+    pub layers: Vec<Layer>,
+    pub properties: Properties,
+}
+
+/// Layer settings that groups pass on to the layers in them.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Inherited {
+    pub visible: bool,
+    pub opacity: f32,
+    pub offset: mint::Vector2<f32>,
+}
+
+impl Default for Inherited {
+    fn default() -> Self {
+        Inherited {
+            visible: true,
+            opacity: 1.0,
+            offset: mint::Vector2 { x: 0.0, y: 0.0 },
+        }
+    }
+}
+
+impl Inherited {
+    /// Combines a layer's own settings with those of its groups.
+    pub(crate) fn apply(&self, visible: bool, opacity: f32, offset: (f32, f32)) -> Self {
+        Inherited {
+            visible: self.visible && visible,
+            opacity: self.opacity * opacity,
+            offset: mint::Vector2 {
+                x: self.offset.x + offset.0,
+                y: self.offset.y + offset.1,
+            },
+        }
+    }
+}
+
+pub(crate) fn map_error(message: &str) -> GameError {
+    GameError::ResourceLoadError(format!("Could not load tile map: {}", message))
+}
+
+/// Checks the map settings that the loaders share.
+pub(crate) fn check_map(orientation: &str, infinite: bool) -> GameResult {
+    if orientation != "orthogonal" {
+        return Err(map_error(&format!(
+            "{} maps are not supported, only orthogonal ones",
+            orientation
+        )));
+    }
+    if infinite {
+        return Err(map_error("infinite maps are not supported"));
+    }
+    Ok(())
+}
+
+/// Resolves a path written in a file relative to the directory the
+/// file is in, getting rid of any `..`, which the filesystem doesn't
+/// understand.
+pub(crate) fn resolve(dir: &path::Path, relative: &str) -> path::PathBuf {
+    let mut resolved = path::PathBuf::new();
+    for component in dir.join(relative).components() {
+        match component {
+            path::Component::ParentDir => {
+                let _ = resolved.pop();
+            }
+            path::Component::CurDir => (),
+            other => resolved.push(other),
+        }
+    }
+    resolved
+}
+
+/// Decodes the tiles of a layer stored as text, returning the global
+/// IDs with their flip flags.
+pub(crate) fn decode_tiles(
+    text: &str,
+    encoding: &str,
+    compression: &str,
+    count: usize,
+) -> GameResult<Vec<u32>> {
+    let ids: Vec<u32> = match encoding {
+        "csv" => text
+            .split(',')
+            .map(|id| id.trim())
+            .filter(|id| !id.is_empty())
+            .map(|id| {
+                id.parse()
+                    .map_err(|_| map_error("bad tile ID in layer data"))
+            })
+            .collect::<GameResult<_>>()?,
+        "base64" => {
+            let bytes = decode_base64(text)?;
+            let bytes = match compression {
+                "" => bytes,
+                "zlib" => miniz_oxide::inflate::decompress_to_vec_zlib(&bytes)
+                    .map_err(|_| map_error("bad zlib layer data"))?,
+                "gzip" => {
+                    let deflated = strip_gzip_header(&bytes)?;
+                    miniz_oxide::inflate::decompress_to_vec(deflated)
+                        .map_err(|_| map_error("bad gzip layer data"))?
+                }
+                other => {
+                    return Err(map_error(&format!(
+                        "{} compressed layers are not supported",
+                        other
+                    )))
+                }
+            };
+            bytes
+                .chunks_exact(4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect()
+        }
+        other => {
+            return Err(map_error(&format!(
+                "unknown layer data encoding '{}'",
+                other
+            )))
+        }
+    };
+    if ids.len() != count {
+        return Err(map_error(&format!(
+            "layer has {} tiles, but should have {}",
+            ids.len(),
+            count
+        )));
+    }
+    Ok(ids)
+}
+
+fn decode_base64(text: &str) -> GameResult<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            c if c.is_ascii_whitespace() => continue,
+            _ => return Err(map_error("bad base64 layer data")),
+        };
+        buffer = (buffer << 6) | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+/// Returns the deflated data inside a gzip file.
+fn strip_gzip_header(data: &[u8]) -> GameResult<&[u8]> {
+    let bad = || map_error("bad gzip layer data");
+    if data.len() < 18 || data[0..2] != [0x1F, 0x8B] {
+        return Err(bad());
+    }
+    let flags = data[3];
+    let mut offset = 10;
+    if flags & 0x04 != 0 {
+        let extra = usize::from(u16::from_le_bytes([data[10], data[11]]));
+        offset += 2 + extra;
+    }
+    // The file name and comment end with a zero byte.
+    for flag in [0x08, 0x10] {
+        if flags & flag != 0 {
+            let end = data
+                .get(offset..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0));
+            offset += end.ok_or_else(bad)? + 1;
+        }
+    }
+    if flags & 0x02 != 0 {
+        offset += 2;
+    }
+    data.get(offset..data.len() - 8).ok_or_else(bad)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flip_flags_split_off() {
+        let tile = Tile::from_raw(0xA000_0005).unwrap();
+        assert_eq!(5, tile.gid);
+        assert!(tile.flip_h && !tile.flip_v && tile.flip_d);
+        assert_eq!(None, Tile::from_raw(FLIPPED_VERTICALLY));
+
+        // Each orientation has to map the tile's corners the way Tiled does.
+        let apply = |tile: Tile, (x, y): (f32, f32)| {
+            let (rotation, [sx, sy]) = tile.orientation();
+            let (x, y) = (x * sx, y * sy);
+            let (sin, cos) = rotation.sin_cos();
+            ((x * cos - y * sin).round(), (x * sin + y * cos).round())
+        };
+        // Transposed, the top right corner goes to the bottom left.
+        let transposed = Tile::from_raw(FLIPPED_DIAGONALLY | 1).unwrap();
+        assert_eq!((-1.0, 1.0), apply(transposed, (1.0, -1.0)));
+        // Turned a quarter clockwise, the top left corner goes to the top right.
+        let turned = Tile::from_raw(FLIPPED_DIAGONALLY | FLIPPED_HORIZONTALLY | 1).unwrap();
+        assert_eq!((1.0, -1.0), apply(turned, (-1.0, -1.0)));
+    }
+
+    #[test]
+    fn layer_data_decodes() {
+        assert_eq!(
+            vec![1, 0, 0x8000_0002],
+            decode_tiles(" 1,0,\n2147483650 ", "csv", "", 3).unwrap()
+        );
+        // [1, 2] as little-endian u32s.
+        assert_eq!(
+            vec![1, 2],
+            decode_tiles("AQAAAAIAAAA=", "base64", "", 2).unwrap()
+        );
+        assert_eq!(
+            vec![1, 2],
+            decode_tiles("eJxjZGBgYAJiAAAYAAQ=", "base64", "zlib", 2).unwrap()
+        );
+        assert_eq!(
+            vec![1, 2],
+            decode_tiles("H4sIAAAAAAACA2NkYGBgAmIAfBeBAwgAAAA=", "base64", "gzip", 2).unwrap()
+        );
+        assert!(decode_tiles("1,2", "csv", "", 3).is_err());
+    }
+
+    #[test]
+    fn tileset_sources_and_paths() {
+        let tileset = Tileset {
+            name: String::from("tiles"),
+            first_gid: 1,
+            tile_width: 16,
+            tile_height: 16,
+            tile_count: 0,
+            columns: 0,
+            spacing: 2,
+            margin: 1,
+            image: path::PathBuf::from("/tiles.png"),
+            image_width: 54,
+            image_height: 36,
+            tiles: HashMap::new(),
+            properties: Properties::new(),
+        }
+        .validate()
+        .unwrap();
+        assert_eq!((3, 6), (tileset.columns, tileset.tile_count));
+        assert!(tileset.contains(6) && !tileset.contains(7));
+        assert_eq!(
+            Rect::new(19.0 / 54.0, 19.0 / 36.0, 16.0 / 54.0, 16.0 / 36.0),
+            tileset.src(4)
+        );
+        assert_eq!(
+            path::PathBuf::from("/images/tiles.png"),
+            resolve(path::Path::new("/maps"), "../images/./tiles.png")
+        );
+        assert_eq!(Some(Color::from_rgba(1, 2, 3, 4)), parse_color("#04010203"));
+    }
+}
@@ -0,0 +1,572 @@
+//! Loading and drawing tile maps made with the [Tiled](https://www.mapeditor.org/)
+//! map editor.
+//!
+//! A [`Tilemap`](struct.Tilemap.html) loads a map saved as `.tmx` or
+//! as JSON, along with its tilesets and their images, from the
+//! [`filesystem`](../../filesystem/index.html).  Tile layers are cut
+//! into square chunks, each drawn with one
+//! [`SpriteBatch`](../spritebatch/struct.SpriteBatch.html) per tileset,
+//! and chunks that are off screen are skipped, so large maps cost
+//! little to draw.  Flipped and rotated tiles are drawn the way Tiled
+//! shows them, and animated tiles move on when the map is updated.
+//!
+//! Object layers aren't drawn; they are there as data, for things like
+//! spawn points, triggers and collision shapes.
+//!
+//! Only orthogonal maps of a fixed size are supported, with tilesets
+//! cut from a single image.
+//!
+//! ```rust,no_run
+//! # use ggez::*;
+//! # use ggez::graphics::tilemap::Tilemap;
+//! # fn t(ctx: &mut Context) -> GameResult {
+//! let mut map = Tilemap::new(ctx, "/maps/level1.tmx")?;
+//! for object in map.objects().filter(|object| object.class == "spawn") {
+//!     println!("Spawn point at {}, {}", object.x, object.y);
+//! }
+//!
+//! // Every frame:
+//! map.update(ctx);
+//! graphics::draw(ctx, &map, graphics::DrawParam::new())?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Read;
+use std::path;
+use std::time;
+
+use crate::context::Context;
+use crate::error::GameResult;
+use crate::graphics::spritebatch::{SpriteBatch, SpriteIdx};
+use crate::graphics::{
+    self, BlendMode, Color, DrawParam, Drawable, FilterMode, Image, Matrix4, Rect,
+};
+use crate::timer;
+
+mod json;
+mod map;
+mod tmx;
+
+use self::map::{map_error, MapData, ReadFile};
+pub use self::map::{
+    Layer, MapObject, ObjectLayer, ObjectShape, Properties, PropertyValue, Tile, TileData,
+    TileFrame, TileLayer, Tileset,
+};
+
+/// The default width and height of chunks, in tiles.
+const DEFAULT_CHUNK_SIZE: u32 = 16;
+
+/// Loads an external tileset, in whichever format its extension says.
+fn load_tileset(path: &path::Path, first_gid: u32, read: ReadFile) -> GameResult<Tileset> {
+    let data = read(path)?;
+    let dir = path.parent().unwrap_or_else(|| path::Path::new("/"));
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("tsx") => tmx::parse_tileset_file(&data, dir, first_gid),
+        _ => json::parse_tileset_file(&data, dir, first_gid),
+    }
+}
+
+/// A square of a tile layer, with one batch for each tileset used in it.
+#[derive(Debug, Clone)]
+struct Chunk {
+    bounds: Rect,
+    batches: Vec<SpriteBatch>,
+}
+
+/// Where an animated tile is, so its `src` can be changed.
+#[derive(Debug, Clone)]
+struct AnimatedTile {
+    layer: usize,
+    chunk: usize,
+    batch: usize,
+    sprite: SpriteIdx,
+    param: DrawParam,
+    tileset: usize,
+    tile_id: u32,
+    frame: usize,
+}
+
+/// A map made with Tiled, ready to draw.
+///
+/// Drawing the map draws its visible tile layers in order, with the
+/// map's top-left corner at the origin of the `DrawParam`.  The
+/// `color` of the `DrawParam` is not used; layers are drawn with their
+/// own opacity.
+#[derive(Clone)]
+pub struct Tilemap {
+    data: MapData,
+    images: Vec<Image>,
+    chunk_size: u32,
+    /// The chunks of each layer, empty for object layers.
+    chunks: Vec<Vec<Chunk>>,
+    animated: Vec<AnimatedTile>,
+    elapsed: time::Duration,
+    blend_mode: Option<BlendMode>,
+}
+
+impl Tilemap {
+    /// Loads a map from a `.tmx`, `.json` or `.tmj` file, along with
+    /// its tilesets and their images.  Paths in the map are followed
+    /// from the directory the map is in.
+    pub fn new<P: AsRef<path::Path>>(ctx: &mut Context, path: P) -> GameResult<Self> {
+        let path = path.as_ref();
+        let mut read = |path: &path::Path| -> GameResult<Vec<u8>> {
+            let mut buf = Vec::new();
+            let mut reader = ctx.filesystem.open(path)?;
+            let _ = reader.read_to_end(&mut buf)?;
+            Ok(buf)
+        };
+        let contents = read(path)?;
+        let dir = path.parent().unwrap_or_else(|| path::Path::new("/"));
+        let data = match path.extension().and_then(|e| e.to_str()) {
+            Some("tmx") => tmx::parse_map(&contents, dir, &mut read)?,
+            Some("json") | Some("tmj") => json::parse_map(&contents, dir, &mut read)?,
+            _ => {
+                return Err(map_error(&format!(
+                    "don't know how to read {}, it should be .tmx, .json or .tmj",
+                    path.display()
+                )))
+            }
+        };
+        Tilemap::from_data(ctx, data)
+    }
+
+    fn from_data(ctx: &mut Context, mut data: MapData) -> GameResult<Self> {
+        let mut images = Vec::with_capacity(data.tilesets.len());
+        for tileset in &mut data.tilesets {
+            let image = Image::new(ctx, &tileset.image)?;
+            // The size is written in the tileset, but the image itself
+            // is what the tiles are cut from.
+            if (tileset.image_width, tileset.image_height)
+                != (u32::from(image.width()), u32::from(image.height()))
+            {
+                tileset.image_width = u32::from(image.width());
+                tileset.image_height = u32::from(image.height());
+                tileset.columns = 0;
+                tileset.tile_count = 0;
+                *tileset = tileset.clone().validate()?;
+            }
+            images.push(image);
+        }
+        let mut map = Tilemap {
+            data,
+            images,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            chunks: Vec::new(),
+            animated: Vec::new(),
+            elapsed: time::Duration::default(),
+            blend_mode: None,
+        };
+        map.build()?;
+        Ok(map)
+    }
+
+    /// Returns the width of the map, in tiles.
+    pub fn width(&self) -> u32 {
+        self.data.width
+    }
+
+    /// Returns the height of the map, in tiles.
+    pub fn height(&self) -> u32 {
+        self.data.height
+    }
+
+    /// Returns the width of the map's grid cells, in pixels.
+    pub fn tile_width(&self) -> u32 {
+        self.data.tile_width
+    }
+
+    /// Returns the height of the map's grid cells, in pixels.
+    pub fn tile_height(&self) -> u32 {
+        self.data.tile_height
+    }
+
+    /// Returns the background color set for the map, if any.  It
+    /// isn't drawn with the map, but can be passed to
+    /// [`graphics::clear()`](../fn.clear.html).
+    pub fn background_color(&self) -> Option<Color> {
+        self.data.background
+    }
+
+    /// Returns the custom properties of the map.
+    pub fn properties(&self) -> &Properties {
+        &self.data.properties
+    }
+
+    /// Returns the tilesets of the map.
+    pub fn tilesets(&self) -> &[Tileset] {
+        &self.data.tilesets
+    }
+
+    /// Returns the tileset a global tile ID belongs to, if any.
+    pub fn tileset_for(&self, gid: u32) -> Option<&Tileset> {
+        self.tileset_index(gid).map(|i| &self.data.tilesets[i])
+    }
+
+    /// Returns the extra information, such as the properties, set for
+    /// a tile in its tileset.
+    pub fn tile_data(&self, gid: u32) -> Option<&TileData> {
+        let tileset = self.tileset_for(gid)?;
+        tileset.tiles.get(&(gid - tileset.first_gid))
+    }
+
+    fn tileset_index(&self, gid: u32) -> Option<usize> {
+        self.data
+            .tilesets
+            .iter()
+            .rposition(|tileset| tileset.first_gid <= gid)
+            .filter(|&i| self.data.tilesets[i].contains(gid))
+    }
+
+    /// Returns the layers of the map, in the order they are drawn.
+    pub fn layers(&self) -> &[Layer] {
+        &self.data.layers
+    }
+
+    /// Returns the first layer with the given name.
+    pub fn layer(&self, name: &str) -> Option<&Layer> {
+        self.data.layers.iter().find(|layer| layer.name() == name)
+    }
+
+    /// Returns the index of the first layer with the given name, for
+    /// [`draw_layer()`](#method.draw_layer).
+    pub fn layer_index(&self, name: &str) -> Option<usize> {
+        self.data
+            .layers
+            .iter()
+            .position(|layer| layer.name() == name)
+    }
+
+    /// Shows or hides a layer.
+    pub fn set_layer_visible(&mut self, index: usize, visible: bool) {
+        match self.data.layers.get_mut(index) {
+            Some(Layer::Tiles(layer)) => layer.visible = visible,
+            Some(Layer::Objects(layer)) => layer.visible = visible,
+            None => (),
+        }
+    }
+
+    /// Returns the objects of all object layers.
+    pub fn objects(&self) -> impl Iterator<Item = &MapObject> {
+        self.data.layers.iter().flat_map(|layer| match layer {
+            Layer::Objects(layer) => layer.objects.iter(),
+            Layer::Tiles(_) => [].iter(),
+        })
+    }
+
+    /// Returns the size of the chunks layers are drawn in, in tiles.
+    pub fn chunk_size(&self) -> u32 {
+        self.chunk_size
+    }
+
+    /// Sets the size of the chunks layers are drawn in, in tiles.  The
+    /// default is 16.  Smaller chunks are culled more closely, but
+    /// take more draw calls.
+    pub fn set_chunk_size(&mut self, tiles: u32) -> GameResult {
+        self.chunk_size = tiles.max(1);
+        self.build()
+    }
+
+    /// Sets the filter mode used to scale the tilesets' images.
+    pub fn set_filter(&mut self, mode: FilterMode) {
+        for image in &mut self.images {
+            image.set_filter(mode);
+        }
+        for batch in self
+            .chunks
+            .iter_mut()
+            .flatten()
+            .flat_map(|c| &mut c.batches)
+        {
+            batch.set_filter(mode);
+        }
+    }
+
+    /// Moves animated tiles on by the time since the last frame.
+    pub fn update(&mut self, ctx: &Context) {
+        self.advance(timer::delta(ctx));
+    }
+
+    /// Moves animated tiles on by the given time.  All tiles sharing an
+    /// animation show the same frame, as in Tiled.
+    pub fn advance(&mut self, dt: time::Duration) {
+        self.elapsed += dt;
+        let (elapsed, tilesets) = (self.elapsed, &self.data.tilesets);
+        for tile in &mut self.animated {
+            let tileset = &tilesets[tile.tileset];
+            let animation = &tileset.tiles[&tile.tile_id].animation;
+            let frame = animation_frame(animation, elapsed);
+            if frame != tile.frame {
+                tile.frame = frame;
+                let param = tile.param.src(tileset.src(animation[frame].tile_id));
+                let batch = &mut self.chunks[tile.layer][tile.chunk].batches[tile.batch];
+                // The sprite was added when building, so it's there.
+                let _ = batch.set(tile.sprite, param);
+            }
+        }
+    }
+
+    /// Draws one layer, whether or not it is visible.  Object layers
+    /// draw nothing.
+    pub fn draw_layer(&self, ctx: &mut Context, index: usize, param: DrawParam) -> GameResult {
+        let chunks = match self.chunks.get(index) {
+            Some(chunks) => chunks,
+            None => return Ok(()),
+        };
+        let view = visible_rect(
+            Matrix4::from(graphics::projection(ctx)),
+            Matrix4::from(param.trans.to_bare_matrix()),
+        );
+        for chunk in chunks {
+            if view.map_or(true, |view| view.overlaps(&chunk.bounds)) {
+                for batch in &chunk.batches {
+                    batch.draw(ctx, param)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Cuts the tile layers into chunks of sprite batches.
+    fn build(&mut self) -> GameResult {
+        self.chunks.clear();
+        self.animated.clear();
+        for (layer_index, layer) in self.data.layers.iter().enumerate() {
+            let layer = match layer {
+                Layer::Tiles(layer) => layer,
+                Layer::Objects(_) => {
+                    self.chunks.push(Vec::new());
+                    continue;
+                }
+            };
+            let size = self.chunk_size;
+            let mut chunks = Vec::new();
+            for chunk_y in (0..layer.height).step_by(size as usize) {
+                for chunk_x in (0..layer.width).step_by(size as usize) {
+                    let mut bounds: Option<Rect> = None;
+                    let mut batches = BTreeMap::new();
+                    for y in chunk_y..(chunk_y + size).min(layer.height) {
+                        for x in chunk_x..(chunk_x + size).min(layer.width) {
+                            let tile = match layer.tile(x, y) {
+                                Some(tile) => tile,
+                                None => continue,
+                            };
+                            let index = self.tileset_index(tile.gid).ok_or_else(|| {
+                                map_error(&format!("tile {} is in no tileset", tile.gid))
+                            })?;
+                            let tileset = &self.data.tilesets[index];
+                            let tile_id = tile.gid - tileset.first_gid;
+                            let (rect, param) = self.tile_param(layer, x, y, tile, tileset);
+                            bounds = Some(bounds.map_or(rect, |b| b.combine_with(rect)));
+
+                            let batch = batches
+                                .entry(index)
+                                .or_insert_with(|| SpriteBatch::new(self.images[index].clone()));
+                            let animation = tileset
+                                .tiles
+                                .get(&tile_id)
+                                .map(|data| &data.animation[..])
+                                .unwrap_or(&[]);
+                            if animation.is_empty() {
+                                let _ = batch.add(param.src(tileset.src(tile_id)));
+                            } else {
+                                let frame = animation_frame(animation, self.elapsed);
+                                let src = tileset.src(animation[frame].tile_id);
+                                let sprite = batch.add(param.src(src));
+                                self.animated.push(AnimatedTile {
+                                    layer: layer_index,
+                                    chunk: chunks.len(),
+                                    // Fixed up below, once the batches are in order.
+                                    batch: index,
+                                    sprite,
+                                    param,
+                                    tileset: index,
+                                    tile_id,
+                                    frame,
+                                });
+                            }
+                        }
+                    }
+                    let bounds = match bounds {
+                        Some(bounds) => bounds,
+                        None => continue,
+                    };
+                    // Turn tileset indices into positions in the chunk.
+                    let tileset_indices = batches.keys().copied().collect::<Vec<_>>();
+                    for tile in self
+                        .animated
+                        .iter_mut()
+                        .filter(|t| t.layer == layer_index && t.chunk == chunks.len())
+                    {
+                        tile.batch = tileset_indices
+                            .iter()
+                            .position(|&i| i == tile.batch)
+                            .expect("animated tile has no batch");
+                    }
+                    let mut batches = batches.into_values().collect::<Vec<_>>();
+                    for batch in &mut batches {
+                        batch.set_blend_mode(self.blend_mode);
+                    }
+                    chunks.push(Chunk { bounds, batches });
+                }
+            }
+            self.chunks.push(chunks);
+        }
+        Ok(())
+    }
+
+    /// Works out where a tile is drawn, and how.
+    fn tile_param(
+        &self,
+        layer: &TileLayer,
+        x: u32,
+        y: u32,
+        tile: Tile,
+        tileset: &Tileset,
+    ) -> (Rect, DrawParam) {
+        let (rotation, scale) = tile.orientation();
+        // Tiles bigger than the grid stick up from the bottom left of
+        // their cell, and turning them swaps their sides.
+        let (w, h) = if tile.flip_d {
+            (tileset.tile_height as f32, tileset.tile_width as f32)
+        } else {
+            (tileset.tile_width as f32, tileset.tile_height as f32)
+        };
+        let left = layer.offset.x + (x * self.data.tile_width) as f32;
+        let bottom = layer.offset.y + ((y + 1) * self.data.tile_height) as f32;
+        let rect = Rect::new(left, bottom - h, w, h);
+        let param = DrawParam::new()
+            .dest([left + w / 2.0, bottom - h / 2.0])
+            .offset([0.5, 0.5])
+            .rotation(rotation)
+            .scale(scale)
+            .color(Color::new(1.0, 1.0, 1.0, layer.opacity));
+        (rect, param)
+    }
+}
+
+/// Returns which frame of an animation is shown after some time.
+fn animation_frame(animation: &[TileFrame], elapsed: time::Duration) -> usize {
+    let total: u128 = animation.iter().map(|f| f.duration.as_nanos()).sum();
+    if total == 0 {
+        return 0;
+    }
+    let mut t = elapsed.as_nanos() % total;
+    for (i, frame) in animation.iter().enumerate() {
+        if t < frame.duration.as_nanos() {
+            return i;
+        }
+        t -= frame.duration.as_nanos();
+    }
+    animation.len() - 1
+}
+
+/// Returns the part of the map that ends up on screen when drawn with
+/// the given transform, or `None` if it can't be worked out.
+fn visible_rect(projection: Matrix4, transform: Matrix4) -> Option<Rect> {
+    let mvp = projection * transform;
+    if mvp.determinant().abs() < f32::EPSILON * f32::EPSILON {
+        return None;
+    }
+    let inverse = mvp.inverse();
+    let corners = [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)];
+    let (mut min, mut max) = ([f32::MAX; 2], [f32::MIN; 2]);
+    for &(x, y) in &corners {
+        let p = inverse.project_point3(glam::Vec3::new(x, y, 0.0));
+        min = [min[0].min(p.x), min[1].min(p.y)];
+        max = [max[0].max(p.x), max[1].max(p.y)];
+    }
+    Some(Rect::new(min[0], min[1], max[0] - min[0], max[1] - min[1]))
+}
+
+impl Drawable for Tilemap {
+    fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        for (index, layer) in self.data.layers.iter().enumerate() {
+            if let Layer::Tiles(layer) = layer {
+                if layer.visible {
+                    self.draw_layer(ctx, index, param)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn dimensions(&self, _ctx: &mut Context) -> Option<Rect> {
+        let bounds = self
+            .chunks
+            .iter()
+            .flatten()
+            .map(|chunk| chunk.bounds)
+            .reduce(Rect::combine_with)?;
+        Some(bounds)
+    }
+
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.blend_mode = mode;
+        for batch in self
+            .chunks
+            .iter_mut()
+            .flatten()
+            .flat_map(|c| &mut c.batches)
+        {
+            batch.set_blend_mode(mode);
+        }
+    }
+
+    fn blend_mode(&self) -> Option<BlendMode> {
+        self.blend_mode
+    }
+}
+
+impl fmt::Debug for Tilemap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Tilemap: {:p}>", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn animations_share_a_clock() {
+        let frame = |tile_id, ms| TileFrame {
+            tile_id,
+            duration: time::Duration::from_millis(ms),
+        };
+        let animation = [frame(4, 100), frame(5, 50), frame(6, 100)];
+        let at = |ms| animation_frame(&animation, time::Duration::from_millis(ms));
+        assert_eq!(
+            vec![0, 0, 1, 2, 2, 0],
+            vec![at(0), at(99), at(100), at(150), at(249), at(250)]
+        );
+        assert_eq!(
+            0,
+            animation_frame(&[frame(1, 0)], time::Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn culling_follows_the_screen() {
+        // The projection ggez sets up for screen coordinates of 800x600.
+        let projection = Matrix4::orthographic_rh_gl(0.0, 800.0, 600.0, 0.0, -1.0, 1.0);
+        let view = visible_rect(projection, Matrix4::IDENTITY).unwrap();
+        assert!((view.x - 0.0).abs() < 0.01 && (view.w - 800.0).abs() < 0.01);
+        assert!((view.y - 0.0).abs() < 0.01 && (view.h - 600.0).abs() < 0.01);
+
+        // Drawing the map moved left and scaled up shows less of it,
+        // further right.
+        let transform = Matrix4::from_translation(glam::Vec3::new(-400.0, 0.0, 0.0))
+            * Matrix4::from_scale(glam::Vec3::new(2.0, 2.0, 1.0));
+        let view = visible_rect(projection, transform).unwrap();
+        assert!((view.x - 200.0).abs() < 0.01 && (view.w - 400.0).abs() < 0.01);
+        assert!((view.h - 300.0).abs() < 0.01);
+
+        // A map squashed flat can't be seen at all, so nothing is culled.
+        let flat = Matrix4::from_scale(glam::Vec3::new(1.0, 0.0, 1.0));
+        assert_eq!(None, visible_rect(projection, flat));
+    }
+}
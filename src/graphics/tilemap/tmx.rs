@@ -0,0 +1,457 @@
+//! Loading maps and tilesets from Tiled's XML formats, `.tmx` and `.tsx`.
+
+use std::collections::HashMap;
+use std::path;
+use std::str::FromStr;
+use std::time;
+
+use xml::reader::{EventReader, XmlEvent};
+
+use super::load_tileset;
+use super::map::*;
+use crate::error::GameResult;
+
+/// Just enough of an XML document to read maps from.
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn parse(data: &[u8]) -> GameResult<Element> {
+        let mut stack = vec![Element::default()];
+        for event in EventReader::new(data) {
+            let event = event.map_err(|e| map_error(&format!("bad XML: {}", e)))?;
+            match event {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => stack.push(Element {
+                    name: name.local_name,
+                    attributes: attributes
+                        .into_iter()
+                        .map(|a| (a.name.local_name, a.value))
+                        .collect(),
+                    ..Element::default()
+                }),
+                XmlEvent::EndElement { .. } => {
+                    let element = stack.pop().expect("unbalanced XML events");
+                    stack
+                        .last_mut()
+                        .expect("unbalanced XML events")
+                        .children
+                        .push(element);
+                }
+                XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push_str(&text);
+                    }
+                }
+                _ => (),
+            }
+        }
+        stack
+            .pop()
+            .and_then(|document| document.children.into_iter().next())
+            .ok_or_else(|| map_error("empty XML document"))
+    }
+
+    fn get(&self, name: &str) -> &str {
+        self.attributes.get(name).map(String::as_str).unwrap_or("")
+    }
+
+    /// Reads an attribute, or returns the default if it is missing.
+    fn attr<T: FromStr>(&self, name: &str, default: T) -> GameResult<T> {
+        match self.attributes.get(name) {
+            Some(value) => value.trim().parse().map_err(|_| {
+                map_error(&format!(
+                    "bad value '{}' for {} of <{}>",
+                    value, name, self.name
+                ))
+            }),
+            None => Ok(default),
+        }
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn properties(&self) -> Properties {
+        self.children("properties")
+            .flat_map(|properties| properties.children("property"))
+            .filter_map(|property| {
+                // Multi-line strings are written as the property's text.
+                let value = match property.attributes.get("value") {
+                    Some(value) => value.as_str(),
+                    None => property.text.as_str(),
+                };
+                PropertyValue::parse(property.get("type"), value)
+                    .map(|value| (property.get("name").to_string(), value))
+            })
+            .collect()
+    }
+
+    /// Reads the `class` of an element, which was called `type` before
+    /// Tiled 1.9.
+    fn class(&self) -> String {
+        match self.attributes.get("class") {
+            Some(class) => class.clone(),
+            None => self.get("type").to_string(),
+        }
+    }
+}
+
+/// Reads a `.tmx` map.
+pub(crate) fn parse_map(data: &[u8], dir: &path::Path, read: ReadFile) -> GameResult<MapData> {
+    let map = Element::parse(data)?;
+    if map.name != "map" {
+        return Err(map_error("a .tmx file has to start with <map>"));
+    }
+    check_map(map.get("orientation"), map.attr("infinite", 0)? != 0)?;
+
+    let mut tilesets = Vec::new();
+    for tileset in map.children("tileset") {
+        let first_gid = tileset.attr("firstgid", 1)?;
+        let tileset = match tileset.attributes.get("source") {
+            Some(source) => load_tileset(&resolve(dir, source), first_gid, read)?,
+            None => parse_tileset(tileset, dir, first_gid)?,
+        };
+        tilesets.push(tileset);
+    }
+
+    let (width, height) = (map.attr("width", 0)?, map.attr("height", 0)?);
+    let mut layers = Vec::new();
+    parse_layers(&map, Inherited::default(), &mut layers)?;
+    Ok(MapData {
+        width,
+        height,
+        tile_width: map.attr("tilewidth", 0)?,
+        tile_height: map.attr("tileheight", 0)?,
+        background: parse_color(map.get("backgroundcolor")),
+        tilesets,
+        layers,
+        properties: map.properties(),
+    })
+}
+
+/// Reads a `.tsx` tileset.
+pub(crate) fn parse_tileset_file(
+    data: &[u8],
+    dir: &path::Path,
+    first_gid: u32,
+) -> GameResult<Tileset> {
+    let tileset = Element::parse(data)?;
+    if tileset.name != "tileset" {
+        return Err(map_error("a .tsx file has to start with <tileset>"));
+    }
+    parse_tileset(&tileset, dir, first_gid)
+}
+
+fn parse_tileset(tileset: &Element, dir: &path::Path, first_gid: u32) -> GameResult<Tileset> {
+    let image = tileset.child("image");
+    let mut tiles = HashMap::new();
+    for tile in tileset.children("tile") {
+        let animation = tile
+            .children("animation")
+            .flat_map(|animation| animation.children("frame"))
+            .map(|frame| {
+                Ok(TileFrame {
+                    tile_id: frame.attr("tileid", 0)?,
+                    duration: time::Duration::from_millis(frame.attr("duration", 0)?),
+                })
+            })
+            .collect::<GameResult<_>>()?;
+        let data = TileData {
+            class: tile.class(),
+            animation,
+            properties: tile.properties(),
+        };
+        let _ = tiles.insert(tile.attr("id", 0)?, data);
+    }
+    Tileset {
+        name: tileset.get("name").to_string(),
+        first_gid,
+        tile_width: tileset.attr("tilewidth", 0)?,
+        tile_height: tileset.attr("tileheight", 0)?,
+        tile_count: tileset.attr("tilecount", 0)?,
+        columns: tileset.attr("columns", 0)?,
+        spacing: tileset.attr("spacing", 0)?,
+        margin: tileset.attr("margin", 0)?,
+        image: image
+            .map(|image| resolve(dir, image.get("source")))
+            .unwrap_or_default(),
+        image_width: image.map_or(Ok(0), |image| image.attr("width", 0))?,
+        image_height: image.map_or(Ok(0), |image| image.attr("height", 0))?,
+        tiles,
+        properties: tileset.properties(),
+    }
+    .validate()
+}
+
+/// Adds the layers in a map or group to `layers`, flattening groups.
+fn parse_layers(parent: &Element, inherited: Inherited, layers: &mut Vec<Layer>) -> GameResult {
+    for element in &parent.children {
+        let inherited = inherited.apply(
+            element.attr("visible", 1)? != 0,
+            element.attr("opacity", 1.0)?,
+            (element.attr("offsetx", 0.0)?, element.attr("offsety", 0.0)?),
+        );
+        match element.name.as_str() {
+            "layer" => layers.push(Layer::Tiles(parse_tile_layer(element, inherited)?)),
+            "objectgroup" => layers.push(Layer::Objects(ObjectLayer {
+                name: element.get("name").to_string(),
+                objects: element
+                    .children("object")
+                    .map(parse_object)
+                    .collect::<GameResult<_>>()?,
+                visible: inherited.visible,
+                opacity: inherited.opacity,
+                offset: inherited.offset,
+                properties: element.properties(),
+            })),
+            "group" => parse_layers(element, inherited, layers)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn parse_tile_layer(layer: &Element, inherited: Inherited) -> GameResult<TileLayer> {
+    let (width, height): (u32, u32) = (layer.attr("width", 0)?, layer.attr("height", 0)?);
+    let count = (width * height) as usize;
+    let data = layer
+        .child("data")
+        .ok_or_else(|| map_error("tile layer has no <data>"))?;
+    if data.child("chunk").is_some() {
+        return Err(map_error("infinite maps are not supported"));
+    }
+    let ids = match data.get("encoding") {
+        "" => {
+            let ids = data
+                .children("tile")
+                .map(|tile| tile.attr("gid", 0))
+                .collect::<GameResult<Vec<u32>>>()?;
+            if ids.len() != count {
+                return Err(map_error("tile layer has the wrong number of tiles"));
+            }
+            ids
+        }
+        encoding => decode_tiles(&data.text, encoding, data.get("compression"), count)?,
+    };
+    Ok(TileLayer {
+        name: layer.get("name").to_string(),
+        width,
+        height,
+        tiles: ids.into_iter().map(Tile::from_raw).collect(),
+        visible: inherited.visible,
+        opacity: inherited.opacity,
+        offset: inherited.offset,
+        properties: layer.properties(),
+    })
+}
+
+fn parse_points(points: &str) -> GameResult<Vec<mint::Point2<f32>>> {
+    points
+        .split_whitespace()
+        .map(|point| {
+            let mut xy = point.split(',').map(str::parse::<f32>);
+            match (xy.next(), xy.next()) {
+                (Some(Ok(x)), Some(Ok(y))) => Ok(mint::Point2 { x, y }),
+                _ => Err(map_error(&format!("bad point '{}'", point))),
+            }
+        })
+        .collect()
+}
+
+fn parse_object(object: &Element) -> GameResult<MapObject> {
+    let shape = if object.child("ellipse").is_some() {
+        ObjectShape::Ellipse
+    } else if object.child("point").is_some() {
+        ObjectShape::Point
+    } else if let Some(polygon) = object.child("polygon") {
+        ObjectShape::Polygon(parse_points(polygon.get("points"))?)
+    } else if let Some(polyline) = object.child("polyline") {
+        ObjectShape::Polyline(parse_points(polyline.get("points"))?)
+    } else if let Some(text) = object.child("text") {
+        ObjectShape::Text(text.text.clone())
+    } else {
+        ObjectShape::Rectangle
+    };
+    Ok(MapObject {
+        id: object.attr("id", 0)?,
+        name: object.get("name").to_string(),
+        class: object.class(),
+        x: object.attr("x", 0.0)?,
+        y: object.attr("y", 0.0)?,
+        width: object.attr("width", 0.0)?,
+        height: object.attr("height", 0.0)?,
+        rotation: object.attr("rotation", 0.0)?,
+        visible: object.attr("visible", 1)? != 0,
+        tile: Tile::from_raw(object.attr("gid", 0)?),
+        shape,
+        properties: object.properties(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TSX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" name="water" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+ <image source="../images/water.png" width="32" height="32"/>
+ <tile id="0" type="water">
+  <properties><property name="deep" type="bool" value="true"/></properties>
+  <animation>
+   <frame tileid="0" duration="100"/>
+   <frame tileid="1" duration="150"/>
+  </animation>
+ </tile>
+</tileset>
+"#;
+
+    const TMX: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" renderorder="right-down" width="3" height="2" tilewidth="16" tileheight="16" infinite="0" backgroundcolor="#ff102030">
+ <properties>
+  <property name="title" value="Test"/>
+  <property name="notes">line one
+line two</property>
+ </properties>
+ <tileset firstgid="1" name="ground" tilewidth="16" tileheight="16" tilecount="6" columns="3">
+  <image source="ground.png" width="48" height="32"/>
+ </tileset>
+ <tileset firstgid="7" source="../tilesets/water.tsx"/>
+ <layer id="1" name="ground" width="3" height="2">
+  <data encoding="csv">
+1,2,3,
+2147483652,0,7
+</data>
+ </layer>
+ <group id="4" name="upper" offsetx="8" opacity="0.5">
+  <layer id="2" name="detail" width="3" height="2" offsetx="2" visible="0">
+   <data encoding="base64">
+    AQAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA
+   </data>
+  </layer>
+  <objectgroup id="3" name="things">
+   <object id="1" name="spawn" class="player" x="4" y="5">
+    <point/>
+   </object>
+   <object id="2" x="0" y="0" width="10" height="20">
+    <ellipse/>
+   </object>
+   <object id="3" x="1" y="2">
+    <polygon points="0,0 10,0 10,10"/>
+    <properties><property name="damage" type="int" value="3"/></properties>
+   </object>
+   <object id="4" gid="1073741832" x="16" y="32" width="16" height="16"/>
+   <object id="5" x="0" y="0" width="50" height="20">
+    <text wrap="1">Hello</text>
+   </object>
+  </objectgroup>
+ </group>
+ <imagelayer id="5" name="sky"/>
+</map>
+"##;
+
+    #[test]
+    fn tmx_maps_load() {
+        let mut requested = Vec::new();
+        let mut read = |path: &path::Path| {
+            requested.push(path.to_path_buf());
+            Ok(TSX.as_bytes().to_vec())
+        };
+        let map = parse_map(TMX.as_bytes(), path::Path::new("/maps"), &mut read).unwrap();
+        assert_eq!(vec![path::PathBuf::from("/tilesets/water.tsx")], requested);
+
+        assert_eq!(
+            (3, 2, 16, 16),
+            (map.width, map.height, map.tile_width, map.tile_height)
+        );
+        assert_eq!(
+            Some(crate::graphics::Color::from_rgba(0x10, 0x20, 0x30, 0xFF)),
+            map.background
+        );
+        assert_eq!(
+            Some(&PropertyValue::String(String::from("line one\nline two"))),
+            map.properties.get("notes")
+        );
+
+        let water = &map.tilesets[1];
+        assert_eq!(7, water.first_gid);
+        assert_eq!(path::PathBuf::from("/images/water.png"), water.image);
+        assert_eq!(
+            path::PathBuf::from("/maps/ground.png"),
+            map.tilesets[0].image
+        );
+        let tile = &water.tiles[&0];
+        assert_eq!("water", tile.class);
+        assert_eq!(time::Duration::from_millis(150), tile.animation[1].duration);
+        assert_eq!(
+            Some(&PropertyValue::Bool(true)),
+            tile.properties.get("deep")
+        );
+
+        assert_eq!(3, map.layers.len());
+        let ground = match &map.layers[0] {
+            Layer::Tiles(layer) => layer,
+            _ => panic!("expected a tile layer"),
+        };
+        assert_eq!(Some(3), ground.tile(2, 0).map(|t| t.gid));
+        assert_eq!(None, ground.tile(1, 1));
+        let flipped = ground.tile(0, 1).unwrap();
+        assert!(flipped.gid == 4 && flipped.flip_h && !flipped.flip_v);
+
+        match &map.layers[1] {
+            Layer::Tiles(detail) => {
+                assert_eq!("detail", detail.name);
+                assert!(!detail.visible);
+                assert_eq!(0.5, detail.opacity);
+                assert_eq!(10.0, detail.offset.x);
+                assert_eq!(Some(1), detail.tile(0, 0).map(|t| t.gid));
+            }
+            _ => panic!("expected a tile layer"),
+        }
+
+        let things = match &map.layers[2] {
+            Layer::Objects(layer) => layer,
+            _ => panic!("expected an object layer"),
+        };
+        assert_eq!(8.0, things.offset.x);
+        let objects = &things.objects;
+        assert_eq!(("spawn", "player"), (&*objects[0].name, &*objects[0].class));
+        assert_eq!(ObjectShape::Point, objects[0].shape);
+        assert_eq!(ObjectShape::Ellipse, objects[1].shape);
+        assert_eq!(
+            ObjectShape::Polygon(vec![
+                mint::Point2 { x: 0.0, y: 0.0 },
+                mint::Point2 { x: 10.0, y: 0.0 },
+                mint::Point2 { x: 10.0, y: 10.0 },
+            ]),
+            objects[2].shape
+        );
+        assert_eq!(
+            Some(&PropertyValue::Int(3)),
+            objects[2].properties.get("damage")
+        );
+        let tile = objects[3].tile.unwrap();
+        assert!(tile.gid == 8 && tile.flip_v);
+        assert_eq!(ObjectShape::Text(String::from("Hello")), objects[4].shape);
+    }
+
+    #[test]
+    fn unsupported_maps_fail() {
+        let mut read = |_: &path::Path| Ok(Vec::new());
+        let isometric = TMX.replace("orthogonal", "isometric");
+        assert!(parse_map(isometric.as_bytes(), path::Path::new("/"), &mut read).is_err());
+        let infinite = TMX.replace(r#"infinite="0""#, r#"infinite="1""#);
+        assert!(parse_map(infinite.as_bytes(), path::Path::new("/"), &mut read).is_err());
+    }
+}
@@ -570,6 +570,27 @@ impl From<FilterMode> for FilterMethod {
     }
 }
 
+/// Specifies which side of a mask drawing is limited to, see
+/// [`graphics::draw_mask()`](fn.draw_mask.html).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MaskMode {
+    /// Only draw where the mask is
+    Inside,
+    /// Only draw where the mask isn't
+    Outside,
+}
+
+impl MaskMode {
+    /// The stencil value everything starts with, and the one the mask
+    /// writes.  Drawing then passes where the stencil value is 1.
+    pub(crate) fn stencil_values(self) -> (u8, u8) {
+        match self {
+            MaskMode::Inside => (0, 1),
+            MaskMode::Outside => (1, 0),
+        }
+    }
+}
+
 /// Specifies how to wrap textures.
 pub use gfx::texture::WrapMode;
 
@@ -579,6 +600,17 @@ mod tests {
     use approx::assert_relative_eq;
     use std::f32::consts::PI;
 
+    #[test]
+    fn headless_test_mask_stencil_values() {
+        // Masked drawing passes where the stencil value is at least 1.
+        for mode in [MaskMode::Inside, MaskMode::Outside] {
+            let (cleared, written) = mode.stencil_values();
+            let passes = |value: u8| value >= 1;
+            assert_eq!(mode == MaskMode::Inside, passes(written));
+            assert_eq!(mode == MaskMode::Outside, passes(cleared));
+        }
+    }
+
     #[test]
     fn headless_test_color_conversions() {
         let white = Color::new(1.0, 1.0, 1.0, 1.0);
@@ -0,0 +1,464 @@
+//! Action mapping on top of the keyboard, mouse and gamepad.
+//!
+//! Instead of checking for specific keys or buttons all over your game,
+//! you give names to the things the player can do ("jump", "fire")
+//! or steer ("move_x"), bind any mix of inputs to them, and query them
+//! by name.  The bindings are plain data that can be saved to and loaded
+//! from a TOML file next to `conf.toml`, so players can rebind their controls.
+//!
+//! ```rust,no_run
+//! # use ggez::{Context, GameResult};
+//! use ggez::input::actions::*;
+//! use ggez::input::keyboard::KeyCode;
+//! use ggez::event::{Axis, Button};
+//!
+//! # fn t(ctx: &mut Context) -> GameResult {
+//! let mut bindings = Bindings::new();
+//! bindings.bind("jump", InputBinding::Key { key: KeyCode::Space });
+//! bindings.bind("jump", InputBinding::GamepadButton { button: Button::South });
+//! bindings.bind_axis(
+//!     "move_x",
+//!     AxisBinding::Buttons {
+//!         negative: InputBinding::Key { key: KeyCode::A },
+//!         positive: InputBinding::Key { key: KeyCode::D },
+//!     },
+//! );
+//! bindings.bind_axis("move_x", AxisBinding::GamepadAxis { axis: Axis::LeftStickX, inverted: false });
+//! let mut actions = ActionMap::new(bindings);
+//!
+//! // Then, once per frame, at the start of `update()`:
+//! actions.update(ctx);
+//! if actions.just_pressed("jump") {
+//!     // ...
+//! }
+//! let speed = actions.axis_value("move_x") * 100.0;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::path;
+
+use crate::context::Context;
+use crate::error::{GameError, GameResult};
+use crate::event::{Axis, Button};
+use crate::filesystem;
+use crate::input::gamepad;
+use crate::input::keyboard::{self, KeyCode};
+use crate::input::mouse::{self, MouseButton};
+
+const BINDINGS_NAME: &str = "/bindings.toml";
+
+/// The dead zone given to new actions and axes.
+pub const DEFAULT_DEAD_ZONE: f32 = 0.1;
+
+fn default_dead_zone() -> f32 {
+    DEFAULT_DEAD_ZONE
+}
+
+/// A single input that can be bound to an action.
+///
+/// Every binding reads as a value between 0.0 and 1.0; for buttons
+/// that is simply 0.0 or 1.0.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum InputBinding {
+    /// A keyboard key.
+    Key {
+        /// The key
+        key: KeyCode,
+    },
+    /// A mouse button.
+    MouseButton {
+        /// The button
+        #[serde(with = "crate::input::mouse::button_serde")]
+        button: MouseButton,
+    },
+    /// A button on any connected gamepad.
+    GamepadButton {
+        /// The button
+        button: Button,
+    },
+    /// One half of an analog axis on any connected gamepad.
+    GamepadAxis {
+        /// The axis
+        axis: Axis,
+        /// Whether this binding reads the positive or the negative half of the axis
+        positive: bool,
+    },
+}
+
+impl InputBinding {
+    /// Returns the current value of this input, between 0.0 and 1.0,
+    /// with analog values inside `dead_zone` reading as 0.0.
+    pub fn value(&self, ctx: &Context, dead_zone: f32) -> f32 {
+        match *self {
+            InputBinding::Key { key } => button_value(keyboard::is_key_pressed(ctx, key)),
+            InputBinding::MouseButton { button } => {
+                button_value(mouse::button_pressed(ctx, button))
+            }
+            InputBinding::GamepadButton { button } => {
+                button_value(gamepad_button_pressed(ctx, button))
+            }
+            InputBinding::GamepadAxis { axis, positive } => {
+                let value = gamepad_axis_value(ctx, axis, dead_zone);
+                if positive {
+                    value.max(0.0)
+                } else {
+                    (-value).max(0.0)
+                }
+            }
+        }
+    }
+}
+
+/// An input that can be bound to an axis.
+///
+/// Every binding reads as a value between -1.0 and 1.0.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum AxisBinding {
+    /// Two inputs pulling the axis in opposite directions, such as a pair of keys.
+    Buttons {
+        /// The input that pulls the axis towards -1.0
+        negative: InputBinding,
+        /// The input that pulls the axis towards 1.0
+        positive: InputBinding,
+    },
+    /// An analog axis on any connected gamepad.
+    GamepadAxis {
+        /// The axis
+        axis: Axis,
+        /// Whether to flip the sign of the axis
+        #[serde(default)]
+        inverted: bool,
+    },
+}
+
+impl AxisBinding {
+    /// Returns the current value of this input, between -1.0 and 1.0,
+    /// with analog values inside `dead_zone` reading as 0.0.
+    pub fn value(&self, ctx: &Context, dead_zone: f32) -> f32 {
+        match *self {
+            AxisBinding::Buttons { negative, positive } => {
+                positive.value(ctx, dead_zone) - negative.value(ctx, dead_zone)
+            }
+            AxisBinding::GamepadAxis { axis, inverted } => {
+                let value = gamepad_axis_value(ctx, axis, dead_zone);
+                if inverted {
+                    -value
+                } else {
+                    value
+                }
+            }
+        }
+    }
+}
+
+fn button_value(pressed: bool) -> f32 {
+    if pressed {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+fn gamepads_enabled(ctx: &Context) -> bool {
+    ctx.conf.modules.gamepad
+}
+
+/// Returns whether `button` is held on any connected gamepad.
+fn gamepad_button_pressed(ctx: &Context, button: Button) -> bool {
+    gamepads_enabled(ctx) && gamepad::gamepads(ctx).any(|(_id, gp)| gp.is_pressed(button))
+}
+
+/// Applies `dead_zone` to `value` and rescales the rest of the
+/// range so the output still covers -1.0 to 1.0 smoothly.
+fn apply_dead_zone(value: f32, dead_zone: f32) -> f32 {
+    let magnitude = value.abs();
+    if magnitude <= dead_zone {
+        0.0
+    } else {
+        (value.signum() * (magnitude - dead_zone) / (1.0 - dead_zone)).clamp(-1.0, 1.0)
+    }
+}
+
+/// Reads `axis` on every connected gamepad and returns the
+/// value furthest from the center.
+fn gamepad_axis_value(ctx: &Context, axis: Axis, dead_zone: f32) -> f32 {
+    if !gamepads_enabled(ctx) {
+        return 0.0;
+    }
+    let value = gamepad::gamepads(ctx)
+        .map(|(_id, gp)| gp.value(axis))
+        .fold(0.0f32, |a, b| if b.abs() > a.abs() { b } else { a });
+    apply_dead_zone(value, dead_zone)
+}
+
+/// The inputs bound to a named action.
+#[derive(Debug, Clone, PartialEq, SmartDefault, Serialize, Deserialize)]
+pub struct Action {
+    /// Analog inputs closer than this to the center don't count as pressed.
+    #[default(DEFAULT_DEAD_ZONE)]
+    #[serde(default = "default_dead_zone")]
+    pub dead_zone: f32,
+    /// The inputs that trigger the action; any one of them being held
+    /// means the action is pressed.
+    pub bindings: Vec<InputBinding>,
+}
+
+/// The inputs bound to a named axis.
+#[derive(Debug, Clone, PartialEq, SmartDefault, Serialize, Deserialize)]
+pub struct ActionAxis {
+    /// Analog inputs closer than this to the center read as 0.0.
+    #[default(DEFAULT_DEAD_ZONE)]
+    #[serde(default = "default_dead_zone")]
+    pub dead_zone: f32,
+    /// The inputs that drive the axis.  Their values are summed and
+    /// clamped to the range -1.0 to 1.0.
+    pub bindings: Vec<AxisBinding>,
+}
+
+/// A set of named actions and axes and the inputs bound to them.
+///
+/// This is the part of an [`ActionMap`](struct.ActionMap.html) that
+/// gets saved and loaded.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Bindings {
+    /// Actions, by name
+    #[serde(default)]
+    pub actions: BTreeMap<String, Action>,
+    /// Axes, by name
+    #[serde(default)]
+    pub axes: BTreeMap<String, ActionAxis>,
+}
+
+impl Bindings {
+    /// Creates a new, empty set of bindings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an input to the named action, creating the action
+    /// if it doesn't exist yet.
+    pub fn bind(&mut self, action: &str, binding: InputBinding) {
+        self.actions
+            .entry(action.to_owned())
+            .or_default()
+            .bindings
+            .push(binding);
+    }
+
+    /// Adds an input to the named axis, creating the axis
+    /// if it doesn't exist yet.
+    pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) {
+        self.axes
+            .entry(axis.to_owned())
+            .or_default()
+            .bindings
+            .push(binding);
+    }
+
+    /// Removes all inputs from the named action.
+    pub fn unbind(&mut self, action: &str) {
+        if let Some(action) = self.actions.get_mut(action) {
+            action.bindings.clear();
+        }
+    }
+
+    /// Removes all inputs from the named axis.
+    pub fn unbind_axis(&mut self, axis: &str) {
+        if let Some(axis) = self.axes.get_mut(axis) {
+            axis.bindings.clear();
+        }
+    }
+
+    /// Load a set of `Bindings` from the given `Read` object,
+    /// formatted as TOML.
+    pub fn from_toml_file<R: io::Read>(file: &mut R) -> GameResult<Bindings> {
+        let mut s = String::new();
+        let _ = file.read_to_string(&mut s)?;
+        let decoded = toml::from_str(&s)?;
+        Ok(decoded)
+    }
+
+    /// Saves the `Bindings` to the given `Write` object,
+    /// formatted as TOML.
+    pub fn to_toml_file<W: io::Write>(&self, file: &mut W) -> GameResult {
+        let s = toml::to_vec(self)?;
+        file.write_all(&s)?;
+        Ok(())
+    }
+
+    /// Looks for a file named `/bindings.toml` in any resource directory,
+    /// the same way `/conf.toml` is found, and loads it if it finds it.
+    /// If it can't read it for some reason, returns an error.
+    pub fn load(ctx: &Context) -> GameResult<Bindings> {
+        let bindings_path = path::Path::new(BINDINGS_NAME);
+        if filesystem::is_file(ctx, bindings_path) {
+            let mut file = filesystem::open(ctx, bindings_path)?;
+            Self::from_toml_file(&mut file)
+        } else {
+            Err(GameError::ConfigError(String::from(
+                "Bindings file not found",
+            )))
+        }
+    }
+
+    /// Saves the `Bindings` to `/bindings.toml` in the user directory,
+    /// overwriting any file already there.
+    pub fn save(&self, ctx: &Context) -> GameResult {
+        let mut file = filesystem::create(ctx, BINDINGS_NAME)?;
+        self.to_toml_file(&mut file)
+    }
+}
+
+/// Tracks the state of a set of [`Bindings`](struct.Bindings.html)
+/// from frame to frame.
+///
+/// Call [`update()`](#method.update) once per frame, before querying
+/// it; `just_pressed()` and `just_released()` compare against the
+/// state seen by the previous call.
+#[derive(Debug, Clone, Default)]
+pub struct ActionMap {
+    bindings: Bindings,
+    pressed: HashSet<String>,
+    last_pressed: HashSet<String>,
+    axis_values: HashMap<String, f32>,
+}
+
+impl ActionMap {
+    /// Creates a new `ActionMap` using the given bindings.
+    pub fn new(bindings: Bindings) -> Self {
+        Self {
+            bindings,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the bindings in use.
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    /// Returns the bindings in use, for rebinding.  Changes
+    /// take effect on the next [`update()`](#method.update).
+    pub fn bindings_mut(&mut self) -> &mut Bindings {
+        &mut self.bindings
+    }
+
+    /// Reads the current input state from the `Context` and updates
+    /// all actions and axes.
+    pub fn update(&mut self, ctx: &Context) {
+        std::mem::swap(&mut self.pressed, &mut self.last_pressed);
+        self.pressed.clear();
+        for (name, action) in &self.bindings.actions {
+            if action
+                .bindings
+                .iter()
+                .any(|b| b.value(ctx, action.dead_zone) > 0.0)
+            {
+                let _ = self.pressed.insert(name.clone());
+            }
+        }
+
+        self.axis_values.clear();
+        for (name, axis) in &self.bindings.axes {
+            let value: f32 = axis
+                .bindings
+                .iter()
+                .map(|b| b.value(ctx, axis.dead_zone))
+                .sum();
+            let _ = self
+                .axis_values
+                .insert(name.clone(), value.clamp(-1.0, 1.0));
+        }
+    }
+
+    /// Returns whether the named action is currently held.
+    pub fn pressed(&self, action: &str) -> bool {
+        self.pressed.contains(action)
+    }
+
+    /// Returns whether the named action started being held this frame.
+    pub fn just_pressed(&self, action: &str) -> bool {
+        self.pressed.contains(action) && !self.last_pressed.contains(action)
+    }
+
+    /// Returns whether the named action stopped being held this frame.
+    pub fn just_released(&self, action: &str) -> bool {
+        !self.pressed.contains(action) && self.last_pressed.contains(action)
+    }
+
+    /// Returns the value of the named axis, between -1.0 and 1.0.
+    /// Unknown axes read as 0.0.
+    pub fn axis_value(&self, axis: &str) -> f32 {
+        self.axis_values.get(axis).copied().unwrap_or(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headless_bindings_round_trip() {
+        let mut b1 = Bindings::new();
+        b1.bind(
+            "jump",
+            InputBinding::Key {
+                key: KeyCode::Space,
+            },
+        );
+        b1.bind(
+            "jump",
+            InputBinding::GamepadButton {
+                button: Button::South,
+            },
+        );
+        b1.bind(
+            "fire",
+            InputBinding::MouseButton {
+                button: MouseButton::Other(4),
+            },
+        );
+        b1.bind(
+            "fire",
+            InputBinding::GamepadAxis {
+                axis: Axis::RightZ,
+                positive: true,
+            },
+        );
+        b1.bind_axis(
+            "move_x",
+            AxisBinding::Buttons {
+                negative: InputBinding::Key { key: KeyCode::A },
+                positive: InputBinding::Key { key: KeyCode::D },
+            },
+        );
+        b1.bind_axis(
+            "move_x",
+            AxisBinding::GamepadAxis {
+                axis: Axis::LeftStickX,
+                inverted: true,
+            },
+        );
+        b1.axes.get_mut("move_x").unwrap().dead_zone = 0.25;
+
+        let mut writer = Vec::new();
+        b1.to_toml_file(&mut writer).unwrap();
+        let mut reader = writer.as_slice();
+        let b2 = Bindings::from_toml_file(&mut reader).unwrap();
+        assert_eq!(b1, b2);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn dead_zone() {
+        assert_eq!(0.0, apply_dead_zone(0.1, 0.2));
+        assert_eq!(0.0, apply_dead_zone(-0.2, 0.2));
+        assert_eq!(1.0, apply_dead_zone(1.0, 0.2));
+        assert!((apply_dead_zone(-0.6, 0.2) + 0.5).abs() < 1e-6);
+    }
+}
@@ -4,12 +4,13 @@
 //! gets fleshed out.  The `gilrs` crate needs help to add better
 //! cross-platform support.  Why not give it a hand?
 use gilrs::ConnectedGamepadsIterator;
+use std::collections::HashSet;
 use std::fmt;
 
-pub use gilrs::{self, Event, Gamepad, Gilrs};
+pub use gilrs::{self, Button, Event, Gamepad, Gilrs};
 
 /// A unique identifier for a particular GamePad
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct GamepadId(pub(crate) gilrs::GamepadId);
 
 use crate::context::Context;
@@ -25,11 +26,44 @@ pub trait GamepadContext {
 
     /// returns an iterator over the connected `Gamepad`s.
     fn gamepads(&self) -> GamepadsIterator;
+
+    /// Records that a button was pressed or released.  ggez calls this
+    /// for every gamepad button event it handles.
+    fn set_button(&mut self, _id: GamepadId, _button: Button, _pressed: bool) {}
+
+    /// Returns whether a button is currently held, according to the
+    /// events passed to [`set_button()`](#method.set_button).
+    fn is_button_pressed(&self, _id: GamepadId, _button: Button) -> bool {
+        false
+    }
+
+    /// Returns whether a button was pressed since the last call to
+    /// [`save_gamepad_state()`](#method.save_gamepad_state), even if
+    /// it has been released again since.
+    fn button_just_pressed(&self, _id: GamepadId, _button: Button) -> bool {
+        false
+    }
+
+    /// Returns whether a button was released since the last call to
+    /// [`save_gamepad_state()`](#method.save_gamepad_state).
+    fn button_just_released(&self, _id: GamepadId, _button: Button) -> bool {
+        false
+    }
+
+    /// Forgets the presses and releases of the current frame, which are
+    /// used by [`button_just_pressed()`](fn.button_just_pressed.html) and
+    /// [`button_just_released()`](fn.button_just_released.html).
+    /// You shouldn't need to call this, except when you're running your own event loop.
+    /// In this case call it right at the end, after `draw` and `update` have finished.
+    fn save_gamepad_state(&mut self) {}
 }
 
 /// A structure that contains gamepad state using `gilrs`.
 pub struct GilrsGamepadContext {
     pub(crate) gilrs: Gilrs,
+    buttons_pressed: HashSet<(GamepadId, Button)>,
+    just_pressed: HashSet<(GamepadId, Button)>,
+    just_released: HashSet<(GamepadId, Button)>,
 }
 
 impl fmt::Debug for GilrsGamepadContext {
@@ -41,14 +75,19 @@ impl fmt::Debug for GilrsGamepadContext {
 impl GilrsGamepadContext {
     pub(crate) fn new() -> GameResult<Self> {
         let gilrs = Gilrs::new()?;
-        Ok(GilrsGamepadContext { gilrs })
+        Ok(Self::from(gilrs))
     }
 }
 
 impl From<Gilrs> for GilrsGamepadContext {
     /// Converts from a `Gilrs` custom instance to a `GilrsGamepadContext`
     fn from(gilrs: Gilrs) -> Self {
-        Self { gilrs }
+        Self {
+            gilrs,
+            buttons_pressed: HashSet::new(),
+            just_pressed: HashSet::new(),
+            just_released: HashSet::new(),
+        }
     }
 }
 
@@ -66,6 +105,33 @@ impl GamepadContext for GilrsGamepadContext {
             wrapped: self.gilrs.gamepads(),
         }
     }
+
+    fn set_button(&mut self, id: GamepadId, button: Button, pressed: bool) {
+        if pressed {
+            if self.buttons_pressed.insert((id, button)) {
+                let _ = self.just_pressed.insert((id, button));
+            }
+        } else if self.buttons_pressed.remove(&(id, button)) {
+            let _ = self.just_released.insert((id, button));
+        }
+    }
+
+    fn is_button_pressed(&self, id: GamepadId, button: Button) -> bool {
+        self.buttons_pressed.contains(&(id, button))
+    }
+
+    fn button_just_pressed(&self, id: GamepadId, button: Button) -> bool {
+        self.just_pressed.contains(&(id, button))
+    }
+
+    fn button_just_released(&self, id: GamepadId, button: Button) -> bool {
+        self.just_released.contains(&(id, button))
+    }
+
+    fn save_gamepad_state(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+    }
 }
 
 /// An iterator of the connected gamepads
@@ -117,6 +183,22 @@ pub fn gamepads(ctx: &Context) -> GamepadsIterator {
     ctx.gamepad_context.gamepads()
 }
 
+/// Returns whether the given button is held on a gamepad.
+pub fn button_pressed(ctx: &Context, id: GamepadId, button: Button) -> bool {
+    ctx.gamepad_context.is_button_pressed(id, button)
+}
+
+/// Returns whether the given button was pressed on a gamepad during the current frame.
+/// This is also true if it was pressed and released again within the same frame.
+pub fn button_just_pressed(ctx: &Context, id: GamepadId, button: Button) -> bool {
+    ctx.gamepad_context.button_just_pressed(id, button)
+}
+
+/// Returns whether the given button was released on a gamepad during the current frame.
+pub fn button_just_released(ctx: &Context, id: GamepadId, button: Button) -> bool {
+    ctx.gamepad_context.button_just_released(id, button)
+}
+
 // Properties gamepads might want:
 // Number of buttons
 // Number of axes
@@ -134,11 +216,6 @@ pub fn list_gamepads() {
 pub fn axis() {
     unimplemented!()
 }
-
-/// Returns the state of the given button on a gamepad.
-pub fn button_pressed() {
-    unimplemented!()
-}
 */
 
 #[cfg(test)]
@@ -149,4 +226,15 @@ mod tests {
     fn gilrs_init() {
         assert!(GilrsGamepadContext::new().is_ok());
     }
+
+    #[test]
+    fn null_context_has_no_buttons() {
+        let mut ctx = NullGamepadContext::default();
+        let id: GamepadId = serde_json::from_str("0").unwrap();
+        ctx.set_button(id, Button::South, true);
+        assert!(!ctx.is_button_pressed(id, Button::South));
+        assert!(!ctx.button_just_pressed(id, Button::South));
+        assert!(!ctx.button_just_released(id, Button::South));
+        ctx.save_gamepad_state();
+    }
 }
@@ -118,6 +118,10 @@ pub struct KeyboardContext {
     /// We COULD use a `Vec<bool>` but turning Rust enums to and from
     /// integers is unsafe and a set really is what we want anyway.
     pressed_keys_set: HashSet<KeyCode>,
+    /// The keys that were pressed and released since the end of the
+    /// previous frame.
+    just_pressed_keys_set: HashSet<KeyCode>,
+    just_released_keys_set: HashSet<KeyCode>,
 
     // These two are necessary for tracking key-repeat.
     last_pressed: Option<KeyCode>,
@@ -130,6 +134,8 @@ impl KeyboardContext {
             active_modifiers: KeyMods::empty(),
             // We just use 256 as a number Big Enough For Keyboard Keys to try to avoid resizing.
             pressed_keys_set: HashSet::with_capacity(256),
+            just_pressed_keys_set: HashSet::new(),
+            just_released_keys_set: HashSet::new(),
             last_pressed: None,
             current_pressed: None,
         }
@@ -137,11 +143,15 @@ impl KeyboardContext {
 
     pub(crate) fn set_key(&mut self, key: KeyCode, pressed: bool) {
         if pressed {
-            let _ = self.pressed_keys_set.insert(key);
+            if self.pressed_keys_set.insert(key) {
+                let _ = self.just_pressed_keys_set.insert(key);
+            }
             self.last_pressed = self.current_pressed;
             self.current_pressed = Some(key);
         } else {
-            let _ = self.pressed_keys_set.remove(&key);
+            if self.pressed_keys_set.remove(&key) {
+                let _ = self.just_released_keys_set.insert(key);
+            }
             self.current_pressed = None;
         }
 
@@ -185,6 +195,24 @@ impl KeyboardContext {
         self.pressed_keys_set.contains(&key)
     }
 
+    pub(crate) fn is_key_just_pressed(&self, key: KeyCode) -> bool {
+        self.just_pressed_keys_set.contains(&key)
+    }
+
+    pub(crate) fn is_key_just_released(&self, key: KeyCode) -> bool {
+        self.just_released_keys_set.contains(&key)
+    }
+
+    /// Forgets the key presses and releases of the current frame, which are used by
+    /// [`is_key_just_pressed()`](fn.is_key_just_pressed.html) and
+    /// [`is_key_just_released()`](fn.is_key_just_released.html).
+    /// You shouldn't need to call this, except when you're running your own event loop.
+    /// In this case call it right at the end, after `draw` and `update` have finished.
+    pub fn save_keyboard_state(&mut self) {
+        self.just_pressed_keys_set.clear();
+        self.just_released_keys_set.clear();
+    }
+
     pub(crate) fn is_key_repeated(&self) -> bool {
         if self.last_pressed.is_some() {
             self.last_pressed == self.current_pressed
@@ -213,6 +241,17 @@ pub fn is_key_pressed(ctx: &Context, key: KeyCode) -> bool {
     ctx.keyboard_context.is_key_pressed(key)
 }
 
+/// Checks if a key was pressed down during the current frame.
+/// This is also true if it was pressed and released again within the same frame.
+pub fn is_key_just_pressed(ctx: &Context, key: KeyCode) -> bool {
+    ctx.keyboard_context.is_key_just_pressed(key)
+}
+
+/// Checks if a key was released during the current frame.
+pub fn is_key_just_released(ctx: &Context, key: KeyCode) -> bool {
+    ctx.keyboard_context.is_key_just_released(key)
+}
+
 /// Checks if the last keystroke sent by the system is repeated,
 /// like when a key is held down for a period of time.
 pub fn is_key_repeated(ctx: &Context) -> bool {
@@ -1,4 +1,6 @@
 //! Input handling modules for keyboard, mouse and gamepad.
+pub mod actions;
 pub mod gamepad;
 pub mod keyboard;
 pub mod mouse;
+pub mod recording;
@@ -5,11 +5,51 @@ use crate::error::GameError;
 use crate::error::GameResult;
 use crate::graphics;
 use crate::graphics::Point2;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use winit::dpi;
 pub use winit::event::MouseButton;
 pub use winit::window::CursorIcon;
 
+/// `winit`'s own serde support writes `MouseButton::Other` in a form TOML
+/// can't represent, so we store it as either a name or a plain number.
+pub(crate) mod button_serde {
+    use super::MouseButton;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    enum Named {
+        Left,
+        Right,
+        Middle,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Named(Named),
+        Other(u16),
+    }
+
+    pub fn serialize<S: Serializer>(button: &MouseButton, s: S) -> Result<S::Ok, S::Error> {
+        let repr = match *button {
+            MouseButton::Left => Repr::Named(Named::Left),
+            MouseButton::Right => Repr::Named(Named::Right),
+            MouseButton::Middle => Repr::Named(Named::Middle),
+            MouseButton::Other(n) => Repr::Other(n),
+        };
+        repr.serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<MouseButton, D::Error> {
+        Ok(match Repr::deserialize(d)? {
+            Repr::Named(Named::Left) => MouseButton::Left,
+            Repr::Named(Named::Right) => MouseButton::Right,
+            Repr::Named(Named::Middle) => MouseButton::Middle,
+            Repr::Other(n) => MouseButton::Other(n),
+        })
+    }
+}
+
 /// Stores state information for the mouse.
 #[derive(Clone, Debug)]
 pub struct MouseContext {
@@ -17,6 +57,8 @@ pub struct MouseContext {
     last_delta: Point2,
     delta: Point2,
     buttons_pressed: HashMap<MouseButton, bool>,
+    just_pressed: HashSet<MouseButton>,
+    just_released: HashSet<MouseButton>,
     cursor_type: CursorIcon,
     cursor_grabbed: bool,
     cursor_hidden: bool,
@@ -30,6 +72,8 @@ impl MouseContext {
             delta: Point2::ZERO,
             cursor_type: CursorIcon::Default,
             buttons_pressed: HashMap::new(),
+            just_pressed: HashSet::new(),
+            just_released: HashSet::new(),
             cursor_grabbed: false,
             cursor_hidden: false,
         }
@@ -55,12 +99,38 @@ impl MouseContext {
     }
 
     pub(crate) fn set_button(&mut self, button: MouseButton, pressed: bool) {
-        let _ = self.buttons_pressed.insert(button, pressed);
+        let was_pressed = self
+            .buttons_pressed
+            .insert(button, pressed)
+            .unwrap_or(false);
+        if pressed && !was_pressed {
+            let _ = self.just_pressed.insert(button);
+        } else if !pressed && was_pressed {
+            let _ = self.just_released.insert(button);
+        }
+    }
+
+    /// Forgets the button presses and releases of the current frame, which are used by
+    /// [`mouse::button_just_pressed`](fn.button_just_pressed.html) and
+    /// [`mouse::button_just_released`](fn.button_just_released.html).
+    /// You shouldn't need to call this, except when you're running your own event loop.
+    /// In this case call it right at the end, after `draw` and `update` have finished.
+    pub fn save_mouse_state(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
     }
 
     fn button_pressed(&self, button: MouseButton) -> bool {
         *(self.buttons_pressed.get(&button).unwrap_or(&false))
     }
+
+    fn button_just_pressed(&self, button: MouseButton) -> bool {
+        self.just_pressed.contains(&button)
+    }
+
+    fn button_just_released(&self, button: MouseButton) -> bool {
+        self.just_released.contains(&button)
+    }
 }
 
 impl Default for MouseContext {
@@ -141,3 +211,14 @@ pub(crate) fn last_delta(ctx: &Context) -> mint::Point2<f32> {
 pub fn button_pressed(ctx: &Context, button: MouseButton) -> bool {
     ctx.mouse_context.button_pressed(button)
 }
+
+/// Returns whether or not the given mouse button was pressed during the current frame.
+/// This is also true if it was pressed and released again within the same frame.
+pub fn button_just_pressed(ctx: &Context, button: MouseButton) -> bool {
+    ctx.mouse_context.button_just_pressed(button)
+}
+
+/// Returns whether or not the given mouse button was released during the current frame.
+pub fn button_just_released(ctx: &Context, button: MouseButton) -> bool {
+    ctx.mouse_context.button_just_released(button)
+}
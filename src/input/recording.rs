@@ -0,0 +1,428 @@
+//! Recording and replaying of input events.
+//!
+//! An [`InputRecorder`](struct.InputRecorder.html) wraps your
+//! [`EventHandler`](../../event/trait.EventHandler.html) and logs every
+//! input event that reaches it, including focus changes and requests to
+//! quit, along with the frame it arrived on.  The
+//! resulting [`InputRecording`](struct.InputRecording.html) can be saved
+//! to and loaded from the user data directory, and fed back into a game
+//! with an [`InputPlayer`](struct.InputPlayer.html) or an
+//! [`event::Runner`](../../event/struct.Runner.html).
+//!
+//! Frame indices are counted from when the recorder (or player) was
+//! created, in calls to [`TimeContext::tick()`](../../timer/struct.TimeContext.html#method.tick).
+//! Inside [`event::run()`](../../event/fn.run.html) the length of each
+//! frame depends on the system clock, so replays there are only as
+//! deterministic as your game logic is.  For exact reproduction, record and
+//! replay with a `Runner` stepping by a fixed delta:
+//!
+//! ```rust,no_run
+//! # use ggez::{Context, GameResult};
+//! # use ggez::event::Runner;
+//! # use ggez::input::recording::InputRecording;
+//! # fn t<S: ggez::event::EventHandler>(ctx: &mut Context, state: &mut S) -> GameResult {
+//! let recording = InputRecording::load(ctx, "/replay.toml")?;
+//! let mut runner = Runner::new(std::time::Duration::from_secs_f64(1.0 / 60.0));
+//! for frame in 0..recording.frames() {
+//!     runner.step(ctx, state, recording.events_for_frame(frame))?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io;
+use std::path;
+
+use crate::context::Context;
+use crate::error::GameResult;
+use crate::event::{
+    Axis, Button, ErrorOrigin, EventHandler, GamepadId, InputEvent, KeyCode, KeyMods, MouseButton,
+};
+use crate::filesystem;
+use crate::timer;
+
+/// A single recorded input event.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    /// The frame the event arrived on, counted from the start of the recording.
+    pub frame: usize,
+    /// The event itself.
+    pub event: InputEvent,
+}
+
+/// A list of input events and the frames they happened on,
+/// in the order they happened.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputRecording {
+    events: Vec<RecordedEvent>,
+}
+
+impl InputRecording {
+    /// Creates a new, empty `InputRecording`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends an event to the recording.  Events must be pushed in order,
+    /// so `frame` may not be smaller than that of the last event.
+    pub fn push(&mut self, frame: usize, event: InputEvent) {
+        debug_assert!(
+            self.events.last().map_or(0, |last| last.frame) <= frame,
+            "InputRecording events pushed out of order"
+        );
+        self.events.push(RecordedEvent { frame, event });
+    }
+
+    /// Returns all recorded events.
+    pub fn events(&self) -> &[RecordedEvent] {
+        &self.events
+    }
+
+    /// Returns the number of frames covered by the recording, that is,
+    /// one past the frame of the last event.
+    pub fn frames(&self) -> usize {
+        self.events.last().map_or(0, |last| last.frame + 1)
+    }
+
+    /// Returns the events that happened on the given frame, in order.
+    pub fn events_for_frame(&self, frame: usize) -> impl Iterator<Item = InputEvent> + '_ {
+        let start = self.events.partition_point(|e| e.frame < frame);
+        self.events[start..]
+            .iter()
+            .take_while(move |e| e.frame == frame)
+            .map(|e| e.event)
+    }
+
+    /// Returns `true` if the recording contains no events.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Load an `InputRecording` from the given `Read` object,
+    /// formatted as TOML.
+    pub fn from_toml_file<R: io::Read>(file: &mut R) -> GameResult<InputRecording> {
+        let mut s = String::new();
+        let _ = file.read_to_string(&mut s)?;
+        let decoded = toml::from_str(&s)?;
+        Ok(decoded)
+    }
+
+    /// Saves the `InputRecording` to the given `Write` object,
+    /// formatted as TOML.
+    pub fn to_toml_file<W: io::Write>(&self, file: &mut W) -> GameResult {
+        let s = toml::to_vec(self)?;
+        file.write_all(&s)?;
+        Ok(())
+    }
+
+    /// Loads an `InputRecording` from the given path in the
+    /// [`filesystem`](../../filesystem/index.html).
+    pub fn load<P: AsRef<path::Path>>(ctx: &Context, path: P) -> GameResult<InputRecording> {
+        let mut file = filesystem::open(ctx, path)?;
+        Self::from_toml_file(&mut file)
+    }
+
+    /// Saves the `InputRecording` to the given path in the user data
+    /// directory, see [`filesystem::create()`](../../filesystem/fn.create.html).
+    pub fn save<P: AsRef<path::Path>>(&self, ctx: &Context, path: P) -> GameResult {
+        let mut file = filesystem::create(ctx, path)?;
+        self.to_toml_file(&mut file)
+    }
+}
+
+/// An [`EventHandler`](../../event/trait.EventHandler.html) that passes
+/// everything through to the wrapped state, recording all input events
+/// on the way.
+#[derive(Debug)]
+pub struct InputRecorder<S> {
+    state: S,
+    start_tick: usize,
+    recording: InputRecording,
+}
+
+impl<S> InputRecorder<S> {
+    /// Starts recording input for `state`.  Frames are counted
+    /// from the current tick of `ctx`.
+    pub fn new(ctx: &Context, state: S) -> Self {
+        Self {
+            state,
+            start_tick: timer::ticks(ctx),
+            recording: InputRecording::new(),
+        }
+    }
+
+    /// Returns the input recorded so far.
+    pub fn recording(&self) -> &InputRecording {
+        &self.recording
+    }
+
+    /// Returns a reference to the wrapped state.
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Returns a mutable reference to the wrapped state.
+    pub fn state_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+
+    /// Stops recording, returning the wrapped state and the recording.
+    pub fn into_inner(self) -> (S, InputRecording) {
+        (self.state, self.recording)
+    }
+
+    fn record(&mut self, ctx: &Context, event: InputEvent) {
+        let frame = timer::ticks(ctx).saturating_sub(self.start_tick);
+        self.recording.push(frame, event);
+    }
+}
+
+impl<S, E> EventHandler<E> for InputRecorder<S>
+where
+    S: EventHandler<E>,
+    E: std::error::Error,
+{
+    fn update(&mut self, ctx: &mut Context) -> Result<(), E> {
+        self.state.update(ctx)
+    }
+
+    fn fixed_update(&mut self, ctx: &mut Context) -> Result<(), E> {
+        self.state.fixed_update(ctx)
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> Result<(), E> {
+        self.state.draw(ctx)
+    }
+
+    fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        self.record(ctx, InputEvent::MouseButtonDown(button));
+        self.state.mouse_button_down_event(ctx, button, x, y);
+    }
+
+    fn mouse_button_up_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        self.record(ctx, InputEvent::MouseButtonUp(button));
+        self.state.mouse_button_up_event(ctx, button, x, y);
+    }
+
+    fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32, dx: f32, dy: f32) {
+        self.record(ctx, InputEvent::MouseMotion { x, y });
+        self.state.mouse_motion_event(ctx, x, y, dx, dy);
+    }
+
+    fn mouse_enter_or_leave(&mut self, ctx: &mut Context, entered: bool) {
+        self.record(ctx, InputEvent::MouseEnterOrLeave(entered));
+        self.state.mouse_enter_or_leave(ctx, entered);
+    }
+
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, x: f32, y: f32) {
+        self.record(ctx, InputEvent::MouseWheel { x, y });
+        self.state.mouse_wheel_event(ctx, x, y);
+    }
+
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: KeyCode,
+        keymods: KeyMods,
+        repeat: bool,
+    ) {
+        self.record(ctx, InputEvent::KeyDown(keycode));
+        self.state.key_down_event(ctx, keycode, keymods, repeat);
+    }
+
+    fn key_up_event(&mut self, ctx: &mut Context, keycode: KeyCode, keymods: KeyMods) {
+        self.record(ctx, InputEvent::KeyUp(keycode));
+        self.state.key_up_event(ctx, keycode, keymods);
+    }
+
+    fn text_input_event(&mut self, ctx: &mut Context, character: char) {
+        self.record(ctx, InputEvent::TextInput(character));
+        self.state.text_input_event(ctx, character);
+    }
+
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, button: Button, id: GamepadId) {
+        self.record(ctx, InputEvent::GamepadButtonDown { button, id });
+        self.state.gamepad_button_down_event(ctx, button, id);
+    }
+
+    fn gamepad_button_up_event(&mut self, ctx: &mut Context, button: Button, id: GamepadId) {
+        self.record(ctx, InputEvent::GamepadButtonUp { button, id });
+        self.state.gamepad_button_up_event(ctx, button, id);
+    }
+
+    fn gamepad_axis_event(&mut self, ctx: &mut Context, axis: Axis, value: f32, id: GamepadId) {
+        self.record(ctx, InputEvent::GamepadAxis { axis, value, id });
+        self.state.gamepad_axis_event(ctx, axis, value, id);
+    }
+
+    fn focus_event(&mut self, ctx: &mut Context, gained: bool) {
+        self.record(ctx, InputEvent::Focus(gained));
+        self.state.focus_event(ctx, gained);
+    }
+
+    fn quit_event(&mut self, ctx: &mut Context) -> bool {
+        self.record(ctx, InputEvent::Quit);
+        self.state.quit_event(ctx)
+    }
+
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        self.state.resize_event(ctx, width, height);
+    }
+
+    fn on_error(&mut self, ctx: &mut Context, origin: ErrorOrigin, e: E) -> bool {
+        self.state.on_error(ctx, origin, e)
+    }
+}
+
+/// An [`EventHandler`](../../event/trait.EventHandler.html) that re-injects
+/// an [`InputRecording`](struct.InputRecording.html) into the wrapped state.
+///
+/// At the start of each frame's first `fixed_update()` or `update()`, the
+/// events recorded for the current frame are applied to the `Context`'s input
+/// state and passed to the matching callbacks.
+///
+/// Live keyboard, mouse, gamepad and focus events never reach the wrapped
+/// state, and while playing they are kept out of the `Context`'s input
+/// state too, so the replay isn't mixed up with whatever the player is
+/// doing.  Resizing the window and asking it to close still work.  Once
+/// every event has been played, or playback is stopped with
+/// [`into_inner()`](#method.into_inner), live input reaches the input
+/// state again.
+#[derive(Debug)]
+pub struct InputPlayer<S> {
+    state: S,
+    start_tick: usize,
+    recording: InputRecording,
+    next: usize,
+}
+
+impl<S> InputPlayer<S> {
+    /// Starts playing back `recording` into `state`.  Frame 0 of the
+    /// recording is the next frame of `ctx`.
+    pub fn new(ctx: &Context, state: S, recording: InputRecording) -> Self {
+        Self {
+            state,
+            start_tick: timer::ticks(ctx),
+            recording,
+            next: 0,
+        }
+    }
+
+    /// Returns `true` once every recorded event has been played.
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.recording.events.len()
+    }
+
+    /// Returns a reference to the wrapped state.
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Returns a mutable reference to the wrapped state.
+    pub fn state_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+
+    /// Stops playback, letting live input through to `ctx` again, and
+    /// returns the wrapped state.
+    pub fn into_inner(self, ctx: &mut Context) -> S {
+        ctx.block_live_input = false;
+        self.state
+    }
+
+    /// Dispatches the events recorded for the current frame, if that
+    /// hasn't happened yet.
+    fn play_events<E>(&mut self, ctx: &mut Context)
+    where
+        S: EventHandler<E>,
+        E: std::error::Error,
+    {
+        // Updates run after the tick that ends the frame the events arrived on.
+        let frame = timer::ticks(ctx)
+            .saturating_sub(self.start_tick)
+            .saturating_sub(1);
+        while let Some(recorded) = self.recording.events.get(self.next) {
+            if recorded.frame > frame {
+                break;
+            }
+            crate::event::dispatch_input_event(ctx, &mut self.state, recorded.event);
+            self.next += 1;
+        }
+        ctx.block_live_input = !self.is_finished();
+    }
+}
+
+impl<S, E> EventHandler<E> for InputPlayer<S>
+where
+    S: EventHandler<E>,
+    E: std::error::Error,
+{
+    fn update(&mut self, ctx: &mut Context) -> Result<(), E> {
+        self.play_events(ctx);
+        self.state.update(ctx)
+    }
+
+    fn fixed_update(&mut self, ctx: &mut Context) -> Result<(), E> {
+        self.play_events(ctx);
+        self.state.fixed_update(ctx)
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> Result<(), E> {
+        self.state.draw(ctx)
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        _keycode: KeyCode,
+        _keymods: KeyMods,
+        _repeat: bool,
+    ) {
+    }
+
+    fn focus_event(&mut self, _ctx: &mut Context, _gained: bool) {}
+
+    fn quit_event(&mut self, ctx: &mut Context) -> bool {
+        self.state.quit_event(ctx)
+    }
+
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        self.state.resize_event(ctx, width, height);
+    }
+
+    fn on_error(&mut self, ctx: &mut Context, origin: ErrorOrigin, e: E) -> bool {
+        self.state.on_error(ctx, origin, e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headless_recording_round_trip() {
+        let mut r1 = InputRecording::new();
+        r1.push(0, InputEvent::KeyDown(KeyCode::Space));
+        r1.push(0, InputEvent::MouseMotion { x: 10.0, y: 20.5 });
+        r1.push(2, InputEvent::MouseButtonDown(MouseButton::Other(4)));
+        r1.push(2, InputEvent::TextInput('q'));
+        r1.push(3, InputEvent::KeyUp(KeyCode::Space));
+        r1.push(3, InputEvent::Quit);
+        let mut writer = Vec::new();
+        r1.to_toml_file(&mut writer).unwrap();
+        let mut reader = writer.as_slice();
+        let r2 = InputRecording::from_toml_file(&mut reader).unwrap();
+        assert_eq!(r1, r2);
+
+        assert_eq!(4, r2.frames());
+        assert_eq!(2, r2.events_for_frame(0).count());
+        assert_eq!(0, r2.events_for_frame(1).count());
+        assert_eq!(
+            vec![
+                InputEvent::MouseButtonDown(MouseButton::Other(4)),
+                InputEvent::TextInput('q')
+            ],
+            r2.events_for_frame(2).collect::<Vec<_>>()
+        );
+    }
+}
@@ -132,3 +132,421 @@ fn volume_persists_after_play() {
         assert_eq!(sound.volume(), volume);
     }
 }
+
+#[test]
+fn sources_play_through_mixer_buses() {
+    let (c, _e) = &mut tests::make_context();
+    let filename = "/pew.ogg";
+    let s1 = audio::Source::new(c, filename).unwrap();
+    test_bus(c, s1);
+    let s2 = audio::SpatialSource::new(c, filename).unwrap();
+    test_bus(c, s2);
+
+    assert_eq!(vec!["sfx".to_string()], audio::mixer(c).buses());
+    audio::mixer(c).set_bus_muted("sfx", true);
+    assert!(audio::mixer(c).bus_muted("sfx"));
+    audio::mixer(c).pause_all();
+    assert!(audio::mixer(c).is_paused());
+    audio::mixer(c).resume_all();
+    assert!(!audio::mixer(c).is_paused());
+
+    #[allow(clippy::float_cmp)]
+    fn test_bus(c: &mut Context, mut sound: impl SoundSource) {
+        assert_eq!(sound.bus(), None);
+        sound.set_bus(Some("sfx"));
+        assert_eq!(sound.bus(), Some("sfx"));
+        audio::mixer(c).set_bus_volume("sfx", 0.5);
+        sound.play(c).unwrap();
+        assert!(sound.playing());
+        assert_eq!(audio::mixer(c).bus_volume("sfx"), 0.5);
+    }
+}
+
+#[test]
+fn streaming_source_loops_and_seeks() {
+    let (c, _e) = &mut tests::make_context();
+    let mut music = audio::StreamingSource::new(c, "/pew.ogg").unwrap();
+    assert!(music.set_loop_points(100, Some(50)).is_err());
+    music.set_loop_points(100, None).unwrap();
+    assert_eq!((100, None), music.loop_points());
+
+    music.set_repeat(true);
+    music.play(c).unwrap();
+    assert!(music.playing());
+
+    music.pause();
+    music
+        .seek(c, std::time::Duration::from_millis(100))
+        .unwrap();
+    assert!(music.paused());
+    assert!(music.elapsed() >= std::time::Duration::from_millis(100));
+
+    assert!(audio::StreamingSource::new(c, "/player.png").is_err());
+}
+
+#[test]
+fn sound_data_reports_decoded_length() {
+    let data = audio::SoundData::from_bytes(include_bytes!("../../resources/pew.wav"));
+    assert_eq!(1, data.channels().unwrap());
+    assert_eq!(44100, data.sample_rate().unwrap());
+    assert_eq!(5899, data.samples().unwrap());
+    assert_eq!(
+        std::time::Duration::from_nanos(5899 * 1_000_000_000 / 44100),
+        data.duration().unwrap()
+    );
+    let broken = audio::SoundData::from_bytes(b"not a sound");
+    assert!(broken.duration().is_err());
+    assert!(broken.clone().samples().is_err());
+
+    // Formats without a length in their header are decoded once,
+    // and clones share the result.
+    let data = audio::SoundData::from_bytes(include_bytes!("../../resources/pew.ogg"));
+    let length = data.samples().unwrap();
+    assert!(length > 0);
+    assert_eq!(length, data.clone().samples().unwrap());
+}
+
+#[test]
+fn play_from_and_seek() {
+    let (c, _e) = &mut tests::make_context();
+    let filename = "/pew.wav";
+    let s1 = audio::Source::new(c, filename).unwrap();
+    test_seek(c, s1);
+    let s2 = audio::SpatialSource::new(c, filename).unwrap();
+    test_seek(c, s2);
+
+    fn test_seek(c: &mut Context, mut sound: impl SoundSource) {
+        let duration = sound.duration().unwrap();
+        assert!(duration > std::time::Duration::from_millis(100));
+
+        let half = duration / 2;
+        sound.play_from(c, half).unwrap();
+        assert!(sound.playing());
+        assert!(sound.elapsed() >= half);
+
+        sound.pause();
+        sound.seek(c, std::time::Duration::from_millis(10)).unwrap();
+        assert!(sound.paused());
+
+        // Starting past the end plays nothing.
+        sound.play_from(c, duration * 2).unwrap();
+        assert!(sound.stopped());
+
+        // Seeking a stopped source does nothing.
+        sound.seek(c, half).unwrap();
+        assert!(sound.stopped());
+    }
+}
+
+#[test]
+fn effects_attach_to_sources_and_buses() {
+    let (c, _e) = &mut tests::make_context();
+    let mut sound = audio::Source::new(c, "/pew.ogg").unwrap();
+    let filter = sound.effects().push(audio::Effect::low_pass(800.0));
+    let _ = sound.effects().push(audio::Effect::reverb());
+    sound.play(c).unwrap();
+    sound.effects().set(filter, audio::Effect::low_pass(400.0));
+    assert_eq!(
+        Some(audio::Effect::low_pass(400.0)),
+        sound.effects().get(filter)
+    );
+
+    let echo = audio::Effect::echo(std::time::Duration::from_millis(250));
+    let _ = audio::mixer(c).bus_effects("sfx").push(echo);
+    assert_eq!(vec![echo], audio::mixer(c).bus_effects("sfx").effects());
+}
+
+#[test]
+fn sound_data_from_samples() {
+    let samples: Vec<f32> = (0..2000).map(|i| (i as f32 / 10.0).sin()).collect();
+    let data = audio::SoundData::from_samples(2, 22050, &samples).unwrap();
+    assert!(data.can_play());
+    assert_eq!(2, data.channels().unwrap());
+    assert_eq!(22050, data.sample_rate().unwrap());
+    assert_eq!(1000, data.samples().unwrap());
+
+    assert!(audio::SoundData::from_samples(0, 22050, &samples).is_err());
+    assert!(audio::SoundData::from_samples(2, 0, &samples).is_err());
+    assert!(audio::SoundData::from_samples(3, 22050, &samples).is_err());
+}
+
+#[test]
+fn procedural_sources_and_wav_export() {
+    let (c, _e) = &mut tests::make_context();
+    let mut tone = audio::ProceduralSource::from_fn(c, 1, 44100, |i| {
+        if i < 4410 {
+            Some((i as f32 * 0.05).sin())
+        } else {
+            None
+        }
+    })
+    .unwrap();
+    assert_eq!(None, tone.duration());
+    tone.play(c).unwrap();
+    assert!(tone.playing());
+    assert!(audio::ProceduralSource::new(c, 0, 44100, std::iter::empty).is_err());
+
+    let data = audio::SoundData::from_samples(1, 44100, &[0.0, 0.5, -0.5, 1.0]).unwrap();
+    data.encode_wav(c, "/procedural.wav").unwrap();
+    let saved = audio::SoundData::new(c, "/procedural.wav").unwrap();
+    assert_eq!(4, saved.samples().unwrap());
+    filesystem::delete(c, "/procedural.wav").unwrap();
+}
+
+#[test]
+fn capture_context_records_played_sounds() {
+    let c = &mut tests::make_capture_context();
+    let mut sound = audio::Source::new(c, "/pew.wav").unwrap();
+    sound.play(c).unwrap();
+
+    struct Silent;
+    impl event::EventHandler<GameError> for Silent {
+        fn update(&mut self, _ctx: &mut Context) -> GameResult {
+            Ok(())
+        }
+        fn draw(&mut self, _ctx: &mut Context) -> GameResult {
+            Ok(())
+        }
+    }
+    let mut runner = event::Runner::new(std::time::Duration::from_millis(100));
+    runner.step(c, &mut Silent, None).unwrap();
+
+    let capture = audio::capture(c).unwrap();
+    assert_eq!(std::time::Duration::from_millis(100), capture.elapsed());
+    assert!(capture.samples().iter().any(|sample| sample.abs() > 0.01));
+    capture.set_follows_timer(false);
+    capture.clear();
+
+    runner.step(c, &mut Silent, None).unwrap();
+    let capture = audio::capture(c).unwrap();
+    assert!(capture.samples().is_empty());
+    capture.advance(std::time::Duration::from_millis(10));
+    assert_eq!(
+        441 * usize::from(capture.channels()),
+        capture.samples().len()
+    );
+}
+
+#[test]
+fn spatial_sources_pan_towards_the_listener() {
+    let c = &mut tests::make_capture_context();
+    let mut sound = audio::SpatialSource::new(c, "/pew.wav").unwrap();
+    sound.set_attenuation(audio::Attenuation::linear(10.0, 1000.0));
+    sound.set_position([100.0, 0.0, 0.0]);
+    sound.play(c).unwrap();
+    audio::mixer(c).set_listener(audio::Listener::new().position([50.0, 0.0, 0.0]));
+
+    let capture = audio::capture(c).unwrap();
+    capture.advance(std::time::Duration::from_millis(100));
+    let samples = capture.samples();
+    let level = |channel: usize| -> f32 {
+        samples
+            .iter()
+            .skip(channel)
+            .step_by(2)
+            .map(|sample| sample.abs())
+            .sum()
+    };
+    assert!(level(1) > 0.0);
+    assert!(level(0) < level(1) * 0.01);
+}
+
+#[test]
+fn sources_fade_out_and_stop() {
+    let c = &mut tests::make_capture_context();
+    let mut sound = audio::Source::new(c, "/pew.ogg").unwrap();
+    sound.set_repeat(true);
+    sound.play(c).unwrap();
+    sound.fade_out(std::time::Duration::from_millis(20));
+
+    let capture = audio::capture(c).unwrap();
+    capture.advance(std::time::Duration::from_millis(100));
+    assert!(sound.stopped());
+    capture.clear();
+    capture.advance(std::time::Duration::from_millis(100));
+    assert!(capture.samples().iter().all(|sample| *sample == 0.0));
+}
+
+#[test]
+fn music_queues_and_crossfades_tracks() {
+    use std::time::Duration;
+    let c = &mut tests::make_capture_context();
+    let mut music = audio::Music::new();
+    music.set_crossfade(Duration::from_millis(50));
+    music.enqueue(audio::Track::new("/pew.ogg"));
+    music.enqueue(audio::Track::new("/pew.wav").loop_section(Duration::from_millis(20), None));
+    assert!(!music.is_playing());
+
+    music.update(c).unwrap();
+    assert_eq!(
+        Some(std::path::Path::new("/pew.ogg")),
+        music.current().map(|t| t.path())
+    );
+    assert_eq!(1, music.queued().count());
+
+    music.skip(c).unwrap();
+    assert!(music.current().unwrap().is_looping());
+    assert_eq!(0, music.queued().count());
+
+    music.duck(0.5, Duration::ZERO);
+    assert_eq!(0.5, music.duck_level());
+    music.unduck(Duration::from_millis(100));
+    assert_eq!(0.5, music.duck_level());
+
+    music.stop(Duration::from_millis(10));
+    audio::capture(c)
+        .unwrap()
+        .advance(Duration::from_millis(100));
+    music.update(c).unwrap();
+    assert!(!music.is_playing());
+    assert_eq!(None, music.current());
+}
+
+#[test]
+fn music_moves_on_when_tracks_end() {
+    use std::path::Path;
+    use std::time::Duration;
+    let c = &mut tests::make_capture_context();
+    let length = audio::StreamingSource::new(c, "/sound.ogg")
+        .unwrap()
+        .duration()
+        .unwrap();
+    let mut music = audio::Music::new();
+    music.set_crossfade(Duration::from_millis(500));
+    music.enqueue(audio::Track::new("/sound.ogg"));
+    music.enqueue(audio::Track::new("/pew.flac"));
+    music.enqueue(audio::Track::new("/pew.wav"));
+    music.update(c).unwrap();
+
+    // The OGG file's length is known, so the next track
+    // starts crossfading in before it ends.
+    audio::capture(c)
+        .unwrap()
+        .advance(length - Duration::from_millis(300));
+    music.update(c).unwrap();
+    assert_eq!(
+        Some(Path::new("/pew.flac")),
+        music.current().map(|t| t.path())
+    );
+
+    // However long the FLAC file is, the next
+    // track starts once it has ended.
+    for _ in 0..100 {
+        if music.current().map(|t| t.path()) != Some(Path::new("/pew.flac")) {
+            break;
+        }
+        audio::capture(c)
+            .unwrap()
+            .advance(Duration::from_millis(50));
+        music.update(c).unwrap();
+    }
+    assert_eq!(
+        Some(Path::new("/pew.wav")),
+        music.current().map(|t| t.path())
+    );
+}
+
+#[test]
+fn sound_pools_limit_their_voices() {
+    let c = &mut tests::make_capture_context();
+    let mut pool = audio::SoundPool::new(c, "/pew.ogg").unwrap();
+    pool.set_max_voices(3);
+    pool.set_seed(1);
+    pool.set_pitch_range(0.9, 1.1);
+    for _ in 0..5 {
+        assert!(pool.play(c).unwrap());
+    }
+    assert_eq!(3, pool.active_voices());
+
+    audio::capture(c)
+        .unwrap()
+        .advance(std::time::Duration::from_millis(20));
+    assert_eq!(3, audio::active_voices(c));
+
+    pool.set_stealing(audio::VoiceStealing::Reject);
+    assert!(!pool.play(c).unwrap());
+    pool.stop();
+    audio::capture(c)
+        .unwrap()
+        .advance(std::time::Duration::from_millis(20));
+    assert_eq!(0, pool.active_voices());
+    assert_eq!(0, audio::active_voices(c));
+}
+
+#[test]
+fn analyzers_measure_sources_and_the_master_output() {
+    let c = &mut tests::make_capture_context();
+    let master = audio::Analyzer::new(1024);
+    audio::mixer(c).set_analyzer(Some(&master));
+    let source_analyzer = audio::Analyzer::new(1024);
+    let mut sound = audio::ProceduralSource::from_fn(c, 1, 44100, |i| {
+        Some((i as f32 * 1000.0 * std::f32::consts::TAU / 44100.0).sin())
+    })
+    .unwrap();
+    sound.set_analyzer(Some(&source_analyzer));
+    sound.set_volume(0.5);
+    sound.play(c).unwrap();
+
+    audio::capture(c)
+        .unwrap()
+        .advance(std::time::Duration::from_millis(100));
+    assert!((source_analyzer.levels().peak - 1.0).abs() < 0.01);
+    assert!((master.levels().peak - 0.5).abs() < 0.05);
+    let spectrum = source_analyzer.spectrum();
+    let loudest = (0..spectrum.len())
+        .max_by(|a, b| spectrum[*a].total_cmp(&spectrum[*b]))
+        .unwrap();
+    assert_eq!(source_analyzer.bin(1000.0), loudest);
+}
+
+/// Builds a four-channel MOD that plays a square wave on its first
+/// channel for one pattern.
+fn square_wave_mod() -> Vec<u8> {
+    let mut data = vec![0u8; 20 + 31 * 30];
+    data[..4].copy_from_slice(b"test");
+    let header = 20;
+    // 32 words long, full volume, looping all of it.
+    data[header + 22..header + 24].copy_from_slice(&32u16.to_be_bytes());
+    data[header + 25] = 64;
+    data[header + 28..header + 30].copy_from_slice(&32u16.to_be_bytes());
+    data.extend([1, 0]);
+    data.extend([0u8; 128]);
+    data.extend(b"M.K.");
+    let mut pattern = vec![0u8; 64 * 4 * 4];
+    // C-2 with sample 1.
+    pattern[..4].copy_from_slice(&[0x01, 0xAC, 0x10, 0x00]);
+    data.extend(pattern);
+    data.extend((0..64).map(|i| if i < 32 { 100u8 } else { (-100i8) as u8 }));
+    data
+}
+
+#[test]
+fn tracker_modules_play() {
+    let c = &mut tests::make_capture_context();
+    let data = audio::SoundData::from_bytes(&square_wave_mod());
+    let mut song = audio::TrackerSource::from_data(c, data).unwrap();
+    assert_eq!(audio::TrackerFormat::Mod, song.format());
+    assert_eq!("test", song.title());
+    assert_eq!(4, song.channels());
+    // 64 rows of 6 ticks at 125 BPM.
+    let duration = song.duration().unwrap();
+    assert!((duration.as_secs_f64() - 7.68).abs() < 1e-3);
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    song.on_row(move |pos| {
+        let _ = sender.send(pos);
+    });
+    song.play(c).unwrap();
+    let capture = audio::capture(c).unwrap();
+    capture.advance(std::time::Duration::from_millis(100));
+    assert!(capture.samples().iter().any(|sample| sample.abs() > 0.1));
+    let first = receiver.try_recv().unwrap();
+    assert_eq!(audio::TrackerPosition::default(), first);
+
+    song.set_channel_muted(0, true);
+    capture.advance(std::time::Duration::from_millis(100));
+    capture.clear();
+    capture.advance(std::time::Duration::from_millis(50));
+    assert!(capture.samples().iter().all(|sample| sample.abs() < 1e-4));
+    assert!(song.playing());
+}
@@ -31,3 +31,20 @@ pub fn context_build_tests() {
         // Can't really test whether or not the window is resizable?
     }
 }
+
+#[test]
+pub fn audio_device_is_saved_in_conf_file() {
+    let conf =
+        conf::Conf::default().audio(conf::AudioSetup::default().output_device(Some("Headphones")));
+    let mut bytes = Vec::new();
+    conf.to_toml_file(&mut bytes).unwrap();
+    let loaded = conf::Conf::from_toml_file(&mut bytes.as_slice()).unwrap();
+    assert_eq!(Some("Headphones"), loaded.audio.output_device.as_deref());
+
+    // Older files without the section still load.
+    let mut old = String::from_utf8(bytes).unwrap();
+    let start = old.find("[audio]").unwrap();
+    old.truncate(start);
+    let loaded = conf::Conf::from_toml_file(&mut old.as_bytes()).unwrap();
+    assert_eq!(None, loaded.audio.output_device);
+}
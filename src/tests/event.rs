@@ -0,0 +1,320 @@
+use crate::event::{EventHandler, InputEvent, KeyCode, KeyMods, MouseButton, Runner};
+use crate::input::actions::{ActionMap, AxisBinding, Bindings, InputBinding};
+use crate::input::recording::{InputPlayer, InputRecorder, InputRecording};
+use crate::input::{keyboard, mouse};
+use crate::tests;
+use crate::*;
+use std::time::Duration;
+
+#[derive(Default)]
+struct Counter {
+    fixed_updates: usize,
+    updates: usize,
+    draws: usize,
+    keys_down: Vec<KeyCode>,
+    clicks: Vec<(f32, f32)>,
+    focus: Vec<bool>,
+}
+
+impl EventHandler for Counter {
+    fn fixed_update(&mut self, _ctx: &mut Context) -> GameResult {
+        self.fixed_updates += 1;
+        Ok(())
+    }
+
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        self.updates += 1;
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.draws += 1;
+        graphics::clear(ctx, graphics::Color::BLACK);
+        graphics::present(ctx)
+    }
+
+    fn key_down_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _: KeyMods, _: bool) {
+        self.keys_down.push(keycode);
+    }
+
+    fn mouse_button_down_event(&mut self, _ctx: &mut Context, _: MouseButton, x: f32, y: f32) {
+        self.clicks.push((x, y));
+    }
+
+    fn focus_event(&mut self, _ctx: &mut Context, gained: bool) {
+        self.focus.push(gained);
+    }
+}
+
+#[test]
+fn runner_steps_frames() {
+    let c = &mut tests::make_headless_context(64, 64);
+    let mut state = Counter::default();
+    let mut runner = Runner::new(Duration::from_millis(10));
+
+    runner
+        .step(
+            c,
+            &mut state,
+            vec![
+                InputEvent::KeyDown(KeyCode::Escape),
+                InputEvent::MouseMotion { x: 10.0, y: 20.0 },
+                InputEvent::MouseButtonDown(MouseButton::Left),
+            ],
+        )
+        .unwrap();
+    assert_eq!(state.updates, 1);
+    assert_eq!(state.draws, 1);
+    assert_eq!(state.keys_down, vec![KeyCode::Escape]);
+    assert_eq!(state.clicks, vec![(10.0, 20.0)]);
+    assert!(keyboard::is_key_pressed(c, KeyCode::Escape));
+    assert!(mouse::button_pressed(c, MouseButton::Left));
+    assert_eq!(timer::delta(c), Duration::from_millis(10));
+
+    for _ in 0..9 {
+        runner.step(c, &mut state, None).unwrap();
+    }
+    assert_eq!(state.updates, 10);
+    assert_eq!(timer::ticks(c), 10);
+
+    // Fill the whole frame log, pushing out the initial guess.
+    for _ in 10..250 {
+        runner.step(c, &mut state, None).unwrap();
+    }
+    assert_eq!(timer::ticks(c), 250);
+    assert_eq!(timer::average_delta(c), Duration::from_millis(10));
+}
+
+#[test]
+fn record_and_replay_input() {
+    let c = &mut tests::make_headless_context(64, 64);
+    let mut runner = Runner::new(Duration::from_millis(10));
+    let mut recorder = InputRecorder::new(c, Counter::default());
+
+    runner
+        .step(c, &mut recorder, vec![InputEvent::KeyDown(KeyCode::A)])
+        .unwrap();
+    runner
+        .step(c, &mut recorder, vec![InputEvent::Focus(false)])
+        .unwrap();
+    runner
+        .step(
+            c,
+            &mut recorder,
+            vec![
+                InputEvent::MouseMotion { x: 5.0, y: 6.0 },
+                InputEvent::MouseButtonDown(MouseButton::Right),
+            ],
+        )
+        .unwrap();
+    let (recorded_state, recording) = recorder.into_inner();
+    assert_eq!(3, recording.frames());
+    assert_eq!(4, recording.events().len());
+
+    recording.save(c, "/recording_test.toml").unwrap();
+    let recording = InputRecording::load(c, "/recording_test.toml").unwrap();
+
+    let mut player = InputPlayer::new(c, Counter::default(), recording);
+    runner.step(c, &mut player, None).unwrap();
+    // Live input doesn't get mixed into the replay.
+    assert!(c.block_live_input);
+    player.key_down_event(c, KeyCode::B, KeyMods::NONE, false);
+    player.focus_event(c, true);
+    while !player.is_finished() {
+        runner.step(c, &mut player, None).unwrap();
+    }
+    assert!(!c.block_live_input);
+    let replayed_state = player.into_inner(c);
+    assert_eq!(3, replayed_state.updates);
+    assert_eq!(recorded_state.keys_down, replayed_state.keys_down);
+    assert_eq!(recorded_state.clicks, replayed_state.clicks);
+    assert_eq!(vec![false], replayed_state.focus);
+}
+
+#[test]
+#[allow(clippy::float_cmp)]
+fn action_map_follows_input() {
+    let c = &mut tests::make_headless_context(64, 64);
+    let mut state = Counter::default();
+    let mut runner = Runner::new(Duration::from_millis(10));
+    let mut bindings = Bindings::new();
+    bindings.bind(
+        "jump",
+        InputBinding::Key {
+            key: KeyCode::Space,
+        },
+    );
+    bindings.bind(
+        "jump",
+        InputBinding::MouseButton {
+            button: MouseButton::Left,
+        },
+    );
+    bindings.bind_axis(
+        "move_x",
+        AxisBinding::Buttons {
+            negative: InputBinding::Key { key: KeyCode::A },
+            positive: InputBinding::Key { key: KeyCode::D },
+        },
+    );
+    let mut actions = ActionMap::new(bindings);
+
+    runner
+        .step(c, &mut state, vec![InputEvent::KeyDown(KeyCode::Space)])
+        .unwrap();
+    actions.update(c);
+    assert!(actions.pressed("jump"));
+    assert!(actions.just_pressed("jump"));
+
+    runner
+        .step(
+            c,
+            &mut state,
+            vec![
+                InputEvent::KeyUp(KeyCode::Space),
+                InputEvent::MouseButtonDown(MouseButton::Left),
+                InputEvent::KeyDown(KeyCode::A),
+            ],
+        )
+        .unwrap();
+    actions.update(c);
+    assert!(actions.pressed("jump"));
+    assert!(!actions.just_pressed("jump"));
+    assert_eq!(-1.0, actions.axis_value("move_x"));
+
+    runner
+        .step(
+            c,
+            &mut state,
+            vec![InputEvent::MouseButtonUp(MouseButton::Left)],
+        )
+        .unwrap();
+    actions.update(c);
+    assert!(actions.just_released("jump"));
+    assert!(!actions.pressed("nonexistent"));
+    assert_eq!(0.0, actions.axis_value("nonexistent"));
+}
+
+/// Records the edge-triggered input state seen by each `update()`.
+#[derive(Default)]
+struct EdgeTracker {
+    frames: Vec<(bool, bool, bool, bool)>,
+}
+
+impl EventHandler for EdgeTracker {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        self.frames.push((
+            keyboard::is_key_just_pressed(ctx, KeyCode::Space),
+            keyboard::is_key_just_released(ctx, KeyCode::Space),
+            mouse::button_just_pressed(ctx, MouseButton::Left),
+            mouse::button_just_released(ctx, MouseButton::Left),
+        ));
+        Ok(())
+    }
+
+    fn draw(&mut self, _ctx: &mut Context) -> GameResult {
+        Ok(())
+    }
+}
+
+#[test]
+fn just_pressed_lasts_one_frame() {
+    let c = &mut tests::make_headless_context(64, 64);
+    let mut state = EdgeTracker::default();
+    let mut runner = Runner::new(Duration::from_millis(10));
+
+    let frames = vec![
+        vec![InputEvent::KeyDown(KeyCode::Space)],
+        vec![InputEvent::MouseButtonDown(MouseButton::Left)],
+        vec![],
+        vec![
+            InputEvent::KeyUp(KeyCode::Space),
+            InputEvent::MouseButtonUp(MouseButton::Left),
+        ],
+        vec![],
+    ];
+    for events in frames {
+        runner.step(c, &mut state, events).unwrap();
+    }
+    assert_eq!(
+        state.frames,
+        vec![
+            (true, false, false, false),
+            (false, false, true, false),
+            (false, false, false, false),
+            (false, true, false, true),
+            (false, false, false, false),
+        ]
+    );
+}
+
+#[test]
+fn press_and_release_in_one_frame_is_seen() {
+    let c = &mut tests::make_headless_context(64, 64);
+    let mut state = EdgeTracker::default();
+    let mut runner = Runner::new(Duration::from_millis(10));
+
+    let frames = vec![
+        vec![
+            InputEvent::KeyDown(KeyCode::Space),
+            InputEvent::KeyUp(KeyCode::Space),
+            InputEvent::MouseButtonDown(MouseButton::Left),
+            InputEvent::MouseButtonUp(MouseButton::Left),
+        ],
+        vec![],
+    ];
+    for events in frames {
+        runner.step(c, &mut state, events).unwrap();
+    }
+    assert_eq!(
+        state.frames,
+        vec![(true, true, true, true), (false, false, false, false)]
+    );
+}
+
+#[test]
+#[allow(clippy::float_cmp)]
+fn fixed_updates_catch_up_and_clamp() {
+    let c = &mut tests::make_headless_context(64, 64);
+    c.conf.timing = conf::TimingSetup::default()
+        .fixed_update_fps(Some(100))
+        .max_fixed_updates(5);
+    let mut state = Counter::default();
+
+    let mut runner = Runner::new(Duration::from_millis(25));
+    runner.step(c, &mut state, None).unwrap();
+    assert_eq!(state.fixed_updates, 2);
+    assert_eq!(state.updates, 1);
+    assert!((timer::interpolation_alpha(c) - 0.5).abs() < 0.01);
+
+    // A long frame only gets `max_fixed_updates` and the rest is dropped.
+    runner.set_delta(Duration::from_secs(1));
+    runner.step(c, &mut state, None).unwrap();
+    assert_eq!(state.fixed_updates, 7);
+    assert!(timer::interpolation_alpha(c) < 1.0);
+
+    c.conf.timing = conf::TimingSetup::default();
+    runner.step(c, &mut state, None).unwrap();
+    assert_eq!(state.fixed_updates, 7);
+    assert_eq!(timer::interpolation_alpha(c), 0.0);
+}
+
+#[test]
+#[allow(clippy::float_cmp)]
+fn zero_fixed_updates_run_nothing() {
+    let c = &mut tests::make_headless_context(64, 64);
+    let mut state = Counter::default();
+    let mut runner = Runner::new(Duration::from_millis(25));
+
+    c.conf.timing = conf::TimingSetup::default().fixed_update_fps(Some(0));
+    runner.step(c, &mut state, None).unwrap();
+    assert_eq!(state.fixed_updates, 0);
+    assert_eq!(timer::interpolation_alpha(c), 0.0);
+
+    c.conf.timing = conf::TimingSetup::default()
+        .fixed_update_fps(Some(100))
+        .max_fixed_updates(0);
+    runner.step(c, &mut state, None).unwrap();
+    assert_eq!(state.fixed_updates, 0);
+    assert_eq!(state.updates, 2);
+}
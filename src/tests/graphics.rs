@@ -109,6 +109,186 @@ fn save_screenshot() {
     save_screenshot_test(c);
 }
 
+#[test]
+fn save_screenshot_headless() {
+    let c = &mut tests::make_headless_context(320, 240);
+    assert_eq!((320.0, 240.0), graphics::drawable_size(c));
+    save_screenshot_test(c);
+}
+
+#[test]
+fn headless_canvas_to_rgba8() {
+    let c = &mut tests::make_headless_context(64, 64);
+    let canvas = graphics::Canvas::with_window_size(c).unwrap();
+    graphics::set_canvas(c, Some(&canvas));
+    graphics::clear(c, Color::new(1.0, 0.0, 0.0, 1.0));
+    graphics::present(c).unwrap();
+    graphics::set_canvas(c, None);
+    let rgba_buf = canvas.to_rgba8(c).unwrap();
+    assert_eq!(64 * 64 * 4, rgba_buf.len());
+    assert_eq!(
+        (255, 0, 0, 255),
+        get_rgba_sample(&rgba_buf, 64, Vec2::new(32.0, 32.0))
+    );
+}
+
+#[test]
+fn texture_atlas_regions_draw_from_sprite_batches() {
+    let c = &mut tests::make_headless_context(64, 64);
+    let mut builder = graphics::TextureAtlas::builder().padding(2);
+    builder.add_file(c, "player", "/player.png").unwrap();
+    let red = graphics::Image::solid(c, 8, Color::new(1.0, 0.0, 0.0, 1.0)).unwrap();
+    builder.add_image(c, "red", &red).unwrap();
+    let atlas = builder.build(c).unwrap();
+    assert_eq!(1, atlas.pages().len());
+    let region = atlas.region("red").unwrap();
+    assert_eq!((8.0, 8.0), (region.rect.w, region.rect.h));
+
+    let canvas = graphics::Canvas::with_window_size(c).unwrap();
+    graphics::set_canvas(c, Some(&canvas));
+    graphics::clear(c, Color::BLACK);
+    let mut batch = graphics::spritebatch::SpriteBatch::new(atlas.page(0).unwrap().clone());
+    let _ = batch.add(
+        graphics::DrawParam::new()
+            .src(region.src)
+            .dest([16.0, 16.0]),
+    );
+    graphics::draw(c, &batch, graphics::DrawParam::new()).unwrap();
+    graphics::present(c).unwrap();
+    graphics::set_canvas(c, None);
+    let rgba_buf = canvas.to_rgba8(c).unwrap();
+    assert_eq!(
+        (255, 0, 0, 255),
+        get_rgba_sample(&rgba_buf, 64, Vec2::new(20.0, 20.0))
+    );
+    assert_eq!(
+        (0, 0, 0, 255),
+        get_rgba_sample(&rgba_buf, 64, Vec2::new(26.0, 20.0))
+    );
+}
+
+#[test]
+fn camera_moves_drawing_until_popped() {
+    let c = &mut tests::make_headless_context(64, 64);
+    let mut camera = graphics::Camera2D::new([100.0, 100.0]);
+    camera.set_zoom(2.0);
+    let corner = camera.world_to_screen(c, [100.0, 100.0]);
+    assert_eq!((32.0, 32.0), (corner.x.round(), corner.y.round()));
+    let back = camera.screen_to_world(c, [40.0, 36.0]);
+    assert_eq!((104.0, 102.0), (back.x.round(), back.y.round()));
+
+    let canvas = graphics::Canvas::with_window_size(c).unwrap();
+    graphics::set_canvas(c, Some(&canvas));
+    graphics::clear(c, Color::BLACK);
+    let red = graphics::Image::solid(c, 4, Color::new(1.0, 0.0, 0.0, 1.0)).unwrap();
+    graphics::push_camera(c, &camera).unwrap();
+    graphics::draw(c, &red, graphics::DrawParam::new().dest([100.0, 100.0])).unwrap();
+    graphics::pop_camera(c).unwrap();
+    assert!(graphics::pop_camera(c).is_err());
+    // Without the camera, the same draw lands in the top left corner.
+    graphics::draw(c, &red, graphics::DrawParam::new().dest([0.0, 0.0])).unwrap();
+    graphics::present(c).unwrap();
+    graphics::set_canvas(c, None);
+    let rgba_buf = canvas.to_rgba8(c).unwrap();
+    let red = (255, 0, 0, 255);
+    let black = (0, 0, 0, 255);
+    assert_eq!(red, get_rgba_sample(&rgba_buf, 64, Vec2::new(38.0, 38.0)));
+    assert_eq!(black, get_rgba_sample(&rgba_buf, 64, Vec2::new(42.0, 42.0)));
+    assert_eq!(red, get_rgba_sample(&rgba_buf, 64, Vec2::new(2.0, 2.0)));
+}
+
+#[test]
+fn nested_scissors_clip_drawing() {
+    let c = &mut tests::make_headless_context(64, 64);
+    let canvas = graphics::Canvas::with_window_size(c).unwrap();
+    graphics::set_canvas(c, Some(&canvas));
+    graphics::clear(c, Color::BLACK);
+    let red = graphics::Image::solid(c, 64, Color::new(1.0, 0.0, 0.0, 1.0)).unwrap();
+    graphics::push_scissor(c, graphics::Rect::new(8.0, 8.0, 16.0, 16.0));
+    graphics::push_scissor(c, graphics::Rect::new(16.0, 16.0, 32.0, 32.0));
+    let mut batch = graphics::spritebatch::SpriteBatch::new(red.clone());
+    let _ = batch.add(graphics::DrawParam::new());
+    graphics::draw(c, &batch, graphics::DrawParam::new()).unwrap();
+    graphics::pop_scissor(c).unwrap();
+    graphics::pop_scissor(c).unwrap();
+    assert!(graphics::pop_scissor(c).is_err());
+    // Not clipped any more.
+    graphics::draw(c, &red, graphics::DrawParam::new().dest([56.0, 56.0])).unwrap();
+    graphics::present(c).unwrap();
+    graphics::set_canvas(c, None);
+    let rgba_buf = canvas.to_rgba8(c).unwrap();
+    let red = (255, 0, 0, 255);
+    let black = (0, 0, 0, 255);
+    assert_eq!(red, get_rgba_sample(&rgba_buf, 64, Vec2::new(20.0, 20.0)));
+    assert_eq!(black, get_rgba_sample(&rgba_buf, 64, Vec2::new(12.0, 12.0)));
+    assert_eq!(black, get_rgba_sample(&rgba_buf, 64, Vec2::new(30.0, 30.0)));
+    assert_eq!(red, get_rgba_sample(&rgba_buf, 64, Vec2::new(60.0, 60.0)));
+}
+
+#[test]
+fn clear_is_not_clipped_by_earlier_draws() {
+    let c = &mut tests::make_headless_context(64, 64);
+    let small = graphics::Canvas::new(
+        c,
+        16,
+        16,
+        conf::NumSamples::One,
+        graphics::get_window_color_format(c),
+    )
+    .unwrap();
+    let big = graphics::Canvas::with_window_size(c).unwrap();
+    graphics::set_canvas(c, Some(&small));
+    let white = graphics::Image::solid(c, 4, Color::WHITE).unwrap();
+    graphics::draw(c, &white, graphics::DrawParam::new()).unwrap();
+    graphics::set_canvas(c, Some(&big));
+    graphics::clear(c, Color::new(0.0, 0.0, 1.0, 1.0));
+    graphics::present(c).unwrap();
+    graphics::set_canvas(c, None);
+    let rgba_buf = big.to_rgba8(c).unwrap();
+    assert_eq!(
+        (0, 0, 255, 255),
+        get_rgba_sample(&rgba_buf, 64, Vec2::new(60.0, 60.0))
+    );
+}
+
+#[test]
+fn masks_limit_drawing_inside_or_outside() {
+    let c = &mut tests::make_headless_context(64, 64);
+    let circle = graphics::Mesh::new_circle(
+        c,
+        graphics::DrawMode::fill(),
+        Vec2::new(32.0, 32.0),
+        16.0,
+        0.5,
+        Color::WHITE,
+    )
+    .unwrap();
+    let red = graphics::Image::solid(c, 64, Color::new(1.0, 0.0, 0.0, 1.0)).unwrap();
+    let red_pixel = (255, 0, 0, 255);
+    let black = (0, 0, 0, 255);
+    for (mode, center, corner) in [
+        (graphics::MaskMode::Inside, red_pixel, black),
+        (graphics::MaskMode::Outside, black, red_pixel),
+    ] {
+        let canvas = graphics::Canvas::with_window_size(c).unwrap();
+        graphics::set_canvas(c, Some(&canvas));
+        graphics::clear(c, Color::BLACK);
+        graphics::draw_mask(c, &circle, graphics::DrawParam::new(), mode).unwrap();
+        assert_eq!(Some(mode), graphics::mask_mode(c));
+        graphics::draw(c, &red, graphics::DrawParam::new()).unwrap();
+        graphics::clear_mask(c);
+        assert!(graphics::extend_mask(c, &circle, graphics::DrawParam::new()).is_err());
+        graphics::present(c).unwrap();
+        graphics::set_canvas(c, None);
+        let rgba_buf = canvas.to_rgba8(c).unwrap();
+        assert_eq!(
+            center,
+            get_rgba_sample(&rgba_buf, 64, Vec2::new(32.0, 32.0))
+        );
+        assert_eq!(corner, get_rgba_sample(&rgba_buf, 64, Vec2::new(2.0, 2.0)));
+    }
+}
+
 // Not supported, see https://github.com/ggez/ggez/issues/751
 // #[test]
 // fn save_screenshot_with_antialiasing() {
@@ -6,12 +6,17 @@ use std::path;
 
 mod audio;
 mod conf;
+mod event;
 mod filesystem;
 mod graphics;
 mod mesh;
 mod text;
+mod timer;
 
-pub fn make_context_from_contextbuilder(mut cb: ContextBuilder) -> (Context, event::EventLoop<()>) {
+/// Build a `Context` from the given `ContextBuilder`, with the `resources` dir mounted.
+pub fn make_context_from_contextbuilder(
+    mut cb: ContextBuilder,
+) -> (Context, crate::event::EventLoop<()>) {
     if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
         let mut path = path::PathBuf::from(manifest_dir);
         path.push("resources");
@@ -21,7 +26,35 @@ pub fn make_context_from_contextbuilder(mut cb: ContextBuilder) -> (Context, eve
 }
 
 /// Make a basic `Context` with sane defaults.
-pub fn make_context() -> (Context, event::EventLoop<()>) {
+pub fn make_context() -> (Context, crate::event::EventLoop<()>) {
     let cb = ContextBuilder::new("ggez_unit_tests", "ggez");
     make_context_from_contextbuilder(cb)
 }
+
+/// Build a headless `Context` from the given `ContextBuilder`, with the `resources` dir mounted.
+pub fn make_headless_context_from_contextbuilder(
+    mut cb: ContextBuilder,
+    width: u16,
+    height: u16,
+) -> Context {
+    if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
+        let mut path = path::PathBuf::from(manifest_dir);
+        path.push("resources");
+        cb = cb.add_resource_path(path);
+    }
+    cb.headless(width, height).unwrap()
+}
+
+/// Make a headless `Context` that doesn't need a display.
+pub fn make_headless_context(width: u16, height: u16) -> Context {
+    let cb = ContextBuilder::new("ggez_unit_tests", "ggez");
+    make_headless_context_from_contextbuilder(cb, width, height)
+}
+
+/// Make a headless `Context` whose audio is captured into memory
+/// instead of being played on a device.
+pub fn make_capture_context() -> Context {
+    let cb = ContextBuilder::new("ggez_unit_tests", "ggez")
+        .modules(crate::conf::ModuleConf::default().audio_capture(true));
+    make_headless_context_from_contextbuilder(cb, 100, 100)
+}
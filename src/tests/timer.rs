@@ -0,0 +1,55 @@
+use crate::event::{EventHandler, Runner};
+use crate::tests;
+use crate::*;
+use std::time::{Duration, Instant};
+
+struct Nothing;
+
+impl EventHandler for Nothing {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        Ok(())
+    }
+
+    fn draw(&mut self, _ctx: &mut Context) -> GameResult {
+        Ok(())
+    }
+}
+
+#[test]
+fn frame_time_statistics() {
+    let c = &mut tests::make_headless_context(64, 64);
+    let mut runner = Runner::new(Duration::from_millis(10));
+    // Fill the whole frame log, pushing out the initial guess.
+    for _ in 0..250 {
+        runner.step(c, &mut Nothing, None).unwrap();
+    }
+    assert!(timer::delta_variance(c) < 1e-12);
+    assert_eq!(timer::delta_percentile(c, 0.0), Duration::from_millis(10));
+    assert_eq!(timer::delta_percentile(c, 100.0), Duration::from_millis(10));
+
+    runner.set_delta(Duration::from_millis(30));
+    runner.step(c, &mut Nothing, None).unwrap();
+    runner.step(c, &mut Nothing, None).unwrap();
+    assert!(timer::delta_variance(c) > 0.0);
+    assert_eq!(timer::delta_percentile(c, 50.0), Duration::from_millis(10));
+    assert_eq!(timer::delta_percentile(c, 99.0), Duration::from_millis(10));
+    assert_eq!(timer::delta_percentile(c, 100.0), Duration::from_millis(30));
+}
+
+#[test]
+fn wait_for_next_frame_paces() {
+    let c = &mut tests::make_headless_context(64, 64);
+    let start = Instant::now();
+    c.timer_context.tick();
+    timer::wait_for_next_frame(c, 50);
+    assert!(start.elapsed() >= Duration::from_millis(20));
+}
+
+#[test]
+fn zero_target_fps_is_uncapped() {
+    let c = &mut tests::make_headless_context(64, 64);
+    let start = Instant::now();
+    c.timer_context.tick();
+    timer::wait_for_next_frame(c, 0);
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
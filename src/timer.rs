@@ -9,7 +9,9 @@
 //! really needs to.  Enabling vsync by setting
 //! [`conf.window_setup.vsync`](../conf/struct.WindowSetup.html#structfield.vsync)
 //! in your [`Conf`](../conf/struct.Conf.html) object is generally the best
-//! way to cap your displayed framerate.
+//! way to cap your displayed framerate.  Where vsync is off or unavailable,
+//! setting [`conf.timing.target_fps`](../conf/struct.TimingSetup.html#structfield.target_fps)
+//! makes [`event::run()`](../event/fn.run.html) sleep between frames instead.
 //!
 //! For a more detailed tutorial in how to handle frame timings in games,
 //! see <http://gafferongames.com/game-physics/fix-your-timestep/>
@@ -100,6 +102,10 @@ pub struct TimeContext {
 /// How many frames we log update times for.
 const TIME_LOG_FRAMES: usize = 200;
 
+/// How long before a frame deadline we stop sleeping and start spinning,
+/// since sleeping is rarely more accurate than a millisecond or two.
+const SPIN_THRESHOLD: time::Duration = time::Duration::from_millis(2);
+
 impl TimeContext {
     /// Creates a new `TimeContext` and initializes the start to this instant.
     pub fn new() -> TimeContext {
@@ -123,11 +129,62 @@ impl TimeContext {
     pub fn tick(&mut self) {
         let now = time::Instant::now();
         let time_since_last = now - self.last_instant;
-        self.frame_durations.push(time_since_last);
+        self.advance(time_since_last);
         self.last_instant = now;
+    }
+
+    /// Like [`tick()`](#method.tick), but records that exactly `dt`
+    /// has passed instead of measuring it with the system clock.
+    ///
+    /// Useful for stepping a game deterministically, for example with an
+    /// [`event::Runner`](../event/struct.Runner.html).  Note that
+    /// [`time_since_start()`](fn.time_since_start.html) still uses the
+    /// system clock.
+    pub fn tick_by(&mut self, dt: time::Duration) {
+        self.advance(dt);
+        self.last_instant = time::Instant::now();
+    }
+
+    /// Returns when the next frame should start to hit `target_fps`,
+    /// counting from the start of the current one.
+    pub(crate) fn next_frame_deadline(&self, target_fps: u32) -> time::Instant {
+        self.last_instant + fps_as_duration(target_fps)
+    }
+
+    /// Returns when an event loop should wake up to start the next frame
+    /// at `target_fps`, leaving time to spin up to the exact deadline.
+    pub(crate) fn next_frame_wakeup(&self, target_fps: u32) -> time::Instant {
+        let deadline = self.next_frame_deadline(target_fps);
+        deadline.checked_sub(SPIN_THRESHOLD).unwrap_or(deadline)
+    }
+
+    /// Returns `false` if it's too early to start the next frame at
+    /// `target_fps`.  If the deadline is close, spins until it has
+    /// passed and returns `true`.
+    pub(crate) fn spin_until_next_frame(&self, target_fps: u32) -> bool {
+        let deadline = self.next_frame_deadline(target_fps);
+        if time::Instant::now() + SPIN_THRESHOLD < deadline {
+            return false;
+        }
+        while time::Instant::now() < deadline {
+            thread::yield_now();
+        }
+        true
+    }
+
+    /// Throws away all but the fractional part of a frame from the time
+    /// waiting to be consumed by [`check_update_time()`](fn.check_update_time.html).
+    pub(crate) fn drop_update_backlog(&mut self, target_fps: u32) {
+        let target_dt = fps_as_duration(target_fps).as_nanos();
+        let remainder = self.residual_update_dt.as_nanos() % target_dt;
+        self.residual_update_dt = time::Duration::from_nanos(remainder as u64);
+    }
+
+    fn advance(&mut self, dt: time::Duration) {
+        self.frame_durations.push(dt);
         self.frame_count += 1;
 
-        self.residual_update_dt += time_since_last;
+        self.residual_update_dt += dt;
     }
 }
 
@@ -158,6 +215,34 @@ pub fn average_delta(ctx: &Context) -> time::Duration {
     }
 }
 
+/// Gets the variance of the frame times over the last 200 frames,
+/// in seconds squared.  A steady frame rate has a variance near zero.
+pub fn delta_variance(ctx: &Context) -> f64 {
+    let tc = &ctx.timer_context;
+    let mean = duration_to_f64(average_delta(ctx));
+    let contents = tc.frame_durations.contents();
+    let sum: f64 = contents
+        .iter()
+        .map(|d| {
+            let diff = duration_to_f64(*d) - mean;
+            diff * diff
+        })
+        .sum();
+    sum / contents.len() as f64
+}
+
+/// Gets the given percentile of the frame times over the last 200
+/// frames.  `percentile` is clamped to the range 0.0 to 100.0, so for
+/// example `delta_percentile(ctx, 99.0)` returns a frame time that only
+/// 1% of recent frames took longer than.
+pub fn delta_percentile(ctx: &Context, percentile: f64) -> time::Duration {
+    let tc = &ctx.timer_context;
+    let mut sorted = tc.frame_durations.contents().to_vec();
+    sorted.sort_unstable();
+    let rank = (percentile.clamp(0.0, 100.0) / 100.0 * (sorted.len() - 1) as f64).round();
+    sorted[rank as usize]
+}
+
 /// A convenience function to convert a Rust `Duration` type
 /// to a (less precise but more useful) `f64`.
 ///
@@ -249,6 +334,45 @@ pub fn remaining_update_time(ctx: &mut Context) -> time::Duration {
     ctx.timer_context.residual_update_dt
 }
 
+/// Returns how far the game is between the last fixed update and the
+/// next one, from 0.0 to 1.0, when fixed updates are enabled with
+/// [`conf::TimingSetup::fixed_update_fps`](../conf/struct.TimingSetup.html#structfield.fixed_update_fps).
+/// Returns 0.0 otherwise.
+///
+/// Call it in [`draw()`](../event/trait.EventHandler.html#tymethod.draw)
+/// to interpolate between the previous and the current state of things
+/// moved in [`fixed_update()`](../event/trait.EventHandler.html#method.fixed_update).
+pub fn interpolation_alpha(ctx: &Context) -> f32 {
+    match ctx.conf.timing.fixed_update_fps.filter(|&fps| fps > 0) {
+        Some(fps) => {
+            let target_dt = duration_to_f64(fps_as_duration(fps));
+            let residual = duration_to_f64(ctx.timer_context.residual_update_dt);
+            (residual / target_dt).min(1.0) as f32
+        }
+        None => 0.0,
+    }
+}
+
+/// Sleeps and then spins until it's time to start the next frame
+/// at `target_fps`, counting from the start of the current one.
+///
+/// [`event::run()`](../event/fn.run.html) does this for you if
+/// [`conf.timing.target_fps`](../conf/struct.TimingSetup.html#structfield.target_fps)
+/// is set; this is for when you're running your own event loop.
+/// A `target_fps` of 0 means no cap and returns right away.
+pub fn wait_for_next_frame(ctx: &Context, target_fps: u32) {
+    if target_fps == 0 {
+        return;
+    }
+    let tc = &ctx.timer_context;
+    let wakeup = tc.next_frame_wakeup(target_fps);
+    let now = time::Instant::now();
+    if wakeup > now {
+        sleep(wakeup - now);
+    }
+    let _ = tc.spin_until_next_frame(target_fps);
+}
+
 /// Pauses the current thread for the target duration.
 /// Just calls [`std::thread::sleep()`](https://doc.rust-lang.org/std/thread/fn.sleep.html)
 /// so it's as accurate as that is (which is usually not very).
@@ -29,6 +29,11 @@ pub trait VFile: Read + Write + Seek + Debug {}
 
 impl<T> VFile for T where T: Read + Write + Seek + Debug {}
 
+/// A `VFile` that can be sent to another thread, such as the audio thread.
+pub trait SendFile: VFile + Send {}
+
+impl<T> SendFile for T where T: VFile + Send {}
+
 /// Options for opening files
 ///
 /// We need our own version of this structure because the one in
@@ -115,6 +120,9 @@ pub trait VFS: Debug {
             OpenOptions::new().write(true).create(true).append(true),
         )
     }
+    /// Open the file at this path for reading, so that it can be read
+    /// from another thread
+    fn open_send(&self, path: &Path) -> GameResult<Box<dyn SendFile>>;
     /// Create a directory at the location by this path
     fn mkdir(&self, path: &Path) -> GameResult;
 
@@ -320,6 +328,15 @@ impl VFS for PhysicalFS {
             .map_err(GameError::from)
     }
 
+    /// Open the file at this path for reading, so that it can be read
+    /// from another thread
+    fn open_send(&self, path: &Path) -> GameResult<Box<dyn SendFile>> {
+        let p = self.to_absolute(path)?;
+        fs::File::open(p)
+            .map(|x| Box::new(x) as Box<dyn SendFile>)
+            .map_err(GameError::from)
+    }
+
     /// Create a directory at the location by this path
     fn mkdir(&self, path: &Path) -> GameResult {
         if self.readonly {
@@ -452,15 +469,16 @@ impl OverlayFS {
     pub fn roots(&self) -> &VecDeque<Box<dyn VFS>> {
         &self.roots
     }
-}
 
-impl VFS for OverlayFS {
-    /// Open the file at this path with the given options
-    fn open_options(&self, path: &Path, open_options: OpenOptions) -> GameResult<Box<dyn VFile>> {
+    /// Opens the file with the first root that has it.
+    fn open_first<F, T>(&self, path: &Path, open: F) -> GameResult<T>
+    where
+        F: Fn(&dyn VFS) -> GameResult<T>,
+    {
         let mut tried: Vec<(PathBuf, GameError)> = vec![];
 
         for vfs in &self.roots {
-            match vfs.open_options(path, open_options) {
+            match open(vfs.as_ref()) {
                 Err(e) => {
                     if let Some(vfs_path) = vfs.to_path_buf() {
                         tried.push((vfs_path, e));
@@ -474,6 +492,19 @@ impl VFS for OverlayFS {
         let errmessage = String::from(convenient_path_to_str(path)?);
         Err(GameError::ResourceNotFound(errmessage, tried))
     }
+}
+
+impl VFS for OverlayFS {
+    /// Open the file at this path with the given options
+    fn open_options(&self, path: &Path, open_options: OpenOptions) -> GameResult<Box<dyn VFile>> {
+        self.open_first(path, |vfs| vfs.open_options(path, open_options))
+    }
+
+    /// Open the file at this path for reading, so that it can be read
+    /// from another thread
+    fn open_send(&self, path: &Path) -> GameResult<Box<dyn SendFile>> {
+        self.open_first(path, |vfs| vfs.open_send(path))
+    }
 
     /// Create a directory at the location by this path
     fn mkdir(&self, path: &Path) -> GameResult {
@@ -643,6 +674,14 @@ impl ZipFS {
             index: idx,
         }
     }
+
+    fn open_wrapper(&self, path: &str) -> GameResult<ZipFileWrapper> {
+        let mut stupid_archive_borrow = self.archive
+            .try_borrow_mut()
+            .expect("Couldn't borrow ZipArchive in ZipFS::open_options(); should never happen! Report a bug at https://github.com/ggez/ggez/");
+        let mut f = stupid_archive_borrow.by_name(path)?;
+        ZipFileWrapper::new(&mut f)
+    }
 }
 
 /// A wrapper to contain a zipfile so we can implement
@@ -697,6 +736,71 @@ impl Debug for ZipFileWrapper {
     }
 }
 
+/// An uncompressed file in a zip archive on disk, read straight out of
+/// the archive through a file handle of its own.  Unlike a
+/// `ZipFileWrapper`, it never holds more of the file in memory than
+/// what is being read, and it can be sent to another thread.
+#[derive(Debug)]
+pub struct ZipStoredFile {
+    file: fs::File,
+    /// Where the file's data starts in the archive.
+    start: u64,
+    len: u64,
+    position: u64,
+}
+
+impl ZipStoredFile {
+    fn open(archive: &Path, start: u64, len: u64) -> GameResult<Self> {
+        let mut file = fs::File::open(archive)?;
+        let _ = file.seek(io::SeekFrom::Start(start))?;
+        Ok(Self {
+            file,
+            start,
+            len,
+            position: 0,
+        })
+    }
+}
+
+impl io::Read for ZipStoredFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.len.saturating_sub(self.position);
+        let max = buf.len().min(usize::try_from(left).unwrap_or(usize::MAX));
+        let read = self.file.read(&mut buf[..max])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl io::Write for ZipStoredFile {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        panic!("Cannot write to a zip file!")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Seek for ZipStoredFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            io::SeekFrom::Start(offset) => Some(offset),
+            io::SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            io::SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        let _ = self.file.seek(io::SeekFrom::Start(self.start + position))?;
+        self.position = position;
+        Ok(position)
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 struct ZipMetadata {
     len: u64,
@@ -750,14 +854,37 @@ impl VFS for ZipFS {
             );
             return Err(GameError::FilesystemError(msg));
         }
-        let mut stupid_archive_borrow = self.archive
-            .try_borrow_mut()
-            .expect("Couldn't borrow ZipArchive in ZipFS::open_options(); should never happen! Report a bug at https://github.com/ggez/ggez/");
-        let mut f = stupid_archive_borrow.by_name(path)?;
-        let zipfile = ZipFileWrapper::new(&mut f)?;
+        let zipfile = self.open_wrapper(path)?;
         Ok(Box::new(zipfile) as Box<dyn VFile>)
     }
 
+    /// Uncompressed files in an archive on disk are read bit by bit as
+    /// they are needed.  Compressed files, and files in archives that
+    /// were read from memory, are decompressed into memory as a whole
+    /// first, like with `open()`.
+    fn open_send(&self, path: &Path) -> GameResult<Box<dyn SendFile>> {
+        let path = convenient_path_to_str(path)?;
+        if let Some(source) = &self.source {
+            let stored = {
+                let mut stupid_archive_borrow = self.archive
+                    .try_borrow_mut()
+                    .expect("Couldn't borrow ZipArchive in ZipFS::open_send(); should never happen! Report a bug at https://github.com/ggez/ggez/");
+                let f = stupid_archive_borrow.by_name(path)?;
+                if f.compression() == zip::CompressionMethod::Stored {
+                    Some((f.data_start(), f.size()))
+                } else {
+                    None
+                }
+            };
+            if let Some((start, len)) = stored {
+                let zipfile = ZipStoredFile::open(source, start, len)?;
+                return Ok(Box::new(zipfile) as Box<dyn SendFile>);
+            }
+        }
+        let zipfile = self.open_wrapper(path)?;
+        Ok(Box::new(zipfile) as Box<dyn SendFile>)
+    }
+
     fn mkdir(&self, path: &Path) -> GameResult {
         let msg = format!(
             "Cannot mkdir {:?} in zipfile {:?}, filesystem read-only",
@@ -896,6 +1023,27 @@ mod tests {
         assert!(!ofs.exists(Path::new("/foobaz.rs")));
     }
 
+    #[test]
+    fn headless_test_open_send_overlay() {
+        let cargo_path = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let fs1 = PhysicalFS::new(cargo_path, true);
+        let fs2 = PhysicalFS::new(&cargo_path.join("src"), true);
+        let mut ofs = OverlayFS::new();
+        ofs.push_back(Box::new(fs1));
+        ofs.push_back(Box::new(fs2));
+
+        let f = ofs.open_send(Path::new("/lib.rs")).unwrap();
+        let line = std::thread::spawn(move || {
+            let mut s = String::new();
+            io::BufReader::new(f).read_line(&mut s).map(|_| s)
+        })
+        .join()
+        .unwrap()
+        .unwrap();
+        assert!(!line.is_empty());
+        assert!(ofs.open_send(Path::new("/foobaz.rs")).is_err());
+    }
+
     #[test]
     fn headless_test_physical_all() {
         let cargo_path = Path::new(env!("CARGO_MANIFEST_DIR"));
@@ -993,6 +1141,49 @@ mod tests {
             .unwrap()
             .read_to_string(&mut contents);
         assert_eq!(contents, "Zip contents!");
+
+        let mut contents = String::new();
+        let _bytes = zfs
+            .open_send(Path::new("/fake_file_name.txt"))
+            .unwrap()
+            .read_to_string(&mut contents);
+        assert_eq!(contents, "Zip contents!");
+    }
+
+    #[test]
+    fn headless_test_zip_open_send_reads_stored_files_incrementally() {
+        let path = std::env::temp_dir().join(format!("ggez-test-{}.zip", std::process::id()));
+        {
+            let mut zip_archive = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+            let options = zip::write::FileOptions::default()
+                .compression_method(zip::CompressionMethod::Stored);
+            zip_archive.start_file("first.txt", options).unwrap();
+            let _bytes = zip_archive.write(b"Some other file").unwrap();
+            zip_archive.start_file("music.raw", options).unwrap();
+            let _bytes = zip_archive.write(b"0123456789").unwrap();
+            let _file = zip_archive.finish().unwrap();
+        }
+        let zfs = ZipFS::new(&path).unwrap();
+        let mut f = zfs.open_send(Path::new("/music.raw")).unwrap();
+        assert!(format!("{:?}", f).starts_with("ZipStoredFile"));
+
+        let mut contents = String::new();
+        let _bytes = f.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "0123456789");
+
+        assert_eq!(7, f.seek(io::SeekFrom::End(-3)).unwrap());
+        let mut contents = String::new();
+        let _bytes = f.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "789");
+        assert_eq!(2, f.seek(io::SeekFrom::Start(2)).unwrap());
+        let mut buf = [0; 3];
+        f.read_exact(&mut buf).unwrap();
+        assert_eq!(b"234", &buf);
+        assert!(f.seek(io::SeekFrom::Current(-10)).is_err());
+
+        drop(f);
+        drop(zfs);
+        fs::remove_file(&path).unwrap();
     }
 
     // BUGGO: TODO: Make sure all functions are tested for OverlayFS and ZipFS!!